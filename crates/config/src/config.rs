@@ -14,6 +14,9 @@ const CONFIG_DIR: &str = "config";
 
 const CONFIG_FIILE: &str = "ramd.toml";
 
+/// Default path of the encrypted node identity, relative to the ramd directory
+const KEYSTORE_FILE: &str = "keystore/node_key.json";
+
 /// This struct gathers all config values used across ramd node
 #[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq, Serialize)]
 #[serde(default)]
@@ -31,8 +34,19 @@ pub struct RamdConfig {
 }
 
 impl RamdConfig {
-    /// Reads config from default path, returns error if config doesn't exists
+    /// Reads config from default path, returns error if config doesn't exists or is invalid
     pub fn read() -> eyre::Result<Self> {
+        let config = Self::read_unvalidated()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks the config values that can't be checked one by one while parsing
+    pub fn validate(&self) -> eyre::Result<()> {
+        self.node.validate()
+    }
+
+    fn read_unvalidated() -> eyre::Result<Self> {
         let home_path = std::env::var("HOME")?;
         let ramd_dir = Self::get_ramd_dir();
 
@@ -54,8 +68,9 @@ impl RamdConfig {
 
     /// Creates default config if not exists otherwise reads it
     pub fn init_or_read() -> eyre::Result<Self> {
-        let config_maybe = RamdConfig::read();
+        let config_maybe = RamdConfig::read_unvalidated();
         if let Ok(config) = config_maybe {
+            config.validate()?;
             return Ok(config);
         };
 
@@ -70,6 +85,10 @@ impl RamdConfig {
         let config = RamdConfig {
             rocks: RocksConfig::new(root_dir.clone()),
             tracing: TracingConfig::new(root_dir.clone()),
+            p2p: P2pConfig {
+                keystore_path: Some(root_dir.join(KEYSTORE_FILE)),
+                ..Default::default()
+            },
             ..Default::default()
         };
 
@@ -92,6 +111,20 @@ impl RamdConfig {
         Ok(config)
     }
 
+    /// Path of the encrypted node identity, the configured one or the default inside the ramd directory
+    pub fn keystore_path(&self) -> eyre::Result<PathBuf> {
+        if let Some(path) = &self.p2p.keystore_path {
+            return Ok(path.clone());
+        }
+
+        let home_path = std::env::var("HOME")?;
+        let ramd_dir = Self::get_ramd_dir();
+
+        Ok([home_path.as_str(), ramd_dir.as_str(), KEYSTORE_FILE]
+            .iter()
+            .collect())
+    }
+
     fn get_ramd_dir() -> String {
         // check if custom dir name is set
         if let Ok(custom_dir) = std::env::var("RAMD_DIR_NAME") {
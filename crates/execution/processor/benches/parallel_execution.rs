@@ -0,0 +1,111 @@
+//! Compares sequential and parallel processing of a batch of GCounter increments
+//! spread over several live objects.
+//!
+//! Run with `cargo bench -p ramd-processor --bench parallel_execution`.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ramd_db::memory::MemoryStorage;
+use ramd_processor::{
+    Action, CreateLiveObjectAction, ExecuteLiveObjectAction, Message, Processor, ProcessorConfig,
+};
+
+const GCOUNTER_WASM: &[u8] = include_bytes!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../../tests/wasms/live_object_gcounter.wasm"
+));
+
+const LIVE_OBJECTS: usize = 8;
+const INCREMENTS_PER_LIVE_OBJECT: usize = 16;
+
+/// Make the module of every live object unique by appending a custom section, so each one gets its own ID.
+fn gcounter_wasm(index: usize) -> Vec<u8> {
+    let name = b"ramd-bench";
+    let payload = (index as u32).to_le_bytes();
+
+    let mut wasm = GCOUNTER_WASM.to_vec();
+    wasm.push(0);
+    wasm.push((1 + name.len() + payload.len()) as u8);
+    wasm.push(name.len() as u8);
+    wasm.extend_from_slice(name);
+    wasm.extend_from_slice(&payload);
+    wasm
+}
+
+fn run(workers: usize) -> Duration {
+    let processor = Processor::new(
+        Arc::new(MemoryStorage::default()),
+        ProcessorConfig {
+            workers,
+            ..ProcessorConfig::default()
+        },
+    );
+
+    let wasms = (0..LIVE_OBJECTS).map(gcounter_wasm).collect::<Vec<_>>();
+    let creates = wasms
+        .iter()
+        .map(|wasm_bytes| {
+            Message::with_timestamp(
+                Action::CreateLiveObject(CreateLiveObjectAction {
+                    wasm_bytes: wasm_bytes.clone(),
+                    memory_limit: None,
+                }),
+                0,
+            )
+        })
+        .collect::<Vec<_>>();
+    let created: serde_json::Value = serde_json::from_str(&processor.process_messages(&creates))
+        .expect("create results must be JSON");
+
+    let live_object_ids = creates
+        .iter()
+        .map(|message| created[&message.id]["result"].as_str().unwrap().to_owned())
+        .collect::<Vec<_>>();
+
+    // Interleave the live objects so that every worker has something to do from the start.
+    let increments = (0..INCREMENTS_PER_LIVE_OBJECT * LIVE_OBJECTS)
+        .map(|index| {
+            Message::with_timestamp(
+                Action::ExecuteLiveObject(ExecuteLiveObjectAction {
+                    live_object_id: live_object_ids[index % LIVE_OBJECTS].clone(),
+                    method: "increment".to_owned(),
+                    args: br#"{"delta": 1}"#.to_vec(),
+                }),
+                index as u64 + 1,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let started = Instant::now();
+    processor.process_messages(&increments);
+    started.elapsed()
+}
+
+fn main() {
+    let parallel_workers = thread::available_parallelism()
+        .map(|workers| workers.get())
+        .unwrap_or(1);
+
+    let sequential = run(1);
+    println!(
+        "workers: {:>3}, messages: {}, elapsed: {:?}",
+        1,
+        LIVE_OBJECTS * INCREMENTS_PER_LIVE_OBJECT,
+        sequential
+    );
+
+    let parallel = run(parallel_workers);
+    println!(
+        "workers: {:>3}, messages: {}, elapsed: {:?}",
+        parallel_workers,
+        LIVE_OBJECTS * INCREMENTS_PER_LIVE_OBJECT,
+        parallel
+    );
+
+    println!(
+        "speed-up: {:.2}x",
+        sequential.as_secs_f64() / parallel.as_secs_f64()
+    );
+}
@@ -0,0 +1,84 @@
+//! Compares executing a GCounter live object on a freshly instantiated runtime every time (cold)
+//! with reusing the runtimes kept by the runtime pool (warm), and checks that both end in the same state.
+//!
+//! Run with `cargo bench -p ramd-processor --bench runtime_pool`.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ramd_db::memory::MemoryStorage;
+use ramd_processor::{
+    Action, CreateLiveObjectAction, ExecuteLiveObjectAction, Message, Processor, ProcessorConfig,
+};
+use ramd_vm::RuntimePoolConfig;
+
+const GCOUNTER_WASM: &[u8] = include_bytes!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../../tests/wasms/live_object_gcounter.wasm"
+));
+
+const INCREMENTS: usize = 64;
+
+/// Send every increment in its own batch, as the RPC does, and measure the average time per message.
+fn run(
+    runtime_pool_config: RuntimePoolConfig,
+) -> (Duration, Vec<String>, BTreeMap<Vec<u8>, Vec<u8>>) {
+    let storage = Arc::new(MemoryStorage::default());
+    let processor = Processor::new(
+        storage.clone(),
+        ProcessorConfig {
+            workers: 1,
+            runtime_pool: runtime_pool_config,
+            ..ProcessorConfig::default()
+        },
+    );
+
+    let create = Message::with_timestamp(
+        Action::CreateLiveObject(CreateLiveObjectAction {
+            wasm_bytes: GCOUNTER_WASM.to_vec(),
+            memory_limit: None,
+        }),
+        0,
+    );
+    let created: serde_json::Value =
+        serde_json::from_str(&processor.process_messages(std::slice::from_ref(&create)))
+            .expect("create result must be JSON");
+    let live_object_id = created[&create.id]["result"].as_str().unwrap().to_owned();
+
+    let mut results = Vec::with_capacity(INCREMENTS);
+    let mut elapsed = Duration::ZERO;
+    for index in 0..INCREMENTS {
+        let increment = Message::with_timestamp(
+            Action::ExecuteLiveObject(ExecuteLiveObjectAction {
+                live_object_id: live_object_id.clone(),
+                method: "increment".to_owned(),
+                args: br#"{"delta": 1}"#.to_vec(),
+            }),
+            index as u64 + 1,
+        );
+
+        let started = Instant::now();
+        results.push(processor.process_messages(&[increment]));
+        elapsed += started.elapsed();
+    }
+
+    let state = storage.data();
+    (elapsed / INCREMENTS as u32, results, state)
+}
+
+fn main() {
+    let (cold, cold_results, cold_state) = run(RuntimePoolConfig {
+        size: 0,
+        ..RuntimePoolConfig::default()
+    });
+    println!("cold: {:?} per message", cold);
+
+    let (warm, warm_results, warm_state) = run(RuntimePoolConfig::default());
+    println!("warm: {:?} per message", warm);
+
+    assert_eq!(cold_results, warm_results, "results must match");
+    assert_eq!(cold_state, warm_state, "state must match");
+
+    println!("speed-up: {:.2}x", cold.as_secs_f64() / warm.as_secs_f64());
+}
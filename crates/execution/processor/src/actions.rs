@@ -1,7 +1,12 @@
 use std::sync::Arc;
 
+use crate::control::ExecutionControl;
+use ramd_db::codec::{encode_field, Decoder};
 use ramd_db::storage::Storage;
-use ramd_vm::{LiveObjectInfo, Runtime};
+use ramd_vm::{
+    validate_determinism, LiveObjectInfo, LiveObjectMetadata, MessageContext, RuntimePool,
+};
+use sha3::{Digest, Keccak256};
 use tracing::{error, info};
 
 pub enum Action {
@@ -10,34 +15,138 @@ pub enum Action {
 }
 
 impl Action {
-    pub(crate) fn perform<S>(&self, cache: Arc<S>) -> eyre::Result<String>
+    pub(crate) fn perform<S>(
+        &self,
+        cache: Arc<S>,
+        control: &ExecutionControl,
+        message: MessageContext,
+        runtime_pool: &RuntimePool<S>,
+    ) -> eyre::Result<String>
     where
         S: Storage<Vec<u8>, Vec<u8>> + 'static,
     {
         match self {
-            Action::CreateLiveObject(action) => action.perform(cache),
-            Action::ExecuteLiveObject(action) => action.perform(cache),
+            Action::CreateLiveObject(action) => action.perform(cache, control),
+            Action::ExecuteLiveObject(action) => {
+                action.perform(cache, control, message, runtime_pool)
+            }
+        }
+    }
+
+    /// The ID of the live object that the action touches.
+    pub fn live_object_id(&self) -> String {
+        match self {
+            Action::CreateLiveObject(action) => hex::encode(Keccak256::digest(&action.wasm_bytes)),
+            Action::ExecuteLiveObject(action) => action.live_object_id.clone(),
+        }
+    }
+
+    /// Encode the content of the action. Variable-length fields are length-prefixed
+    /// so that different actions never share an encoding.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        match self {
+            Action::CreateLiveObject(action) => {
+                bytes.push(0);
+                encode_field(&mut bytes, &action.wasm_bytes);
+                if let Some(memory_limit) = action.memory_limit {
+                    encode_field(&mut bytes, &(memory_limit as u64).to_le_bytes());
+                }
+            }
+            Action::ExecuteLiveObject(action) => {
+                bytes.push(1);
+                encode_field(&mut bytes, action.live_object_id.as_bytes());
+                encode_field(&mut bytes, action.method.as_bytes());
+                encode_field(&mut bytes, &action.args);
+            }
         }
+
+        bytes
+    }
+
+    /// Decode an action encoded by `encode`.
+    pub(crate) fn decode(bytes: &[u8]) -> eyre::Result<Self> {
+        let mut decoder = Decoder::new(bytes);
+
+        let action = match decoder.byte()? {
+            0 => {
+                let wasm_bytes = decoder.field()?.to_vec();
+                let memory_limit = match decoder.is_empty() {
+                    true => None,
+                    false => Some(decoder.u64_field()? as usize),
+                };
+
+                Action::CreateLiveObject(CreateLiveObjectAction {
+                    wasm_bytes,
+                    memory_limit,
+                })
+            }
+            1 => Action::ExecuteLiveObject(ExecuteLiveObjectAction {
+                live_object_id: decoder.string_field()?,
+                method: decoder.string_field()?,
+                args: decoder.field()?.to_vec(),
+            }),
+            tag => return Err(eyre::eyre!("Unknown action tag `{}`", tag)),
+        };
+        decoder.finish()?;
+
+        Ok(action)
     }
 }
 
 pub struct CreateLiveObjectAction {
     pub wasm_bytes: Vec<u8>,
+    /// The maximum size of the WASM (guest) memory in bytes. The operator's default applies if unset.
+    pub memory_limit: Option<usize>,
 }
 
 impl CreateLiveObjectAction {
-    fn perform<S>(&self, cache: Arc<S>) -> eyre::Result<String>
+    fn perform<S>(&self, cache: Arc<S>, control: &ExecutionControl) -> eyre::Result<String>
     where
         S: Storage<Vec<u8>, Vec<u8>>,
     {
-        let live_object_info = LiveObjectInfo::new(self.wasm_bytes.clone());
+        if let Err(e) = control.memory_limits.resolve(self.memory_limit) {
+            error!(target: "ramd::processor", "Failed to create live object with error `{}`", e.to_string());
+            return Err(e);
+        }
+
+        if let Err(e) = validate_determinism(&self.wasm_bytes, control.float_policy) {
+            error!(target: "ramd::processor", "Failed to create live object with error `{}`", e.to_string());
+            return Err(e.into());
+        }
+
+        let live_object_info = match LiveObjectInfo::new(self.wasm_bytes.clone(), self.memory_limit)
+        {
+            Ok(live_object_info) => live_object_info,
+            Err(e) => {
+                error!(target: "ramd::processor", "Failed to create live object with error `{}`", e.to_string());
+                return Err(e);
+            }
+        };
         let live_object_id = live_object_info.id.clone();
+
+        // The ID only covers the code, so the same code can't be deployed again under another memory limit.
+        match LiveObjectMetadata::load(cache.as_ref(), &live_object_id) {
+            Ok(Some(existing)) if existing.memory_limit != self.memory_limit => {
+                let e = eyre::eyre!(
+                    "Live object `{}` already exists with memory limit {:?}, not {:?}",
+                    live_object_id,
+                    existing.memory_limit,
+                    self.memory_limit
+                );
+                error!(target: "ramd::processor", "Failed to create live object with error `{}`", e.to_string());
+                return Err(e);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!(target: "ramd::processor", "Failed to read the existing live object with error `{}`", e.to_string());
+                return Err(e);
+            }
+        }
         info!(target: "ramd::processor", "Successfully created live object with id `{}`", live_object_id);
 
-        if let Err(e) = cache.set(
-            live_object_id.as_bytes().to_vec(),
-            live_object_info.try_into()?,
-        ) {
+        if let Err(e) = live_object_info.store(cache.as_ref()) {
             error!(target: "ramd::processor", "Failed to store the created live object with error `{}`", e.to_string());
             return Err(e);
         }
@@ -54,25 +163,127 @@ pub struct ExecuteLiveObjectAction {
 }
 
 impl ExecuteLiveObjectAction {
-    fn perform<S>(&self, cache: Arc<S>) -> eyre::Result<String>
+    fn perform<S>(
+        &self,
+        cache: Arc<S>,
+        control: &ExecutionControl,
+        message: MessageContext,
+        runtime_pool: &RuntimePool<S>,
+    ) -> eyre::Result<String>
     where
         S: Storage<Vec<u8>, Vec<u8>> + 'static,
     {
-        let live_object_info_bytes = match cache.get(self.live_object_id.as_bytes().to_vec()) {
-            Ok(bytes) => bytes,
+        let live_object_info = match LiveObjectInfo::load(cache.as_ref(), &self.live_object_id) {
+            Ok(Some(live_object_info)) => live_object_info,
+            Ok(None) => {
+                let e = eyre::eyre!("Live object `{}` doesn't exist", self.live_object_id);
+                error!(target: "ramd::processor", "Failed to get wasm bytes from cache with error `{}`", e.to_string());
+                return Err(e);
+            }
             Err(e) => {
                 error!(target: "ramd::processor", "Failed to get wasm bytes from cache with error `{}`", e.to_string());
                 return Err(e);
             }
         };
-        let live_object_info = LiveObjectInfo::try_from(live_object_info_bytes.clone())?;
         info!(target: "ramd::processor", "Successfully read live object with id `{}`", live_object_info.id);
 
-        let mut runtime = Runtime::new(cache, live_object_info)?;
-        let result = runtime.run(self.method.clone(), self.args.clone())?;
+        if let Some(abi) = &live_object_info.abi {
+            if let Err(e) = abi.validate_args(&self.method, &self.args) {
+                error!(target: "ramd::processor", "Failed to validate arguments with error `{}`", e.to_string());
+                return Err(e);
+            }
+        }
+
+        let abi = live_object_info.abi.clone();
+        let mut runtime = runtime_pool.acquire(cache, live_object_info, message)?;
+        runtime.set_interrupt_handle(control.interrupt_handle.clone());
+        runtime.set_timeout(control.timeout);
+
+        let result = runtime.run(self.method.clone(), self.args.clone());
+        runtime_pool.release(runtime);
+        let result = result?;
         info!(target: "ramd::processor", "Successfully called method `{}` to get result `{}`", self.method, result);
 
+        if let Some(abi) = &abi {
+            if let Err(e) = abi.validate_result(&self.method, &result) {
+                error!(target: "ramd::processor", "Failed to validate result with error `{}`", e.to_string());
+                return Err(e);
+            }
+        }
+
         info!(target: "ramd::processor", "Successfully performed execute action");
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create(memory_limit: Option<usize>) -> Action {
+        Action::CreateLiveObject(CreateLiveObjectAction {
+            wasm_bytes: b"\0asm\x01\0\0\0".to_vec(),
+            memory_limit,
+        })
+    }
+
+    fn execute() -> Action {
+        Action::ExecuteLiveObject(ExecuteLiveObjectAction {
+            live_object_id: "a".repeat(64),
+            method: "increment".to_owned(),
+            args: br#"{"delta": 1}"#.to_vec(),
+        })
+    }
+
+    #[test]
+    fn actions_round_trip() {
+        for action in [create(None), create(Some(1 << 20)), execute()] {
+            let bytes = action.encode();
+            assert_eq!(Action::decode(&bytes).unwrap().encode(), bytes);
+        }
+
+        let Action::CreateLiveObject(action) = Action::decode(&create(Some(7)).encode()).unwrap()
+        else {
+            panic!("decoded the wrong action");
+        };
+        assert_eq!(action.memory_limit, Some(7));
+
+        let Action::ExecuteLiveObject(action) = Action::decode(&execute().encode()).unwrap() else {
+            panic!("decoded the wrong action");
+        };
+        assert_eq!(action.live_object_id, "a".repeat(64));
+        assert_eq!(action.method, "increment");
+        assert_eq!(action.args, br#"{"delta": 1}"#);
+    }
+
+    #[test]
+    fn truncated_actions_are_rejected() {
+        for action in [create(None), execute()] {
+            let bytes = action.encode();
+            for len in 0..bytes.len() {
+                assert!(Action::decode(&bytes[..len]).is_err(), "{}", len);
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_actions_are_rejected() {
+        let mut unknown_tag = execute().encode();
+        unknown_tag[0] = 2;
+        assert!(Action::decode(&unknown_tag).is_err());
+
+        let mut trailing = execute().encode();
+        trailing.push(0);
+        assert!(Action::decode(&trailing).is_err());
+
+        // The method, right after the 64 bytes of the live object ID, isn't UTF-8.
+        let mut invalid_method = execute().encode();
+        invalid_method[1 + 8 + 64 + 8] = 0xff;
+        assert!(Action::decode(&invalid_method).is_err());
+
+        // A memory limit that isn't 8 bytes long.
+        let mut short_memory_limit = create(None).encode();
+        encode_field(&mut short_memory_limit, &[1, 2, 3]);
+        assert!(Action::decode(&short_memory_limit).is_err());
+    }
+}
@@ -0,0 +1,14 @@
+use std::time::Duration;
+
+use ramd_vm::{FloatPolicy, InterruptHandle, MemoryLimits};
+
+/// How long the execution of a message may take, how much memory and storage it may use, how floating-point
+/// operations of created live objects are dealt with, and the handle to cancel it.
+#[derive(Clone, Default)]
+pub(crate) struct ExecutionControl {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) interrupt_handle: InterruptHandle,
+    pub(crate) memory_limits: MemoryLimits,
+    pub(crate) storage_quota: Option<u64>,
+    pub(crate) float_policy: FloatPolicy,
+}
@@ -1,7 +1,13 @@
 mod actions;
+mod control;
 mod message;
+mod message_log;
 mod processor;
+mod scheduler;
+mod storage_usage;
+mod validator;
 
 pub use crate::actions::*;
 pub use crate::message::*;
+pub use crate::message_log::*;
 pub use crate::processor::*;
@@ -1,20 +1,79 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::control::ExecutionControl;
 use crate::Action;
 use ramd_db::storage::Storage;
+use ramd_vm::{MessageContext, RuntimePool};
+use sha3::{Digest, Keccak256};
+
+/// The nonce of the next message created by this process, which is never zero.
+static NEXT_NONCE: AtomicU64 = AtomicU64::new(1);
 
 pub struct Message {
-    // TODO: add ID and dependencies.
-    // pub id: some_cryptographic_hash,
+    /// Hex-encoded Keccak-256 hash of the timestamp, the nonce and the action.
+    pub id: String,
+    /// Milliseconds since the Unix epoch at which the message was created.
+    pub timestamp: u64,
+    /// Tells apart messages with the same action created in the same millisecond. Zero for messages logged
+    /// before messages had nonces, whose IDs only cover the timestamp and the action.
+    pub nonce: u64,
+    // TODO: add dependencies.
     // pub predecessors: Vec<some_cryptographic_hash>,
     pub action: Action,
 }
 
 impl Message {
-    pub(crate) fn process<S>(&self, cache: Arc<S>) -> eyre::Result<String>
+    pub fn new(action: Action) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default();
+
+        Self::with_nonce(
+            action,
+            timestamp,
+            NEXT_NONCE.fetch_add(1, Ordering::Relaxed),
+        )
+    }
+
+    /// A message without a nonce, whose ID only depends on the timestamp and the action.
+    pub fn with_timestamp(action: Action, timestamp: u64) -> Self {
+        Self::with_nonce(action, timestamp, 0)
+    }
+
+    pub fn with_nonce(action: Action, timestamp: u64, nonce: u64) -> Self {
+        let mut hasher = Keccak256::new();
+        hasher.update(timestamp.to_le_bytes());
+        if nonce != 0 {
+            hasher.update(nonce.to_le_bytes());
+        }
+        hasher.update(action.encode());
+        let id = hex::encode(hasher.finalize());
+
+        Self {
+            id,
+            timestamp,
+            nonce,
+            action,
+        }
+    }
+
+    pub(crate) fn process<S>(
+        &self,
+        cache: Arc<S>,
+        control: &ExecutionControl,
+        runtime_pool: &RuntimePool<S>,
+    ) -> eyre::Result<String>
     where
         S: Storage<Vec<u8>, Vec<u8>> + 'static,
     {
-        self.action.perform(cache)
+        let message = MessageContext {
+            id: self.id.clone(),
+            timestamp: self.timestamp,
+        };
+
+        self.action.perform(cache, control, message, runtime_pool)
     }
 }
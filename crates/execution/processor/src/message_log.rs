@@ -0,0 +1,192 @@
+use std::ops::Range;
+
+use crate::{Action, Message};
+use ramd_db::codec::{encode_field, Decoder};
+use ramd_db::storage::Storage;
+use sha3::{Digest, Keccak256};
+
+/// Prefix of the keys of log entries, which are followed by the big-endian sequence number
+/// so that the entries are stored in the order they were applied.
+pub const MESSAGE_LOG_KEY_PREFIX: &[u8] = b"ramd::message_log::";
+/// Key of the number of log entries and the ID of the last logged message.
+pub const MESSAGE_LOG_HEAD_KEY: &[u8] = b"ramd::message_log_head";
+/// Key of the sequence number of the first entry that wasn't pruned, which is absent until the log is pruned.
+pub const MESSAGE_LOG_TAIL_KEY: &[u8] = b"ramd::message_log_tail";
+
+/// A message applied to the state, as recorded in the message log.
+pub struct LogEntry {
+    /// The position of the message in the log, starting at 0.
+    pub sequence: u64,
+    pub message: Message,
+    /// The ID of the message logged right before this one, or nothing for the first entry. As a message ID is
+    /// the hash of its content, this chains every entry to the one before it. Messages don't name the messages
+    /// they depend on yet, so the chain only records the order they were applied in.
+    pub predecessors: Vec<String>,
+    /// Keccak-256 hash of the result of the message.
+    pub result_hash: Vec<u8>,
+}
+
+impl LogEntry {
+    fn encode(message: &Message, predecessors: &[String], result_hash: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        encode_field(&mut bytes, message.id.as_bytes());
+        bytes.extend_from_slice(&message.timestamp.to_le_bytes());
+        bytes.extend_from_slice(&(predecessors.len() as u64).to_le_bytes());
+        for predecessor in predecessors {
+            encode_field(&mut bytes, predecessor.as_bytes());
+        }
+        encode_field(&mut bytes, result_hash);
+        encode_field(&mut bytes, &message.action.encode());
+        bytes.extend_from_slice(&message.nonce.to_le_bytes());
+
+        bytes
+    }
+
+    fn decode(sequence: u64, bytes: &[u8]) -> eyre::Result<Self> {
+        let mut decoder = Decoder::new(bytes);
+
+        let id = decoder.string_field()?;
+        let timestamp = decoder.u64()?;
+        let predecessors = (0..decoder.u64()?)
+            .map(|_| decoder.string_field())
+            .collect::<eyre::Result<Vec<_>>>()?;
+        let result_hash = decoder.field()?.to_vec();
+        let action = Action::decode(decoder.field()?)?;
+        // Entries logged before messages had nonces end with the action.
+        let nonce = match decoder.is_empty() {
+            true => 0,
+            false => decoder.u64()?,
+        };
+        decoder.finish()?;
+
+        // The ID is derived from the content, so a mismatch means the entry is corrupt.
+        let message = Message::with_nonce(action, timestamp, nonce);
+        if message.id != id {
+            return Err(eyre::eyre!(
+                "Log entry {} claims ID `{}` but its content hashes to `{}`",
+                sequence,
+                id,
+                message.id
+            ));
+        }
+
+        Ok(Self {
+            sequence,
+            message,
+            predecessors,
+            result_hash,
+        })
+    }
+}
+
+/// The append-only log of the messages applied to the state. It lives in the same storage as the state,
+/// so entries written through a cache are committed together with the changes of their messages.
+pub struct MessageLog;
+
+impl MessageLog {
+    /// Append a successfully applied message and the hash of its result.
+    pub fn append<S>(storage: &S, message: &Message, result: &str) -> eyre::Result<()>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + ?Sized,
+    {
+        let (length, last_message_id) = Self::head(storage)?;
+        let predecessors = last_message_id.into_iter().collect::<Vec<_>>();
+
+        storage.set(
+            Self::entry_key(length),
+            LogEntry::encode(message, &predecessors, &Self::result_hash(result)),
+        )?;
+
+        let mut head = (length + 1).to_be_bytes().to_vec();
+        head.extend_from_slice(message.id.as_bytes());
+        storage.set(Vec::from(MESSAGE_LOG_HEAD_KEY), head)
+    }
+
+    /// The number of entries in the log.
+    pub fn len<S>(storage: &S) -> eyre::Result<u64>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + ?Sized,
+    {
+        Ok(Self::head(storage)?.0)
+    }
+
+    /// The sequence number of the oldest entry kept, as entries before it were pruned.
+    pub fn first<S>(storage: &S) -> eyre::Result<u64>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + ?Sized,
+    {
+        let Some(tail) = storage.get_opt(Vec::from(MESSAGE_LOG_TAIL_KEY))? else {
+            return Ok(0);
+        };
+        let tail = <[u8; 8]>::try_from(tail.as_slice())
+            .map_err(|_| eyre::eyre!("Message log tail is malformed"))?;
+
+        Ok(u64::from_be_bytes(tail))
+    }
+
+    /// Delete the entries older than the most recent `retention` ones, returning the range of pruned sequence numbers.
+    /// The head is kept, so entries appended later still link to the last logged message.
+    pub fn prune<S>(storage: &S, retention: u64) -> eyre::Result<Range<u64>>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + ?Sized,
+    {
+        let first = Self::first(storage)?;
+        let new_first = Self::len(storage)?.saturating_sub(retention).max(first);
+
+        // Move the tail first, so that an interrupted prune never leaves the log with a gap.
+        if new_first > first {
+            storage.set(
+                Vec::from(MESSAGE_LOG_TAIL_KEY),
+                Vec::from(new_first.to_be_bytes()),
+            )?;
+        }
+        for sequence in first..new_first {
+            storage.delete(Self::entry_key(sequence))?;
+        }
+
+        Ok(first..new_first)
+    }
+
+    /// The key of the entry at `sequence`, so that ranges of entries can be addressed in the storage.
+    pub fn entry_key(sequence: u64) -> Vec<u8> {
+        let mut key = MESSAGE_LOG_KEY_PREFIX.to_vec();
+        key.extend_from_slice(&sequence.to_be_bytes());
+        key
+    }
+
+    /// Read the entry at `sequence`, or `None` if the log is shorter or the entry was pruned.
+    pub fn entry<S>(storage: &S, sequence: u64) -> eyre::Result<Option<LogEntry>>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + ?Sized,
+    {
+        storage
+            .get_opt(Self::entry_key(sequence))?
+            .map(|bytes| LogEntry::decode(sequence, &bytes))
+            .transpose()
+    }
+
+    /// Keccak-256 hash of the result of a message, as recorded in the log.
+    pub fn result_hash(result: &str) -> Vec<u8> {
+        Keccak256::digest(result.as_bytes()).to_vec()
+    }
+
+    /// The number of entries and the ID of the last logged message.
+    fn head<S>(storage: &S) -> eyre::Result<(u64, Option<String>)>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + ?Sized,
+    {
+        let Some(head) = storage.get_opt(Vec::from(MESSAGE_LOG_HEAD_KEY))? else {
+            return Ok((0, None));
+        };
+        if head.len() < 8 {
+            return Err(eyre::eyre!("Message log head is malformed"));
+        }
+
+        let (length, last_message_id) = head.split_at(8);
+        let length = u64::from_be_bytes(length.try_into()?);
+        let last_message_id = String::from_utf8(last_message_id.to_vec())?;
+
+        Ok((length, Some(last_message_id)))
+    }
+}
@@ -1,52 +1,348 @@
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+use crate::control::ExecutionControl;
 use crate::message::Message;
-use ramd_cache::{Cache, InMemoryCache};
+use crate::message_log::MessageLog;
+use crate::scheduler::{execute_groups, group_by_live_object, ConflictTracker, Execution};
+use crate::validator::{CommitValidator, Transaction};
+use ramd_cache::{
+    Cache, CommitHook, InMemoryCache, ReadCache, ReadCacheStats, DEFAULT_READ_CACHE_SIZE,
+};
+use ramd_db::compaction::pending_compaction_writes;
+use ramd_db::state_tree::state_tree_writes;
 use ramd_db::storage::Storage;
-use serde_json::json;
-use tracing::error;
+use ramd_vm::{
+    CompilerBackend, FloatPolicy, InterruptHandle, InterruptReason, MemoryLimits, RuntimePool,
+    RuntimePoolConfig,
+};
+use serde_json::{json, Map, Value};
+use tracing::{debug, error, info};
+
+/// The number of times messages are processed alongside concurrently processed messages before they are processed
+/// exclusively, if the other messages keep committing changes to the keys they read.
+pub const OPTIMISTIC_COMMIT_ATTEMPTS: usize = 3;
+
+/// How a `Processor` executes messages. Executions taking longer than `timeout` are interrupted,
+/// and live objects can't use more memory than `memory_limits` allow nor store more bytes than `storage_quota`.
+/// Live objects must be deterministic under `float_policy` and are compiled by `compiler`, or loaded from their
+/// artifacts in `precompiled_dir`, and their runtimes are kept for later messages to the same live object as configured by `runtime_pool`.
+/// Reads of the storage go through a cache of up to `read_cache_size` bytes shared by all messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessorConfig {
+    /// The number of threads executing messages to different live objects in parallel.
+    pub workers: usize,
+    pub timeout: Option<Duration>,
+    pub memory_limits: MemoryLimits,
+    /// The number of bytes each live object may store, or `None` if unlimited.
+    pub storage_quota: Option<u64>,
+    pub compiler: CompilerBackend,
+    pub float_policy: FloatPolicy,
+    pub runtime_pool: RuntimePoolConfig,
+    /// The number of bytes, counting both keys and values, of recently read storage kept in memory.
+    pub read_cache_size: usize,
+    /// The directory of the artifacts written by `ramd precompile`, which are loaded instead of compiling.
+    pub precompiled_dir: Option<PathBuf>,
+}
+
+impl Default for ProcessorConfig {
+    fn default() -> Self {
+        Self {
+            workers: 1,
+            timeout: None,
+            memory_limits: MemoryLimits::default(),
+            storage_quota: None,
+            compiler: CompilerBackend::default(),
+            float_policy: FloatPolicy::default(),
+            runtime_pool: RuntimePoolConfig::default(),
+            read_cache_size: DEFAULT_READ_CACHE_SIZE,
+            precompiled_dir: None,
+        }
+    }
+}
 
 pub struct Processor<S>
 where
     S: Storage<Vec<u8>, Vec<u8>> + 'static,
 {
-    storage: Arc<S>,
+    storage: Arc<ReadCache<S>>,
+    config: ProcessorConfig,
+    /// Instantiated runtimes of recently executed live objects.
+    runtime_pool: RuntimePool<InMemoryCache<ReadCache<S>>>,
+    /// Interrupt handles of the messages being processed, keyed by message ID.
+    running: Mutex<HashMap<String, InterruptHandle>>,
+    /// Orders the commits of messages processed concurrently.
+    validator: CommitValidator,
 }
 
 impl<S> Processor<S>
 where
     S: Storage<Vec<u8>, Vec<u8>> + 'static,
 {
-    pub fn new(storage: Arc<S>) -> Self {
-        Self { storage }
+    /// Create a new `Processor` that executes messages as configured by `config`.
+    pub fn new(storage: Arc<S>, config: ProcessorConfig) -> Self {
+        let mut runtime_pool = RuntimePool::new(
+            config.runtime_pool,
+            config.memory_limits,
+            config.compiler,
+            config.float_policy,
+        );
+        if let Some(precompiled_dir) = &config.precompiled_dir {
+            runtime_pool = runtime_pool.precompiled_dir(precompiled_dir.clone());
+        }
+        if let Some(storage_quota) = config.storage_quota {
+            runtime_pool = runtime_pool.storage_quota(storage_quota);
+        }
+
+        Self {
+            storage: Arc::new(ReadCache::new(storage, config.read_cache_size)),
+            config: ProcessorConfig {
+                workers: config.workers.max(1),
+                ..config
+            },
+            runtime_pool,
+            running: Mutex::new(HashMap::new()),
+            validator: CommitValidator::default(),
+        }
+    }
+
+    /// The storage behind the cache of storage reads. Writes that bypass it leave stale values in the cache.
+    pub fn storage(&self) -> Arc<ReadCache<S>> {
+        self.storage.clone()
+    }
+
+    /// Hit and miss statistics of the cache of storage reads.
+    pub fn read_cache_stats(&self) -> eyre::Result<ReadCacheStats> {
+        self.storage.stats()
+    }
+
+    /// Cancel the message with the given ID if it is being processed.
+    /// The message then fails and its changes are discarded.
+    pub fn cancel(&self, message_id: &str) -> bool {
+        let Ok(running) = self.running.lock() else {
+            return false;
+        };
+
+        match running.get(message_id) {
+            Some(interrupt_handle) => {
+                interrupt_handle.interrupt(InterruptReason::Cancelled);
+                info!(target: "ramd::processor", "Cancelled message `{}`", message_id);
+                true
+            }
+            None => false,
+        }
     }
 
+    /// Process the messages and commit the changes made by the successful ones, appending them to the message log.
+    /// Each message runs in its own savepoint, so a failed message leaves no trace in the cache.
+    /// Returns a JSON object mapping each message ID to either its result or its error. If the messages can't be
+    /// committed at all, nothing is committed and every message ID maps to that error.
+    ///
+    /// Messages touching different live objects are executed in parallel. Their read and write sets
+    /// are then validated in batch order, and the ones that observed a stale value are re-executed,
+    /// so the outcome is the same as processing the messages one by one.
+    ///
+    /// Concurrent calls are validated against each other in the same way when they commit. A call whose messages
+    /// read keys that another call committed in the meantime starts over, exclusively after
+    /// `OPTIMISTIC_COMMIT_ATTEMPTS` conflicts, so concurrent messages to the same live object never lose updates.
     pub fn process_messages(&self, messages: &[Message]) -> String {
-        let cache = Arc::new(InMemoryCache::new(self.storage.clone()));
+        let controls = messages
+            .iter()
+            .map(|_| ExecutionControl {
+                timeout: self.config.timeout,
+                interrupt_handle: InterruptHandle::new(),
+                memory_limits: self.config.memory_limits,
+                storage_quota: self.config.storage_quota,
+                float_policy: self.config.float_policy,
+            })
+            .collect::<Vec<_>>();
+
+        if let Ok(mut running) = self.running.lock() {
+            for (message, control) in messages.iter().zip(controls.iter()) {
+                running.insert(message.id.clone(), control.interrupt_handle.clone());
+            }
+        }
+
+        let results = self.process_controlled_messages(messages, &controls);
+
+        if let Ok(mut running) = self.running.lock() {
+            for message in messages {
+                running.remove(&message.id);
+            }
+        }
+
+        results
+    }
+
+    /// Process the messages, starting over if messages processed concurrently committed changes to keys they read.
+    /// Once they conflicted `OPTIMISTIC_COMMIT_ATTEMPTS` times, they are processed while no other messages commit.
+    fn process_controlled_messages(
+        &self,
+        messages: &[Message],
+        controls: &[ExecutionControl],
+    ) -> String {
+        for attempt in 0..=OPTIMISTIC_COMMIT_ATTEMPTS {
+            let transaction = if attempt < OPTIMISTIC_COMMIT_ATTEMPTS {
+                self.validator.begin()
+            } else {
+                self.validator.begin_exclusive()
+            };
+
+            let results = transaction.and_then(|transaction| {
+                self.try_process_controlled_messages(messages, controls, &transaction)
+            });
+            match results {
+                Ok(Some(results)) => return results,
+                Ok(None) => {
+                    debug!(target: "ramd::processor", "Messages conflicted with concurrently processed messages on attempt {}", attempt + 1);
+                }
+                Err(err) => return failed_results(messages, &err.to_string()),
+            }
+        }
+
+        // Nothing commits during the exclusive attempt, so it can't conflict.
+        error!(target: "ramd::processor", "Failed to commit messages processed exclusively");
+        failed_results(messages, "Failed to commit messages processed exclusively")
+    }
+
+    /// Process the messages and commit their changes. Returns `None` without committing anything if messages
+    /// processed concurrently committed changes to keys they read in the meantime.
+    fn try_process_controlled_messages(
+        &self,
+        messages: &[Message],
+        controls: &[ExecutionControl],
+        transaction: &Transaction,
+    ) -> eyre::Result<Option<String>> {
+        let cache =
+            Arc::new(InMemoryCache::new(self.storage.clone()).with_commit_hook(commit_hook()));
 
         // TODO: add to messsage pool and then process messages.
 
-        let mut results = json!({});
+        let (message_groups, groups) = group_by_live_object(messages);
+        let mut executions = execute_groups(
+            &self.storage,
+            messages,
+            controls,
+            &groups,
+            self.config.workers,
+            &self.runtime_pool,
+        );
+
+        let mut conflicts = ConflictTracker::default();
+        // Keys read by the executions the results are taken from, which must not change before the commit.
+        let mut read_set: HashSet<Vec<u8>> = HashSet::new();
+
+        let mut results = Map::new();
+        let mut successes = Vec::new();
+
+        for (index, message) in messages.iter().enumerate() {
+            let group = message_groups[index];
 
-        for message in messages {
-            match message.process(cache.clone()) {
+            let speculative = executions[index]
+                .take()
+                .filter(|execution| conflicts.is_valid(group, &execution.read_set));
+
+            let execution = match speculative {
+                Some(execution) => {
+                    if let Err(err) = Self::apply(&cache, &execution) {
+                        error!(target: "ramd::processor", "Failed to apply message `{}` with error `{}`", message.id, err.to_string());
+                        return Err(err);
+                    }
+
+                    execution
+                }
+                None => {
+                    debug!(target: "ramd::processor", "Re-executing message `{}` due to a conflict", message.id);
+                    conflicts.invalidate(group);
+
+                    match Execution::run(message, &cache, &controls[index], &self.runtime_pool) {
+                        Ok(execution) => execution,
+                        Err(err) => {
+                            error!(target: "ramd::processor", "Failed to re-execute message `{}` with error `{}`", message.id, err.to_string());
+                            return Err(err);
+                        }
+                    }
+                }
+            };
+
+            conflicts.record(group, &execution.write_set);
+            read_set.extend(execution.read_set);
+
+            let result = match execution.result {
                 Ok(result) => {
-                    // TODO: set result as a value of the message ID. Until we have message ID, we return the final result.
-                    results = json!(result);
+                    successes.push((message, result.clone()));
+                    json!({ "result": result })
                 }
                 Err(err) => {
-                    // TODO: log message ID.
-                    error!(target: "ramd::processor", "Failed to process a message with error `{}`", err.to_string());
-                    return err.to_string();
+                    error!(target: "ramd::processor", "Failed to process message `{}` with error `{}`", message.id, err);
+                    json!({ "error": err })
+                }
+            };
+
+            results.insert(message.id.clone(), result);
+        }
+
+        // The message log is appended to while committing, as commits don't overlap, so that
+        // concurrently processed messages don't conflict over the head of the log.
+        let write_set = cache.write_set()?.into_keys().collect();
+        let committed = transaction.commit(&read_set, write_set, || {
+            for (message, result) in successes.iter() {
+                if let Err(err) = MessageLog::append(cache.as_ref(), message, result) {
+                    error!(target: "ramd::processor", "Failed to log message `{}` with error `{}`", message.id, err.to_string());
+                    return Err(err);
                 }
             }
+
+            if let Err(err) = cache.commit() {
+                error!(target: "ramd::processor", "Failed to commit cache with error `{}`", err.to_string());
+                return Err(err);
+            }
+
+            Ok(())
+        })?;
+        if !committed {
+            return Ok(None);
+        }
+
+        for (message, _) in successes {
+            info!(target: "ramd::processor", "Successfully processed message `{}`", message.id);
         }
 
-        if let Err(err) = cache.commit() {
-            error!(target: "ramd::processor", "Failed to commit cache with error `{}`", err.to_string());
-            return err.to_string();
+        Ok(Some(Value::Object(results).to_string()))
+    }
+
+    /// Apply the writes of a validated speculative execution to the cache.
+    fn apply(cache: &InMemoryCache<ReadCache<S>>, execution: &Execution) -> eyre::Result<()> {
+        for (key, value) in execution.write_set.iter() {
+            match value {
+                Some(value) => cache.set(key.clone(), value.clone())?,
+                None => cache.delete(key.clone())?,
+            }
         }
 
-        results.to_string()
+        Ok(())
     }
 }
+
+/// The results of messages that all failed with the same error, mapping each message ID to the error.
+fn failed_results(messages: &[Message], err: &str) -> String {
+    let results = messages
+        .iter()
+        .map(|message| (message.id.clone(), json!({ "error": err })))
+        .collect::<Map<_, _>>();
+
+    Value::Object(results).to_string()
+}
+
+/// Update the state trees of the live objects a commit touches in the same batch as their state,
+/// so that a state root never disagrees with the state it was computed over, and mark the live objects
+/// it deletes keys of for compaction.
+fn commit_hook() -> CommitHook {
+    Arc::new(|storage, changes| {
+        let mut writes = state_tree_writes(storage, changes)?;
+        writes.extend(pending_compaction_writes(changes));
+        Ok(writes)
+    })
+}
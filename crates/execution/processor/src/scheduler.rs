@@ -0,0 +1,257 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::control::ExecutionControl;
+use crate::message::Message;
+use crate::storage_usage::account_storage_usage;
+use ramd_cache::{Cache, InMemoryCache};
+use ramd_db::storage::Storage;
+use ramd_vm::RuntimePool;
+use tracing::error;
+
+/// The outcome of executing a single message together with the keys it touched.
+pub(crate) struct Execution {
+    pub(crate) result: Result<String, String>,
+    pub(crate) read_set: HashSet<Vec<u8>>,
+    pub(crate) write_set: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl Execution {
+    /// Execute the message on top of the given cache inside its own savepoint and account for the storage its
+    /// changes take. The changes are kept in the cache if the message succeeds and rolled back otherwise.
+    /// The VM traps writes past the storage quota as they happen, and the accounting checks the quota again.
+    pub(crate) fn run<S>(
+        message: &Message,
+        cache: &Arc<InMemoryCache<S>>,
+        control: &ExecutionControl,
+        runtime_pool: &RuntimePool<InMemoryCache<S>>,
+    ) -> eyre::Result<Self>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + 'static,
+    {
+        cache.savepoint()?;
+
+        let result = message
+            .process(cache.clone(), control, runtime_pool)
+            .and_then(|result| {
+                account_storage_usage(cache, control.storage_quota)?;
+                Ok(result)
+            });
+        let read_set = cache.read_set()?;
+
+        match result {
+            Ok(result) => {
+                let write_set = cache.write_set()?;
+                cache.release_savepoint()?;
+
+                Ok(Self {
+                    result: Ok(result),
+                    read_set,
+                    write_set,
+                })
+            }
+            Err(err) => {
+                cache.rollback_to_savepoint()?;
+
+                Ok(Self {
+                    result: Err(err.to_string()),
+                    read_set,
+                    write_set: BTreeMap::new(),
+                })
+            }
+        }
+    }
+}
+
+/// Split the messages into groups by the live object they touch, keeping the batch order within each group.
+/// Returns the group of every message and the message indices of every group.
+pub(crate) fn group_by_live_object(messages: &[Message]) -> (Vec<usize>, Vec<Vec<usize>>) {
+    let mut group_ids = HashMap::new();
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut message_groups = Vec::with_capacity(messages.len());
+
+    for (index, message) in messages.iter().enumerate() {
+        let group = *group_ids
+            .entry(message.action.live_object_id())
+            .or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+
+        groups[group].push(index);
+        message_groups.push(group);
+    }
+
+    (message_groups, groups)
+}
+
+/// Tells which speculative executions can be trusted while their results are taken in batch order.
+/// An execution can't be trusted if it read a key that a message of another group wrote earlier in the batch,
+/// or if an earlier execution of its group was discarded, since it ran on top of that execution's writes.
+#[derive(Default)]
+pub(crate) struct ConflictTracker {
+    /// The group that last wrote each key.
+    last_writers: HashMap<Vec<u8>, usize>,
+    /// The groups whose speculative executions can't be trusted anymore.
+    stale_groups: HashSet<usize>,
+}
+
+impl ConflictTracker {
+    /// Whether the speculative execution of a message of `group` that read `read_set` can be trusted.
+    pub(crate) fn is_valid(&self, group: usize, read_set: &HashSet<Vec<u8>>) -> bool {
+        !self.stale_groups.contains(&group)
+            && read_set.iter().all(|key| {
+                self.last_writers
+                    .get(key)
+                    .map_or(true, |writer| *writer == group)
+            })
+    }
+
+    /// Distrust the remaining speculative executions of `group`, as one of its messages is re-executed.
+    pub(crate) fn invalidate(&mut self, group: usize) {
+        self.stale_groups.insert(group);
+    }
+
+    /// Record the writes of the execution taken for a message of `group`.
+    pub(crate) fn record(&mut self, group: usize, write_set: &BTreeMap<Vec<u8>, Option<Vec<u8>>>) {
+        for key in write_set.keys() {
+            self.last_writers.insert(key.clone(), group);
+        }
+    }
+}
+
+/// Speculatively execute every group on a pool of workers. Each group runs in order on its own cache
+/// over the storage, so it only observes the writes of earlier messages in the same group.
+pub(crate) fn execute_groups<S>(
+    storage: &Arc<S>,
+    messages: &[Message],
+    controls: &[ExecutionControl],
+    groups: &[Vec<usize>],
+    workers: usize,
+    runtime_pool: &RuntimePool<InMemoryCache<S>>,
+) -> Vec<Option<Execution>>
+where
+    S: Storage<Vec<u8>, Vec<u8>> + 'static,
+{
+    let queue = Mutex::new(groups.iter().collect::<VecDeque<_>>());
+    let executions = Mutex::new((0..messages.len()).map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for _ in 0..workers.min(groups.len()) {
+            scope.spawn(|| loop {
+                let group = match queue.lock() {
+                    Ok(mut queue) => match queue.pop_front() {
+                        Some(group) => group,
+                        None => break,
+                    },
+                    Err(_) => break,
+                };
+
+                let cache = Arc::new(InMemoryCache::new(storage.clone()));
+                for &index in group {
+                    // A message without a speculative execution is simply re-executed later.
+                    let execution = match Execution::run(&messages[index], &cache, &controls[index], runtime_pool) {
+                        Ok(execution) => execution,
+                        Err(err) => {
+                            error!(target: "ramd::processor", "Failed to speculatively execute message `{}` with error `{}`", messages[index].id, err.to_string());
+                            break;
+                        }
+                    };
+
+                    if let Ok(mut executions) = executions.lock() {
+                        executions[index] = Some(execution);
+                    }
+                }
+            });
+        }
+    });
+
+    executions
+        .into_inner()
+        .unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Action, CreateLiveObjectAction, ExecuteLiveObjectAction};
+
+    fn execute(live_object_id: &str) -> Message {
+        Message::new(Action::ExecuteLiveObject(ExecuteLiveObjectAction {
+            live_object_id: live_object_id.to_owned(),
+            method: "add".to_owned(),
+            args: vec![],
+        }))
+    }
+
+    fn keys(keys: &[&[u8]]) -> HashSet<Vec<u8>> {
+        keys.iter().map(|key| key.to_vec()).collect()
+    }
+
+    fn writes(keys: &[&[u8]]) -> BTreeMap<Vec<u8>, Option<Vec<u8>>> {
+        keys.iter()
+            .map(|key| (key.to_vec(), Some(vec![1])))
+            .collect()
+    }
+
+    #[test]
+    fn groups_messages_by_live_object_in_batch_order() {
+        let messages = vec![
+            execute("a"),
+            execute("b"),
+            execute("a"),
+            execute("c"),
+            execute("b"),
+        ];
+
+        let (message_groups, groups) = group_by_live_object(&messages);
+
+        assert_eq!(message_groups, vec![0, 1, 0, 2, 1]);
+        assert_eq!(groups, vec![vec![0, 2], vec![1, 4], vec![3]]);
+    }
+
+    #[test]
+    fn groups_a_created_live_object_with_its_executions() {
+        let create = Message::new(Action::CreateLiveObject(CreateLiveObjectAction {
+            wasm_bytes: b"\0asm".to_vec(),
+            memory_limit: None,
+        }));
+        let live_object_id = create.action.live_object_id();
+        let messages = vec![create, execute("a"), execute(&live_object_id)];
+
+        let (message_groups, _) = group_by_live_object(&messages);
+
+        assert_eq!(message_groups, vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn trusts_reads_of_keys_only_written_by_the_same_group() {
+        let mut conflicts = ConflictTracker::default();
+        conflicts.record(0, &writes(&[b"a"]));
+
+        assert!(conflicts.is_valid(0, &keys(&[b"a", b"b"])));
+        assert!(conflicts.is_valid(1, &keys(&[b"b"])));
+    }
+
+    #[test]
+    fn distrusts_reads_of_keys_written_by_another_group() {
+        let mut conflicts = ConflictTracker::default();
+        conflicts.record(0, &writes(&[b"a"]));
+
+        assert!(!conflicts.is_valid(1, &keys(&[b"a"])));
+
+        // Once the other group writes the key, reads of it by the first group are distrusted in turn.
+        conflicts.record(1, &writes(&[b"a"]));
+        assert!(!conflicts.is_valid(0, &keys(&[b"a"])));
+    }
+
+    #[test]
+    fn distrusts_the_rest_of_a_re_executed_group() {
+        let mut conflicts = ConflictTracker::default();
+        conflicts.invalidate(1);
+
+        assert!(!conflicts.is_valid(1, &keys(&[])));
+        assert!(conflicts.is_valid(0, &keys(&[])));
+    }
+}
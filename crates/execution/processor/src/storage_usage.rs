@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+
+use ramd_cache::InMemoryCache;
+use ramd_db::storage::Storage;
+use ramd_db::storage_usage::{entry_len, read_storage_usage, state_key_owner, storage_usage_write};
+
+/// Bring the storage usage of the live objects written since the most recent savepoint of the cache up to date
+/// with the changes made since. Fails without updating anything if a live object that grew ends up storing more
+/// than `storage_quota` bytes.
+pub(crate) fn account_storage_usage<S>(
+    cache: &InMemoryCache<S>,
+    storage_quota: Option<u64>,
+) -> eyre::Result<()>
+where
+    S: Storage<Vec<u8>, Vec<u8>>,
+{
+    // The bytes added and removed per live object.
+    let mut changes: BTreeMap<Vec<u8>, (u64, u64)> = BTreeMap::new();
+    for (key, value) in cache.write_set()? {
+        let Some(live_object_id) = state_key_owner(&key) else {
+            continue;
+        };
+
+        let old_value = cache.get_opt_before_savepoint(key.clone())?;
+        let (added, removed) = changes.entry(live_object_id.to_vec()).or_default();
+        *added += entry_len(&key, value.as_deref());
+        *removed += entry_len(&key, old_value.as_deref());
+    }
+
+    let mut writes = Vec::new();
+    for (live_object_id, (added, removed)) in changes {
+        if added == removed {
+            continue;
+        }
+
+        let usage = read_storage_usage(cache, &live_object_id)?
+            .saturating_sub(removed)
+            .saturating_add(added);
+        if let Some(storage_quota) = storage_quota {
+            if added > removed && usage > storage_quota {
+                return Err(eyre::eyre!(
+                    "Storage usage of {} bytes exceeds the quota of {}",
+                    usage,
+                    storage_quota
+                ));
+            }
+        }
+
+        writes.push(storage_usage_write(&live_object_id, usage));
+    }
+
+    cache.write_batch(writes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ramd_cache::Cache;
+    use ramd_db::memory::MemoryStorage;
+    use ramd_db::storage_usage::{count_storage_usage, storage_usage_key};
+    use std::sync::Arc;
+
+    fn live_object_id() -> Vec<u8> {
+        "a".repeat(64).into_bytes()
+    }
+
+    fn state_key(key: &str) -> Vec<u8> {
+        let mut state_key = live_object_id();
+        state_key.extend_from_slice(key.as_bytes());
+        state_key
+    }
+
+    /// Run the writes in a savepoint and account for them, keeping them only if they fit the quota.
+    fn apply(
+        cache: &InMemoryCache<MemoryStorage>,
+        writes: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+        storage_quota: Option<u64>,
+    ) -> eyre::Result<()> {
+        cache.savepoint()?;
+        cache.write_batch(writes)?;
+
+        match account_storage_usage(cache, storage_quota) {
+            Ok(()) => cache.release_savepoint(),
+            Err(err) => {
+                cache.rollback_to_savepoint()?;
+                Err(err)
+            }
+        }
+    }
+
+    fn usage(cache: &InMemoryCache<MemoryStorage>) -> u64 {
+        read_storage_usage(cache, &live_object_id()).unwrap()
+    }
+
+    #[test]
+    fn writes_overwrites_and_deletes_are_counted() {
+        let cache = InMemoryCache::new(Arc::new(MemoryStorage::default()));
+        let key_len = state_key("k").len() as u64;
+
+        apply(&cache, vec![(state_key("k"), Some(vec![0; 10]))], None).unwrap();
+        assert_eq!(usage(&cache), key_len + 10);
+
+        apply(&cache, vec![(state_key("k"), Some(vec![0; 4]))], None).unwrap();
+        assert_eq!(usage(&cache), key_len + 4);
+
+        // Deleting a key that doesn't exist changes nothing.
+        apply(&cache, vec![(state_key("x"), None)], None).unwrap();
+        assert_eq!(usage(&cache), key_len + 4);
+
+        apply(&cache, vec![(state_key("k"), None)], None).unwrap();
+        assert_eq!(usage(&cache), 0);
+        assert_eq!(
+            cache.get_opt(storage_usage_key(&live_object_id())).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn writes_up_to_the_quota_are_allowed() {
+        let cache = InMemoryCache::new(Arc::new(MemoryStorage::default()));
+        let quota = state_key("k").len() as u64 + 10;
+
+        apply(
+            &cache,
+            vec![(state_key("k"), Some(vec![0; 10]))],
+            Some(quota),
+        )
+        .unwrap();
+        assert_eq!(usage(&cache), quota);
+
+        // Overwriting with a value of the same size keeps the live object at its quota.
+        apply(
+            &cache,
+            vec![(state_key("k"), Some(vec![1; 10]))],
+            Some(quota),
+        )
+        .unwrap();
+        assert_eq!(usage(&cache), quota);
+    }
+
+    #[test]
+    fn writes_over_the_quota_are_rejected() {
+        let cache = InMemoryCache::new(Arc::new(MemoryStorage::default()));
+        let quota = state_key("k").len() as u64 + 10;
+
+        assert!(apply(
+            &cache,
+            vec![(state_key("k"), Some(vec![0; 11]))],
+            Some(quota)
+        )
+        .is_err());
+        assert_eq!(usage(&cache), 0);
+        assert_eq!(cache.get_opt(state_key("k")).unwrap(), None);
+
+        apply(
+            &cache,
+            vec![(state_key("k"), Some(vec![0; 10]))],
+            Some(quota),
+        )
+        .unwrap();
+        assert!(apply(
+            &cache,
+            vec![(state_key("k"), Some(vec![0; 11]))],
+            Some(quota)
+        )
+        .is_err());
+        assert!(apply(&cache, vec![(state_key("l"), Some(vec![]))], Some(quota)).is_err());
+        assert_eq!(usage(&cache), quota);
+    }
+
+    #[test]
+    fn shrinking_is_allowed_over_the_quota() {
+        let cache = InMemoryCache::new(Arc::new(MemoryStorage::default()));
+        let key_len = state_key("k").len() as u64;
+        apply(&cache, vec![(state_key("k"), Some(vec![0; 100]))], None).unwrap();
+
+        // A live object over a quota lowered since it stored its state can still free space.
+        let quota = Some(key_len);
+        apply(&cache, vec![(state_key("k"), Some(vec![0; 50]))], quota).unwrap();
+        assert_eq!(usage(&cache), key_len + 50);
+
+        apply(&cache, vec![(state_key("k"), None)], quota).unwrap();
+        assert_eq!(usage(&cache), 0);
+    }
+
+    #[test]
+    fn metadata_and_other_keys_are_not_counted() {
+        let cache = InMemoryCache::new(Arc::new(MemoryStorage::default()));
+
+        let writes = vec![
+            (live_object_id(), Some(vec![0; 100])),
+            (Vec::from(*b"ramd::other"), Some(vec![0; 100])),
+        ];
+        apply(&cache, writes, Some(1)).unwrap();
+        assert_eq!(usage(&cache), 0);
+    }
+
+    #[test]
+    fn counting_matches_accounting() {
+        let cache = InMemoryCache::new(Arc::new(MemoryStorage::default()));
+        let writes = vec![
+            (live_object_id(), Some(vec![0; 100])),
+            (state_key("k"), Some(vec![0; 10])),
+            (state_key("l"), Some(vec![0; 3])),
+        ];
+        apply(&cache, writes.clone(), None).unwrap();
+
+        let entries = writes
+            .into_iter()
+            .map(|(key, value)| Ok((key, value.unwrap_or_default())));
+        assert_eq!(
+            count_storage_usage(entries).unwrap(),
+            BTreeMap::from([(live_object_id(), usage(&cache))])
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+
+/// Orders the commits of batches processed concurrently over the same storage. A batch may only commit
+/// if no batch that committed after it began wrote a key it read, since its results could be based
+/// on stale values otherwise.
+#[derive(Default)]
+pub(crate) struct CommitValidator {
+    state: Mutex<ValidatorState>,
+    /// Held while committing, or by an exclusive batch for as long as it is in progress.
+    exclusive: Mutex<()>,
+}
+
+#[derive(Default)]
+struct ValidatorState {
+    /// The number of commits so far.
+    version: u64,
+    /// The keys written by recent commits, with the version each commit produced. A commit is forgotten
+    /// once every batch in progress began after it.
+    recent_writes: VecDeque<(u64, HashSet<Vec<u8>>)>,
+    /// The versions the batches in progress began at, with the number of batches for each.
+    active: BTreeMap<u64, usize>,
+}
+
+impl ValidatorState {
+    fn prune(&mut self) {
+        let oldest = self.active.keys().next().copied().unwrap_or(self.version);
+        while matches!(self.recent_writes.front(), Some((version, _)) if *version <= oldest) {
+            self.recent_writes.pop_front();
+        }
+    }
+}
+
+/// A batch in progress. It stops holding on to the writes of later commits once dropped.
+pub(crate) struct Transaction<'a> {
+    validator: &'a CommitValidator,
+    version: u64,
+    /// Keeps other batches from committing while an exclusive batch is in progress.
+    exclusive: Option<MutexGuard<'a, ()>>,
+}
+
+impl CommitValidator {
+    /// Begin a batch that may conflict with the batches committing while it is in progress.
+    pub(crate) fn begin(&self) -> eyre::Result<Transaction<'_>> {
+        self.begin_with(None)
+    }
+
+    /// Begin a batch that no other batch commits during, so that it can't conflict. Other batches keep
+    /// executing but wait to commit until it is dropped.
+    pub(crate) fn begin_exclusive(&self) -> eyre::Result<Transaction<'_>> {
+        let exclusive = self.exclusive()?;
+        self.begin_with(Some(exclusive))
+    }
+
+    fn begin_with<'a>(
+        &'a self,
+        exclusive: Option<MutexGuard<'a, ()>>,
+    ) -> eyre::Result<Transaction<'a>> {
+        let mut state = self.state()?;
+        let version = state.version;
+        *state.active.entry(version).or_default() += 1;
+
+        Ok(Transaction {
+            validator: self,
+            version,
+            exclusive,
+        })
+    }
+
+    fn state(&self) -> eyre::Result<MutexGuard<ValidatorState>> {
+        self.state
+            .lock()
+            .map_err(|err| eyre::eyre!(err.to_string()))
+    }
+
+    fn exclusive(&self) -> eyre::Result<MutexGuard<()>> {
+        self.exclusive
+            .lock()
+            .map_err(|err| eyre::eyre!(err.to_string()))
+    }
+}
+
+impl Transaction<'_> {
+    /// Run `commit`, which writes `write_set` to the storage, unless a key in `read_set` was committed
+    /// since the transaction began. Returns `false` without running it on such a conflict.
+    /// Commits don't overlap, so `commit` may also read and write keys outside of both sets.
+    pub(crate) fn commit<F>(
+        &self,
+        read_set: &HashSet<Vec<u8>>,
+        write_set: HashSet<Vec<u8>>,
+        commit: F,
+    ) -> eyre::Result<bool>
+    where
+        F: FnOnce() -> eyre::Result<()>,
+    {
+        // Commits are serialized by the exclusive lock alone. The state is only locked to check for conflicts
+        // and to record the commit, so that batches can begin and end while `commit` writes to the storage.
+        let _exclusive = match self.exclusive {
+            Some(_) => None,
+            None => Some(self.validator.exclusive()?),
+        };
+        let conflict = self
+            .validator
+            .state()?
+            .recent_writes
+            .iter()
+            .filter(|(version, _)| *version > self.version)
+            .any(|(_, writes)| !writes.is_disjoint(read_set));
+        if conflict {
+            return Ok(false);
+        }
+
+        // A batch beginning meanwhile begins before this commit, so it is checked against its writes.
+        // Record the writes even if the commit fails halfway, as some of them may have reached the storage.
+        let result = commit();
+        let mut state = self.validator.state()?;
+        state.version += 1;
+        let version = state.version;
+        state.recent_writes.push_back((version, write_set));
+        state.prune();
+
+        result.map(|_| true)
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        // Let other batches commit again only after this one stopped being active.
+        let _exclusive = self.exclusive.take();
+        let Ok(mut state) = self.validator.state() else {
+            return;
+        };
+
+        if let Some(count) = state.active.get_mut(&self.version) {
+            *count -= 1;
+            if *count == 0 {
+                state.active.remove(&self.version);
+            }
+        }
+        state.prune();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    fn keys(keys: &[&[u8]]) -> HashSet<Vec<u8>> {
+        keys.iter().map(|key| key.to_vec()).collect()
+    }
+
+    fn commit(transaction: &Transaction, read_set: &[&[u8]], write_set: &[&[u8]]) -> bool {
+        transaction
+            .commit(&keys(read_set), keys(write_set), || Ok(()))
+            .unwrap()
+    }
+
+    #[test]
+    fn conflicts_with_later_commits_of_keys_it_read() {
+        let validator = CommitValidator::default();
+        let first = validator.begin().unwrap();
+        let second = validator.begin().unwrap();
+
+        assert!(commit(&second, &[], &[b"a"]));
+        assert!(!commit(&first, &[b"a"], &[b"b"]));
+        assert!(commit(&first, &[b"b"], &[b"b"]));
+    }
+
+    #[test]
+    fn ignores_commits_from_before_it_began() {
+        let validator = CommitValidator::default();
+        assert!(commit(&validator.begin().unwrap(), &[], &[b"a"]));
+
+        let transaction = validator.begin().unwrap();
+        assert!(commit(&transaction, &[b"a"], &[b"a"]));
+    }
+
+    #[test]
+    fn conflicts_with_commits_that_failed_halfway() {
+        let validator = CommitValidator::default();
+        let first = validator.begin().unwrap();
+        let second = validator.begin().unwrap();
+
+        assert!(second
+            .commit(&keys(&[]), keys(&[b"a"]), || Err(eyre::eyre!("failed")))
+            .is_err());
+        assert!(!commit(&first, &[b"a"], &[]));
+    }
+
+    #[test]
+    fn forgets_writes_once_no_batch_began_before_them() {
+        let validator = CommitValidator::default();
+        let first = validator.begin().unwrap();
+        let second = validator.begin().unwrap();
+        assert!(commit(&second, &[], &[b"a"]));
+        drop(second);
+
+        // The first batch still needs the write to detect its conflict.
+        assert_eq!(validator.state().unwrap().recent_writes.len(), 1);
+
+        drop(first);
+        let state = validator.state().unwrap();
+        assert!(state.recent_writes.is_empty());
+        assert!(state.active.is_empty());
+    }
+
+    #[test]
+    fn exclusive_batches_keep_others_from_committing() {
+        let validator = CommitValidator::default();
+        let transaction = validator.begin().unwrap();
+        let exclusive = validator.begin_exclusive().unwrap();
+        let committed = AtomicBool::new(false);
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                assert!(commit(&transaction, &[], &[b"a"]));
+                committed.store(true, Ordering::SeqCst);
+            });
+
+            std::thread::sleep(Duration::from_millis(100));
+            assert!(!committed.load(Ordering::SeqCst));
+
+            // The exclusive batch commits without waiting for itself, and others commit once it is dropped.
+            assert!(commit(&exclusive, &[], &[b"b"]));
+            drop(exclusive);
+        });
+        assert!(committed.load(Ordering::SeqCst));
+    }
+}
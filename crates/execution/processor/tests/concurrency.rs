@@ -0,0 +1,86 @@
+//! Messages processed concurrently to the same live object don't lose updates.
+
+use std::sync::Arc;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_db::storage::Storage;
+use ramd_processor::{
+    Action, CreateLiveObjectAction, ExecuteLiveObjectAction, Message, Processor, ProcessorConfig,
+};
+
+/// A live object whose `increment` method adds one to the little-endian counter at key `c`.
+const COUNTER_MODULE: &str = r#"
+(module
+  (import "env" "storage_read_into" (func $storage_read_into (param i32 i32) (result i64)))
+  (import "env" "storage_write" (func $storage_write (param i32 i32)))
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  ;; Slices of the key at 64, of the counter at 72, and of the result at 80.
+  (data (i32.const 16) "\40\00\00\00\01\00\00\00\48\00\00\00\08\00\00\00\50\00\00\00\02\00\00\00")
+  (data (i32.const 64) "c")
+  (data (i32.const 80) "ok")
+  (func $allocate (export "allocate") (param $len i32) (result i32)
+    (local $slice i32)
+    (local.set $slice (global.get $next))
+    (i32.store (local.get $slice) (i32.add (local.get $slice) (i32.const 8)))
+    (i32.store offset=4 (local.get $slice) (local.get $len))
+    (global.set $next (i32.add (global.get $next) (i32.add (local.get $len) (i32.const 8))))
+    (local.get $slice))
+  (func $deallocate (export "deallocate") (param i32))
+  (func (export "increment") (param $args i32) (result i32)
+    (call $deallocate (local.get $args))
+    (i64.store (i32.const 72) (i64.const 0))
+    (drop (call $storage_read_into (i32.const 16) (i32.const 24)))
+    (i64.store (i32.const 72) (i64.add (i64.load (i32.const 72)) (i64.const 1)))
+    (call $storage_write (i32.const 16) (i32.const 24))
+    (i32.const 32)))
+"#;
+
+const THREADS: u64 = 4;
+const INCREMENTS: u64 = 25;
+
+fn increment(live_object_id: &str) -> Message {
+    Message::new(Action::ExecuteLiveObject(ExecuteLiveObjectAction {
+        live_object_id: live_object_id.to_owned(),
+        method: "increment".to_owned(),
+        args: Vec::new(),
+    }))
+}
+
+#[test]
+fn concurrent_increments_are_all_counted() {
+    let storage = Arc::new(MemoryStorage::default());
+    let processor = Processor::new(storage.clone(), ProcessorConfig::default());
+
+    let create = Message::new(Action::CreateLiveObject(CreateLiveObjectAction {
+        wasm_bytes: wat::parse_str(COUNTER_MODULE).expect("crafted module must parse"),
+        memory_limit: None,
+    }));
+    let live_object_id = create.action.live_object_id();
+    processor.process_messages(&[create]);
+
+    std::thread::scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                for _ in 0..INCREMENTS {
+                    let message = increment(&live_object_id);
+                    let message_id = message.id.clone();
+                    let results = processor.process_messages(&[message]);
+                    assert!(
+                        results.contains(&message_id) && results.contains("\"result\""),
+                        "unexpected results: {}",
+                        results
+                    );
+                }
+            });
+        }
+    });
+
+    let mut key = live_object_id.into_bytes();
+    key.push(b'c');
+    let counter = storage.get(key).unwrap();
+    assert_eq!(
+        u64::from_le_bytes(counter.try_into().unwrap()),
+        THREADS * INCREMENTS
+    );
+}
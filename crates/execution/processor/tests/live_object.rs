@@ -0,0 +1,53 @@
+//! Creating live objects through the processor.
+
+use std::sync::Arc;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_processor::{Action, CreateLiveObjectAction, Message, Processor, ProcessorConfig};
+use ramd_vm::LiveObjectMetadata;
+use serde_json::Value;
+
+const WASM_BYTES: &[u8] = include_bytes!("../../../../tests/wasms/live_object_read_only.wasm");
+
+/// Create the live object and return its ID, or the error.
+fn create(
+    processor: &Processor<MemoryStorage>,
+    memory_limit: Option<usize>,
+) -> Result<String, String> {
+    let message = Message::new(Action::CreateLiveObject(CreateLiveObjectAction {
+        wasm_bytes: WASM_BYTES.to_vec(),
+        memory_limit,
+    }));
+    let message_id = message.id.clone();
+    let results: Value = serde_json::from_str(&processor.process_messages(&[message])).unwrap();
+
+    let outcome = &results[&message_id];
+    match outcome.get("result") {
+        Some(result) => Ok(result.as_str().unwrap().to_owned()),
+        None => Err(outcome["error"].as_str().unwrap().to_owned()),
+    }
+}
+
+#[test]
+fn live_objects_can_be_created_again_with_the_same_memory_limit() {
+    let storage = Arc::new(MemoryStorage::default());
+    let processor = Processor::new(storage, ProcessorConfig::default());
+
+    let live_object_id = create(&processor, Some(1 << 20)).unwrap();
+    assert_eq!(create(&processor, Some(1 << 20)).unwrap(), live_object_id);
+}
+
+#[test]
+fn live_objects_cant_be_created_again_with_another_memory_limit() {
+    let storage = Arc::new(MemoryStorage::default());
+    let processor = Processor::new(storage.clone(), ProcessorConfig::default());
+
+    let live_object_id = create(&processor, Some(1 << 20)).unwrap();
+    assert!(create(&processor, Some(2 << 20)).is_err());
+    assert!(create(&processor, None).is_err());
+
+    let metadata = LiveObjectMetadata::load(storage.as_ref(), &live_object_id)
+        .unwrap()
+        .unwrap();
+    assert_eq!(metadata.memory_limit, Some(1 << 20));
+}
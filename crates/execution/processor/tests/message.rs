@@ -0,0 +1,56 @@
+//! Message IDs and how messages are recorded in the message log.
+
+use std::collections::BTreeSet;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_processor::{Action, ExecuteLiveObjectAction, Message, MessageLog};
+
+fn increment() -> Action {
+    Action::ExecuteLiveObject(ExecuteLiveObjectAction {
+        live_object_id: "a".repeat(64),
+        method: "increment".to_owned(),
+        args: vec![],
+    })
+}
+
+#[test]
+fn identical_actions_get_distinct_ids() {
+    let ids = (0..1000)
+        .map(|_| Message::new(increment()).id)
+        .collect::<BTreeSet<_>>();
+
+    assert_eq!(ids.len(), 1000);
+}
+
+#[test]
+fn the_nonce_tells_apart_messages_of_the_same_millisecond() {
+    let first = Message::with_nonce(increment(), 1, 1);
+    let second = Message::with_nonce(increment(), 1, 2);
+
+    assert_ne!(first.id, second.id);
+    assert_eq!(first.id, Message::with_nonce(increment(), 1, 1).id);
+}
+
+#[test]
+fn logged_messages_keep_their_ids() {
+    let storage = MemoryStorage::default();
+    let messages = [
+        Message::with_timestamp(increment(), 1),
+        Message::new(increment()),
+        Message::new(increment()),
+    ];
+
+    for message in messages.iter() {
+        MessageLog::append(&storage, message, "result").unwrap();
+    }
+
+    for (sequence, message) in messages.iter().enumerate() {
+        let entry = MessageLog::entry(&storage, sequence as u64)
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.message.id, message.id);
+        assert_eq!(entry.message.nonce, message.nonce);
+        assert_eq!(entry.message.timestamp, message.timestamp);
+    }
+    assert_eq!(MessageLog::len(&storage).unwrap(), 3);
+}
@@ -0,0 +1,134 @@
+//! Processing a batch on several workers ends in the same results and state as processing it on one.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_processor::{
+    Action, CreateLiveObjectAction, ExecuteLiveObjectAction, Message, Processor, ProcessorConfig,
+};
+
+const LIVE_OBJECTS: usize = 3;
+const MESSAGES_PER_LIVE_OBJECT: usize = 12;
+
+/// A live object whose `increment` method adds one to the little-endian counter at key `c`, and whose `fail` method
+/// does the same but then traps. The name tells the live objects apart, as they get their ID from the code.
+fn counter_module(name: usize) -> Vec<u8> {
+    let module = format!(
+        r#"
+(module
+  (import "env" "storage_read_into" (func $storage_read_into (param i32 i32) (result i64)))
+  (import "env" "storage_write" (func $storage_write (param i32 i32)))
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  ;; Slices of the key at 64, of the counter at 72, and of the result at 80.
+  (data (i32.const 16) "\40\00\00\00\01\00\00\00\48\00\00\00\08\00\00\00\50\00\00\00\02\00\00\00")
+  (data (i32.const 64) "c")
+  (data (i32.const 80) "ok")
+  (data (i32.const 96) "{name}")
+  (func $allocate (export "allocate") (param $len i32) (result i32)
+    (local $slice i32)
+    (local.set $slice (global.get $next))
+    (i32.store (local.get $slice) (i32.add (local.get $slice) (i32.const 8)))
+    (i32.store offset=4 (local.get $slice) (local.get $len))
+    (global.set $next (i32.add (global.get $next) (i32.add (local.get $len) (i32.const 8))))
+    (local.get $slice))
+  (func $deallocate (export "deallocate") (param i32))
+  (func $increment
+    (i64.store (i32.const 72) (i64.const 0))
+    (drop (call $storage_read_into (i32.const 16) (i32.const 24)))
+    (i64.store (i32.const 72) (i64.add (i64.load (i32.const 72)) (i64.const 1)))
+    (call $storage_write (i32.const 16) (i32.const 24)))
+  (func (export "increment") (param $args i32) (result i32)
+    (call $deallocate (local.get $args))
+    (call $increment)
+    (i32.const 32))
+  (func (export "fail") (param $args i32) (result i32)
+    (call $deallocate (local.get $args))
+    (call $increment)
+    unreachable))
+"#
+    );
+
+    wat::parse_str(module).expect("crafted module must parse")
+}
+
+/// The same batch on every call: the live objects are created in it, and their increments are interleaved
+/// with failing messages and with messages sent before the live object exists.
+fn batch() -> Vec<Message> {
+    let live_object_ids = (0..LIVE_OBJECTS)
+        .map(|name| {
+            Action::CreateLiveObject(CreateLiveObjectAction {
+                wasm_bytes: counter_module(name),
+                memory_limit: None,
+            })
+            .live_object_id()
+        })
+        .collect::<Vec<_>>();
+    let execute = |live_object: usize, method: &str| {
+        Action::ExecuteLiveObject(ExecuteLiveObjectAction {
+            live_object_id: live_object_ids[live_object].clone(),
+            method: method.to_owned(),
+            args: Vec::new(),
+        })
+    };
+
+    let mut actions = vec![execute(LIVE_OBJECTS - 1, "increment")];
+    for name in 0..LIVE_OBJECTS {
+        actions.push(Action::CreateLiveObject(CreateLiveObjectAction {
+            wasm_bytes: counter_module(name),
+            memory_limit: None,
+        }));
+    }
+    for index in 0..LIVE_OBJECTS * MESSAGES_PER_LIVE_OBJECT {
+        let method = match index % 5 {
+            4 => "fail",
+            _ => "increment",
+        };
+        actions.push(execute(index % LIVE_OBJECTS, method));
+    }
+
+    actions
+        .into_iter()
+        .enumerate()
+        .map(|(nonce, action)| Message::with_nonce(action, 0, nonce as u64 + 1))
+        .collect()
+}
+
+fn process(workers: usize) -> (serde_json::Value, BTreeMap<Vec<u8>, Vec<u8>>) {
+    let storage = Arc::new(MemoryStorage::default());
+    let processor = Processor::new(
+        storage.clone(),
+        ProcessorConfig {
+            workers,
+            ..ProcessorConfig::default()
+        },
+    );
+
+    let results = serde_json::from_str(&processor.process_messages(&batch())).unwrap();
+    (results, storage.data())
+}
+
+#[test]
+fn parallel_processing_matches_sequential_processing() {
+    let (sequential_results, sequential_state) = process(1);
+    let (parallel_results, parallel_state) = process(4);
+
+    assert_eq!(parallel_results, sequential_results);
+    assert_eq!(parallel_state, sequential_state);
+
+    // Failed messages are in the results, but their increments aren't counted.
+    let messages = batch();
+    let errors = messages
+        .iter()
+        .filter(|message| parallel_results[&message.id].get("error").is_some())
+        .count();
+    assert_eq!(errors, 1 + LIVE_OBJECTS * MESSAGES_PER_LIVE_OBJECT / 5);
+
+    let counters = parallel_state
+        .iter()
+        .filter(|(key, _)| key.len() == 65 && key.ends_with(b"c"))
+        .map(|(_, value)| u64::from_le_bytes(value.as_slice().try_into().unwrap()))
+        .sum::<u64>();
+    assert_eq!(counters as usize, messages.len() - LIVE_OBJECTS - errors);
+}
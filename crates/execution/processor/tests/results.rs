@@ -0,0 +1,180 @@
+//! The results of a batch of messages, and which of their changes are committed.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_db::storage::Storage;
+use ramd_processor::{
+    Action, CreateLiveObjectAction, ExecuteLiveObjectAction, Message, MessageLog, Processor,
+    ProcessorConfig,
+};
+use serde_json::Value;
+
+/// A live object whose `set` method writes key `k`, and whose `fail` method writes key `f` and then traps.
+const FALLIBLE_MODULE: &str = r#"
+(module
+  (import "env" "storage_write" (func $storage_write (param i32 i32)))
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  ;; Slices of the key `k` at 64, of the key `f` at 65, of the value at 72, and of the result at 80.
+  (data (i32.const 16) "\40\00\00\00\01\00\00\00\41\00\00\00\01\00\00\00\48\00\00\00\01\00\00\00\50\00\00\00\02\00\00\00")
+  (data (i32.const 64) "kf")
+  (data (i32.const 72) "v")
+  (data (i32.const 80) "ok")
+  (func $allocate (export "allocate") (param $len i32) (result i32)
+    (local $slice i32)
+    (local.set $slice (global.get $next))
+    (i32.store (local.get $slice) (i32.add (local.get $slice) (i32.const 8)))
+    (i32.store offset=4 (local.get $slice) (local.get $len))
+    (global.set $next (i32.add (global.get $next) (i32.add (local.get $len) (i32.const 8))))
+    (local.get $slice))
+  (func $deallocate (export "deallocate") (param i32))
+  (func (export "set") (param $args i32) (result i32)
+    (call $deallocate (local.get $args))
+    (call $storage_write (i32.const 16) (i32.const 32))
+    (i32.const 40))
+  (func (export "fail") (param $args i32) (result i32)
+    (call $deallocate (local.get $args))
+    (call $storage_write (i32.const 24) (i32.const 32))
+    unreachable))
+"#;
+
+/// A storage whose writes fail while `fail_writes` is set.
+#[derive(Default)]
+struct TestStorage {
+    inner: MemoryStorage,
+    fail_writes: AtomicBool,
+}
+
+impl TestStorage {
+    fn check_writable(&self) -> eyre::Result<()> {
+        match self.fail_writes.load(Ordering::SeqCst) {
+            true => Err(eyre::eyre!("Writes are failing")),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Storage<Vec<u8>, Vec<u8>> for TestStorage {
+    fn has(&self, key: Vec<u8>) -> eyre::Result<bool> {
+        self.inner.has(key)
+    }
+
+    fn get(&self, key: Vec<u8>) -> eyre::Result<Vec<u8>> {
+        self.inner.get(key)
+    }
+
+    fn get_opt(&self, key: Vec<u8>) -> eyre::Result<Option<Vec<u8>>> {
+        self.inner.get_opt(key)
+    }
+
+    fn set(&self, key: Vec<u8>, value: Vec<u8>) -> eyre::Result<()> {
+        self.check_writable()?;
+        self.inner.set(key, value)
+    }
+
+    fn delete(&self, key: Vec<u8>) -> eyre::Result<()> {
+        self.check_writable()?;
+        self.inner.delete(key)
+    }
+
+    fn write_batch(&self, writes: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> eyre::Result<()> {
+        self.check_writable()?;
+        self.inner.write_batch(writes)
+    }
+}
+
+fn create() -> Message {
+    Message::new(Action::CreateLiveObject(CreateLiveObjectAction {
+        wasm_bytes: wat::parse_str(FALLIBLE_MODULE).expect("crafted module must parse"),
+        memory_limit: None,
+    }))
+}
+
+fn execute(live_object_id: &str, method: &str) -> Message {
+    Message::new(Action::ExecuteLiveObject(ExecuteLiveObjectAction {
+        live_object_id: live_object_id.to_owned(),
+        method: method.to_owned(),
+        args: Vec::new(),
+    }))
+}
+
+fn state_key(live_object_id: &str, key: &str) -> Vec<u8> {
+    let mut state_key = live_object_id.as_bytes().to_vec();
+    state_key.extend_from_slice(key.as_bytes());
+    state_key
+}
+
+#[test]
+fn a_failed_message_is_rolled_back_while_the_others_commit() {
+    let storage = Arc::new(TestStorage::default());
+    let processor = Processor::new(storage.clone(), ProcessorConfig::default());
+
+    let create = create();
+    let live_object_id = create.action.live_object_id();
+    let messages = vec![
+        create,
+        execute(&live_object_id, "fail"),
+        execute(&live_object_id, "set"),
+        execute(&"0".repeat(64), "set"),
+    ];
+    let results: Value = serde_json::from_str(&processor.process_messages(&messages)).unwrap();
+
+    // Every message has an outcome under its ID, and nothing else is in the results.
+    let results = results.as_object().unwrap();
+    assert_eq!(results.len(), messages.len());
+    assert_eq!(results[&messages[0].id]["result"], live_object_id.as_str());
+    assert!(results[&messages[1].id]["error"].is_string());
+    assert_eq!(results[&messages[2].id]["result"], "ok");
+    assert!(results[&messages[3].id]["error"]
+        .as_str()
+        .unwrap()
+        .contains("doesn't exist"));
+
+    assert_eq!(
+        storage.get_opt(state_key(&live_object_id, "k")).unwrap(),
+        Some(Vec::from(*b"v"))
+    );
+    assert_eq!(
+        storage.get_opt(state_key(&live_object_id, "f")).unwrap(),
+        None
+    );
+
+    // Only the successful messages are logged.
+    assert_eq!(MessageLog::len(storage.as_ref()).unwrap(), 2);
+    assert_eq!(
+        MessageLog::entry(storage.as_ref(), 1)
+            .unwrap()
+            .unwrap()
+            .message
+            .id,
+        messages[2].id
+    );
+}
+
+#[test]
+fn every_message_gets_the_error_if_the_commit_fails() {
+    let storage = Arc::new(TestStorage::default());
+    let processor = Processor::new(storage.clone(), ProcessorConfig::default());
+    storage.fail_writes.store(true, Ordering::SeqCst);
+
+    let create = create();
+    let live_object_id = create.action.live_object_id();
+    let messages = vec![create, execute(&live_object_id, "set")];
+    let results: Value = serde_json::from_str(&processor.process_messages(&messages)).unwrap();
+
+    let results = results.as_object().unwrap();
+    assert_eq!(results.len(), messages.len());
+    for message in messages.iter() {
+        assert!(
+            results[&message.id]["error"]
+                .as_str()
+                .unwrap()
+                .contains("Writes are failing"),
+            "{:?}",
+            results
+        );
+    }
+    assert_eq!(MessageLog::len(storage.as_ref()).unwrap(), 0);
+}
@@ -0,0 +1,224 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, HashSet};
+
+use crate::ABI_SECTION;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use wasmparser::{ExternalKind, Parser, Payload};
+
+/// The methods a live object exports, read from the `ramd_abi` custom section of its WASM module.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Abi {
+    pub methods: Vec<AbiMethod>,
+    /// Who frees the `MemorySlice`s that the host allocates in the WASM (guest) memory.
+    #[serde(default)]
+    pub allocation_ownership: AllocationOwnership,
+}
+
+/// Who frees the `MemorySlice`s that the host allocates in the WASM (guest) memory,
+/// i.e. the arguments of a call and the values returned by host functions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AllocationOwnership {
+    /// The guest takes ownership of the slices and frees them itself.
+    #[default]
+    Guest,
+    /// The guest only borrows the slices, and the host frees them once the call returns.
+    Host,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AbiMethod {
+    pub name: String,
+    /// The schema of the JSON arguments.
+    #[serde(default)]
+    pub args: Schema,
+    /// The schema of the JSON return value.
+    #[serde(default)]
+    pub returns: Schema,
+    /// Whether the method leaves the state of the live object untouched.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// The shape of a JSON value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Schema {
+    #[default]
+    Any,
+    Null,
+    Boolean,
+    Integer,
+    Number,
+    String,
+    Array {
+        items: Box<Schema>,
+    },
+    Object {
+        #[serde(default)]
+        properties: BTreeMap<String, Schema>,
+        /// Properties that must be present. Other properties are optional.
+        #[serde(default)]
+        required: Vec<String>,
+    },
+}
+
+impl Abi {
+    /// Read the ABI from the custom section of the WASM module, if it has one.
+    /// Every method in the ABI must be exported as a function.
+    pub fn from_wasm(wasm_bytes: &[u8]) -> eyre::Result<Option<Self>> {
+        let mut abi: Option<Abi> = None;
+        let mut exported_functions = HashSet::new();
+
+        for payload in Parser::new(0).parse_all(wasm_bytes) {
+            match payload? {
+                Payload::CustomSection(section) if section.name() == ABI_SECTION => {
+                    if abi.is_some() {
+                        return Err(eyre::eyre!("Duplicate `{}` custom section", ABI_SECTION));
+                    }
+
+                    abi = Some(serde_json::from_slice(section.data())?);
+                }
+                Payload::ExportSection(exports) => {
+                    for export in exports {
+                        let export = export?;
+                        if export.kind == ExternalKind::Func {
+                            exported_functions.insert(export.name.to_string());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(abi) = &abi {
+            let mut names = HashSet::new();
+            for method in abi.methods.iter() {
+                if !names.insert(method.name.as_str()) {
+                    return Err(eyre::eyre!("Duplicate method `{}` in the ABI", method.name));
+                }
+
+                if !exported_functions.contains(&method.name) {
+                    return Err(eyre::eyre!(
+                        "Method `{}` in the ABI is not an exported function",
+                        method.name
+                    ));
+                }
+            }
+        }
+
+        Ok(abi)
+    }
+
+    /// Find the method with the given name.
+    pub fn method(&self, name: &str) -> Option<&AbiMethod> {
+        self.methods.iter().find(|method| method.name == name)
+    }
+
+    /// Check that the method is in the ABI and that the arguments match its schema.
+    pub fn validate_args(&self, method: &str, args: &[u8]) -> eyre::Result<()> {
+        let abi_method = self
+            .method(method)
+            .ok_or(eyre::eyre!("Method `{}` is not in the ABI", method))?;
+
+        let args: Value = serde_json::from_slice(args)
+            .map_err(|err| eyre::eyre!("Arguments of `{}` are not JSON: {}", method, err))?;
+
+        abi_method
+            .args
+            .validate(&args, "args")
+            .map_err(|err| eyre::eyre!("Invalid arguments of `{}`: {}", method, err))
+    }
+
+    /// Check that the value returned by the method matches its schema.
+    pub fn validate_result(&self, method: &str, result: &str) -> eyre::Result<()> {
+        let abi_method = self
+            .method(method)
+            .ok_or(eyre::eyre!("Method `{}` is not in the ABI", method))?;
+
+        let result: Value = serde_json::from_str(result)
+            .map_err(|err| eyre::eyre!("Result of `{}` is not JSON: {}", method, err))?;
+
+        abi_method
+            .returns
+            .validate(&result, "result")
+            .map_err(|err| eyre::eyre!("Invalid result of `{}`: {}", method, err))
+    }
+}
+
+impl Schema {
+    /// Check that the value matches the schema. `path` locates the value in error messages.
+    pub fn validate(&self, value: &Value, path: &str) -> Result<(), String> {
+        let matches = match (self, value) {
+            (Schema::Any, _) => true,
+            (Schema::Null, Value::Null) => true,
+            (Schema::Boolean, Value::Bool(_)) => true,
+            (Schema::Integer, Value::Number(number)) => number.is_i64() || number.is_u64(),
+            (Schema::Number, Value::Number(_)) => true,
+            (Schema::String, Value::String(_)) => true,
+            (Schema::Array { items }, Value::Array(values)) => {
+                for (index, value) in values.iter().enumerate() {
+                    items.validate(value, &format!("{}[{}]", path, index))?;
+                }
+                true
+            }
+            (
+                Schema::Object {
+                    properties,
+                    required,
+                },
+                Value::Object(values),
+            ) => {
+                for name in required {
+                    if !values.contains_key(name) {
+                        return Err(format!("`{}.{}` is missing", path, name));
+                    }
+                }
+
+                for (name, value) in values {
+                    if let Some(schema) = properties.get(name) {
+                        schema.validate(value, &format!("{}.{}", path, name))?;
+                    }
+                }
+                true
+            }
+            _ => false,
+        };
+
+        if !matches {
+            return Err(format!("`{}` is not {}", path, self));
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Schema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Schema::Any => write!(f, "any value"),
+            Schema::Null => write!(f, "null"),
+            Schema::Boolean => write!(f, "a boolean"),
+            Schema::Integer => write!(f, "an integer"),
+            Schema::Number => write!(f, "a number"),
+            Schema::String => write!(f, "a string"),
+            Schema::Array { .. } => write!(f, "an array"),
+            Schema::Object { .. } => write!(f, "an object"),
+        }
+    }
+}
@@ -0,0 +1,364 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{
+    artifact_file_name, check_interrupt, exported_function, has_passive_segments, load_artifact,
+    track_deallocate, validate_determinism, AllocationOwnership, CompilerBackend, Context,
+    DeallocationEnv, DefaultHostFunctions, FloatPolicy, HostAllocations, HostFunctions,
+    ImportObject, InterruptHandle, LiveObjectInfo, MemoryLimits, MessageContext, ReadOnly, Runtime,
+    VmError, DEALLOCATE_IMPORT, INTERRUPT_IMPORT, MAX_METERED_COST, VM_IMPORT_MODULE,
+};
+use ramd_db::storage::Storage;
+use tracing::{debug, info, warn};
+use wasmer::{Engine, Function, FunctionEnv, Instance, Module, Store, Type};
+
+/// Builds a `Runtime` for a live object. Without further configuration it gets the default host functions,
+/// the default memory limits and compiler, no timeout, unlimited storage, and its storage keys prefixed with
+/// the live object ID.
+pub struct RuntimeBuilder<S>
+where
+    S: Storage<Vec<u8>, Vec<u8>> + 'static,
+{
+    storage: Arc<S>,
+    live_object_info: LiveObjectInfo,
+    message: MessageContext,
+    memory_limits: MemoryLimits,
+    metered_cost_limit: u64,
+    storage_quota: Option<u64>,
+    timeout: Option<Duration>,
+    interrupt_handle: InterruptHandle,
+    namespace: Option<Vec<u8>>,
+    allocation_ownership: Option<AllocationOwnership>,
+    host_functions: Vec<Box<dyn HostFunctions<S>>>,
+    compiler: CompilerBackend,
+    float_policy: FloatPolicy,
+    precompiled_dir: Option<PathBuf>,
+    compiled_module: Option<CompiledModule>,
+}
+
+/// A WASM module compiled by a `RuntimeBuilder`, together with the engine it was compiled for.
+#[derive(Clone)]
+pub(crate) struct CompiledModule {
+    engine: Engine,
+    module: Module,
+    /// Whether the module has passive data or element segments, whose instances can't be reset.
+    has_passive_segments: bool,
+}
+
+impl CompiledModule {
+    /// Whether an instance of the module can be restored to a snapshot of it.
+    pub(crate) fn is_resettable(&self) -> bool {
+        !self.has_passive_segments
+    }
+}
+
+impl<S> RuntimeBuilder<S>
+where
+    S: Storage<Vec<u8>, Vec<u8>> + 'static,
+{
+    /// Create a new `RuntimeBuilder`.
+    pub fn new(storage: Arc<S>, live_object_info: LiveObjectInfo) -> Self {
+        Self {
+            storage,
+            live_object_info,
+            message: MessageContext::default(),
+            memory_limits: MemoryLimits::default(),
+            metered_cost_limit: MAX_METERED_COST,
+            storage_quota: None,
+            timeout: None,
+            interrupt_handle: InterruptHandle::new(),
+            namespace: None,
+            allocation_ownership: None,
+            host_functions: vec![Box::new(DefaultHostFunctions)],
+            compiler: CompilerBackend::default(),
+            float_policy: FloatPolicy::default(),
+            precompiled_dir: None,
+            compiled_module: None,
+        }
+    }
+
+    /// Set the message that the runtime executes.
+    pub fn message(mut self, message: MessageContext) -> Self {
+        self.message = message;
+        self
+    }
+
+    /// Set the memory limits that the memory limit of the live object is resolved against.
+    pub fn memory_limits(mut self, memory_limits: MemoryLimits) -> Self {
+        self.memory_limits = memory_limits;
+        self
+    }
+
+    /// Set the cost that an execution may spend on metered host functions.
+    pub fn metered_cost_limit(mut self, metered_cost_limit: u64) -> Self {
+        self.metered_cost_limit = metered_cost_limit;
+        self
+    }
+
+    /// Set the number of bytes the live object may store. Writes that would store more trap.
+    pub fn storage_quota(mut self, storage_quota: u64) -> Self {
+        self.storage_quota = Some(storage_quota);
+        self
+    }
+
+    /// Interrupt `run` if it takes longer than the timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the handle that interrupts the WASM instance.
+    pub fn interrupt_handle(mut self, interrupt_handle: InterruptHandle) -> Self {
+        self.interrupt_handle = interrupt_handle;
+        self
+    }
+
+    /// Prefix the storage keys of the live object with the namespace instead of its ID.
+    pub fn namespace(mut self, namespace: Vec<u8>) -> Self {
+        self.namespace = Some(namespace);
+        self
+    }
+
+    /// Set who frees the `MemorySlice`s that the host allocates, instead of following the ABI of the live object.
+    pub fn allocation_ownership(mut self, allocation_ownership: AllocationOwnership) -> Self {
+        self.allocation_ownership = Some(allocation_ownership);
+        self
+    }
+
+    /// Add a set of host functions. They replace the host functions with the same names added before.
+    pub fn host_functions(mut self, host_functions: impl HostFunctions<S> + 'static) -> Self {
+        self.host_functions.push(Box::new(host_functions));
+        self
+    }
+
+    /// Remove all host functions, including the default ones.
+    pub fn clear_host_functions(mut self) -> Self {
+        self.host_functions.clear();
+        self
+    }
+
+    /// Set the compiler that compiles the WASM module.
+    pub fn compiler(mut self, compiler: CompilerBackend) -> Self {
+        self.compiler = compiler;
+        self
+    }
+
+    /// Set how floating-point operations of the WASM module are dealt with.
+    pub fn float_policy(mut self, float_policy: FloatPolicy) -> Self {
+        self.float_policy = float_policy;
+        self
+    }
+
+    /// Load the module from its artifact in the directory, written by `ramd precompile`, instead of compiling it.
+    /// The module is compiled as usual if it has no artifact there, or one compiled for another engine.
+    pub fn precompiled_dir(mut self, precompiled_dir: PathBuf) -> Self {
+        self.precompiled_dir = Some(precompiled_dir);
+        self
+    }
+
+    /// Instantiate a module compiled by an earlier build of the same live object instead of compiling it again.
+    pub(crate) fn compiled_module(mut self, compiled_module: CompiledModule) -> Self {
+        self.compiled_module = Some(compiled_module);
+        self
+    }
+
+    /// Compile and instantiate the WASM module of the live object.
+    pub fn build(self) -> Result<Runtime, VmError> {
+        let (runtime, _, _) = self.build_with_env()?;
+        Ok(runtime)
+    }
+
+    /// Build the `Runtime`, keeping the function environment so that its `Context` can be changed later,
+    /// and the compiled module so that it can be instantiated again.
+    pub(crate) fn build_with_env(
+        self,
+    ) -> Result<(Runtime, FunctionEnv<Context<S>>, CompiledModule), VmError> {
+        let memory_limit = self
+            .memory_limits
+            .resolve(self.live_object_info.memory_limit)
+            .map_err(|err| VmError::LimitExceeded(err.to_string()))?;
+
+        let compiled_module = match self.compiled_module {
+            Some(compiled_module) => compiled_module,
+            None => {
+                validate_determinism(&self.live_object_info.wasm_bytes, self.float_policy)?;
+                let engine = self.compiler.engine(memory_limit)?;
+
+                let has_passive_segments = has_passive_segments(&self.live_object_info.wasm_bytes)?;
+
+                // Load the artifact of the WASM module, or compile it.
+                let precompiled = self.precompiled_dir.as_deref().and_then(|precompiled_dir| {
+                    load_precompiled(
+                        precompiled_dir,
+                        &engine,
+                        &self.live_object_info,
+                        self.compiler,
+                        memory_limit,
+                    )
+                });
+                let module = match precompiled {
+                    Some(module) => module,
+                    None => Module::new(&engine, &self.live_object_info.wasm_bytes)
+                        .map_err(|err| VmError::Compile(err.to_string()))?,
+                };
+
+                CompiledModule {
+                    engine,
+                    module,
+                    has_passive_segments,
+                }
+            }
+        };
+        let mut store = Store::new(compiled_module.engine.clone());
+        let module = &compiled_module.module;
+
+        let allocation_ownership = self.allocation_ownership.unwrap_or(
+            self.live_object_info
+                .abi
+                .as_ref()
+                .map(|abi| abi.allocation_ownership)
+                .unwrap_or_default(),
+        );
+        let host_allocations = HostAllocations::default();
+        let read_only_methods = self
+            .live_object_info
+            .abi
+            .iter()
+            .flat_map(|abi| abi.methods.iter())
+            .filter(|method| method.read_only)
+            .map(|method| method.name.clone())
+            .collect();
+        let read_only = ReadOnly::default();
+
+        // Create a function environment.
+        let mut context = Context::new(
+            self.storage,
+            self.live_object_info.id,
+            self.message,
+            memory_limit,
+        );
+        if let Some(namespace) = self.namespace {
+            context.key_prefix = namespace;
+        }
+        context.metered_cost_limit = self.metered_cost_limit;
+        context.storage_quota = self.storage_quota;
+        context.host_allocations = host_allocations.clone();
+        context.read_only = read_only.clone();
+        let function_env = FunctionEnv::new(&mut store, context);
+
+        // Create an import object.
+        let host_functions = self
+            .host_functions
+            .iter()
+            .map(|host_functions| host_functions.as_ref())
+            .collect::<Vec<_>>();
+        let mut import_object =
+            ImportObject::with_host_functions(&mut store, &function_env, &host_functions);
+
+        // The host function that the WASM instance calls to check whether it is interrupted.
+        let interrupt_env = FunctionEnv::new(&mut store, self.interrupt_handle.clone());
+        import_object.0.define(
+            VM_IMPORT_MODULE,
+            INTERRUPT_IMPORT,
+            Function::new_typed_with_env(&mut store, &interrupt_env, check_interrupt),
+        );
+
+        // The host function that calls to `deallocate` from within the WASM instance go through.
+        let deallocation_env = FunctionEnv::new(
+            &mut store,
+            DeallocationEnv {
+                host_allocations: host_allocations.clone(),
+                deallocate: None,
+            },
+        );
+        import_object.0.define(
+            VM_IMPORT_MODULE,
+            DEALLOCATE_IMPORT,
+            Function::new_typed_with_env(&mut store, &deallocation_env, track_deallocate),
+        );
+
+        // Instantiate the WASM instance.
+        let instance = Instance::new(&mut store, module, &import_object.0)
+            .map_err(|err| VmError::Instantiate(err.to_string()))?;
+
+        // Check the exports that the host calls into before running anything.
+        let memory = instance
+            .exports
+            .get_memory("memory")
+            .cloned()
+            .map_err(|_| VmError::MissingExport("memory".to_string()))?;
+        let allocate =
+            exported_function(&instance, &store, "allocate", &[Type::I32], &[Type::I32])?;
+        let deallocate = exported_function(&instance, &store, "deallocate", &[Type::I32], &[])?;
+        deallocation_env.as_mut(&mut store).deallocate = Some(deallocate);
+
+        // Set the Context of the WASM instance. The WASM instance will use this Context to interact with the host.
+        let context = function_env.as_mut(&mut store);
+
+        context.memory = Some(memory);
+        context.allocate = Some(allocate);
+
+        info!(target: "ramd::vm", "Runtime is created");
+
+        let runtime = Runtime {
+            store,
+            instance,
+            interrupt_handle: self.interrupt_handle,
+            interrupt_env,
+            timeout: self.timeout,
+            memory_limit,
+            host_allocations,
+            allocation_ownership,
+            leaked_allocations: Vec::new(),
+            read_only_methods,
+            read_only,
+        };
+
+        Ok((runtime, function_env, compiled_module))
+    }
+}
+
+/// The module loaded from its artifact in the precompiled directory, if there is a compatible one.
+fn load_precompiled(
+    precompiled_dir: &Path,
+    engine: &Engine,
+    live_object_info: &LiveObjectInfo,
+    compiler: CompilerBackend,
+    memory_limit: usize,
+) -> Option<Module> {
+    let path = precompiled_dir.join(artifact_file_name(&live_object_info.id, compiler));
+    let artifact = std::fs::read(&path).ok()?;
+
+    match load_artifact(
+        engine,
+        &artifact,
+        compiler,
+        memory_limit,
+        &live_object_info.hash,
+    ) {
+        Ok(module) => {
+            debug!(target: "ramd::vm", "Loaded live object `{}` from `{}`", live_object_info.id, path.display());
+            Some(module)
+        }
+        Err(err) => {
+            warn!(target: "ramd::vm", "Compiling live object `{}` instead of loading `{}`: {}", live_object_info.id, path.display(), err);
+            None
+        }
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::Arc;
+
+use crate::{
+    validate_determinism, ExportState, FloatPolicy, Interrupt, LimitingTunables, LiveObjectInfo,
+    TrackDeallocations, VmError,
+};
+use ramd_db::codec::{encode_field, Decoder};
+use serde::{Deserialize, Serialize};
+use wasmer::sys::{BaseTunables, EngineBuilder, Features, NativeEngineExt};
+use wasmer::{CompilerConfig, Engine, Module, Target};
+
+/// The compiler that turns WASM modules into native code.
+/// Each one is only available if the VM is built with the cargo feature of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompilerBackend {
+    /// Compiles in linear time, which bounds the cost of compiling untrusted modules, but produces slower code.
+    Singlepass,
+    /// Optimizes the code for fast execution at the cost of slower compilation.
+    Cranelift,
+}
+
+impl Default for CompilerBackend {
+    fn default() -> Self {
+        if cfg!(feature = "singlepass") && !cfg!(feature = "cranelift") {
+            CompilerBackend::Singlepass
+        } else {
+            CompilerBackend::Cranelift
+        }
+    }
+}
+
+impl std::fmt::Display for CompilerBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompilerBackend::Singlepass => write!(f, "singlepass"),
+            CompilerBackend::Cranelift => write!(f, "cranelift"),
+        }
+    }
+}
+
+impl CompilerBackend {
+    /// Whether the VM is built with this compiler.
+    pub fn is_available(&self) -> bool {
+        match self {
+            CompilerBackend::Singlepass => cfg!(feature = "singlepass"),
+            CompilerBackend::Cranelift => cfg!(feature = "cranelift"),
+        }
+    }
+
+    /// Create an engine that compiles with this compiler. Its instances can be interrupted, can't grow
+    /// their memory past the limit, and canonicalize NaNs. Modules using threads or SIMD fail to compile.
+    pub(crate) fn engine(&self, memory_limit: usize) -> Result<Engine, VmError> {
+        let mut compiler = self.compiler_config()?;
+        compiler.push_middleware(Arc::new(Interrupt::default()));
+        compiler.push_middleware(Arc::new(TrackDeallocations::default()));
+        compiler.push_middleware(Arc::new(ExportState));
+
+        let mut features = Features::new();
+        features.threads(false).simd(false);
+
+        let mut engine: Engine = EngineBuilder::new(compiler)
+            .set_features(Some(features))
+            .into();
+        let base_tunables = BaseTunables::for_target(&Target::default());
+        engine.set_tunables(LimitingTunables::new(base_tunables, memory_limit));
+
+        Ok(engine)
+    }
+
+    fn compiler_config(&self) -> Result<Box<dyn CompilerConfig>, VmError> {
+        match self {
+            #[cfg(feature = "singlepass")]
+            CompilerBackend::Singlepass => {
+                let mut compiler = wasmer::Singlepass::default();
+                compiler.canonicalize_nans(true);
+                Ok(Box::new(compiler))
+            }
+            #[cfg(feature = "cranelift")]
+            CompilerBackend::Cranelift => {
+                let mut compiler = wasmer::Cranelift::default();
+                compiler.canonicalize_nans(true);
+                Ok(Box::new(compiler))
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(VmError::Compile(format!(
+                "The VM is built without the `{}` compiler",
+                self
+            ))),
+        }
+    }
+}
+
+/// Marks a file as an artifact of `precompile`.
+const ARTIFACT_MAGIC: &[u8] = b"ramd::artifact";
+
+/// Version of the artifact header and of the engine the code is compiled for. The middlewares rewrite the
+/// code and add imports and globals to the `ModuleInfo`, which the artifact carries as transformed, so bump
+/// it whenever the middlewares or the engine features change.
+const ARTIFACT_VERSION: u8 = 1;
+
+/// The file name of the artifact of the live object compiled by the compiler.
+pub fn artifact_file_name(live_object_id: &str, compiler: CompilerBackend) -> String {
+    format!("{}.{}.wasmu", live_object_id, compiler)
+}
+
+/// Validate the WASM module of the live object against the float policy, compile it ahead of time and serialize
+/// the native code. The artifact only loads into an engine of the same compiler, memory limit, wasmer version
+/// and target, and for the same code, which its header records.
+pub fn precompile(
+    live_object_info: &LiveObjectInfo,
+    compiler: CompilerBackend,
+    float_policy: FloatPolicy,
+    memory_limit: usize,
+) -> Result<Vec<u8>, VmError> {
+    validate_determinism(&live_object_info.wasm_bytes, float_policy)?;
+
+    let engine = compiler.engine(memory_limit)?;
+    let module = Module::new(&engine, &live_object_info.wasm_bytes)
+        .map_err(|err| VmError::Compile(err.to_string()))?;
+    let module = module
+        .serialize()
+        .map_err(|err| VmError::Compile(err.to_string()))?;
+
+    let mut artifact = ARTIFACT_MAGIC.to_vec();
+    artifact.push(ARTIFACT_VERSION);
+    encode_field(&mut artifact, &artifact_header(compiler, memory_limit));
+    encode_field(&mut artifact, &live_object_info.hash);
+    encode_field(&mut artifact, &module);
+
+    Ok(artifact)
+}
+
+/// Load an artifact of `precompile` into the engine, after checking that it was compiled for the engine and for
+/// the code with `hash`.
+pub(crate) fn load_artifact(
+    engine: &Engine,
+    artifact: &[u8],
+    compiler: CompilerBackend,
+    memory_limit: usize,
+    hash: &[u8],
+) -> Result<Module, VmError> {
+    let malformed = |err: eyre::Report| VmError::Compile(format!("Malformed artifact: {}", err));
+
+    let artifact = artifact
+        .strip_prefix(ARTIFACT_MAGIC)
+        .ok_or_else(|| VmError::Compile("Not an artifact of `ramd precompile`".to_string()))?;
+    let mut decoder = Decoder::new(artifact);
+    let version = decoder.byte().map_err(malformed)?;
+    if version != ARTIFACT_VERSION {
+        return Err(VmError::Compile(format!(
+            "Artifact has version {}, but this binary loads version {}",
+            version, ARTIFACT_VERSION
+        )));
+    }
+    if decoder.field().map_err(malformed)? != artifact_header(compiler, memory_limit) {
+        return Err(VmError::Compile(format!(
+            "Artifact wasn't compiled for the `{}` compiler with a memory limit of {} bytes by wasmer {} for this target",
+            compiler,
+            memory_limit,
+            wasmer::VERSION
+        )));
+    }
+    if decoder.field().map_err(malformed)? != hash {
+        return Err(VmError::Compile(
+            "Artifact was compiled from other code".to_string(),
+        ));
+    }
+    let module = decoder.field().map_err(malformed)?;
+    decoder.finish().map_err(malformed)?;
+
+    // Safety: wasmer checks that the bytes are a module it serialized, and the header that they were compiled
+    // with the middlewares and the engine of this binary.
+    unsafe { Module::deserialize(engine, module.to_vec()) }
+        .map_err(|err| VmError::Compile(err.to_string()))
+}
+
+/// What the compiled code depends on besides the module: the compiler, the memory limit that the tunables
+/// lay out the memory for, and the wasmer version and target the code is compiled by and for.
+fn artifact_header(compiler: CompilerBackend, memory_limit: usize) -> Vec<u8> {
+    let target = Target::default();
+    format!(
+        "{} {} {} {} {:?}",
+        compiler,
+        memory_limit,
+        wasmer::VERSION,
+        target.triple(),
+        target.cpu_features()
+    )
+    .into_bytes()
+}
@@ -13,5 +13,24 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-/// The maximum size of the WASM memory.
-pub const MAX_WASM_MEMORY_SIZE: usize = 2 * 1024 * 1024; // 2MB
+/// The WASM memory limit of live objects that don't set their own.
+pub const DEFAULT_WASM_MEMORY_SIZE: usize = 2 * 1024 * 1024; // 2MB
+/// The largest WASM memory limit that a live object may set.
+pub const MAX_WASM_MEMORY_SIZE: usize = 64 * 1024 * 1024; // 64MB
+pub const ABI_SECTION: &str = "ramd_abi";
+pub const HOST_IMPORT_MODULE: &str = "env";
+pub const RANDOM_SEED_DOMAIN: &[u8] = b"ramd::random_seed";
+
+// The costs of host functions, charged per call and per 32-byte word of input.
+pub const KECCAK256_BASE_COST: u64 = 30;
+pub const KECCAK256_WORD_COST: u64 = 6;
+pub const SHA256_BASE_COST: u64 = 60;
+pub const SHA256_WORD_COST: u64 = 12;
+pub const ED25519_VERIFY_BASE_COST: u64 = 2000;
+pub const ED25519_VERIFY_WORD_COST: u64 = 6;
+pub const SECP256K1_RECOVER_COST: u64 = 3000;
+pub const MAX_METERED_COST: u64 = 10_000_000; // The cost a single execution may spend on host functions.
+
+// The number of idle runtimes kept per live object, and how long they are kept.
+pub const DEFAULT_RUNTIME_POOL_SIZE: usize = 4;
+pub const DEFAULT_RUNTIME_POOL_IDLE_TIMEOUT_MS: u64 = 60_000;
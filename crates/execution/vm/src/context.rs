@@ -13,12 +13,53 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::sync::Arc;
-
-use crate::{MemorySlice, MemorySliceError, MemorySlicePtr, MAX_WASM_MEMORY_SIZE};
+use crate::{
+    HostAllocations, MemorySlice, MemorySlicePtr, VmError, MAX_METERED_COST, RANDOM_SEED_DOMAIN,
+};
 use ramd_db::storage::Storage;
+use ramd_db::storage_usage::{entry_len, read_storage_usage, state_key_owner};
+use sha3::{Digest, Keccak256};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use wasmer::{Function, Memory, StoreMut, Value};
 
+/// The message being executed. The WASM instance learns about its execution only through the message,
+/// so every replica executing the same message sees the same values.
+#[derive(Debug, Clone, Default)]
+pub struct MessageContext {
+    /// The ID of the message.
+    pub id: String,
+    /// Milliseconds since the Unix epoch at which the message was created.
+    pub timestamp: u64,
+}
+
+impl MessageContext {
+    /// Derive a pseudo-random seed from the message ID.
+    pub fn random_seed(&self) -> Vec<u8> {
+        let mut hasher = Keccak256::new();
+        hasher.update(RANDOM_SEED_DOMAIN);
+        hasher.update(self.id.as_bytes());
+        hasher.finalize().to_vec()
+    }
+}
+
+/// Whether the method being called is read-only, in which case host functions refuse to write the storage.
+/// It is shared between the `Runtime`, which sets it before each call, and the `Context` of host functions.
+#[derive(Debug, Clone, Default)]
+pub struct ReadOnly(Arc<AtomicBool>);
+
+impl ReadOnly {
+    /// Mark the method being called as read-only or not.
+    pub fn set(&self, read_only: bool) {
+        self.0.store(read_only, Ordering::SeqCst);
+    }
+
+    /// Whether the method being called is read-only.
+    pub fn get(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
 /// The context of the WASM (guest) instance that is shared across import functions.
 pub struct Context<S>
 where
@@ -26,6 +67,22 @@ where
 {
     pub storage: Arc<S>,
     pub key_prefix: Vec<u8>,
+    pub live_object_id: String,
+    pub message: MessageContext,
+    /// The maximum size of the WASM (guest) memory in bytes.
+    pub memory_limit: usize,
+    /// The cost spent on metered host functions so far.
+    pub metered_cost: u64,
+    /// The cost the execution may spend on metered host functions.
+    pub metered_cost_limit: u64,
+    /// The maximum number of bytes the live object may store, or `None` for unlimited storage.
+    pub storage_quota: Option<u64>,
+    /// The bytes the writes of the current message added to the state, less the bytes they freed.
+    pub storage_usage_delta: i64,
+    /// The `MemorySlice`s allocated by host functions during the current call.
+    pub host_allocations: HostAllocations,
+    /// Whether the method being called is read-only.
+    pub read_only: ReadOnly,
     pub memory: Option<Memory>,
     pub allocate: Option<Function>,
 }
@@ -35,10 +92,24 @@ where
     S: Storage<Vec<u8>, Vec<u8>> + 'static,
 {
     /// Create a new `Context`.
-    pub fn new(storage: Arc<S>, key_prefix: String) -> Self {
+    pub fn new(
+        storage: Arc<S>,
+        live_object_id: String,
+        message: MessageContext,
+        memory_limit: usize,
+    ) -> Self {
         Self {
             storage,
-            key_prefix: key_prefix.as_bytes().to_vec(),
+            key_prefix: live_object_id.as_bytes().to_vec(),
+            live_object_id,
+            message,
+            memory_limit,
+            metered_cost: 0,
+            metered_cost_limit: MAX_METERED_COST,
+            storage_quota: None,
+            storage_usage_delta: 0,
+            host_allocations: HostAllocations::default(),
+            read_only: ReadOnly::default(),
             memory: None,
             allocate: None,
         }
@@ -50,29 +121,103 @@ where
         prefixed_key
     }
 
+    /// Charge the cost of a host function call on `len` bytes of input.
+    /// Fails once the execution has spent more than the metered cost limit.
+    pub fn charge(&mut self, base_cost: u64, word_cost: u64, len: usize) -> Result<(), VmError> {
+        let words = (len as u64).div_ceil(32);
+        let cost = base_cost.saturating_add(word_cost.saturating_mul(words));
+        self.metered_cost = self.metered_cost.saturating_add(cost);
+
+        if self.metered_cost > self.metered_cost_limit {
+            return Err(VmError::LimitExceeded(format!(
+                "Metered cost of {} exceeds the limit of {}",
+                self.metered_cost, self.metered_cost_limit
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Account for the bytes that writing `value` at the prefixed key, or deleting it if `None`, adds to the state
+    /// of the live object. Fails before the write if it grows the live object past the storage quota during
+    /// the current message, while writes that free space are allowed over the quota.
+    pub fn charge_storage(&mut self, key: &[u8], value: Option<&[u8]>) -> Result<(), VmError> {
+        let (Some(storage_quota), Some(live_object_id)) =
+            (self.storage_quota, state_key_owner(key))
+        else {
+            return Ok(());
+        };
+
+        let old_value = self
+            .storage
+            .get_opt(Vec::from(key))
+            .map_err(|err| VmError::Storage(err.to_string()))?;
+        let delta = self
+            .storage_usage_delta
+            .saturating_add(entry_len(key, value) as i64)
+            .saturating_sub(entry_len(key, old_value.as_deref()) as i64);
+
+        if delta > 0 {
+            let usage = read_storage_usage(self.storage.as_ref(), live_object_id)
+                .map_err(|err| VmError::Storage(err.to_string()))?
+                .saturating_add(delta as u64);
+            if usage > storage_quota {
+                return Err(VmError::LimitExceeded(format!(
+                    "Storage usage of {} bytes exceeds the quota of {}",
+                    usage, storage_quota
+                )));
+            }
+        }
+        self.storage_usage_delta = delta;
+
+        Ok(())
+    }
+
+    /// Fail if the method being called is read-only, before it writes the storage.
+    pub fn check_writable(&self) -> Result<(), VmError> {
+        if self.read_only.get() {
+            return Err(VmError::ReadOnly(self.live_object_id.clone()));
+        }
+
+        Ok(())
+    }
+
     /// Read data from the WASM (guest) memory.
     pub fn read_memory(
         &self,
         store: &StoreMut,
         memory_slice_ptr: MemorySlicePtr,
-    ) -> eyre::Result<Vec<u8>, MemorySliceError> {
-        let memory = self.memory();
+    ) -> Result<Vec<u8>, VmError> {
+        let memory = self.memory()?;
         let memory_view = memory.view(store);
         let memory_slice = MemorySlice::new(&memory_view, memory_slice_ptr)?;
 
-        let data = memory_slice.read(&memory_view, MAX_WASM_MEMORY_SIZE)?;
+        let data = memory_slice.read(&memory_view, self.memory_limit)?;
 
         Ok(data)
     }
 
+    /// Get the length of a `MemorySlice` in the WASM (guest) memory.
+    pub fn memory_slice_len(
+        &self,
+        store: &StoreMut,
+        memory_slice_ptr: MemorySlicePtr,
+    ) -> Result<usize, VmError> {
+        let memory = self.memory()?;
+        let memory_view = memory.view(store);
+        let memory_slice = MemorySlice::new(&memory_view, memory_slice_ptr)?;
+
+        Ok(memory_slice.len as usize)
+    }
+
     /// Write data to the WASM (guest) memory.
     pub fn write_memory(
         &self,
         store: &StoreMut,
         memory_slice_ptr: MemorySlicePtr,
         data: &[u8],
-    ) -> eyre::Result<(), MemorySliceError> {
-        let memory = self.memory();
+    ) -> Result<(), VmError> {
+        let memory = self.memory()?;
         let memory_view = memory.view(store);
         let memory_slice = MemorySlice::new(&memory_view, memory_slice_ptr)?;
 
@@ -81,33 +226,61 @@ where
         Ok(())
     }
 
-    /// Allocate memory in the WASM (guest) memory.
+    /// Allocate memory in the WASM (guest) memory. The allocation is recorded in the host allocations.
     pub fn allocate_memory(
         &self,
         store: &mut StoreMut,
         len: usize,
-    ) -> eyre::Result<MemorySlicePtr> {
-        let memory_slice_ptr = self.allocate().call(store, &[Value::from(len as u32)])?;
+    ) -> Result<MemorySlicePtr, VmError> {
+        let allocation_failed = |reason: String| VmError::AllocationFailed { len, reason };
+
+        let len_value = u32::try_from(len).map_err(|_| {
+            allocation_failed("Length doesn't fit in the WASM address space".to_string())
+        })?;
 
-        let memory_slice_ptr: MemorySlicePtr = memory_slice_ptr[0]
-            .clone()
+        let memory_slice_ptr = self
+            .allocate()?
+            .call(store, &[Value::from(len_value)])
+            .map_err(|err| allocation_failed(VmError::from(err).to_string()))?;
+
+        let memory_slice_ptr: MemorySlicePtr = memory_slice_ptr
+            .first()
+            .cloned()
+            .ok_or(allocation_failed(
+                "`allocate` returned no pointer".to_string(),
+            ))?
             .try_into()
-            .map_err(|err: &str| eyre::eyre!(err))?;
+            .map_err(|err: &str| allocation_failed(err.to_string()))?;
+
+        self.host_allocations.record(memory_slice_ptr);
 
         Ok(memory_slice_ptr)
     }
 
+    /// Allocate a `MemorySlice` in the WASM (guest) memory, write the data to it and return its pointer.
+    pub fn allocate_and_write_memory(
+        &self,
+        store: &mut StoreMut,
+        data: &[u8],
+    ) -> Result<MemorySlicePtr, VmError> {
+        let data_ptr = self.allocate_memory(store, data.len())?;
+
+        self.write_memory(store, data_ptr, data)?;
+
+        Ok(data_ptr)
+    }
+
     /// Get the memory of the WASM instance.
-    fn memory(&self) -> &Memory {
+    fn memory(&self) -> Result<&Memory, VmError> {
         self.memory
             .as_ref()
-            .expect("ramd::VM: Context must have Memory")
+            .ok_or(VmError::MissingExport("memory".to_string()))
     }
 
     /// Get the function to allocate memory in the WASM instance.
-    fn allocate(&self) -> &Function {
+    fn allocate(&self) -> Result<&Function, VmError> {
         self.allocate
             .as_ref()
-            .expect("ramd::VM: Context must have allocate function")
+            .ok_or(VmError::MissingExport("allocate".to_string()))
     }
 }
@@ -0,0 +1,66 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use ed25519_dalek::{Signature as Ed25519Signature, VerifyingKey as Ed25519VerifyingKey};
+use k256::ecdsa::{
+    RecoveryId, Signature as Secp256k1Signature, VerifyingKey as Secp256k1VerifyingKey,
+};
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+
+/// Hash the data with Keccak-256.
+pub fn keccak256(data: &[u8]) -> Vec<u8> {
+    Keccak256::digest(data).to_vec()
+}
+
+/// Hash the data with SHA-256.
+pub fn sha256(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data).to_vec()
+}
+
+/// Verify the 64-byte Ed25519 signature of the message against the 32-byte public key.
+/// Malformed signatures and public keys fail the verification.
+pub fn ed25519_verify(signature: &[u8], message: &[u8], public_key: &[u8]) -> bool {
+    let Ok(public_key) = <[u8; 32]>::try_from(public_key) else {
+        return false;
+    };
+    let Ok(public_key) = Ed25519VerifyingKey::from_bytes(&public_key) else {
+        return false;
+    };
+    let Ok(signature) = Ed25519Signature::from_slice(signature) else {
+        return false;
+    };
+
+    public_key.verify_strict(message, &signature).is_ok()
+}
+
+/// Recover the 65-byte uncompressed public key that produced the 64-byte secp256k1 signature of the 32-byte message hash.
+/// Returns `None` if the signature, the recovery ID or the message hash is malformed.
+pub fn secp256k1_recover(
+    message_hash: &[u8],
+    signature: &[u8],
+    recovery_id: u8,
+) -> Option<Vec<u8>> {
+    if message_hash.len() != 32 {
+        return None;
+    }
+
+    let signature = Secp256k1Signature::from_slice(signature).ok()?;
+    let recovery_id = RecoveryId::from_byte(recovery_id)?;
+    let public_key =
+        Secp256k1VerifyingKey::recover_from_prehash(message_hash, &signature, recovery_id).ok()?;
+
+    Some(public_key.to_encoded_point(false).as_bytes().to_vec())
+}
@@ -0,0 +1,150 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::Mutex;
+
+use crate::{
+    insert_function_import, shift_operator, HostAllocations, MemorySlicePtr, Untransformed,
+};
+use wasmer::wasmparser::Operator;
+use wasmer::{
+    Function, FunctionEnvMut, FunctionMiddleware, FunctionType, LocalFunctionIndex,
+    MiddlewareError, MiddlewareReaderState, ModuleMiddleware, RuntimeError, Type,
+};
+use wasmer_types::{ExportIndex, ModuleInfo};
+
+/// The name of the `VM_IMPORT_MODULE` host function that calls to the `deallocate` export are redirected to.
+pub const DEALLOCATE_IMPORT: &str = "deallocate";
+
+/// A middleware that lets the host see which `MemorySlice`s the WASM (guest) instance frees. Calls to the
+/// `deallocate` export from within the module go to the `DEALLOCATE_IMPORT` host function instead, which
+/// records the free in the host allocations and then calls `deallocate`.
+///
+/// Frees that don't call `deallocate` directly, e.g. through a table or straight into the allocator of the
+/// guest, stay unseen.
+#[derive(Debug, Default)]
+pub struct TrackDeallocations {
+    indexes: Mutex<Option<DeallocationIndexes>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DeallocationIndexes {
+    /// The index of the `DEALLOCATE_IMPORT` function.
+    import: u32,
+    /// The index of the `deallocate` export, if the module has one.
+    deallocate: Option<u32>,
+    num_imported_functions: u32,
+}
+
+impl ModuleMiddleware for TrackDeallocations {
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        let Some(indexes) = self.indexes.lock().ok().and_then(|indexes| *indexes) else {
+            return Box::new(Untransformed {
+                middleware: "TrackDeallocations",
+            });
+        };
+
+        // `deallocate` itself keeps calling the function it is, which the host function calls in the end.
+        let function_index = indexes.num_imported_functions + local_function_index.as_u32();
+        Box::new(FunctionTrackDeallocations {
+            import: indexes.import,
+            deallocate: indexes
+                .deallocate
+                .filter(|deallocate| *deallocate != function_index),
+        })
+    }
+
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let import = insert_function_import(
+            module_info,
+            DEALLOCATE_IMPORT,
+            FunctionType::new(vec![Type::I32], vec![]),
+        );
+
+        let deallocate = match module_info.exports.get("deallocate") {
+            Some(ExportIndex::Function(index)) => Some(index.as_u32()),
+            _ => None,
+        };
+
+        if let Ok(mut indexes) = self.indexes.lock() {
+            *indexes = Some(DeallocationIndexes {
+                import: import.as_u32(),
+                deallocate,
+                num_imported_functions: module_info.num_imported_functions as u32,
+            });
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FunctionTrackDeallocations {
+    import: u32,
+    /// The index of the `deallocate` export, unless this function is `deallocate`.
+    deallocate: Option<u32>,
+}
+
+impl FunctionMiddleware for FunctionTrackDeallocations {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        let operator = match shift_operator(operator, self.import) {
+            Operator::Call { function_index } if Some(function_index) == self.deallocate => {
+                Operator::Call {
+                    function_index: self.import,
+                }
+            }
+            Operator::ReturnCall { function_index } if Some(function_index) == self.deallocate => {
+                Operator::ReturnCall {
+                    function_index: self.import,
+                }
+            }
+            operator => operator,
+        };
+        state.push_operator(operator);
+
+        Ok(())
+    }
+}
+
+/// The function environment of the `DEALLOCATE_IMPORT` host function.
+#[derive(Clone, Default)]
+pub(crate) struct DeallocationEnv {
+    pub(crate) host_allocations: HostAllocations,
+    /// The `deallocate` export of the WASM (guest) instance, set once it is instantiated.
+    pub(crate) deallocate: Option<Function>,
+}
+
+/// The host function that the `TrackDeallocations` middleware redirects calls to `deallocate` to.
+/// Forgets the `MemorySlice` if the host allocated it, then frees it.
+pub(crate) fn track_deallocate(
+    mut env: FunctionEnvMut<DeallocationEnv>,
+    memory_slice_ptr: MemorySlicePtr,
+) -> Result<(), RuntimeError> {
+    let (deallocation_env, mut store) = env.data_and_store_mut();
+    deallocation_env.host_allocations.release(memory_slice_ptr);
+
+    let deallocate = deallocation_env
+        .deallocate
+        .clone()
+        .ok_or_else(|| RuntimeError::new("ramd::VM: `deallocate` is missing"))?;
+    deallocate.call(&mut store, &[memory_slice_ptr.into()])?;
+
+    Ok(())
+}
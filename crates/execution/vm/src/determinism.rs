@@ -0,0 +1,77 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{VmError, HOST_IMPORT_MODULE};
+use serde::{Deserialize, Serialize};
+use wasmparser::{Parser, Payload, TypeRef, Validator, WasmFeatures};
+
+/// How the VM deals with floating-point operations, whose NaN results differ across platforms.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FloatPolicy {
+    /// Allow floating-point operations, and replace every NaN they produce with the canonical NaN.
+    #[default]
+    Canonicalize,
+    /// Reject WASM modules that use floating-point types or operations.
+    Reject,
+}
+
+/// The WASM features that a live object may use. Threads and SIMD are left out, as the results
+/// of shared memory races and of some SIMD operations differ across platforms.
+pub fn deterministic_features(float_policy: FloatPolicy) -> WasmFeatures {
+    WasmFeatures {
+        threads: false,
+        simd: false,
+        relaxed_simd: false,
+        floats: float_policy == FloatPolicy::Canonicalize,
+        ..WasmFeatures::default()
+    }
+}
+
+/// Check that the WASM module only uses deterministic features, and that it only imports host functions.
+/// Imports from other namespaces, e.g. WASI clocks and random numbers, would differ across replicas.
+pub fn validate_determinism(wasm_bytes: &[u8], float_policy: FloatPolicy) -> Result<(), VmError> {
+    Validator::new_with_features(deterministic_features(float_policy))
+        .validate_all(wasm_bytes)
+        .map_err(|err| VmError::InvalidModule(err.to_string()))?;
+
+    for payload in Parser::new(0).parse_all(wasm_bytes) {
+        let Payload::ImportSection(reader) =
+            payload.map_err(|err| VmError::InvalidModule(err.to_string()))?
+        else {
+            continue;
+        };
+
+        for import in reader {
+            let import = import.map_err(|err| VmError::InvalidModule(err.to_string()))?;
+
+            if import.module != HOST_IMPORT_MODULE {
+                return Err(VmError::InvalidModule(format!(
+                    "Import `{}::{}` is not a host function of the `{}` namespace",
+                    import.module, import.name, HOST_IMPORT_MODULE
+                )));
+            }
+
+            if !matches!(import.ty, TypeRef::Func(_)) {
+                return Err(VmError::InvalidModule(format!(
+                    "Import `{}::{}` is not a function",
+                    import.module, import.name
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
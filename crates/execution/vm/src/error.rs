@@ -0,0 +1,143 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{InterruptReason, MemorySliceError};
+use wasmer::RuntimeError;
+
+/// Errors of the VM. Host functions return them to the WASM instance as traps,
+/// and the `Runtime` recovers them from the traps so callers see the original error.
+#[derive(Debug, Clone)]
+pub enum VmError {
+    /// The WASM module failed to compile.
+    Compile(String),
+    /// The WASM module is invalid, or uses a feature or an import that isn't deterministic.
+    InvalidModule(String),
+    /// The WASM module failed to instantiate, e.g. because it imports an unknown host function.
+    Instantiate(String),
+    /// The WASM module doesn't export an item that the VM needs.
+    MissingExport(String),
+    /// An exported function doesn't have the signature that the VM expects.
+    BadSignature {
+        name: String,
+        expected: String,
+        found: String,
+    },
+    /// The WASM instance trapped. The backtrace lists the WASM frames, innermost first.
+    Trap {
+        message: String,
+        backtrace: Vec<String>,
+    },
+    /// The WASM instance failed to allocate memory for data from the host.
+    AllocationFailed { len: usize, reason: String },
+    /// The storage failed.
+    Storage(String),
+    /// A read-only method of the live object with this ID tried to write the storage.
+    ReadOnly(String),
+    /// A `MemorySlice` of the WASM (guest) memory is invalid.
+    Memory(MemorySliceError),
+    /// A function returned something other than expected.
+    InvalidResult(String),
+    /// The execution exceeded a limit.
+    LimitExceeded(String),
+    /// The execution was interrupted.
+    Interrupted(InterruptReason),
+}
+
+impl std::fmt::Display for VmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VmError::Compile(reason) => write!(f, "Failed to compile the WASM module: {}", reason),
+            VmError::InvalidModule(reason) => write!(f, "Invalid WASM module: {}", reason),
+            VmError::Instantiate(reason) => {
+                write!(f, "Failed to instantiate the WASM module: {}", reason)
+            }
+            VmError::MissingExport(name) => write!(f, "Missing `{}` export", name),
+            VmError::BadSignature {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Export `{}` has signature `{}`, expected `{}`",
+                name, found, expected
+            ),
+            VmError::Trap { message, backtrace } => {
+                write!(f, "WASM trap: {}", message)?;
+                for frame in backtrace {
+                    write!(f, "\n    at {}", frame)?;
+                }
+                Ok(())
+            }
+            VmError::AllocationFailed { len, reason } => {
+                write!(f, "Failed to allocate {} bytes: {}", len, reason)
+            }
+            VmError::Storage(reason) => write!(f, "Storage error: {}", reason),
+            VmError::ReadOnly(live_object_id) => write!(
+                f,
+                "Read-only method of live object `{}` tried to write the storage",
+                live_object_id
+            ),
+            VmError::Memory(err) => write!(f, "Memory error: {}", err),
+            VmError::InvalidResult(reason) => write!(f, "Invalid result: {}", reason),
+            VmError::LimitExceeded(reason) => write!(f, "Limit exceeded: {}", reason),
+            VmError::Interrupted(reason) => write!(f, "Execution is interrupted: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+impl From<MemorySliceError> for VmError {
+    fn from(err: MemorySliceError) -> Self {
+        VmError::Memory(err)
+    }
+}
+
+impl From<VmError> for RuntimeError {
+    fn from(err: VmError) -> Self {
+        RuntimeError::user(Box::new(err))
+    }
+}
+
+impl From<RuntimeError> for VmError {
+    fn from(err: RuntimeError) -> Self {
+        // Errors of host functions travel through the WASM instance as user errors.
+        if let Some(err) = err.downcast_ref::<VmError>() {
+            return err.clone();
+        }
+
+        let backtrace = err
+            .trace()
+            .iter()
+            .map(|frame| {
+                let function = match frame.function_name() {
+                    Some(name) => name.to_string(),
+                    None => format!("<func {}>", frame.func_index()),
+                };
+                format!(
+                    "{} ({}:0x{:x})",
+                    function,
+                    frame.module_name(),
+                    frame.module_offset()
+                )
+            })
+            .collect();
+
+        VmError::Trap {
+            message: err.message(),
+            backtrace,
+        }
+    }
+}
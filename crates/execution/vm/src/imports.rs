@@ -13,36 +13,100 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::{Context, MemorySlicePtr};
+use crate::{
+    ed25519_verify, keccak256, secp256k1_recover, sha256, Context, MemorySlicePtr, VmError,
+    ED25519_VERIFY_BASE_COST, ED25519_VERIFY_WORD_COST, HOST_IMPORT_MODULE, KECCAK256_BASE_COST,
+    KECCAK256_WORD_COST, SECP256K1_RECOVER_COST, SHA256_BASE_COST, SHA256_WORD_COST,
+};
 use ramd_db::storage::Storage;
-use wasmer::{imports, AsStoreMut, Function, FunctionEnv, FunctionEnvMut, Imports};
+use wasmer::{imports, AsStoreMut, Function, FunctionEnv, FunctionEnvMut, Imports, StoreMut};
+
+/// A set of host functions that WASM instances can import.
+pub trait HostFunctions<S>
+where
+    S: Storage<Vec<u8>, Vec<u8>> + 'static,
+{
+    /// Define the host functions in the imports.
+    fn define(
+        &self,
+        store: &mut StoreMut,
+        function_env: &FunctionEnv<Context<S>>,
+        imports: &mut Imports,
+    );
+}
 
 /// The import object that has import functions.
 pub struct ImportObject(pub Imports);
 
 impl ImportObject {
-    /// Create a new `ImportObject`.
-    pub fn new<S>(mut store: &mut impl AsStoreMut, function_env: &FunctionEnv<Context<S>>) -> Self
+    /// Create a new `ImportObject` with the default host functions.
+    pub fn new<S>(store: &mut impl AsStoreMut, function_env: &FunctionEnv<Context<S>>) -> Self
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + 'static,
+    {
+        Self::with_host_functions(store, function_env, &[&DefaultHostFunctions])
+    }
+
+    /// Create a new `ImportObject` with the given sets of host functions.
+    /// A host function replaces the one with the same name in an earlier set.
+    pub fn with_host_functions<S>(
+        store: &mut impl AsStoreMut,
+        function_env: &FunctionEnv<Context<S>>,
+        host_functions: &[&dyn HostFunctions<S>],
+    ) -> Self
     where
         S: Storage<Vec<u8>, Vec<u8>> + 'static,
     {
-        let import_object = imports! {
-            "env" => {
+        let mut store = store.as_store_mut();
+        let mut imports = Imports::new();
+
+        for host_functions in host_functions {
+            host_functions.define(&mut store, function_env, &mut imports);
+        }
+
+        ImportObject(imports)
+    }
+}
+
+/// The host functions of live objects: storage access, the message being executed and cryptography.
+pub struct DefaultHostFunctions;
+
+impl<S> HostFunctions<S> for DefaultHostFunctions
+where
+    S: Storage<Vec<u8>, Vec<u8>> + 'static,
+{
+    fn define(
+        &self,
+        mut store: &mut StoreMut,
+        function_env: &FunctionEnv<Context<S>>,
+        imports: &mut Imports,
+    ) {
+        imports.extend(&imports! {
+            HOST_IMPORT_MODULE => {
                 "storage_has" => Function::new_typed_with_env(&mut store, function_env, Self::storage_has),
                 "storage_read" => Function::new_typed_with_env(&mut store, function_env, Self::storage_read),
+                "storage_read_into" => Function::new_typed_with_env(&mut store, function_env, Self::storage_read_into),
                 "storage_write" => Function::new_typed_with_env(&mut store, function_env, Self::storage_write),
                 "storage_delete" => Function::new_typed_with_env(&mut store, function_env, Self::storage_delete),
+                "message_timestamp" => Function::new_typed_with_env(&mut store, function_env, Self::message_timestamp),
+                "message_id" => Function::new_typed_with_env(&mut store, function_env, Self::message_id),
+                "live_object_id" => Function::new_typed_with_env(&mut store, function_env, Self::live_object_id),
+                "random_seed" => Function::new_typed_with_env(&mut store, function_env, Self::random_seed),
+                "keccak256" => Function::new_typed_with_env(&mut store, function_env, Self::keccak256),
+                "sha256" => Function::new_typed_with_env(&mut store, function_env, Self::sha256),
+                "ed25519_verify" => Function::new_typed_with_env(&mut store, function_env, Self::ed25519_verify),
+                "secp256k1_recover" => Function::new_typed_with_env(&mut store, function_env, Self::secp256k1_recover),
             }
-        };
-
-        ImportObject(import_object)
+        });
     }
+}
 
+impl DefaultHostFunctions {
     /// Check if data corresponding to the key exists in the storage.
     fn storage_has<S>(
         mut env: FunctionEnvMut<Context<S>>,
         key_ptr: MemorySlicePtr,
-    ) -> eyre::Result<u32, wasmer::RuntimeError>
+    ) -> Result<u32, VmError>
     where
         S: Storage<Vec<u8>, Vec<u8>> + 'static,
     {
@@ -53,7 +117,7 @@ impl ImportObject {
         let has = context
             .storage
             .has(context.prefix_key(key))
-            .map_err(|err| wasmer::RuntimeError::new(err.to_string()))?;
+            .map_err(|err| VmError::Storage(err.to_string()))?;
 
         Ok(has as u32)
     }
@@ -62,7 +126,7 @@ impl ImportObject {
     fn storage_read<S>(
         mut env: FunctionEnvMut<Context<S>>,
         key_ptr: MemorySlicePtr,
-    ) -> eyre::Result<MemorySlicePtr, wasmer::RuntimeError>
+    ) -> Result<MemorySlicePtr, VmError>
     where
         S: Storage<Vec<u8>, Vec<u8>> + 'static,
     {
@@ -73,35 +137,62 @@ impl ImportObject {
         let value = context
             .storage
             .get(context.prefix_key(key))
-            .map_err(|err| wasmer::RuntimeError::new(err.to_string()))?;
+            .map_err(|err| VmError::Storage(err.to_string()))?;
+
+        context.allocate_and_write_memory(&mut store, &value)
+    }
+
+    /// Read data from the storage into a `MemorySlice` provided by the guest, without allocating guest memory.
+    /// Returns the length of the value, or -1 if the key doesn't exist. The value is written only if it fits
+    /// in the buffer, so the guest can retry with a larger buffer if the returned length exceeds its buffer.
+    fn storage_read_into<S>(
+        mut env: FunctionEnvMut<Context<S>>,
+        key_ptr: MemorySlicePtr,
+        buffer_ptr: MemorySlicePtr,
+    ) -> Result<i64, VmError>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + 'static,
+    {
+        let (context, store) = env.data_and_store_mut();
+
+        let key = context.read_memory(&store, key_ptr)?;
+
+        let value = context
+            .storage
+            .get_opt(context.prefix_key(key))
+            .map_err(|err| VmError::Storage(err.to_string()))?;
 
-        let value_ptr = context
-            .allocate_memory(&mut store, value.len())
-            .map_err(|err| wasmer::RuntimeError::new(err.to_string()))?;
+        let Some(value) = value else {
+            return Ok(-1);
+        };
 
-        context.write_memory(&store, value_ptr, &value)?;
+        if value.len() <= context.memory_slice_len(&store, buffer_ptr)? {
+            context.write_memory(&store, buffer_ptr, &value)?;
+        }
 
-        Ok(value_ptr)
+        Ok(value.len() as i64)
     }
 
-    /// Write data to the storage.
+    /// Write data to the storage. Traps if it grows the live object past its storage quota.
     fn storage_write<S>(
         mut env: FunctionEnvMut<Context<S>>,
         key_ptr: MemorySlicePtr,
         value_ptr: MemorySlicePtr,
-    ) -> eyre::Result<(), wasmer::RuntimeError>
+    ) -> Result<(), VmError>
     where
         S: Storage<Vec<u8>, Vec<u8>> + 'static,
     {
         let (context, store) = env.data_and_store_mut();
+        context.check_writable()?;
 
-        let key = context.read_memory(&store, key_ptr)?;
+        let key = context.prefix_key(context.read_memory(&store, key_ptr)?);
         let value = context.read_memory(&store, value_ptr)?;
+        context.charge_storage(&key, Some(&value))?;
 
         context
             .storage
-            .set(context.prefix_key(key), value)
-            .map_err(|err| wasmer::RuntimeError::new(err.to_string()))?;
+            .set(key, value)
+            .map_err(|err| VmError::Storage(err.to_string()))?;
 
         Ok(())
     }
@@ -110,19 +201,140 @@ impl ImportObject {
     fn storage_delete<S>(
         mut env: FunctionEnvMut<Context<S>>,
         key_ptr: MemorySlicePtr,
-    ) -> eyre::Result<(), wasmer::RuntimeError>
+    ) -> Result<(), VmError>
     where
         S: Storage<Vec<u8>, Vec<u8>> + 'static,
     {
         let (context, store) = env.data_and_store_mut();
+        context.check_writable()?;
 
-        let key = context.read_memory(&store, key_ptr)?;
+        let key = context.prefix_key(context.read_memory(&store, key_ptr)?);
+        context.charge_storage(&key, None)?;
 
         context
             .storage
-            .delete(context.prefix_key(key))
-            .map_err(|err| wasmer::RuntimeError::new(err.to_string()))?;
+            .delete(key)
+            .map_err(|err| VmError::Storage(err.to_string()))?;
 
         Ok(())
     }
+
+    /// Get the timestamp of the message being executed, in milliseconds since the Unix epoch.
+    fn message_timestamp<S>(env: FunctionEnvMut<Context<S>>) -> Result<u64, VmError>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + 'static,
+    {
+        Ok(env.data().message.timestamp)
+    }
+
+    /// Get the ID of the message being executed.
+    fn message_id<S>(mut env: FunctionEnvMut<Context<S>>) -> Result<MemorySlicePtr, VmError>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + 'static,
+    {
+        let (context, mut store) = env.data_and_store_mut();
+
+        context.allocate_and_write_memory(&mut store, context.message.id.as_bytes())
+    }
+
+    /// Get the ID of the live object being executed.
+    fn live_object_id<S>(mut env: FunctionEnvMut<Context<S>>) -> Result<MemorySlicePtr, VmError>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + 'static,
+    {
+        let (context, mut store) = env.data_and_store_mut();
+
+        context.allocate_and_write_memory(&mut store, context.live_object_id.as_bytes())
+    }
+
+    /// Get a 32-byte pseudo-random seed derived from the ID of the message being executed.
+    fn random_seed<S>(mut env: FunctionEnvMut<Context<S>>) -> Result<MemorySlicePtr, VmError>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + 'static,
+    {
+        let (context, mut store) = env.data_and_store_mut();
+
+        context.allocate_and_write_memory(&mut store, &context.message.random_seed())
+    }
+
+    /// Hash the data with Keccak-256.
+    fn keccak256<S>(
+        mut env: FunctionEnvMut<Context<S>>,
+        data_ptr: MemorySlicePtr,
+    ) -> Result<MemorySlicePtr, VmError>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + 'static,
+    {
+        let (context, mut store) = env.data_and_store_mut();
+
+        let data = context.read_memory(&store, data_ptr)?;
+        context.charge(KECCAK256_BASE_COST, KECCAK256_WORD_COST, data.len())?;
+
+        context.allocate_and_write_memory(&mut store, &keccak256(&data))
+    }
+
+    /// Hash the data with SHA-256.
+    fn sha256<S>(
+        mut env: FunctionEnvMut<Context<S>>,
+        data_ptr: MemorySlicePtr,
+    ) -> Result<MemorySlicePtr, VmError>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + 'static,
+    {
+        let (context, mut store) = env.data_and_store_mut();
+
+        let data = context.read_memory(&store, data_ptr)?;
+        context.charge(SHA256_BASE_COST, SHA256_WORD_COST, data.len())?;
+
+        context.allocate_and_write_memory(&mut store, &sha256(&data))
+    }
+
+    /// Verify the Ed25519 signature of the message. Returns 1 if the signature is valid, and 0 otherwise.
+    fn ed25519_verify<S>(
+        mut env: FunctionEnvMut<Context<S>>,
+        signature_ptr: MemorySlicePtr,
+        message_ptr: MemorySlicePtr,
+        public_key_ptr: MemorySlicePtr,
+    ) -> Result<u32, VmError>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + 'static,
+    {
+        let (context, store) = env.data_and_store_mut();
+
+        let signature = context.read_memory(&store, signature_ptr)?;
+        let message = context.read_memory(&store, message_ptr)?;
+        let public_key = context.read_memory(&store, public_key_ptr)?;
+        context.charge(
+            ED25519_VERIFY_BASE_COST,
+            ED25519_VERIFY_WORD_COST,
+            message.len(),
+        )?;
+
+        Ok(ed25519_verify(&signature, &message, &public_key) as u32)
+    }
+
+    /// Recover the uncompressed secp256k1 public key that signed the message hash.
+    /// Returns an empty `MemorySlice` if the public key can't be recovered.
+    fn secp256k1_recover<S>(
+        mut env: FunctionEnvMut<Context<S>>,
+        message_hash_ptr: MemorySlicePtr,
+        signature_ptr: MemorySlicePtr,
+        recovery_id: u32,
+    ) -> Result<MemorySlicePtr, VmError>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + 'static,
+    {
+        let (context, mut store) = env.data_and_store_mut();
+
+        let message_hash = context.read_memory(&store, message_hash_ptr)?;
+        let signature = context.read_memory(&store, signature_ptr)?;
+        context.charge(SECP256K1_RECOVER_COST, 0, 0)?;
+
+        let public_key = u8::try_from(recovery_id)
+            .ok()
+            .and_then(|recovery_id| secp256k1_recover(&message_hash, &signature, recovery_id))
+            .unwrap_or_default();
+
+        context.allocate_and_write_memory(&mut store, &public_key)
+    }
 }
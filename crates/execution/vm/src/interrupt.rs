@@ -0,0 +1,347 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{insert_function_import, shift_operator, Untransformed, VmError};
+use wasmer::wasmparser::{BlockType, Operator};
+use wasmer::{
+    FunctionEnvMut, FunctionMiddleware, FunctionType, GlobalInit, GlobalType, LocalFunctionIndex,
+    MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Mutability, RuntimeError, Type,
+};
+use wasmer_types::ModuleInfo;
+
+/// The name of the `VM_IMPORT_MODULE` host function that the WASM (guest) instance calls to learn whether it must stop.
+pub const INTERRUPT_IMPORT: &str = "check_interrupt";
+
+/// The number of function calls and loop iterations between two checks of the interrupt handle.
+pub const INTERRUPT_CHECK_INTERVAL: i32 = 10_000;
+
+const NOT_INTERRUPTED: u8 = 0;
+
+/// The reason why an execution is interrupted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptReason {
+    Timeout,
+    Cancelled,
+}
+
+impl InterruptReason {
+    fn to_u8(self) -> u8 {
+        match self {
+            InterruptReason::Timeout => 1,
+            InterruptReason::Cancelled => 2,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(InterruptReason::Timeout),
+            2 => Some(InterruptReason::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for InterruptReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// A middleware that makes the WASM (guest) instance call the `INTERRUPT_IMPORT` host function every
+/// `INTERRUPT_CHECK_INTERVAL` function calls and loop iterations. The host function traps once the interrupt
+/// handle of the instance is set, which lets the host stop a running instance from another thread.
+///
+/// The host function is added as the last function import, so the indexes of the functions defined by the
+/// module move up by one, and every reference to them is moved along.
+#[derive(Debug, Default)]
+pub struct Interrupt {
+    indexes: Mutex<Option<InterruptIndexes>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct InterruptIndexes {
+    /// The index of the `INTERRUPT_IMPORT` function, which was the index of the first function defined by the module.
+    check: u32,
+    /// A global counting down to the next check.
+    countdown: u32,
+}
+
+impl ModuleMiddleware for Interrupt {
+    fn generate_function_middleware(
+        &self,
+        _local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        let Some(indexes) = self.indexes.lock().ok().and_then(|indexes| *indexes) else {
+            return Box::new(Untransformed {
+                middleware: "Interrupt",
+            });
+        };
+
+        Box::new(FunctionInterrupt {
+            indexes,
+            entered: false,
+        })
+    }
+
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let check = insert_function_import(
+            module_info,
+            INTERRUPT_IMPORT,
+            FunctionType::new(vec![], vec![]),
+        );
+
+        let countdown = module_info
+            .globals
+            .push(GlobalType::new(Type::I32, Mutability::Var));
+        module_info
+            .global_initializers
+            .push(GlobalInit::I32Const(INTERRUPT_CHECK_INTERVAL));
+
+        if let Ok(mut indexes) = self.indexes.lock() {
+            *indexes = Some(InterruptIndexes {
+                check: check.as_u32(),
+                countdown: countdown.as_u32(),
+            });
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FunctionInterrupt {
+    indexes: InterruptIndexes,
+    entered: bool,
+}
+
+impl FunctionInterrupt {
+    /// Count down, and call the host to check the interrupt handle once the countdown runs out.
+    fn count_down(&self, state: &mut MiddlewareReaderState<'_>) {
+        let countdown = self.indexes.countdown;
+        state.extend(&[
+            Operator::GlobalGet {
+                global_index: countdown,
+            },
+            Operator::I32Const { value: 1 },
+            Operator::I32Sub,
+            Operator::GlobalSet {
+                global_index: countdown,
+            },
+            Operator::GlobalGet {
+                global_index: countdown,
+            },
+            Operator::I32Eqz,
+            Operator::If {
+                blockty: BlockType::Empty,
+            },
+            Operator::I32Const {
+                value: INTERRUPT_CHECK_INTERVAL,
+            },
+            Operator::GlobalSet {
+                global_index: countdown,
+            },
+            Operator::Call {
+                function_index: self.indexes.check,
+            },
+            Operator::End,
+        ]);
+    }
+}
+
+impl FunctionMiddleware for FunctionInterrupt {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        if !self.entered {
+            self.entered = true;
+            self.count_down(state);
+        }
+
+        let is_loop = matches!(operator, Operator::Loop { .. });
+        state.push_operator(shift_operator(operator, self.indexes.check));
+
+        if is_loop {
+            self.count_down(state);
+        }
+
+        Ok(())
+    }
+}
+
+/// The host function that the `Interrupt` middleware makes the WASM (guest) instance call. Traps if the
+/// execution is interrupted.
+pub(crate) fn check_interrupt(env: FunctionEnvMut<InterruptHandle>) -> Result<(), RuntimeError> {
+    match env.data().reason() {
+        Some(reason) => Err(RuntimeError::new(format!(
+            "ramd::VM: Execution is interrupted: {}",
+            reason
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// A handle that interrupts the execution of the runtime it is set on, from any thread.
+/// Once interrupted, the handle stays interrupted and any runtime it is set on refuses to run.
+#[derive(Clone, Default)]
+pub struct InterruptHandle {
+    reason: Arc<AtomicU8>,
+}
+
+impl InterruptHandle {
+    /// Create a new `InterruptHandle`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Interrupt the execution. Only the first reason is kept.
+    pub fn interrupt(&self, reason: InterruptReason) {
+        let _ = self.reason.compare_exchange(
+            NOT_INTERRUPTED,
+            reason.to_u8(),
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+    }
+
+    /// The reason of the interruption, if interrupted.
+    pub fn reason(&self) -> Option<InterruptReason> {
+        InterruptReason::from_u8(self.reason.load(Ordering::SeqCst))
+    }
+
+    /// Interrupt the execution with `InterruptReason::Timeout` once `timeout` has passed, unless the returned
+    /// guard is dropped first.
+    pub(crate) fn interrupt_after(&self, timeout: Duration) -> Result<Deadline, VmError> {
+        Watchdog::get()?.schedule(Instant::now() + timeout, self.clone())
+    }
+}
+
+/// The deadlines of all running executions, watched by a single thread that interrupts the ones that pass.
+struct Watchdog {
+    deadlines: Mutex<Deadlines>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct Deadlines {
+    /// Keyed by the deadline and a sequence number that tells apart equal deadlines.
+    pending: BTreeMap<(Instant, u64), InterruptHandle>,
+    next_sequence: u64,
+}
+
+impl Watchdog {
+    /// The watchdog shared by all runtimes, started on first use.
+    fn get() -> Result<&'static Arc<Watchdog>, VmError> {
+        static WATCHDOG: OnceLock<Result<Arc<Watchdog>, String>> = OnceLock::new();
+
+        WATCHDOG
+            .get_or_init(|| {
+                let watchdog = Arc::new(Watchdog {
+                    deadlines: Mutex::new(Deadlines::default()),
+                    changed: Condvar::new(),
+                });
+
+                let thread_watchdog = watchdog.clone();
+                thread::Builder::new()
+                    .name("ramd-vm-watchdog".to_string())
+                    .spawn(move || thread_watchdog.watch())
+                    .map_err(|err| err.to_string())?;
+
+                Ok(watchdog)
+            })
+            .as_ref()
+            .map_err(|err| VmError::Instantiate(format!("Failed to start the watchdog: {}", err)))
+    }
+
+    fn schedule(
+        self: &Arc<Self>,
+        deadline: Instant,
+        interrupt_handle: InterruptHandle,
+    ) -> Result<Deadline, VmError> {
+        let mut deadlines = self
+            .deadlines
+            .lock()
+            .map_err(|err| VmError::Instantiate(err.to_string()))?;
+
+        let key = (deadline, deadlines.next_sequence);
+        deadlines.next_sequence += 1;
+        deadlines.pending.insert(key, interrupt_handle);
+
+        // Wake the watchdog up if the new deadline is the earliest one.
+        if deadlines.pending.keys().next() == Some(&key) {
+            self.changed.notify_one();
+        }
+
+        Ok(Deadline {
+            watchdog: self.clone(),
+            key,
+        })
+    }
+
+    /// Interrupt the executions whose deadline passed, sleeping until the next deadline in between.
+    fn watch(&self) {
+        let Ok(mut deadlines) = self.deadlines.lock() else {
+            return;
+        };
+
+        loop {
+            let now = Instant::now();
+            while let Some(entry) = deadlines.pending.first_entry() {
+                if entry.key().0 > now {
+                    break;
+                }
+                entry.remove().interrupt(InterruptReason::Timeout);
+            }
+
+            let next = deadlines
+                .pending
+                .keys()
+                .next()
+                .map(|(deadline, _)| *deadline);
+            deadlines = match next {
+                Some(deadline) => match self
+                    .changed
+                    .wait_timeout(deadlines, deadline.saturating_duration_since(now))
+                {
+                    Ok((deadlines, _)) => deadlines,
+                    Err(_) => return,
+                },
+                None => match self.changed.wait(deadlines) {
+                    Ok(deadlines) => deadlines,
+                    Err(_) => return,
+                },
+            };
+        }
+    }
+}
+
+/// A deadline watched by the watchdog. Dropping it stops watching.
+pub(crate) struct Deadline {
+    watchdog: Arc<Watchdog>,
+    key: (Instant, u64),
+}
+
+impl Drop for Deadline {
+    fn drop(&mut self) {
+        if let Ok(mut deadlines) = self.watchdog.deadlines.lock() {
+            deadlines.pending.remove(&self.key);
+        }
+    }
+}
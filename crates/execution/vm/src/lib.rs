@@ -13,16 +13,40 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+mod abi;
+mod builder;
+mod compiler;
 mod constants;
 mod context;
+mod crypto;
+mod deallocations;
+mod determinism;
+mod error;
 mod imports;
+mod interrupt;
 mod live_object_info;
 mod memory;
+mod middleware;
+mod pool;
 mod runtime;
+mod snapshot;
+mod tunables;
 
+pub use crate::abi::*;
+pub use crate::builder::*;
+pub use crate::compiler::*;
 pub use crate::constants::*;
 pub use crate::context::*;
+pub use crate::crypto::*;
+pub use crate::deallocations::*;
+pub use crate::determinism::*;
+pub use crate::error::*;
 pub use crate::imports::*;
+pub use crate::interrupt::*;
 pub use crate::live_object_info::*;
 pub use crate::memory::*;
+pub use crate::middleware::*;
+pub use crate::pool::*;
 pub use crate::runtime::*;
+pub use crate::snapshot::*;
+pub use crate::tunables::*;
@@ -13,43 +13,172 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use serde::{Deserialize, Serialize};
+use crate::Abi;
+use ramd_db::codec::{encode_field, Decoder};
+pub use ramd_db::keys::LIVE_OBJECT_CODE_KEY_PREFIX;
+use ramd_db::storage::Storage;
 use sha3::{Digest, Keccak256};
 
-#[derive(Serialize, Deserialize)]
+/// Version of the binary encoding of the metadata, written as its first byte.
+const METADATA_ENCODING_VERSION: u8 = 1;
+
+/// A live object and its code.
 pub struct LiveObjectInfo {
     pub id: String,
     pub hash: Vec<u8>,
     pub wasm_bytes: Vec<u8>,
+    /// The maximum size of the WASM (guest) memory in bytes. The operator's default applies if unset.
+    pub memory_limit: Option<usize>,
+    /// The methods of the live object, if its WASM module describes them.
+    pub abi: Option<Abi>,
 }
 
-impl TryInto<Vec<u8>> for LiveObjectInfo {
-    type Error = serde_json::Error;
-
-    fn try_into(self) -> Result<Vec<u8>, Self::Error> {
-        serde_json::to_vec(&self)
-    }
-}
-
-impl TryFrom<Vec<u8>> for LiveObjectInfo {
-    type Error = serde_json::Error;
-
-    fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
-        serde_json::from_slice(&bytes)
-    }
+/// Everything about a live object except its code.
+pub struct LiveObjectMetadata {
+    pub id: String,
+    pub hash: Vec<u8>,
+    /// The maximum size of the WASM (guest) memory in bytes. The operator's default applies if unset.
+    pub memory_limit: Option<usize>,
+    /// The methods of the live object, if its WASM module describes them.
+    pub abi: Option<Abi>,
 }
 
 impl LiveObjectInfo {
-    pub fn new(wasm_bytes: Vec<u8>) -> Self {
+    /// Create a new `LiveObjectInfo`, reading the ABI from the WASM module.
+    pub fn new(wasm_bytes: Vec<u8>, memory_limit: Option<usize>) -> eyre::Result<Self> {
         let hash = Keccak256::digest(&wasm_bytes).to_vec();
 
         // TODO: modify `id` to be defined when the LiveObject is instantiated.
         let id = hex::encode(hash.clone());
 
-        Self {
+        let abi = Abi::from_wasm(&wasm_bytes)?;
+
+        Ok(Self {
             id,
             hash,
             wasm_bytes,
+            memory_limit,
+            abi,
+        })
+    }
+
+    /// Read a live object and its code, or `None` if it doesn't exist.
+    pub fn load<S>(storage: &S, live_object_id: &str) -> eyre::Result<Option<Self>>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + ?Sized,
+    {
+        let Some(metadata) = LiveObjectMetadata::load(storage, live_object_id)? else {
+            return Ok(None);
+        };
+        let wasm_bytes = storage
+            .get_opt(code_key(live_object_id))?
+            .ok_or_else(|| eyre::eyre!("Code of live object `{}` is missing", live_object_id))?;
+
+        Ok(Some(Self {
+            id: metadata.id,
+            hash: metadata.hash,
+            wasm_bytes,
+            memory_limit: metadata.memory_limit,
+            abi: metadata.abi,
+        }))
+    }
+
+    /// Write the metadata and the code of the live object.
+    pub fn store<S>(self, storage: &S) -> eyre::Result<()>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + ?Sized,
+    {
+        let metadata = LiveObjectMetadata {
+            id: self.id,
+            hash: self.hash,
+            memory_limit: self.memory_limit,
+            abi: self.abi,
+        };
+
+        storage.set(code_key(&metadata.id), self.wasm_bytes)?;
+        storage.set(metadata.id.clone().into_bytes(), metadata.encode()?)
+    }
+}
+
+impl LiveObjectMetadata {
+    /// Read the metadata of a live object without its code, or `None` if it doesn't exist.
+    pub fn load<S>(storage: &S, live_object_id: &str) -> eyre::Result<Option<Self>>
+    where
+        S: Storage<Vec<u8>, Vec<u8>> + ?Sized,
+    {
+        storage
+            .get_opt(live_object_id.to_owned().into_bytes())?
+            .map(|bytes| {
+                Self::decode(&bytes).map_err(|err| {
+                    eyre::eyre!(
+                        "Malformed metadata of live object `{}`: {}",
+                        live_object_id,
+                        err
+                    )
+                })
+            })
+            .transpose()
+    }
+
+    /// The version byte, then the length-prefixed ID and hash, then the memory limit and the ABI,
+    /// each behind a presence byte. The ABI is small and nested, so it is kept as JSON.
+    fn encode(&self) -> eyre::Result<Vec<u8>> {
+        let mut bytes = vec![METADATA_ENCODING_VERSION];
+
+        encode_field(&mut bytes, self.id.as_bytes());
+        encode_field(&mut bytes, &self.hash);
+        match self.memory_limit {
+            Some(memory_limit) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&(memory_limit as u64).to_le_bytes());
+            }
+            None => bytes.push(0),
         }
+        match &self.abi {
+            Some(abi) => {
+                bytes.push(1);
+                encode_field(&mut bytes, &serde_json::to_vec(abi)?);
+            }
+            None => bytes.push(0),
+        }
+
+        Ok(bytes)
     }
+
+    fn decode(bytes: &[u8]) -> eyre::Result<Self> {
+        let mut decoder = Decoder::new(bytes);
+
+        let version = decoder.byte()?;
+        if version != METADATA_ENCODING_VERSION {
+            return Err(eyre::eyre!(
+                "Unsupported live object metadata encoding version {}",
+                version
+            ));
+        }
+
+        let id = decoder.string_field()?;
+        let hash = decoder.field()?.to_vec();
+        let memory_limit = match decoder.byte()? {
+            0 => None,
+            _ => Some(usize::try_from(decoder.u64()?)?),
+        };
+        let abi = match decoder.byte()? {
+            0 => None,
+            _ => Some(serde_json::from_slice(decoder.field()?)?),
+        };
+        decoder.finish()?;
+
+        Ok(Self {
+            id,
+            hash,
+            memory_limit,
+            abi,
+        })
+    }
+}
+
+fn code_key(live_object_id: &str) -> Vec<u8> {
+    let mut key = LIVE_OBJECT_CODE_KEY_PREFIX.to_vec();
+    key.extend_from_slice(live_object_id.as_bytes());
+    key
 }
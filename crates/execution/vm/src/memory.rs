@@ -13,12 +13,14 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::BTreeSet;
 use std::mem::size_of;
+use std::sync::{Arc, Mutex};
 
-use crate::MAX_WASM_MEMORY_SIZE;
+use crate::VmError;
 use wasmer::{MemoryAccessError, WasmPtr};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum MemorySliceError {
     ExceedMaxWASMMemorySize,
     ExceedMemorySliceSize,
@@ -37,7 +39,7 @@ impl std::error::Error for MemorySliceError {}
 
 impl From<MemorySliceError> for wasmer::RuntimeError {
     fn from(err: MemorySliceError) -> Self {
-        wasmer::RuntimeError::new(err.to_string())
+        VmError::Memory(err).into()
     }
 }
 
@@ -64,10 +66,10 @@ impl MemorySlice {
         let memory_slice_ptr_bytes = wasm_ptr
             .deref(memory)
             .read()
-            .map_err(|err| MemorySliceError::ReadError(err))?;
+            .map_err(MemorySliceError::ReadError)?;
         let memory_slice = MemorySlice::from_memory_slice_ptr_bytes(memory_slice_ptr_bytes);
 
-        MemorySlice::validate(&memory_slice)?;
+        MemorySlice::validate(&memory_slice, memory.data_size())?;
 
         Ok(memory_slice)
     }
@@ -86,7 +88,7 @@ impl MemorySlice {
 
         memory
             .read(self.ptr as u64, &mut data)
-            .map_err(|err| MemorySliceError::ReadError(err))?;
+            .map_err(MemorySliceError::ReadError)?;
 
         Ok(data)
     }
@@ -103,7 +105,7 @@ impl MemorySlice {
 
         memory
             .write(self.ptr as u64, data)
-            .map_err(|err| MemorySliceError::WriteError(err))?;
+            .map_err(MemorySliceError::WriteError)?;
 
         Ok(())
     }
@@ -116,16 +118,50 @@ impl MemorySlice {
         }
     }
 
-    /// Validate the memory slice.
-    fn validate(memory_slice: &MemorySlice) -> eyre::Result<(), MemorySliceError> {
+    /// Validate the memory slice against the current size of the WASM (guest) memory.
+    fn validate(
+        memory_slice: &MemorySlice,
+        memory_size: u64,
+    ) -> eyre::Result<(), MemorySliceError> {
         if memory_slice.ptr == 0 {
             return Err(MemorySliceError::NullPointer);
         }
 
-        if memory_slice.len > (MAX_WASM_MEMORY_SIZE as u32 - memory_slice.ptr) {
+        if memory_slice.ptr as u64 + memory_slice.len as u64 > memory_size {
             return Err(MemorySliceError::ExceedMaxWASMMemorySize);
         }
 
         Ok(())
     }
 }
+
+/// The `MemorySlice`s that the host allocated in the WASM (guest) memory during a call and that are still live.
+/// It is shared between the `Runtime`, the `Context` of host functions and the host function that `deallocate`
+/// calls are redirected to.
+#[derive(Debug, Clone, Default)]
+pub struct HostAllocations(Arc<Mutex<BTreeSet<MemorySlicePtr>>>);
+
+impl HostAllocations {
+    /// Record a `MemorySlice` allocated by the host.
+    pub fn record(&self, memory_slice_ptr: MemorySlicePtr) {
+        if let Ok(mut allocations) = self.0.lock() {
+            allocations.insert(memory_slice_ptr);
+        }
+    }
+
+    /// Forget a freed `MemorySlice`. Returns whether the host allocated it and it was still live.
+    pub fn release(&self, memory_slice_ptr: MemorySlicePtr) -> bool {
+        self.0
+            .lock()
+            .map(|mut allocations| allocations.remove(&memory_slice_ptr))
+            .unwrap_or_default()
+    }
+
+    /// Take the `MemorySlice`s that are still live, leaving none behind.
+    pub fn take(&self) -> Vec<MemorySlicePtr> {
+        self.0
+            .lock()
+            .map(|mut allocations| std::mem::take(&mut *allocations).into_iter().collect())
+            .unwrap_or_default()
+    }
+}
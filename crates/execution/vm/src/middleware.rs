@@ -0,0 +1,140 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use wasmer::wasmparser::Operator;
+use wasmer::{
+    FunctionMiddleware, FunctionType, GlobalInit, MiddlewareError, MiddlewareReaderState,
+};
+use wasmer_types::{
+    entity::PrimaryMap, ExportIndex, FunctionIndex, ImportIndex, ImportKey, ModuleInfo,
+    SignatureIndex,
+};
+
+/// The module of the host functions that middlewares make the WASM (guest) instance call.
+/// Live objects can only import from `env`, so it never clashes with their imports.
+pub const VM_IMPORT_MODULE: &str = "ramd";
+
+/// Add a function import of `VM_IMPORT_MODULE` after the other function imports and return its index.
+/// The indexes of the functions defined by the module move up by one, and every reference to them in the module
+/// info is moved along. References in function bodies must be moved with `shift_operator`.
+pub(crate) fn insert_function_import(
+    module_info: &mut ModuleInfo,
+    field: &str,
+    function_type: FunctionType,
+) -> FunctionIndex {
+    let inserted = FunctionIndex::from_u32(module_info.num_imported_functions as u32);
+    let shift = |index: FunctionIndex| {
+        FunctionIndex::from_u32(shift_function_index(index.as_u32(), inserted.as_u32()))
+    };
+
+    let signature = module_info.signatures.push(function_type);
+    let mut functions = PrimaryMap::<FunctionIndex, SignatureIndex>::new();
+    for (index, function_signature) in module_info.functions.iter() {
+        if index == inserted {
+            functions.push(signature);
+        }
+        functions.push(*function_signature);
+    }
+    if functions.len() == module_info.functions.len() {
+        functions.push(signature);
+    }
+    module_info.functions = functions;
+
+    for export in module_info.exports.values_mut() {
+        if let ExportIndex::Function(index) = export {
+            *index = shift(*index);
+        }
+    }
+    module_info.start_function = module_info.start_function.map(shift);
+    for table_initializer in module_info.table_initializers.iter_mut() {
+        for index in table_initializer.elements.iter_mut() {
+            *index = shift(*index);
+        }
+    }
+    for elements in module_info.passive_elements.values_mut() {
+        for index in elements.iter_mut() {
+            *index = shift(*index);
+        }
+    }
+    for global_initializer in module_info.global_initializers.values_mut() {
+        if let GlobalInit::RefFunc(index) = global_initializer {
+            *index = shift(*index);
+        }
+    }
+    module_info.function_names = module_info
+        .function_names
+        .drain()
+        .map(|(index, name)| (shift(index), name))
+        .collect();
+
+    let import_idx = module_info.imports.len() as u32;
+    module_info.imports.insert(
+        ImportKey {
+            module: VM_IMPORT_MODULE.to_string(),
+            field: field.to_string(),
+            import_idx,
+        },
+        ImportIndex::Function(inserted),
+    );
+    module_info.num_imported_functions += 1;
+
+    inserted
+}
+
+/// The index that a function has after a function import is inserted at `inserted`.
+pub(crate) fn shift_function_index(function_index: u32, inserted: u32) -> u32 {
+    if function_index >= inserted {
+        function_index + 1
+    } else {
+        function_index
+    }
+}
+
+/// Move the function indexes referenced by an operator to where they are after a function import is inserted
+/// at `inserted`.
+pub(crate) fn shift_operator(operator: Operator<'_>, inserted: u32) -> Operator<'_> {
+    match operator {
+        Operator::Call { function_index } => Operator::Call {
+            function_index: shift_function_index(function_index, inserted),
+        },
+        Operator::ReturnCall { function_index } => Operator::ReturnCall {
+            function_index: shift_function_index(function_index, inserted),
+        },
+        Operator::RefFunc { function_index } => Operator::RefFunc {
+            function_index: shift_function_index(function_index, inserted),
+        },
+        operator => operator,
+    }
+}
+
+/// A function middleware of a module middleware whose `transform_module_info` didn't run, which fails the
+/// compilation instead of emitting functions that reference indexes that don't exist.
+#[derive(Debug)]
+pub(crate) struct Untransformed {
+    pub(crate) middleware: &'static str,
+}
+
+impl FunctionMiddleware for Untransformed {
+    fn feed<'a>(
+        &mut self,
+        _operator: Operator<'a>,
+        _state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        Err(MiddlewareError::new(
+            self.middleware,
+            "the module info must be transformed before function middlewares are generated",
+        ))
+    }
+}
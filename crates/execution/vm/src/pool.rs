@@ -0,0 +1,279 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{
+    CompiledModule, CompilerBackend, Context, FloatPolicy, InstanceSnapshot, InterruptHandle,
+    LiveObjectInfo, MemoryLimits, MessageContext, Runtime, RuntimeBuilder, VmError,
+    DEFAULT_RUNTIME_POOL_IDLE_TIMEOUT_MS, DEFAULT_RUNTIME_POOL_SIZE,
+};
+use ramd_db::storage::Storage;
+use tracing::debug;
+use wasmer::FunctionEnv;
+
+/// How many idle runtimes a `RuntimePool` keeps per live object, and how long it keeps them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuntimePoolConfig {
+    /// The maximum number of idle runtimes per live object. Zero disables pooling.
+    pub size: usize,
+    /// Idle runtimes are dropped once they haven't been used for this long.
+    pub idle_timeout: Duration,
+}
+
+impl Default for RuntimePoolConfig {
+    fn default() -> Self {
+        Self {
+            size: DEFAULT_RUNTIME_POOL_SIZE,
+            idle_timeout: Duration::from_millis(DEFAULT_RUNTIME_POOL_IDLE_TIMEOUT_MS),
+        }
+    }
+}
+
+/// A pool of runtimes, keyed by live object ID and memory limit, that saves compiling and instantiating the WASM module
+/// on every message. A runtime is reset to its state right after instantiation before it is reused,
+/// so a message can't observe anything left behind by an earlier one. Runtimes whose memory or tables
+/// have grown, whose memory is too large to copy cheaply, or whose module has passive segments can't be
+/// reset, so the pool also keeps the compiled module to instantiate fresh ones quickly.
+pub struct RuntimePool<S>
+where
+    S: Storage<Vec<u8>, Vec<u8>> + 'static,
+{
+    config: RuntimePoolConfig,
+    memory_limits: MemoryLimits,
+    compiler: CompilerBackend,
+    float_policy: FloatPolicy,
+    precompiled_dir: Option<PathBuf>,
+    storage_quota: Option<u64>,
+    /// The pooled live objects by ID and memory limit, as the engine of a compiled module enforces the limit.
+    live_objects: Mutex<HashMap<(String, usize), PooledLiveObject<S>>>,
+}
+
+/// The compiled module and the idle runtimes of a live object.
+struct PooledLiveObject<S>
+where
+    S: Storage<Vec<u8>, Vec<u8>> + 'static,
+{
+    compiled_module: CompiledModule,
+    idle: Vec<PooledRuntime<S>>,
+    last_used: Instant,
+}
+
+impl<S> RuntimePool<S>
+where
+    S: Storage<Vec<u8>, Vec<u8>> + 'static,
+{
+    /// Create a new `RuntimePool` whose runtimes are limited by `memory_limits`, and whose modules
+    /// are validated against `float_policy` and compiled by `compiler`.
+    pub fn new(
+        config: RuntimePoolConfig,
+        memory_limits: MemoryLimits,
+        compiler: CompilerBackend,
+        float_policy: FloatPolicy,
+    ) -> Self {
+        Self {
+            config,
+            memory_limits,
+            compiler,
+            float_policy,
+            precompiled_dir: None,
+            storage_quota: None,
+            live_objects: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Load the modules from their artifacts in the directory, written by `ramd precompile`, instead of
+    /// compiling them. Modules without a compatible artifact there are compiled as usual.
+    pub fn precompiled_dir(mut self, precompiled_dir: PathBuf) -> Self {
+        self.precompiled_dir = Some(precompiled_dir);
+        self
+    }
+
+    /// Set the number of bytes each live object may store. Writes that would store more trap.
+    pub fn storage_quota(mut self, storage_quota: u64) -> Self {
+        self.storage_quota = Some(storage_quota);
+        self
+    }
+
+    /// Take an idle runtime of the live object and bind it to the storage and the message,
+    /// or build a new one if there is none.
+    pub fn acquire(
+        &self,
+        storage: Arc<S>,
+        live_object_info: LiveObjectInfo,
+        message: MessageContext,
+    ) -> Result<PooledRuntime<S>, VmError> {
+        self.evict_idle();
+
+        let memory_limit = self
+            .memory_limits
+            .resolve(live_object_info.memory_limit)
+            .map_err(|err| VmError::LimitExceeded(err.to_string()))?;
+        let key = (live_object_info.id.clone(), memory_limit);
+
+        let (idle, compiled_module) = match self.live_objects.lock() {
+            Ok(mut live_objects) => match live_objects.get_mut(&key) {
+                Some(live_object) => {
+                    live_object.last_used = Instant::now();
+                    (
+                        live_object.idle.pop(),
+                        Some(live_object.compiled_module.clone()),
+                    )
+                }
+                None => (None, None),
+            },
+            Err(_) => (None, None),
+        };
+
+        if let Some(mut runtime) = idle {
+            debug!(target: "ramd::vm", "Reusing a pooled runtime of live object `{}`", live_object_info.id);
+
+            let context = runtime.function_env.as_mut(&mut runtime.runtime.store);
+            context.storage = storage;
+            context.message = message;
+            context.metered_cost = 0;
+            context.storage_usage_delta = 0;
+
+            return Ok(runtime);
+        }
+
+        let mut builder = RuntimeBuilder::new(storage, live_object_info)
+            .message(message)
+            .memory_limits(self.memory_limits)
+            .compiler(self.compiler)
+            .float_policy(self.float_policy);
+        if let Some(precompiled_dir) = &self.precompiled_dir {
+            builder = builder.precompiled_dir(precompiled_dir.clone());
+        }
+        if let Some(storage_quota) = self.storage_quota {
+            builder = builder.storage_quota(storage_quota);
+        }
+        if let Some(compiled_module) = compiled_module {
+            builder = builder.compiled_module(compiled_module);
+        }
+
+        let (mut runtime, function_env, compiled_module) = builder.build_with_env()?;
+        let snapshot = match compiled_module.is_resettable() {
+            true => InstanceSnapshot::take(&mut runtime.store, &runtime.instance)?,
+            false => None,
+        };
+
+        Ok(PooledRuntime {
+            runtime,
+            function_env,
+            compiled_module,
+            snapshot,
+            key,
+            failed: false,
+        })
+    }
+
+    /// Reset the runtime and keep it for later messages to the same live object.
+    /// Runtimes whose last run failed, or whose memory has grown, are dropped instead.
+    pub fn release(&self, mut runtime: PooledRuntime<S>) {
+        if self.config.size == 0 {
+            return;
+        }
+
+        let reusable = !runtime.failed && runtime.reset();
+
+        let Ok(mut live_objects) = self.live_objects.lock() else {
+            return;
+        };
+
+        let live_object =
+            live_objects
+                .entry(runtime.key.clone())
+                .or_insert_with(|| PooledLiveObject {
+                    compiled_module: runtime.compiled_module.clone(),
+                    idle: Vec::new(),
+                    last_used: Instant::now(),
+                });
+        live_object.last_used = Instant::now();
+
+        if reusable && live_object.idle.len() < self.config.size {
+            live_object.idle.push(runtime);
+        }
+    }
+
+    /// Drop the compiled modules and runtimes of the live objects that haven't been used for longer than the idle timeout.
+    pub fn evict_idle(&self) {
+        if let Ok(mut live_objects) = self.live_objects.lock() {
+            let idle_timeout = self.config.idle_timeout;
+            live_objects.retain(|_, live_object| live_object.last_used.elapsed() < idle_timeout);
+        }
+    }
+}
+
+/// A runtime taken from a `RuntimePool`. Give it back with `RuntimePool::release` once the message is done.
+pub struct PooledRuntime<S>
+where
+    S: Storage<Vec<u8>, Vec<u8>> + 'static,
+{
+    runtime: Runtime,
+    function_env: FunctionEnv<Context<S>>,
+    compiled_module: CompiledModule,
+    /// The state right after instantiation, or `None` if the runtime can't be reset.
+    snapshot: Option<InstanceSnapshot>,
+    /// The live object ID and the memory limit that the runtime is pooled under.
+    key: (String, usize),
+    failed: bool,
+}
+
+impl<S> PooledRuntime<S>
+where
+    S: Storage<Vec<u8>, Vec<u8>> + 'static,
+{
+    /// Replace the interrupt handle, e.g. with one that is shared with whoever may cancel the execution.
+    pub fn set_interrupt_handle(&mut self, interrupt_handle: InterruptHandle) {
+        self.runtime.set_interrupt_handle(interrupt_handle);
+    }
+
+    /// Interrupt `run` if it takes longer than the timeout.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.runtime.set_timeout(timeout);
+    }
+
+    /// Run the specified function with arguments on the WASM instance.
+    /// A runtime whose run fails isn't reused, as the failure may have left the instance in any state.
+    pub fn run(&mut self, method: String, args: Vec<u8>) -> Result<String, VmError> {
+        let result = self.runtime.run(method, args);
+        self.failed |= result.is_err();
+        result
+    }
+
+    /// Reset the runtime to its state right after instantiation. Returns false if it can't be reset.
+    fn reset(&mut self) -> bool {
+        // Detach the handle of the finished message first, so that it can't interrupt a later one.
+        self.runtime.set_interrupt_handle(InterruptHandle::new());
+        self.runtime.set_timeout(None);
+
+        let Some(snapshot) = &self.snapshot else {
+            return false;
+        };
+
+        match snapshot.restore(&mut self.runtime.store, &self.runtime.instance) {
+            Ok(true) => true,
+            Ok(false) => {
+                debug!(target: "ramd::vm", "Dropping a runtime of live object `{}` whose memory or tables have grown", self.key.0);
+                false
+            }
+            Err(_) => false,
+        }
+    }
+}
@@ -13,118 +13,213 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::BTreeSet;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::{
-    Context, ImportObject, LiveObjectInfo, MemorySlice, MemorySlicePtr, MAX_WASM_MEMORY_SIZE,
+    AllocationOwnership, HostAllocations, InterruptHandle, LiveObjectInfo, MemoryLimits,
+    MemorySlice, MemorySlicePtr, MessageContext, ReadOnly, RuntimeBuilder, VmError,
 };
 use ramd_db::storage::Storage;
-use tracing::info;
-use wasmer::{FunctionEnv, Instance, Module, Store, Value};
+use tracing::{error, warn};
+use wasmer::{
+    AsStoreRef, Function, FunctionEnv, FunctionType, Instance, Memory, Store, Type, Value,
+};
 
 /// The runtime that creates and runs the WASM instance.
 pub struct Runtime {
-    store: Store,
-    instance: Instance,
+    pub(crate) store: Store,
+    pub(crate) instance: Instance,
+    pub(crate) interrupt_handle: InterruptHandle,
+    /// The handle that the `check_interrupt` host function of the instance reads.
+    pub(crate) interrupt_env: FunctionEnv<InterruptHandle>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) memory_limit: usize,
+    pub(crate) host_allocations: HostAllocations,
+    pub(crate) allocation_ownership: AllocationOwnership,
+    /// The `MemorySlice`s that the last call left allocated although the guest owned them.
+    pub(crate) leaked_allocations: Vec<MemorySlicePtr>,
+    /// The methods that the ABI of the live object declares read-only.
+    pub(crate) read_only_methods: BTreeSet<String>,
+    pub(crate) read_only: ReadOnly,
 }
 
 impl Runtime {
-    /// Create a new `Runtime`.
-    pub fn new<S>(storage: Arc<S>, live_object_info: LiveObjectInfo) -> eyre::Result<Self>
+    /// Create a new `Runtime` that executes the given message with the default host functions.
+    /// The WASM (guest) memory is limited to the memory limit of the live object,
+    /// or to the default of `memory_limits` if the live object doesn't set one.
+    /// Use `RuntimeBuilder` for more control.
+    pub fn new<S>(
+        storage: Arc<S>,
+        live_object_info: LiveObjectInfo,
+        message: MessageContext,
+        memory_limits: &MemoryLimits,
+    ) -> Result<Self, VmError>
     where
         S: Storage<Vec<u8>, Vec<u8>> + 'static,
     {
-        // Create a Store.
-        let mut store = Store::default();
-
-        // Compile the WASM module.
-        let module = Module::new(&store, live_object_info.wasm_bytes)?;
+        RuntimeBuilder::new(storage, live_object_info)
+            .message(message)
+            .memory_limits(*memory_limits)
+            .build()
+    }
 
-        // Create a function environment.
-        let function_env = FunctionEnv::new(&mut store, Context::new(storage, live_object_info.id));
+    /// Replace the interrupt handle, e.g. with one that is shared with whoever may cancel the execution.
+    pub fn set_interrupt_handle(&mut self, interrupt_handle: InterruptHandle) {
+        *self.interrupt_env.as_mut(&mut self.store) = interrupt_handle.clone();
+        self.interrupt_handle = interrupt_handle;
+    }
 
-        // Create an import object.
-        let import_object = ImportObject::new(&mut store, &function_env);
+    /// Get the handle that interrupts the WASM instance.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt_handle.clone()
+    }
 
-        // Instantiate the WASM instance.
-        let instance = Instance::new(&mut store, &module, &import_object.0)?;
+    /// Interrupt `run` if it takes longer than the timeout.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
 
-        // Set the Context of the WASM instance. The WASM instance will use this Context to interact with the host.
-        let mut function_env = function_env.into_mut(&mut store);
-        let context = function_env.data_mut();
+    /// The `MemorySlice`s that the host allocated during the last call with `AllocationOwnership::Guest`,
+    /// and that the guest neither freed through `deallocate` nor returned.
+    pub fn leaked_allocations(&self) -> &[MemorySlicePtr] {
+        &self.leaked_allocations
+    }
 
-        let memory = instance.exports.get_memory("memory").cloned()?;
-        let allocate = instance.exports.get_function("allocate").cloned()?;
+    /// Run the specified function with arguments on the WASM instance.
+    /// The run is interrupted if it exceeds the timeout or the interrupt handle is triggered.
+    pub fn run(&mut self, method: String, args: Vec<u8>) -> Result<String, VmError> {
+        if let Some(reason) = self.interrupt_handle.reason() {
+            return Err(VmError::Interrupted(reason));
+        }
 
-        context.memory = Some(memory);
-        context.allocate = Some(allocate);
+        // The shared watchdog interrupts the WASM instance unless the call finishes in time.
+        let deadline = self
+            .timeout
+            .map(|timeout| self.interrupt_handle.interrupt_after(timeout))
+            .transpose()?;
+        let result = self.run_uninterrupted(method, args);
+        drop(deadline);
+
+        match self.interrupt_handle.reason() {
+            Some(reason) => {
+                error!(target: "ramd::vm", "Execution is interrupted: {}", reason);
+                Err(VmError::Interrupted(reason))
+            }
+            None => result,
+        }
+    }
 
-        info!(target: "ramd::vm", "Runtime is created");
+    /// Run the specified function with arguments on the WASM instance without a timeout.
+    fn run_uninterrupted(&mut self, method: String, args: Vec<u8>) -> Result<String, VmError> {
+        // Forget the host allocations of an earlier call that failed.
+        self.host_allocations.take();
+        self.leaked_allocations.clear();
 
-        Ok(Self { store, instance })
-    }
+        // Host functions refuse to write the storage during calls to read-only methods.
+        self.read_only.set(self.read_only_methods.contains(&method));
 
-    /// Run the specified function with arguments on the WASM instance.
-    pub fn run(&mut self, method: String, args: Vec<u8>) -> eyre::Result<String> {
         // Allocate `MemorySlice`.
+        let args_len = args.len();
         let args_ptr = self
-            .call_function("allocate", &[Value::from(args.len() as u32)])?
-            .ok_or(eyre::eyre!("Failed to allocate memory"))?;
+            .call_function("allocate", &[Value::from(args_len as u32)])
+            .map_err(|err| VmError::AllocationFailed {
+                len: args_len,
+                reason: err.to_string(),
+            })?;
+        self.host_allocations.record(args_ptr);
 
         // Write parameters to `MemorySlice`.
         self.write_memory(args_ptr, args.as_slice())?;
 
         // Call function.
-        let result_ptr = self
-            .call_function(&method, &[args_ptr.into()])?
-            .ok_or(eyre::eyre!("Failed to call the function `{}`", method))?;
+        let result_ptr = self.call_function(&method, &[args_ptr.into()])?;
 
         // Read return value from `MemorySlice`.
         let result = self.read_memory(result_ptr)?;
-        let result = String::from_utf8(result)?;
+        let result = String::from_utf8(result).map_err(|err| {
+            VmError::InvalidResult(format!("`{}` returned invalid UTF-8: {}", method, err))
+        })?;
 
         // Deallocate `MemorySlice`.
-        self.call_function("deallocate", &[result_ptr.into()])?;
+        self.deallocate(result_ptr)?;
+
+        // The `MemorySlice`s that the host allocated during the call are still live unless the guest freed them
+        // through `deallocate` or returned them. A guest that only borrowed them gets them reclaimed, while one
+        // that owned them leaked them. They aren't reclaimed from such a guest, as it may have freed them without
+        // calling `deallocate`, and freeing them again would corrupt its allocator.
+        let live_allocations = self.host_allocations.take();
+        match self.allocation_ownership {
+            AllocationOwnership::Host => {
+                for memory_slice_ptr in live_allocations {
+                    self.deallocate(memory_slice_ptr)?;
+                }
+            }
+            AllocationOwnership::Guest if !live_allocations.is_empty() => {
+                warn!(target: "ramd::vm", "`{}` leaked {} memory slices allocated by the host", method, live_allocations.len());
+                self.leaked_allocations = live_allocations;
+            }
+            AllocationOwnership::Guest => {}
+        }
 
         Ok(result)
     }
 
-    /// Call the specified function with arguments on the WASM instance.
-    fn call_function(
-        &mut self,
-        method: &str,
-        args_ptr: &[Value],
-    ) -> eyre::Result<Option<MemorySlicePtr>> {
-        let func = self.instance.exports.get_function(method)?;
+    /// Free a `MemorySlice` with the `deallocate` export, forgetting it if the host allocated it.
+    fn deallocate(&mut self, memory_slice_ptr: MemorySlicePtr) -> Result<(), VmError> {
+        self.host_allocations.release(memory_slice_ptr);
 
-        let result_ptr = func.call(&mut self.store, args_ptr)?;
+        let deallocate =
+            exported_function(&self.instance, &self.store, "deallocate", &[Type::I32], &[])?;
+        deallocate.call(&mut self.store, &[memory_slice_ptr.into()])?;
 
-        if result_ptr.is_empty() {
-            return Ok(None);
-        }
+        Ok(())
+    }
 
-        let result_ptr: MemorySlicePtr = result_ptr[0]
-            .clone()
+    /// Call the specified function, which takes and returns a `MemorySlicePtr`, on the WASM instance.
+    fn call_function(&mut self, method: &str, args: &[Value]) -> Result<MemorySlicePtr, VmError> {
+        let func = exported_function(
+            &self.instance,
+            &self.store,
+            method,
+            &[Type::I32],
+            &[Type::I32],
+        )?;
+
+        let result = func.call(&mut self.store, args)?;
+
+        let result_ptr: MemorySlicePtr = result
+            .first()
+            .cloned()
+            .ok_or(VmError::InvalidResult(format!(
+                "`{}` returned no value",
+                method
+            )))?
             .try_into()
-            .map_err(|err: &str| eyre::eyre!(err))?;
+            .map_err(|err: &str| VmError::InvalidResult(err.to_string()))?;
 
-        Ok(Some(result_ptr))
+        Ok(result_ptr)
     }
 
     /// Read data from the WASM (guest) memory.
-    fn read_memory(&mut self, memory_slice_ptr: MemorySlicePtr) -> eyre::Result<Vec<u8>> {
-        let memory = self.instance.exports.get_memory("memory")?;
+    fn read_memory(&mut self, memory_slice_ptr: MemorySlicePtr) -> Result<Vec<u8>, VmError> {
+        let memory = self.memory()?;
         let memory_view = memory.view(&self.store);
         let memory_slice = MemorySlice::new(&memory_view, memory_slice_ptr)?;
 
-        let data = memory_slice.read(&memory_view, MAX_WASM_MEMORY_SIZE)?;
+        let data = memory_slice.read(&memory_view, self.memory_limit)?;
 
         Ok(data)
     }
 
     /// Write data to the WASM (guest) memory.
-    fn write_memory(&mut self, memory_slice_ptr: MemorySlicePtr, data: &[u8]) -> eyre::Result<()> {
-        let memory = self.instance.exports.get_memory("memory")?;
+    fn write_memory(
+        &mut self,
+        memory_slice_ptr: MemorySlicePtr,
+        data: &[u8],
+    ) -> Result<(), VmError> {
+        let memory = self.memory()?;
         let memory_view = memory.view(&self.store);
         let memory_slice = MemorySlice::new(&memory_view, memory_slice_ptr)?;
 
@@ -132,4 +227,38 @@ impl Runtime {
 
         Ok(())
     }
+
+    /// Get the memory of the WASM instance.
+    fn memory(&self) -> Result<&Memory, VmError> {
+        self.instance
+            .exports
+            .get_memory("memory")
+            .map_err(|_| VmError::MissingExport("memory".to_string()))
+    }
+}
+
+/// Get the exported function with the given name, checking that it has the expected signature.
+pub(crate) fn exported_function(
+    instance: &Instance,
+    store: &impl AsStoreRef,
+    name: &str,
+    params: &[Type],
+    results: &[Type],
+) -> Result<Function, VmError> {
+    let function = instance
+        .exports
+        .get_function(name)
+        .map_err(|_| VmError::MissingExport(name.to_string()))?;
+
+    let expected = FunctionType::new(params, results);
+    let found = function.ty(store);
+    if found != expected {
+        return Err(VmError::BadSignature {
+            name: name.to_string(),
+            expected: expected.to_string(),
+            found: found.to_string(),
+        });
+    }
+
+    Ok(function.clone())
 }
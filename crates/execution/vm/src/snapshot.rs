@@ -0,0 +1,222 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashSet;
+
+use crate::{MemorySliceError, VmError};
+use wasmer::{
+    ExportIndex, Extern, FunctionMiddleware, Global, Instance, LocalFunctionIndex, Memory,
+    ModuleMiddleware, Mutability, Store, Table, Value,
+};
+use wasmer_types::ModuleInfo;
+use wasmparser::{DataKind, ElementKind, Parser, Payload};
+
+/// The prefix of the names under which `ExportState` exports mutable globals.
+pub const EXPORTED_GLOBAL_PREFIX: &str = "ramd_global_";
+
+/// The prefix of the names under which `ExportState` exports tables.
+pub const EXPORTED_TABLE_PREFIX: &str = "ramd_table_";
+
+/// The largest memory, in bytes, of an instance that is reset for reuse. Resetting copies the whole memory,
+/// which would cost more than a fresh instance for larger ones.
+pub const MAX_RESETTABLE_MEMORY_SIZE: u64 = 8 * 1024 * 1024;
+
+/// A middleware that exports every mutable global and table of the module that isn't exported yet,
+/// so that the host can save and restore the whole state of an instance.
+#[derive(Debug, Default)]
+pub struct ExportState;
+
+impl ModuleMiddleware for ExportState {
+    fn generate_function_middleware(
+        &self,
+        _local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        Box::new(NoopFunctionMiddleware)
+    }
+
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let exported = module_info
+            .exports
+            .values()
+            .filter_map(|export| match export {
+                ExportIndex::Global(index) => Some(*index),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+
+        let unexported = module_info
+            .globals
+            .iter()
+            .filter(|(index, ty)| {
+                ty.mutability == Mutability::Var
+                    && !module_info.is_imported_global(*index)
+                    && !exported.contains(index)
+            })
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        for index in unexported {
+            module_info.exports.insert(
+                format!("{}{}", EXPORTED_GLOBAL_PREFIX, index.as_u32()),
+                ExportIndex::Global(index),
+            );
+        }
+
+        let exported_tables = module_info
+            .exports
+            .values()
+            .filter_map(|export| match export {
+                ExportIndex::Table(index) => Some(*index),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        let unexported_tables = module_info
+            .tables
+            .keys()
+            .filter(|index| !exported_tables.contains(index))
+            .collect::<Vec<_>>();
+
+        for index in unexported_tables {
+            module_info.exports.insert(
+                format!("{}{}", EXPORTED_TABLE_PREFIX, index.as_u32()),
+                ExportIndex::Table(index),
+            );
+        }
+    }
+}
+
+/// Whether the module has passive data or element segments. `data.drop` and `elem.drop` change
+/// which of them an instance can still use, which a snapshot can't capture.
+pub(crate) fn has_passive_segments(wasm_bytes: &[u8]) -> Result<bool, VmError> {
+    for payload in Parser::new(0).parse_all(wasm_bytes) {
+        match payload.map_err(|err| VmError::InvalidModule(err.to_string()))? {
+            Payload::DataSection(reader) => {
+                for data in reader {
+                    let data = data.map_err(|err| VmError::InvalidModule(err.to_string()))?;
+                    if matches!(data.kind, DataKind::Passive) {
+                        return Ok(true);
+                    }
+                }
+            }
+            Payload::ElementSection(reader) => {
+                for element in reader {
+                    let element = element.map_err(|err| VmError::InvalidModule(err.to_string()))?;
+                    if matches!(element.kind, ElementKind::Passive) {
+                        return Ok(true);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(false)
+}
+
+#[derive(Debug)]
+struct NoopFunctionMiddleware;
+
+impl FunctionMiddleware for NoopFunctionMiddleware {}
+
+/// The memory, mutable globals and tables of a WASM (guest) instance at some point in time.
+pub(crate) struct InstanceSnapshot {
+    memory: Vec<u8>,
+    globals: Vec<(Global, Value)>,
+    tables: Vec<(Table, Vec<Value>)>,
+}
+
+impl InstanceSnapshot {
+    /// Take a snapshot of the instance. Every mutable global and table must be exported, see `ExportState`.
+    /// Returns `None` if the instance can't be restored cheaply, as its memory is larger than
+    /// `MAX_RESETTABLE_MEMORY_SIZE`.
+    pub(crate) fn take(store: &mut Store, instance: &Instance) -> Result<Option<Self>, VmError> {
+        let memory_view = memory(instance)?.view(store);
+        if memory_view.data_size() > MAX_RESETTABLE_MEMORY_SIZE {
+            return Ok(None);
+        }
+        let memory = memory_view
+            .copy_to_vec()
+            .map_err(MemorySliceError::ReadError)?;
+
+        let mut globals = Vec::new();
+        let mut tables = Vec::new();
+        for (_, export) in instance.exports.iter() {
+            match export {
+                Extern::Global(global) if global.ty(store).mutability == Mutability::Var => {
+                    globals.push((global.clone(), global.get(store)));
+                }
+                Extern::Table(table) => {
+                    let elements = (0..table.size(store))
+                        .map(|index| table.get(store, index))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or(VmError::Instantiate(
+                            "Failed to read a table element".to_string(),
+                        ))?;
+                    tables.push((table.clone(), elements));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Some(Self {
+            memory,
+            globals,
+            tables,
+        }))
+    }
+
+    /// Restore the instance to the snapshot. Returns false without touching the instance
+    /// if its memory or a table has grown since the snapshot, as neither can shrink.
+    pub(crate) fn restore(&self, store: &mut Store, instance: &Instance) -> Result<bool, VmError> {
+        if self
+            .tables
+            .iter()
+            .any(|(table, elements)| table.size(store) as usize != elements.len())
+        {
+            return Ok(false);
+        }
+
+        let memory = memory(instance)?;
+        let memory_view = memory.view(store);
+        if memory_view.data_size() != self.memory.len() as u64 {
+            return Ok(false);
+        }
+
+        memory_view
+            .write(0, &self.memory)
+            .map_err(MemorySliceError::WriteError)?;
+
+        for (global, value) in self.globals.iter() {
+            global.set(store, value.clone())?;
+        }
+
+        for (table, elements) in self.tables.iter() {
+            for (index, element) in elements.iter().enumerate() {
+                table
+                    .set(store, index as u32, element.clone())
+                    .map_err(|err| VmError::Instantiate(err.to_string()))?;
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+fn memory(instance: &Instance) -> Result<&Memory, VmError> {
+    instance
+        .exports
+        .get_memory("memory")
+        .map_err(|_| VmError::MissingExport("memory".to_string()))
+}
@@ -0,0 +1,162 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::ptr::NonNull;
+
+use crate::{DEFAULT_WASM_MEMORY_SIZE, MAX_WASM_MEMORY_SIZE};
+use serde::{Deserialize, Serialize};
+use wasmer::sys::{BaseTunables, Tunables};
+use wasmer::vm::{VMMemory, VMMemoryDefinition, VMTable, VMTableDefinition};
+use wasmer_types::{
+    MemoryError, MemoryStyle, MemoryType, Pages, TableStyle, TableType, WASM_MAX_PAGES,
+    WASM_PAGE_SIZE,
+};
+
+/// The WASM (guest) memory sizes, in bytes, that live objects may use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryLimits {
+    /// The memory limit of live objects that don't set their own.
+    pub default_size: usize,
+    /// The largest memory limit a live object may set.
+    pub max_size: usize,
+}
+
+impl Default for MemoryLimits {
+    fn default() -> Self {
+        Self {
+            default_size: DEFAULT_WASM_MEMORY_SIZE,
+            max_size: MAX_WASM_MEMORY_SIZE,
+        }
+    }
+}
+
+impl MemoryLimits {
+    /// Resolve the memory limit of a live object, falling back to the default if it doesn't set one.
+    pub fn resolve(&self, memory_limit: Option<usize>) -> eyre::Result<usize> {
+        let memory_limit = memory_limit.unwrap_or(self.default_size);
+
+        if memory_limit < WASM_PAGE_SIZE {
+            return Err(eyre::eyre!(
+                "Memory limit of {} bytes is less than a single page of {} bytes",
+                memory_limit,
+                WASM_PAGE_SIZE
+            ));
+        }
+
+        if memory_limit > self.max_size {
+            return Err(eyre::eyre!(
+                "Memory limit of {} bytes exceeds the maximum of {} bytes",
+                memory_limit,
+                self.max_size
+            ));
+        }
+
+        Ok(memory_limit)
+    }
+}
+
+/// Tunables that cap the WASM (guest) memory, so that the guest can't grow its memory past the limit.
+pub struct LimitingTunables {
+    limit: Pages,
+    base: BaseTunables,
+}
+
+impl LimitingTunables {
+    /// Create a new `LimitingTunables` that allows at most `memory_limit` bytes of memory,
+    /// rounded down to whole pages.
+    pub fn new(base: BaseTunables, memory_limit: usize) -> Self {
+        Self {
+            limit: Pages((memory_limit / WASM_PAGE_SIZE).min(WASM_MAX_PAGES as usize) as u32),
+            base,
+        }
+    }
+
+    /// Cap the maximum of the requested memory at the limit.
+    fn adjust_memory(&self, requested: &MemoryType) -> MemoryType {
+        let mut adjusted = *requested;
+        if requested
+            .maximum
+            .map_or(true, |maximum| maximum > self.limit)
+        {
+            adjusted.maximum = Some(self.limit);
+        }
+        adjusted
+    }
+
+    /// Ensure the memory fits in the limit.
+    fn validate_memory(&self, ty: &MemoryType) -> Result<(), MemoryError> {
+        if ty.minimum > self.limit {
+            return Err(MemoryError::Generic(format!(
+                "Minimum memory of {} pages exceeds the limit of {} pages",
+                ty.minimum.0, self.limit.0
+            )));
+        }
+
+        match ty.maximum {
+            Some(maximum) if maximum <= self.limit => Ok(()),
+            Some(maximum) => Err(MemoryError::Generic(format!(
+                "Maximum memory of {} pages exceeds the limit of {} pages",
+                maximum.0, self.limit.0
+            ))),
+            None => Err(MemoryError::Generic("Maximum memory is unset".to_string())),
+        }
+    }
+}
+
+impl Tunables for LimitingTunables {
+    fn memory_style(&self, memory: &MemoryType) -> MemoryStyle {
+        let adjusted = self.adjust_memory(memory);
+        self.base.memory_style(&adjusted)
+    }
+
+    fn table_style(&self, table: &TableType) -> TableStyle {
+        self.base.table_style(table)
+    }
+
+    fn create_host_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+    ) -> Result<VMMemory, MemoryError> {
+        let adjusted = self.adjust_memory(ty);
+        self.validate_memory(&adjusted)?;
+        self.base.create_host_memory(&adjusted, style)
+    }
+
+    unsafe fn create_vm_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+        vm_definition_location: NonNull<VMMemoryDefinition>,
+    ) -> Result<VMMemory, MemoryError> {
+        let adjusted = self.adjust_memory(ty);
+        self.validate_memory(&adjusted)?;
+        self.base
+            .create_vm_memory(&adjusted, style, vm_definition_location)
+    }
+
+    fn create_host_table(&self, ty: &TableType, style: &TableStyle) -> Result<VMTable, String> {
+        self.base.create_host_table(ty, style)
+    }
+
+    unsafe fn create_vm_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+        vm_definition_location: NonNull<VMTableDefinition>,
+    ) -> Result<VMTable, String> {
+        self.base.create_vm_table(ty, style, vm_definition_location)
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Read-only methods can't write the storage, and results are checked against the schema in the ABI.
+
+use std::sync::Arc;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_db::storage::Storage;
+use ramd_vm::{CompilerBackend, LiveObjectInfo, Runtime, RuntimeBuilder, VmError};
+
+/// The live object of `tests/live-object-read-only.sh`, whose ABI declares `get`, `sneaky_set` and `count`
+/// read-only although `sneaky_set` writes the storage.
+const READ_ONLY_MODULE: &str = include_str!("../../../../tests/wasms/live_object_read_only.wat");
+
+fn available_compilers() -> Vec<CompilerBackend> {
+    [CompilerBackend::Cranelift, CompilerBackend::Singlepass]
+        .into_iter()
+        .filter(CompilerBackend::is_available)
+        .collect()
+}
+
+fn live_object_info() -> LiveObjectInfo {
+    LiveObjectInfo::new(
+        wat::parse_str(READ_ONLY_MODULE).expect("fixture must parse"),
+        None,
+    )
+    .unwrap()
+}
+
+fn runtime(compiler: CompilerBackend, storage: Arc<MemoryStorage>) -> Runtime {
+    RuntimeBuilder::new(storage, live_object_info())
+        .namespace(Vec::new())
+        .compiler(compiler)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn read_only_methods_fail_to_write() {
+    for compiler in available_compilers() {
+        let storage = Arc::new(MemoryStorage::default());
+        let mut runtime = runtime(compiler, storage.clone());
+
+        let result = runtime.run("sneaky_set".to_string(), b"\"k\"".to_vec());
+        assert!(
+            matches!(result, Err(VmError::ReadOnly(_))),
+            "unexpected result: {:?}",
+            result
+        );
+        assert_eq!(storage.get_opt(Vec::from(*b"\"k\"")).unwrap(), None);
+    }
+}
+
+#[test]
+fn writable_methods_write_after_a_read_only_one() {
+    for compiler in available_compilers() {
+        let storage = Arc::new(MemoryStorage::default());
+        let mut runtime = runtime(compiler, storage.clone());
+
+        assert!(runtime
+            .run("sneaky_set".to_string(), b"\"k\"".to_vec())
+            .is_err());
+        assert_eq!(
+            runtime.run("set".to_string(), b"\"k\"".to_vec()).unwrap(),
+            "\"k\""
+        );
+
+        // Reading stays allowed in read-only methods.
+        assert_eq!(
+            runtime.run("get".to_string(), b"\"k\"".to_vec()).unwrap(),
+            "\"k\""
+        );
+        assert_eq!(
+            storage.get_opt(Vec::from(*b"\"k\"")).unwrap(),
+            Some(b"\"k\"".to_vec())
+        );
+    }
+}
+
+#[test]
+fn results_are_validated_against_the_abi() {
+    let abi = live_object_info().abi.expect("fixture must have an ABI");
+
+    abi.validate_result("get", "\"v\"").unwrap();
+    assert!(abi.validate_result("get", "1").is_err());
+    assert!(abi.validate_result("count", "\"k\"").is_err());
+    abi.validate_result("count", "3").unwrap();
+    assert!(abi.validate_result("count", "not json").is_err());
+    assert!(abi.validate_result("missing", "1").is_err());
+}
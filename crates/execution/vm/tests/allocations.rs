@@ -0,0 +1,146 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The memory slices that the host allocates in the WASM (guest) memory are reclaimed when the guest only
+//! borrows them, reported when the guest owns them but doesn't free them, and never freed twice.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_vm::{AllocationOwnership, CompilerBackend, LiveObjectInfo, Runtime, RuntimeBuilder};
+
+/// A live object with an allocator that counts live slices and traps on a double free. Its methods read the
+/// key given as arguments and return the number of live slices, not counting the returned one:
+/// `read` keeps the value and the arguments, `read_and_free` frees both through `deallocate`.
+const ALLOCATIONS_MODULE: &str = r#"
+(module
+  (import "env" "storage_read" (func $storage_read (param i32) (result i32)))
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  (global $live (mut i32) (i32.const 0))
+  ;; A slice is a header of the data pointer, the length and whether it was freed, followed by the data.
+  (func $allocate (export "allocate") (param $len i32) (result i32)
+    (local $slice i32)
+    (local.set $slice (global.get $next))
+    (i32.store (local.get $slice) (i32.add (local.get $slice) (i32.const 12)))
+    (i32.store offset=4 (local.get $slice) (local.get $len))
+    (i32.store offset=8 (local.get $slice) (i32.const 0))
+    (global.set $next (i32.add (global.get $next) (i32.add (local.get $len) (i32.const 12))))
+    (global.set $live (i32.add (global.get $live) (i32.const 1)))
+    (local.get $slice))
+  (func $deallocate (export "deallocate") (param $slice i32)
+    (if (i32.load offset=8 (local.get $slice)) (then unreachable))
+    (i32.store offset=8 (local.get $slice) (i32.const 1))
+    (global.set $live (i32.sub (global.get $live) (i32.const 1))))
+  (func $live_count (result i32)
+    (local $count i32)
+    (local $result i32)
+    (local.set $count (global.get $live))
+    (local.set $result (call $allocate (i32.const 1)))
+    (i32.store8 (i32.load (local.get $result)) (i32.add (i32.const 48) (local.get $count)))
+    (local.get $result))
+  (func (export "read") (param $args i32) (result i32)
+    (drop (call $storage_read (local.get $args)))
+    (call $live_count))
+  (func (export "read_and_free") (param $args i32) (result i32)
+    (call $deallocate (call $storage_read (local.get $args)))
+    (call $deallocate (local.get $args))
+    (call $live_count)))
+"#;
+
+fn available_compilers() -> Vec<CompilerBackend> {
+    [CompilerBackend::Cranelift, CompilerBackend::Singlepass]
+        .into_iter()
+        .filter(CompilerBackend::is_available)
+        .collect()
+}
+
+fn runtime(compiler: CompilerBackend, allocation_ownership: AllocationOwnership) -> Runtime {
+    let storage = MemoryStorage::new(BTreeMap::from([(b"k".to_vec(), b"v".to_vec())]));
+    let live_object_info = LiveObjectInfo::new(
+        wat::parse_str(ALLOCATIONS_MODULE).expect("crafted module must parse"),
+        None,
+    )
+    .unwrap();
+
+    RuntimeBuilder::new(Arc::new(storage), live_object_info)
+        .namespace(Vec::new())
+        .compiler(compiler)
+        .allocation_ownership(allocation_ownership)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn borrowed_slices_are_reclaimed() {
+    for compiler in available_compilers() {
+        let mut runtime = runtime(compiler, AllocationOwnership::Host);
+
+        // The arguments and the value are live during each call, and reclaimed after it.
+        for _ in 0..3 {
+            assert_eq!(runtime.run("read".to_string(), b"k".to_vec()).unwrap(), "2");
+            assert!(runtime.leaked_allocations().is_empty());
+        }
+    }
+}
+
+#[test]
+fn borrowed_slices_freed_by_the_guest_are_not_freed_again() {
+    for compiler in available_compilers() {
+        let mut runtime = runtime(compiler, AllocationOwnership::Host);
+
+        // The allocator traps on a double free, which would fail the call.
+        for _ in 0..3 {
+            assert_eq!(
+                runtime
+                    .run("read_and_free".to_string(), b"k".to_vec())
+                    .unwrap(),
+                "0"
+            );
+        }
+    }
+}
+
+#[test]
+fn owned_slices_left_allocated_are_reported_as_leaked() {
+    for compiler in available_compilers() {
+        let mut runtime = runtime(compiler, AllocationOwnership::Guest);
+
+        assert_eq!(runtime.run("read".to_string(), b"k".to_vec()).unwrap(), "2");
+        assert_eq!(runtime.leaked_allocations().len(), 2);
+
+        // Leaked slices are left to the guest, and only the ones of the last call are reported.
+        assert_eq!(runtime.run("read".to_string(), b"k".to_vec()).unwrap(), "4");
+        assert_eq!(runtime.leaked_allocations().len(), 2);
+    }
+}
+
+#[test]
+fn owned_slices_freed_by_the_guest_are_not_reported() {
+    for compiler in available_compilers() {
+        let mut runtime = runtime(compiler, AllocationOwnership::Guest);
+
+        for _ in 0..3 {
+            assert_eq!(
+                runtime
+                    .run("read_and_free".to_string(), b"k".to_vec())
+                    .unwrap(),
+                "0"
+            );
+            assert!(runtime.leaked_allocations().is_empty());
+        }
+    }
+}
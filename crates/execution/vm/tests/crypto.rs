@@ -0,0 +1,178 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//! Known answers of the hash and signature primitives that live objects call, and how they reject malformed input.
+
+use k256::ecdsa::SigningKey;
+use ramd_vm::{ed25519_verify, keccak256, secp256k1_recover, sha256};
+
+fn bytes(hex: &str) -> Vec<u8> {
+    hex::decode(hex).unwrap()
+}
+
+/// The first test of RFC 8032, section 7.1: the empty message.
+const ED25519_PUBLIC_KEY: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+const ED25519_SIGNATURE: &str = "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b";
+
+/// The uncompressed public key of the secp256k1 private key 1, which is the generator point.
+const SECP256K1_GENERATOR: &str = "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+
+/// A signature of the message hash by the private key 1, and its recovery ID.
+fn secp256k1_signature(message_hash: &[u8]) -> (Vec<u8>, u8) {
+    let mut private_key = [0; 32];
+    private_key[31] = 1;
+    let signing_key = SigningKey::from_bytes(&private_key.into()).unwrap();
+    let (signature, recovery_id) = signing_key.sign_prehash_recoverable(message_hash).unwrap();
+
+    (signature.to_bytes().to_vec(), recovery_id.to_byte())
+}
+
+#[test]
+fn keccak256_matches_known_answers() {
+    assert_eq!(
+        keccak256(b""),
+        bytes("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470")
+    );
+    assert_eq!(
+        keccak256(b"abc"),
+        bytes("4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45")
+    );
+}
+
+#[test]
+fn sha256_matches_known_answers() {
+    assert_eq!(
+        sha256(b""),
+        bytes("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+    );
+    assert_eq!(
+        sha256(b"abc"),
+        bytes("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+    );
+}
+
+#[test]
+fn ed25519_verifies_the_rfc_8032_vectors() {
+    assert!(ed25519_verify(
+        &bytes(ED25519_SIGNATURE),
+        b"",
+        &bytes(ED25519_PUBLIC_KEY)
+    ));
+    // The second test: the message 0x72.
+    assert!(ed25519_verify(
+        &bytes("92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00"),
+        &[0x72],
+        &bytes("3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c")
+    ));
+}
+
+#[test]
+fn ed25519_rejects_other_messages_and_malformed_input() {
+    let signature = bytes(ED25519_SIGNATURE);
+    let public_key = bytes(ED25519_PUBLIC_KEY);
+
+    assert!(!ed25519_verify(&signature, b"other", &public_key));
+
+    let mut tampered = signature.clone();
+    tampered[0] ^= 1;
+    assert!(!ed25519_verify(&tampered, b"", &public_key));
+
+    // A scalar `s` that isn't reduced modulo the group order.
+    let mut unreduced = signature.clone();
+    unreduced[63] |= 0xf0;
+    assert!(!ed25519_verify(&unreduced, b"", &public_key));
+
+    assert!(!ed25519_verify(&signature[..63], b"", &public_key));
+    assert!(!ed25519_verify(
+        &[signature.clone(), vec![0]].concat(),
+        b"",
+        &public_key
+    ));
+    assert!(!ed25519_verify(&signature, b"", &public_key[..31]));
+    assert!(!ed25519_verify(
+        &signature,
+        b"",
+        &[public_key.clone(), vec![0]].concat()
+    ));
+    assert!(!ed25519_verify(&[], b"", &[]));
+
+    // The identity point is a public key of small order, which strict verification refuses.
+    let mut identity = [0; 32];
+    identity[0] = 1;
+    assert!(!ed25519_verify(&signature, b"", &identity));
+}
+
+#[test]
+fn secp256k1_recovers_the_signing_public_key() {
+    let message_hash = keccak256(b"hello");
+    let (signature, recovery_id) = secp256k1_signature(&message_hash);
+
+    assert_eq!(
+        secp256k1_recover(&message_hash, &signature, recovery_id),
+        Some(bytes(SECP256K1_GENERATOR))
+    );
+
+    // Another message or recovery ID recovers another key, if any.
+    let other_hash = keccak256(b"other");
+    assert_ne!(
+        secp256k1_recover(&other_hash, &signature, recovery_id),
+        Some(bytes(SECP256K1_GENERATOR))
+    );
+    assert_ne!(
+        secp256k1_recover(&message_hash, &signature, recovery_id ^ 1),
+        Some(bytes(SECP256K1_GENERATOR))
+    );
+}
+
+#[test]
+fn secp256k1_rejects_malformed_input() {
+    let message_hash = keccak256(b"hello");
+    let (signature, recovery_id) = secp256k1_signature(&message_hash);
+
+    assert_eq!(
+        secp256k1_recover(&message_hash[..31], &signature, recovery_id),
+        None
+    );
+    assert_eq!(
+        secp256k1_recover(
+            &[message_hash.clone(), vec![0]].concat(),
+            &signature,
+            recovery_id
+        ),
+        None
+    );
+    assert_eq!(
+        secp256k1_recover(&message_hash, &signature[..63], recovery_id),
+        None
+    );
+    assert_eq!(
+        secp256k1_recover(
+            &message_hash,
+            &[signature.clone(), vec![0]].concat(),
+            recovery_id
+        ),
+        None
+    );
+    assert_eq!(secp256k1_recover(&message_hash, &signature, 4), None);
+
+    // `r` and `s` must be in the range 1 to the group order minus one.
+    assert_eq!(
+        secp256k1_recover(&message_hash, &[0; 64], recovery_id),
+        None
+    );
+    assert_eq!(
+        secp256k1_recover(&message_hash, &[0xff; 64], recovery_id),
+        None
+    );
+}
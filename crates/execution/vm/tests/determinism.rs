@@ -0,0 +1,153 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Crafted modules that must be accepted or rejected by the deterministic profile of the VM.
+
+use std::sync::Arc;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_vm::{
+    validate_determinism, CompilerBackend, FloatPolicy, LiveObjectInfo, RuntimeBuilder, VmError,
+};
+
+/// A live object whose `nan` method returns `1` if subtracting zero from a signaling NaN
+/// yields the canonical NaN, and `0` otherwise.
+const NAN_MODULE: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (global $signaling_nan (mut i32) (i32.const 0x7fa00000))
+  (func (export "allocate") (param $len i32) (result i32)
+    (i32.store (i32.const 64) (i32.const 72))
+    (i32.store (i32.const 68) (local.get $len))
+    (i32.const 64))
+  (func (export "deallocate") (param i32))
+  (func (export "nan") (param i32) (result i32)
+    (i32.store8 (i32.const 128)
+      (i32.add
+        (i32.const 48)
+        (i32.eq
+          (i32.reinterpret_f32
+            (f32.sub (f32.reinterpret_i32 (global.get $signaling_nan)) (f32.const 0)))
+          (i32.const 0x7fc00000))))
+    (i32.store (i32.const 64) (i32.const 128))
+    (i32.store (i32.const 68) (i32.const 1))
+    (i32.const 64)))
+"#;
+
+fn wasm(wat: &str) -> Vec<u8> {
+    wat::parse_str(wat).expect("crafted module must parse")
+}
+
+fn assert_rejected(wat: &str, float_policy: FloatPolicy) {
+    let result = validate_determinism(&wasm(wat), float_policy);
+    assert!(
+        matches!(result, Err(VmError::InvalidModule(_))),
+        "expected the module to be rejected, got {:?}",
+        result
+    );
+}
+
+fn available_compilers() -> Vec<CompilerBackend> {
+    [CompilerBackend::Cranelift, CompilerBackend::Singlepass]
+        .into_iter()
+        .filter(CompilerBackend::is_available)
+        .collect()
+}
+
+#[test]
+fn rejects_simd() {
+    let wat = r#"
+    (module
+      (func (result i32)
+        (i32x4.extract_lane 0 (v128.const i32x4 1 2 3 4))))
+    "#;
+
+    assert_rejected(wat, FloatPolicy::Canonicalize);
+}
+
+#[test]
+fn rejects_threads() {
+    assert_rejected(r#"(module (memory 1 1 shared))"#, FloatPolicy::Canonicalize);
+
+    let wat = r#"
+    (module
+      (memory 1)
+      (func (result i32)
+        (i32.atomic.load (i32.const 0))))
+    "#;
+    assert_rejected(wat, FloatPolicy::Canonicalize);
+}
+
+#[test]
+fn float_policy_decides_on_float_operations() {
+    let wat = r#"
+    (module
+      (func (param f64) (result f64)
+        (f64.sqrt (local.get 0))))
+    "#;
+
+    assert!(validate_determinism(&wasm(wat), FloatPolicy::Canonicalize).is_ok());
+    assert_rejected(wat, FloatPolicy::Reject);
+}
+
+#[test]
+fn rejects_imports_outside_of_host_functions() {
+    let wasi = r#"
+    (module
+      (import "wasi_snapshot_preview1" "random_get" (func (param i32 i32) (result i32))))
+    "#;
+    assert_rejected(wasi, FloatPolicy::Canonicalize);
+
+    let memory = r#"(module (import "env" "memory" (memory 1)))"#;
+    assert_rejected(memory, FloatPolicy::Canonicalize);
+
+    let host_function = r#"
+    (module
+      (import "env" "message_timestamp" (func (result i64))))
+    "#;
+    assert!(validate_determinism(&wasm(host_function), FloatPolicy::Canonicalize).is_ok());
+}
+
+#[test]
+fn builder_rejects_non_deterministic_modules() {
+    let wat = r#"
+    (module
+      (memory (export "memory") 1)
+      (func (export "allocate") (param i32) (result i32) (i32.const 64))
+      (func (export "deallocate") (param i32))
+      (func (export "sqrt") (param f64) (result f64) (f64.sqrt (local.get 0))))
+    "#;
+    let live_object_info = LiveObjectInfo::new(wasm(wat), None).unwrap();
+
+    let result = RuntimeBuilder::new(Arc::new(MemoryStorage::default()), live_object_info)
+        .float_policy(FloatPolicy::Reject)
+        .build();
+
+    assert!(matches!(result, Err(VmError::InvalidModule(_))));
+}
+
+#[test]
+fn canonicalizes_nans() {
+    for compiler in available_compilers() {
+        let live_object_info = LiveObjectInfo::new(wasm(NAN_MODULE), None).unwrap();
+        let mut runtime = RuntimeBuilder::new(Arc::new(MemoryStorage::default()), live_object_info)
+            .compiler(compiler)
+            .build()
+            .unwrap();
+
+        let result = runtime.run("nan".to_string(), Vec::new()).unwrap();
+        assert_eq!(result, "1", "{} must canonicalize NaNs", compiler);
+    }
+}
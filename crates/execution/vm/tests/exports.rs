@@ -0,0 +1,113 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The VM checks the exports it calls, failing with the missing export or the mismatched signature.
+
+use std::sync::Arc;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_vm::{CompilerBackend, LiveObjectInfo, Runtime, RuntimeBuilder, VmError};
+
+/// A well-typed `allocate` export, which bumps a pointer through the memory.
+const ALLOCATE: &str = r#"
+  (global $next (mut i32) (i32.const 1024))
+  (func $allocate (export "allocate") (param $len i32) (result i32)
+    (local $slice i32)
+    (local.set $slice (global.get $next))
+    (i32.store (local.get $slice) (i32.add (local.get $slice) (i32.const 8)))
+    (i32.store offset=4 (local.get $slice) (local.get $len))
+    (global.set $next (i32.add (global.get $next) (i32.add (local.get $len) (i32.const 8))))
+    (local.get $slice))
+"#;
+
+fn available_compilers() -> Vec<CompilerBackend> {
+    [CompilerBackend::Cranelift, CompilerBackend::Singlepass]
+        .into_iter()
+        .filter(CompilerBackend::is_available)
+        .collect()
+}
+
+fn build(compiler: CompilerBackend, wat: &str) -> Result<Runtime, VmError> {
+    let live_object_info = LiveObjectInfo::new(
+        wat::parse_str(wat).expect("crafted module must parse"),
+        None,
+    )
+    .unwrap();
+
+    RuntimeBuilder::new(Arc::new(MemoryStorage::default()), live_object_info)
+        .compiler(compiler)
+        .build()
+}
+
+#[test]
+fn a_module_without_deallocate_is_missing_an_export() {
+    let wat = format!(r#"(module (memory (export "memory") 1) {})"#, ALLOCATE);
+
+    for compiler in available_compilers() {
+        match build(compiler, &wat) {
+            Err(VmError::MissingExport(name)) => assert_eq!(name, "deallocate", "{}", compiler),
+            Err(err) => panic!("{}: unexpected error: {}", compiler, err),
+            Ok(_) => panic!("{}: built a module without `deallocate`", compiler),
+        }
+    }
+}
+
+#[test]
+fn a_mistyped_allocate_has_a_bad_signature() {
+    let wat = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "allocate") (param i64) (result i32) (i32.const 0))
+  (func (export "deallocate") (param i32)))
+"#;
+
+    for compiler in available_compilers() {
+        match build(compiler, wat) {
+            Err(VmError::BadSignature { name, .. }) => assert_eq!(name, "allocate", "{}", compiler),
+            Err(err) => panic!("{}: unexpected error: {}", compiler, err),
+            Ok(_) => panic!("{}: built a module with a mistyped `allocate`", compiler),
+        }
+    }
+}
+
+#[test]
+fn a_mistyped_method_has_a_bad_signature() {
+    let wat = format!(
+        r#"
+(module
+  (memory (export "memory") 1)
+  {}
+  (func (export "deallocate") (param i32))
+  (func (export "get") (param i32) (result i64) (i64.const 0)))
+"#,
+        ALLOCATE
+    );
+
+    for compiler in available_compilers() {
+        let mut runtime = build(compiler, &wat).unwrap();
+
+        match runtime.run("get".to_string(), Vec::new()) {
+            Err(VmError::BadSignature {
+                name,
+                expected,
+                found,
+            }) => {
+                assert_eq!(name, "get", "{}", compiler);
+                assert_ne!(expected, found, "{}", compiler);
+            }
+            result => panic!("{}: unexpected result: {:?}", compiler, result),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Host functions added to the `RuntimeBuilder` replace the default ones, which can be removed altogether.
+
+use std::sync::Arc;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_vm::{
+    CompilerBackend, Context, HostFunctions, LiveObjectInfo, MessageContext, RuntimeBuilder,
+    VmError, HOST_IMPORT_MODULE,
+};
+use wasmer::{Function, FunctionEnv, Imports, StoreMut};
+
+/// A live object whose `timestamp` method returns `ok` if `message_timestamp` is 42, and `no` otherwise.
+const TIMESTAMP_MODULE: &str = r#"
+(module
+  (import "env" "message_timestamp" (func $message_timestamp (result i64)))
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  ;; Slices of the results at 64 and 80.
+  (data (i32.const 16) "\40\00\00\00\02\00\00\00\50\00\00\00\02\00\00\00")
+  (data (i32.const 64) "ok")
+  (data (i32.const 80) "no")
+  (func $allocate (export "allocate") (param $len i32) (result i32)
+    (local $slice i32)
+    (local.set $slice (global.get $next))
+    (i32.store (local.get $slice) (i32.add (local.get $slice) (i32.const 8)))
+    (i32.store offset=4 (local.get $slice) (local.get $len))
+    (global.set $next (i32.add (global.get $next) (i32.add (local.get $len) (i32.const 8))))
+    (local.get $slice))
+  (func $deallocate (export "deallocate") (param i32))
+  (func (export "timestamp") (param $args i32) (result i32)
+    (call $deallocate (local.get $args))
+    (if (result i32) (i64.eq (call $message_timestamp) (i64.const 42))
+      (then (i32.const 16))
+      (else (i32.const 24)))))
+"#;
+
+/// Host functions whose `message_timestamp` is always 42.
+struct FixedTimestamp;
+
+impl HostFunctions<MemoryStorage> for FixedTimestamp {
+    fn define(
+        &self,
+        store: &mut StoreMut,
+        _function_env: &FunctionEnv<Context<MemoryStorage>>,
+        imports: &mut Imports,
+    ) {
+        imports.define(
+            HOST_IMPORT_MODULE,
+            "message_timestamp",
+            Function::new_typed(store, || -> u64 { 42 }),
+        );
+    }
+}
+
+fn available_compilers() -> Vec<CompilerBackend> {
+    [CompilerBackend::Cranelift, CompilerBackend::Singlepass]
+        .into_iter()
+        .filter(CompilerBackend::is_available)
+        .collect()
+}
+
+fn builder(compiler: CompilerBackend) -> RuntimeBuilder<MemoryStorage> {
+    let live_object_info = LiveObjectInfo::new(
+        wat::parse_str(TIMESTAMP_MODULE).expect("crafted module must parse"),
+        None,
+    )
+    .unwrap();
+
+    RuntimeBuilder::new(Arc::new(MemoryStorage::default()), live_object_info)
+        .message(MessageContext {
+            id: "message".to_string(),
+            timestamp: 7,
+        })
+        .compiler(compiler)
+}
+
+#[test]
+fn a_custom_host_function_replaces_the_default_one() {
+    for compiler in available_compilers() {
+        let mut runtime = builder(compiler).build().unwrap();
+        assert_eq!(
+            runtime.run("timestamp".to_string(), Vec::new()).unwrap(),
+            "no"
+        );
+
+        let mut runtime = builder(compiler)
+            .host_functions(FixedTimestamp)
+            .build()
+            .unwrap();
+        assert_eq!(
+            runtime.run("timestamp".to_string(), Vec::new()).unwrap(),
+            "ok",
+            "{}",
+            compiler
+        );
+    }
+}
+
+#[test]
+fn modules_importing_a_cleared_host_function_fail_to_instantiate() {
+    for compiler in available_compilers() {
+        let result = builder(compiler).clear_host_functions().build();
+        assert!(
+            matches!(result, Err(VmError::Instantiate(_))),
+            "{}: instantiated without `message_timestamp`",
+            compiler
+        );
+
+        // Only the host functions added after clearing are defined.
+        let mut runtime = builder(compiler)
+            .clear_host_functions()
+            .host_functions(FixedTimestamp)
+            .build()
+            .unwrap();
+        assert_eq!(
+            runtime.run("timestamp".to_string(), Vec::new()).unwrap(),
+            "ok"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Interrupting running live objects, and running them with the interrupt checks in place.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_vm::{
+    CompilerBackend, InterruptHandle, InterruptReason, LiveObjectInfo, MessageContext, Runtime,
+    RuntimeBuilder, VmError,
+};
+
+/// A live object whose `calls` method returns the sum of what its functions return, through an import, direct
+/// and indirect calls and a start function, and whose other methods never return.
+const MODULE: &str = r#"
+(module
+  (type $digit (func (result i32)))
+  (import "env" "message_timestamp" (func $timestamp (result i64)))
+  (memory (export "memory") 1)
+  (global $started (mut i32) (i32.const 0))
+  (table 2 funcref)
+  (elem (i32.const 0) $one $two)
+  (func $one (result i32) (i32.const 1))
+  (func $two (result i32) (i32.const 2))
+  (func $start (global.set $started (i32.const 1)))
+  (start $start)
+  (func (export "allocate") (param $len i32) (result i32)
+    (i32.store (i32.const 64) (i32.const 72))
+    (i32.store (i32.const 68) (local.get $len))
+    (i32.const 64))
+  (func (export "deallocate") (param i32))
+  (func $digit (param $digit i32) (result i32)
+    (i32.store8 (i32.const 128) (i32.add (i32.const 48) (local.get $digit)))
+    (i32.store (i32.const 64) (i32.const 128))
+    (i32.store (i32.const 68) (i32.const 1))
+    (i32.const 64))
+  (func (export "calls") (param i32) (result i32)
+    (call $digit
+      (i32.add
+        (i32.add (call $one) (call_indirect (type $digit) (i32.const 1)))
+        (i32.add (global.get $started) (i32.wrap_i64 (call $timestamp))))))
+  (func (export "spin") (param i32) (result i32)
+    (loop $forever (br $forever))
+    (unreachable))
+  (func (export "spin_calls") (param i32) (result i32)
+    (loop $forever (drop (call $one)) (br $forever))
+    (unreachable)))
+"#;
+
+fn available_compilers() -> Vec<CompilerBackend> {
+    [CompilerBackend::Cranelift, CompilerBackend::Singlepass]
+        .into_iter()
+        .filter(CompilerBackend::is_available)
+        .collect()
+}
+
+fn runtime(compiler: CompilerBackend) -> Runtime {
+    let wasm = wat::parse_str(MODULE).expect("crafted module must parse");
+    let live_object_info = LiveObjectInfo::new(wasm, None).unwrap();
+
+    RuntimeBuilder::new(Arc::new(MemoryStorage::default()), live_object_info)
+        .message(MessageContext {
+            id: "message".to_string(),
+            timestamp: 2,
+        })
+        .compiler(compiler)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn calls_reach_the_same_functions_with_the_interrupt_checks_in_place() {
+    for compiler in available_compilers() {
+        let mut runtime = runtime(compiler);
+        runtime.set_timeout(Some(Duration::from_secs(10)));
+
+        let result = runtime.run("calls".to_string(), Vec::new()).unwrap();
+        assert_eq!(result, "6", "{} must keep the function indexes", compiler);
+    }
+}
+
+#[test]
+fn timeout_interrupts_loops() {
+    for compiler in available_compilers() {
+        let mut runtime = runtime(compiler);
+        runtime.set_timeout(Some(Duration::from_millis(100)));
+
+        let result = runtime.run("spin".to_string(), Vec::new());
+        assert!(
+            matches!(result, Err(VmError::Interrupted(InterruptReason::Timeout))),
+            "{} must interrupt the loop, got {:?}",
+            compiler,
+            result
+        );
+    }
+}
+
+#[test]
+fn cancelling_interrupts_from_another_thread() {
+    for compiler in available_compilers() {
+        let mut runtime = runtime(compiler);
+        let interrupt_handle = InterruptHandle::new();
+        runtime.set_interrupt_handle(interrupt_handle.clone());
+
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            interrupt_handle.interrupt(InterruptReason::Cancelled);
+        });
+
+        let result = runtime.run("spin_calls".to_string(), Vec::new());
+        canceller.join().unwrap();
+
+        assert!(
+            matches!(
+                result,
+                Err(VmError::Interrupted(InterruptReason::Cancelled))
+            ),
+            "{} must interrupt the loop, got {:?}",
+            compiler,
+            result
+        );
+    }
+}
+
+#[test]
+fn cancelled_handles_refuse_to_run() {
+    for compiler in available_compilers() {
+        let mut runtime = runtime(compiler);
+        let interrupt_handle = InterruptHandle::new();
+        interrupt_handle.interrupt(InterruptReason::Cancelled);
+        interrupt_handle.interrupt(InterruptReason::Timeout);
+        runtime.set_interrupt_handle(interrupt_handle);
+
+        let result = runtime.run("calls".to_string(), Vec::new());
+        assert!(matches!(
+            result,
+            Err(VmError::Interrupted(InterruptReason::Cancelled))
+        ));
+    }
+}
+
+#[test]
+fn one_watchdog_serves_many_timeouts() {
+    let runtimes = (0..4)
+        .map(|_| runtime(available_compilers()[0]))
+        .collect::<Vec<_>>();
+
+    let handles = runtimes
+        .into_iter()
+        .enumerate()
+        .map(|(index, mut runtime)| {
+            thread::spawn(move || {
+                runtime.set_timeout(Some(Duration::from_millis(50 * (index as u64 + 1))));
+                runtime.run("spin".to_string(), Vec::new())
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        assert!(matches!(
+            handle.join().unwrap(),
+            Err(VmError::Interrupted(InterruptReason::Timeout))
+        ));
+    }
+}
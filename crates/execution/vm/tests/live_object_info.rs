@@ -0,0 +1,130 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The metadata of live objects reads back as it was stored, and malformed metadata is refused.
+
+use ramd_db::memory::MemoryStorage;
+use ramd_db::storage::Storage;
+use ramd_vm::{LiveObjectInfo, LiveObjectMetadata};
+
+/// A live object with an ABI.
+const ABI_WASM: &[u8] = include_bytes!("../../../../tests/wasms/live_object_read_only.wasm");
+
+/// A live object without an ABI.
+const PLAIN_MODULE: &str = r#"(module (memory (export "memory") 1))"#;
+
+/// Store the live object and return its ID and the encoded metadata.
+fn store(live_object_info: LiveObjectInfo) -> (MemoryStorage, String, Vec<u8>) {
+    let storage = MemoryStorage::default();
+    let live_object_id = live_object_info.id.clone();
+    live_object_info.store(&storage).unwrap();
+    let bytes = storage.get(live_object_id.clone().into_bytes()).unwrap();
+
+    (storage, live_object_id, bytes)
+}
+
+#[test]
+fn live_objects_round_trip() {
+    let plain = wat::parse_str(PLAIN_MODULE).unwrap();
+    for (wasm_bytes, memory_limit) in [(ABI_WASM.to_vec(), Some(1 << 20)), (plain, None)] {
+        let original = LiveObjectInfo::new(wasm_bytes.clone(), memory_limit).unwrap();
+        let (id, hash, abi) = (
+            original.id.clone(),
+            original.hash.clone(),
+            original.abi.clone(),
+        );
+        let (storage, _, _) = store(original);
+
+        let loaded = LiveObjectInfo::load(&storage, &id).unwrap().unwrap();
+        assert_eq!(loaded.id, id);
+        assert_eq!(loaded.hash, hash);
+        assert_eq!(loaded.wasm_bytes, wasm_bytes);
+        assert_eq!(loaded.memory_limit, memory_limit);
+        assert_eq!(loaded.abi, abi);
+    }
+}
+
+#[test]
+fn truncated_metadata_is_rejected() {
+    let (storage, live_object_id, bytes) =
+        store(LiveObjectInfo::new(ABI_WASM.to_vec(), Some(1 << 20)).unwrap());
+
+    for len in 0..bytes.len() {
+        storage
+            .set(
+                live_object_id.clone().into_bytes(),
+                Vec::from(&bytes[..len]),
+            )
+            .unwrap();
+        assert!(
+            LiveObjectMetadata::load(&storage, &live_object_id).is_err(),
+            "{}",
+            len
+        );
+    }
+}
+
+#[test]
+fn corrupt_metadata_is_rejected() {
+    let (storage, live_object_id, bytes) =
+        store(LiveObjectInfo::new(ABI_WASM.to_vec(), None).unwrap());
+    let corruptions: [(usize, u8); 3] = [
+        // An unknown encoding version.
+        (0, 2),
+        // An ID that isn't UTF-8.
+        (1 + 8, 0xff),
+        // An ABI that isn't JSON.
+        (bytes.len() - 1, b'!'),
+    ];
+
+    for (index, byte) in corruptions {
+        let mut corrupt = bytes.clone();
+        corrupt[index] = byte;
+        storage
+            .set(live_object_id.clone().into_bytes(), corrupt)
+            .unwrap();
+        assert!(
+            LiveObjectMetadata::load(&storage, &live_object_id).is_err(),
+            "{}",
+            index
+        );
+    }
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    storage
+        .set(live_object_id.clone().into_bytes(), trailing)
+        .unwrap();
+    assert!(LiveObjectMetadata::load(&storage, &live_object_id).is_err());
+}
+
+#[test]
+fn missing_code_is_reported() {
+    let (storage, live_object_id, _) = store(LiveObjectInfo::new(ABI_WASM.to_vec(), None).unwrap());
+    storage
+        .delete(
+            [
+                ramd_vm::LIVE_OBJECT_CODE_KEY_PREFIX,
+                live_object_id.as_bytes(),
+            ]
+            .concat(),
+        )
+        .unwrap();
+
+    assert!(LiveObjectMetadata::load(&storage, &live_object_id)
+        .unwrap()
+        .is_some());
+    assert!(LiveObjectInfo::load(&storage, &live_object_id).is_err());
+}
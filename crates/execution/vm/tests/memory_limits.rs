@@ -0,0 +1,108 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The guest can grow its memory up to the limit resolved from the `MemoryLimits`, and no further.
+
+use std::sync::Arc;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_vm::{CompilerBackend, LiveObjectInfo, MemoryLimits, Runtime, RuntimeBuilder, VmError};
+use wasmer_types::WASM_PAGE_SIZE;
+
+/// A live object whose `grow` method returns whether its memory could grow by a page.
+const GROW_MODULE: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "allocate") (param $len i32) (result i32)
+    (i32.store (i32.const 64) (i32.const 72))
+    (i32.store (i32.const 68) (local.get $len))
+    (i32.const 64))
+  (func (export "deallocate") (param i32))
+  (func (export "grow") (param i32) (result i32)
+    (i32.store8 (i32.const 128)
+      (i32.add (i32.const 48) (i32.ne (memory.grow (i32.const 1)) (i32.const -1))))
+    (i32.store (i32.const 64) (i32.const 128))
+    (i32.store (i32.const 68) (i32.const 1))
+    (i32.const 64)))
+"#;
+
+/// Limits of two pages by default, and of at most four pages.
+const MEMORY_LIMITS: MemoryLimits = MemoryLimits {
+    default_size: 2 * WASM_PAGE_SIZE,
+    max_size: 4 * WASM_PAGE_SIZE,
+};
+
+fn available_compilers() -> Vec<CompilerBackend> {
+    [CompilerBackend::Cranelift, CompilerBackend::Singlepass]
+        .into_iter()
+        .filter(CompilerBackend::is_available)
+        .collect()
+}
+
+fn build(compiler: CompilerBackend, memory_limit: Option<usize>) -> Result<Runtime, VmError> {
+    let live_object_info = LiveObjectInfo::new(
+        wat::parse_str(GROW_MODULE).expect("crafted module must parse"),
+        memory_limit,
+    )
+    .unwrap();
+
+    RuntimeBuilder::new(Arc::new(MemoryStorage::default()), live_object_info)
+        .memory_limits(MEMORY_LIMITS)
+        .compiler(compiler)
+        .build()
+}
+
+/// Grow the memory page by page until it fails, and return the number of pages it ends up with.
+fn grow_to_limit(runtime: &mut Runtime) -> usize {
+    let mut pages = 1;
+    while runtime.run("grow".to_string(), Vec::new()).unwrap() == "1" {
+        pages += 1;
+        assert!(pages <= 8, "memory grew past every limit");
+    }
+    pages
+}
+
+#[test]
+fn memory_grows_up_to_the_default_limit() {
+    for compiler in available_compilers() {
+        let mut runtime = build(compiler, None).unwrap();
+
+        assert_eq!(grow_to_limit(&mut runtime), 2, "{}", compiler);
+        // Failing to grow leaves the runtime usable.
+        assert_eq!(runtime.run("grow".to_string(), Vec::new()).unwrap(), "0");
+    }
+}
+
+#[test]
+fn memory_grows_up_to_the_limit_of_the_live_object() {
+    for compiler in available_compilers() {
+        let mut runtime = build(compiler, Some(3 * WASM_PAGE_SIZE)).unwrap();
+
+        assert_eq!(grow_to_limit(&mut runtime), 3, "{}", compiler);
+    }
+}
+
+#[test]
+fn memory_limits_above_the_maximum_are_rejected() {
+    for compiler in available_compilers() {
+        let result = build(compiler, Some(5 * WASM_PAGE_SIZE));
+
+        assert!(
+            matches!(result, Err(VmError::LimitExceeded(_))),
+            "{}: built a runtime past the maximum memory limit",
+            compiler
+        );
+    }
+}
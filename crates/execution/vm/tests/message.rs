@@ -0,0 +1,128 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Live objects see the message being executed, and the random seed derived from it, through host functions.
+
+use std::sync::Arc;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_db::storage::Storage;
+use ramd_vm::{CompilerBackend, LiveObjectInfo, MessageContext, RuntimeBuilder};
+
+/// A live object whose `context` method writes the message ID at key `a`, its own ID at `b`, the random seed
+/// at `c` and the message timestamp, little-endian, at `d`.
+const CONTEXT_MODULE: &str = r#"
+(module
+  (import "env" "storage_write" (func $storage_write (param i32 i32)))
+  (import "env" "message_id" (func $message_id (result i32)))
+  (import "env" "live_object_id" (func $live_object_id (result i32)))
+  (import "env" "random_seed" (func $random_seed (result i32)))
+  (import "env" "message_timestamp" (func $message_timestamp (result i64)))
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  ;; Slices of the keys at 64 to 67, of the result at 80 and of the timestamp at 96.
+  (data (i32.const 16) "\40\00\00\00\01\00\00\00\41\00\00\00\01\00\00\00\42\00\00\00\01\00\00\00\43\00\00\00\01\00\00\00")
+  (data (i32.const 48) "\50\00\00\00\02\00\00\00\60\00\00\00\08\00\00\00")
+  (data (i32.const 64) "abcd")
+  (data (i32.const 80) "ok")
+  (func $allocate (export "allocate") (param $len i32) (result i32)
+    (local $slice i32)
+    (local.set $slice (global.get $next))
+    (i32.store (local.get $slice) (i32.add (local.get $slice) (i32.const 8)))
+    (i32.store offset=4 (local.get $slice) (local.get $len))
+    (global.set $next (i32.add (global.get $next) (i32.add (local.get $len) (i32.const 8))))
+    (local.get $slice))
+  (func $deallocate (export "deallocate") (param i32))
+  (func (export "context") (param $args i32) (result i32)
+    (call $deallocate (local.get $args))
+    (call $storage_write (i32.const 16) (call $message_id))
+    (call $storage_write (i32.const 24) (call $live_object_id))
+    (call $storage_write (i32.const 32) (call $random_seed))
+    (i64.store (i32.const 96) (call $message_timestamp))
+    (call $storage_write (i32.const 40) (i32.const 56))
+    (i32.const 48)))
+"#;
+
+fn available_compilers() -> Vec<CompilerBackend> {
+    [CompilerBackend::Cranelift, CompilerBackend::Singlepass]
+        .into_iter()
+        .filter(CompilerBackend::is_available)
+        .collect()
+}
+
+fn live_object_info() -> LiveObjectInfo {
+    LiveObjectInfo::new(
+        wat::parse_str(CONTEXT_MODULE).expect("crafted module must parse"),
+        None,
+    )
+    .unwrap()
+}
+
+/// Execute `context` for the message in a fresh storage and read back the keys `a` to `d`.
+fn execute(compiler: CompilerBackend, message: &MessageContext) -> Vec<Vec<u8>> {
+    let storage = Arc::new(MemoryStorage::default());
+    let mut runtime = RuntimeBuilder::new(storage.clone(), live_object_info())
+        .namespace(Vec::new())
+        .message(message.clone())
+        .compiler(compiler)
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        runtime.run("context".to_string(), Vec::new()).unwrap(),
+        "ok"
+    );
+
+    [b'a', b'b', b'c', b'd']
+        .into_iter()
+        .map(|key| storage.get(vec![key]).unwrap())
+        .collect()
+}
+
+#[test]
+fn the_host_functions_return_the_message() {
+    let message = MessageContext {
+        id: "0xmessage".to_string(),
+        timestamp: 1_700_000_000_000,
+    };
+
+    for compiler in available_compilers() {
+        let values = execute(compiler, &message);
+
+        assert_eq!(values[0], message.id.as_bytes(), "{}", compiler);
+        assert_eq!(values[1], live_object_info().id.as_bytes(), "{}", compiler);
+        assert_eq!(values[2], message.random_seed(), "{}", compiler);
+        assert_eq!(values[3], message.timestamp.to_le_bytes(), "{}", compiler);
+    }
+}
+
+#[test]
+fn the_random_seed_is_the_same_when_the_message_runs_again() {
+    let message = MessageContext {
+        id: "0xmessage".to_string(),
+        timestamp: 1_700_000_000_000,
+    };
+    let other_message = MessageContext {
+        id: "0xother".to_string(),
+        ..message.clone()
+    };
+
+    for compiler in available_compilers() {
+        let seed = execute(compiler, &message).swap_remove(2);
+
+        assert_eq!(execute(compiler, &message)[2], seed, "{}", compiler);
+        assert_ne!(execute(compiler, &other_message)[2], seed, "{}", compiler);
+    }
+}
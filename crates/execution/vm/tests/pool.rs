@@ -0,0 +1,245 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Pooled runtimes must behave like freshly instantiated ones, whatever an earlier message left behind.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_vm::{
+    CompilerBackend, FloatPolicy, LiveObjectInfo, MemoryLimits, MessageContext, RuntimeBuilder,
+    RuntimePool, RuntimePoolConfig,
+};
+
+/// The size of a WASM memory page in bytes.
+const WASM_PAGE_SIZE: usize = 65536;
+
+/// A live object whose `touch` method returns the state it found as digits, then changes all of it:
+/// a global, a byte of memory, and the table.
+const STATE_MODULE: &str = r#"
+(module
+  (type $digit (func (result i32)))
+  (memory (export "memory") 1)
+  (global $counter (mut i32) (i32.const 0))
+  (table 2 funcref)
+  (elem (i32.const 0) $one $two)
+  (func $one (result i32) (i32.const 1))
+  (func $two (result i32) (i32.const 2))
+  (func (export "allocate") (param $len i32) (result i32)
+    (i32.store (i32.const 64) (i32.const 72))
+    (i32.store (i32.const 68) (local.get $len))
+    (i32.const 64))
+  (func (export "deallocate") (param i32))
+  (func (export "touch") (param i32) (result i32)
+    (i32.store8 (i32.const 128) (i32.add (i32.const 48) (global.get $counter)))
+    (i32.store8 (i32.const 129) (i32.add (i32.const 48) (i32.load8_u (i32.const 256))))
+    (i32.store8 (i32.const 130) (i32.add (i32.const 48) (call_indirect (type $digit) (i32.const 0))))
+    (global.set $counter (i32.add (global.get $counter) (i32.const 1)))
+    (i32.store8 (i32.const 256) (i32.const 7))
+    (table.set (i32.const 0) (table.get (i32.const 1)))
+    (i32.store (i32.const 64) (i32.const 128))
+    (i32.store (i32.const 68) (i32.const 3))
+    (i32.const 64))
+  (func (export "grow_table") (param i32) (result i32)
+    (drop (table.grow (ref.null func) (i32.const 1)))
+    (i32.store8 (i32.const 128) (i32.add (i32.const 48) (table.size)))
+    (i32.store (i32.const 64) (i32.const 128))
+    (i32.store (i32.const 68) (i32.const 1))
+    (i32.const 64)))
+"#;
+
+/// A live object whose `init` method copies a passive data segment into memory and drops it, which makes
+/// a second `init` on the same instance trap.
+const PASSIVE_MODULE: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (data $greeting "hi")
+  (func (export "allocate") (param $len i32) (result i32)
+    (i32.store (i32.const 64) (i32.const 72))
+    (i32.store (i32.const 68) (local.get $len))
+    (i32.const 64))
+  (func (export "deallocate") (param i32))
+  (func (export "init") (param i32) (result i32)
+    (memory.init $greeting (i32.const 128) (i32.const 0) (i32.const 2))
+    (data.drop $greeting)
+    (i32.store (i32.const 64) (i32.const 128))
+    (i32.store (i32.const 68) (i32.const 2))
+    (i32.const 64)))
+"#;
+
+/// A live object whose `grow` method returns whether its memory could grow by a page.
+const GROW_MODULE: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "allocate") (param $len i32) (result i32)
+    (i32.store (i32.const 64) (i32.const 72))
+    (i32.store (i32.const 68) (local.get $len))
+    (i32.const 64))
+  (func (export "deallocate") (param i32))
+  (func (export "grow") (param i32) (result i32)
+    (i32.store8 (i32.const 128)
+      (i32.add (i32.const 48) (i32.ne (memory.grow (i32.const 1)) (i32.const -1))))
+    (i32.store (i32.const 64) (i32.const 128))
+    (i32.store (i32.const 68) (i32.const 1))
+    (i32.const 64)))
+"#;
+
+fn available_compilers() -> Vec<CompilerBackend> {
+    [CompilerBackend::Cranelift, CompilerBackend::Singlepass]
+        .into_iter()
+        .filter(CompilerBackend::is_available)
+        .collect()
+}
+
+fn live_object_info(wat: &str) -> LiveObjectInfo {
+    live_object_info_with_limit(wat, None)
+}
+
+fn live_object_info_with_limit(wat: &str, memory_limit: Option<usize>) -> LiveObjectInfo {
+    LiveObjectInfo::new(
+        wat::parse_str(wat).expect("crafted module must parse"),
+        memory_limit,
+    )
+    .unwrap()
+}
+
+fn pool(compiler: CompilerBackend) -> RuntimePool<MemoryStorage> {
+    RuntimePool::new(
+        RuntimePoolConfig {
+            size: 1,
+            idle_timeout: Duration::from_secs(60),
+        },
+        MemoryLimits::default(),
+        compiler,
+        FloatPolicy::default(),
+    )
+}
+
+/// Run the method on a runtime taken from the pool and give the runtime back.
+fn run_pooled(pool: &RuntimePool<MemoryStorage>, wat: &str, method: &str) -> String {
+    run_pooled_with_limit(pool, wat, None, method)
+}
+
+/// Run the method on a runtime of the live object with the given memory limit taken from the pool.
+fn run_pooled_with_limit(
+    pool: &RuntimePool<MemoryStorage>,
+    wat: &str,
+    memory_limit: Option<usize>,
+    method: &str,
+) -> String {
+    let mut runtime = pool
+        .acquire(
+            Arc::new(MemoryStorage::default()),
+            live_object_info_with_limit(wat, memory_limit),
+            MessageContext::default(),
+        )
+        .unwrap();
+    let result = runtime.run(method.to_string(), Vec::new());
+    pool.release(runtime);
+
+    result.unwrap()
+}
+
+/// Run the method on a freshly instantiated runtime.
+fn run_fresh(compiler: CompilerBackend, wat: &str, method: &str) -> String {
+    RuntimeBuilder::new(Arc::new(MemoryStorage::default()), live_object_info(wat))
+        .compiler(compiler)
+        .build()
+        .unwrap()
+        .run(method.to_string(), Vec::new())
+        .unwrap()
+}
+
+#[test]
+fn pooled_runtimes_start_from_the_same_globals_memory_and_tables() {
+    for compiler in available_compilers() {
+        let pool = pool(compiler);
+        let fresh = run_fresh(compiler, STATE_MODULE, "touch");
+        assert_eq!(fresh, "001");
+
+        for _ in 0..3 {
+            assert_eq!(
+                run_pooled(&pool, STATE_MODULE, "touch"),
+                fresh,
+                "{}",
+                compiler
+            );
+        }
+    }
+}
+
+#[test]
+fn pooled_runtimes_with_grown_tables_are_not_reused() {
+    for compiler in available_compilers() {
+        let pool = pool(compiler);
+        let fresh = run_fresh(compiler, STATE_MODULE, "grow_table");
+        assert_eq!(fresh, "3");
+
+        for _ in 0..3 {
+            assert_eq!(
+                run_pooled(&pool, STATE_MODULE, "grow_table"),
+                fresh,
+                "{}",
+                compiler
+            );
+        }
+        assert_eq!(
+            run_pooled(&pool, STATE_MODULE, "touch"),
+            run_fresh(compiler, STATE_MODULE, "touch")
+        );
+    }
+}
+
+#[test]
+fn pooled_runtimes_with_passive_segments_behave_like_fresh_ones() {
+    for compiler in available_compilers() {
+        let pool = pool(compiler);
+        let fresh = run_fresh(compiler, PASSIVE_MODULE, "init");
+        assert_eq!(fresh, "hi");
+
+        for _ in 0..3 {
+            assert_eq!(
+                run_pooled(&pool, PASSIVE_MODULE, "init"),
+                fresh,
+                "{}",
+                compiler
+            );
+        }
+    }
+}
+
+#[test]
+fn pooled_runtimes_are_kept_apart_by_memory_limit() {
+    for compiler in available_compilers() {
+        let pool = pool(compiler);
+        let one_page = Some(WASM_PAGE_SIZE);
+        let two_pages = Some(2 * WASM_PAGE_SIZE);
+
+        // The same code under a larger limit doesn't get the engine compiled for the smaller one.
+        assert_eq!(
+            run_pooled_with_limit(&pool, GROW_MODULE, one_page, "grow"),
+            "0"
+        );
+        assert_eq!(
+            run_pooled_with_limit(&pool, GROW_MODULE, two_pages, "grow"),
+            "1"
+        );
+        assert_eq!(
+            run_pooled_with_limit(&pool, GROW_MODULE, one_page, "grow"),
+            "0"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//! Runtimes load the artifacts of `precompile` in place of compiling, as long as they fit the engine and the code.
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_vm::{
+    artifact_file_name, precompile, CompilerBackend, FloatPolicy, LiveObjectInfo, MemoryLimits,
+    MessageContext, RuntimeBuilder, RuntimePool, RuntimePoolConfig, DEFAULT_WASM_MEMORY_SIZE,
+};
+
+/// A live object whose `name` method returns the letter.
+fn named_module(letter: char) -> Vec<u8> {
+    wat::parse_str(format!(
+        r#"
+(module
+  (memory (export "memory") 1)
+  (data (i32.const 128) "{}")
+  (func (export "allocate") (param $len i32) (result i32)
+    (i32.store (i32.const 64) (i32.const 72))
+    (i32.store (i32.const 68) (local.get $len))
+    (i32.const 64))
+  (func (export "deallocate") (param i32))
+  (func (export "name") (param i32) (result i32)
+    (i32.store (i32.const 64) (i32.const 128))
+    (i32.store (i32.const 68) (i32.const 1))
+    (i32.const 64)))
+"#,
+        letter
+    ))
+    .expect("crafted module must parse")
+}
+
+/// Live object `A`, whose stored code is that of `B`. A runtime answers `A` if it loaded the artifact of `A`,
+/// and `B` if it compiled the code instead.
+fn swapped_live_object() -> LiveObjectInfo {
+    LiveObjectInfo {
+        wasm_bytes: named_module('B'),
+        ..LiveObjectInfo::new(named_module('A'), None).unwrap()
+    }
+}
+
+/// Write the artifact of live object `A` into `dir`.
+fn write_artifact(dir: &Path, compiler: CompilerBackend, artifact: &[u8]) {
+    let live_object_id = swapped_live_object().id;
+    std::fs::write(
+        dir.join(artifact_file_name(&live_object_id, compiler)),
+        artifact,
+    )
+    .unwrap();
+}
+
+fn available_compilers() -> Vec<CompilerBackend> {
+    [CompilerBackend::Cranelift, CompilerBackend::Singlepass]
+        .into_iter()
+        .filter(CompilerBackend::is_available)
+        .collect()
+}
+
+fn artifact(
+    live_object_info: &LiveObjectInfo,
+    compiler: CompilerBackend,
+    memory_limit: usize,
+) -> Vec<u8> {
+    precompile(
+        live_object_info,
+        compiler,
+        FloatPolicy::default(),
+        memory_limit,
+    )
+    .unwrap()
+}
+
+fn run(dir: &Path, compiler: CompilerBackend) -> String {
+    RuntimeBuilder::new(Arc::new(MemoryStorage::default()), swapped_live_object())
+        .compiler(compiler)
+        .precompiled_dir(dir.to_path_buf())
+        .build()
+        .unwrap()
+        .run("name".to_string(), Vec::new())
+        .unwrap()
+}
+
+#[test]
+fn artifacts_are_loaded_instead_of_compiling() {
+    for compiler in available_compilers() {
+        let dir = tempfile::tempdir().unwrap();
+        let live_object_info = LiveObjectInfo::new(named_module('A'), None).unwrap();
+        write_artifact(
+            dir.path(),
+            compiler,
+            &artifact(&live_object_info, compiler, DEFAULT_WASM_MEMORY_SIZE),
+        );
+
+        assert_eq!(run(dir.path(), compiler), "A", "{}", compiler);
+
+        let pool = RuntimePool::new(
+            RuntimePoolConfig {
+                size: 1,
+                idle_timeout: Duration::from_secs(60),
+            },
+            MemoryLimits::default(),
+            compiler,
+            FloatPolicy::default(),
+        )
+        .precompiled_dir(dir.path().to_path_buf());
+        let mut runtime = pool
+            .acquire(
+                Arc::new(MemoryStorage::default()),
+                swapped_live_object(),
+                MessageContext::default(),
+            )
+            .unwrap();
+        assert_eq!(
+            runtime.run("name".to_string(), Vec::new()).unwrap(),
+            "A",
+            "{}",
+            compiler
+        );
+    }
+}
+
+#[test]
+fn live_objects_without_an_artifact_are_compiled() {
+    for compiler in available_compilers() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(run(dir.path(), compiler), "B", "{}", compiler);
+    }
+}
+
+#[test]
+fn artifacts_for_another_memory_limit_are_ignored() {
+    for compiler in available_compilers() {
+        let dir = tempfile::tempdir().unwrap();
+        let live_object_info = LiveObjectInfo::new(named_module('A'), None).unwrap();
+        write_artifact(
+            dir.path(),
+            compiler,
+            &artifact(&live_object_info, compiler, 2 * DEFAULT_WASM_MEMORY_SIZE),
+        );
+
+        assert_eq!(run(dir.path(), compiler), "B", "{}", compiler);
+    }
+}
+
+#[test]
+fn artifacts_of_other_code_are_ignored() {
+    for compiler in available_compilers() {
+        let dir = tempfile::tempdir().unwrap();
+        let live_object_info = LiveObjectInfo::new(named_module('C'), None).unwrap();
+        write_artifact(
+            dir.path(),
+            compiler,
+            &artifact(&live_object_info, compiler, DEFAULT_WASM_MEMORY_SIZE),
+        );
+
+        assert_eq!(run(dir.path(), compiler), "B", "{}", compiler);
+    }
+}
+
+#[test]
+fn corrupt_artifacts_are_ignored() {
+    for compiler in available_compilers() {
+        let dir = tempfile::tempdir().unwrap();
+        let live_object_info = LiveObjectInfo::new(named_module('A'), None).unwrap();
+        let mut corrupt = artifact(&live_object_info, compiler, DEFAULT_WASM_MEMORY_SIZE);
+        corrupt.truncate(corrupt.len() / 2);
+        write_artifact(dir.path(), compiler, &corrupt);
+
+        assert_eq!(run(dir.path(), compiler), "B", "{}", compiler);
+
+        write_artifact(dir.path(), compiler, b"not an artifact");
+        assert_eq!(run(dir.path(), compiler), "B", "{}", compiler);
+    }
+}
@@ -0,0 +1,200 @@
+// Copyright (C) 2024 Jihoon Song
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The storage host functions, and the storage quota they enforce as the live object writes.
+
+use std::sync::Arc;
+
+use ramd_db::memory::MemoryStorage;
+use ramd_db::storage::Storage;
+use ramd_vm::{CompilerBackend, LiveObjectInfo, Runtime, RuntimeBuilder, VmError};
+
+/// A live object whose `write` method writes its arguments at key `k`, and whose `delete` method deletes it.
+/// Its `read` method reads `k` into a 4-byte buffer filled with `-`, writes the length that
+/// `storage_read_into` returned at key `n`, little-endian, and returns the buffer.
+const STORAGE_MODULE: &str = r#"
+(module
+  (import "env" "storage_write" (func $storage_write (param i32 i32)))
+  (import "env" "storage_delete" (func $storage_delete (param i32)))
+  (import "env" "storage_read_into" (func $storage_read_into (param i32 i32) (result i64)))
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  ;; Slices of the key `k` at 64, of the result at 80, of the buffer at 96, of the length at 104
+  ;; and of the key `n` at 65.
+  (data (i32.const 16) "\40\00\00\00\01\00\00\00\50\00\00\00\02\00\00\00")
+  (data (i32.const 32) "\60\00\00\00\04\00\00\00\68\00\00\00\08\00\00\00\41\00\00\00\01\00\00\00")
+  (data (i32.const 64) "kn")
+  (data (i32.const 80) "ok")
+  (data (i32.const 96) "----")
+  (func $allocate (export "allocate") (param $len i32) (result i32)
+    (local $slice i32)
+    (local.set $slice (global.get $next))
+    (i32.store (local.get $slice) (i32.add (local.get $slice) (i32.const 8)))
+    (i32.store offset=4 (local.get $slice) (local.get $len))
+    (global.set $next (i32.add (global.get $next) (i32.add (local.get $len) (i32.const 8))))
+    (local.get $slice))
+  (func $deallocate (export "deallocate") (param i32))
+  (func (export "write") (param $args i32) (result i32)
+    (call $storage_write (i32.const 16) (local.get $args))
+    (call $deallocate (local.get $args))
+    (i32.const 24))
+  (func (export "delete") (param $args i32) (result i32)
+    (call $deallocate (local.get $args))
+    (call $storage_delete (i32.const 16))
+    (i32.const 24))
+  (func (export "read") (param $args i32) (result i32)
+    (call $deallocate (local.get $args))
+    (i64.store (i32.const 104) (call $storage_read_into (i32.const 16) (i32.const 32)))
+    (call $storage_write (i32.const 48) (i32.const 40))
+    (i32.const 32)))
+"#;
+
+fn available_compilers() -> Vec<CompilerBackend> {
+    [CompilerBackend::Cranelift, CompilerBackend::Singlepass]
+        .into_iter()
+        .filter(CompilerBackend::is_available)
+        .collect()
+}
+
+fn live_object_info() -> LiveObjectInfo {
+    LiveObjectInfo::new(
+        wat::parse_str(STORAGE_MODULE).expect("crafted module must parse"),
+        None,
+    )
+    .unwrap()
+}
+
+/// The key of the live object, as stored.
+fn state_key(key: u8) -> Vec<u8> {
+    let mut state_key = live_object_info().id.into_bytes();
+    state_key.push(key);
+    state_key
+}
+
+/// Run `read`, and return the buffer and the length that `storage_read_into` returned.
+fn read(runtime: &mut Runtime, storage: &MemoryStorage) -> (String, i64) {
+    let buffer = runtime.run("read".to_string(), Vec::new()).unwrap();
+    let len = storage.get(state_key(b'n')).unwrap();
+
+    (buffer, i64::from_le_bytes(len.try_into().unwrap()))
+}
+
+fn runtime(
+    compiler: CompilerBackend,
+    storage: Arc<MemoryStorage>,
+    storage_quota: Option<u64>,
+) -> Runtime {
+    let mut builder = RuntimeBuilder::new(storage, live_object_info()).compiler(compiler);
+    if let Some(storage_quota) = storage_quota {
+        builder = builder.storage_quota(storage_quota);
+    }
+    builder.build().unwrap()
+}
+
+#[test]
+fn writes_past_the_storage_quota_trap() {
+    for compiler in available_compilers() {
+        let storage = Arc::new(MemoryStorage::default());
+        let quota = state_key(b'k').len() as u64 + 10;
+        let mut runtime = runtime(compiler, storage.clone(), Some(quota));
+
+        assert_eq!(runtime.run("write".to_string(), vec![0; 10]).unwrap(), "ok");
+
+        let result = runtime.run("write".to_string(), vec![1; 11]);
+        assert!(
+            matches!(result, Err(VmError::LimitExceeded(_))),
+            "{}: {:?}",
+            compiler,
+            result
+        );
+        // The write that didn't fit never reached the storage.
+        assert_eq!(storage.get(state_key(b'k')).unwrap(), vec![0; 10]);
+
+        // Freed space can be written again.
+        assert_eq!(runtime.run("delete".to_string(), Vec::new()).unwrap(), "ok");
+        assert_eq!(runtime.run("write".to_string(), vec![2; 10]).unwrap(), "ok");
+        assert_eq!(storage.get(state_key(b'k')).unwrap(), vec![2; 10]);
+    }
+}
+
+#[test]
+fn storage_is_unlimited_without_a_quota() {
+    for compiler in available_compilers() {
+        let storage = Arc::new(MemoryStorage::default());
+        let mut runtime = runtime(compiler, storage.clone(), None);
+
+        assert_eq!(
+            runtime.run("write".to_string(), vec![0; 4096]).unwrap(),
+            "ok"
+        );
+        assert_eq!(storage.get(state_key(b'k')).unwrap(), vec![0; 4096]);
+    }
+}
+
+#[test]
+fn storage_read_into_fills_a_buffer_that_fits() {
+    for compiler in available_compilers() {
+        let storage = Arc::new(MemoryStorage::default());
+        let mut runtime = runtime(compiler, storage.clone(), None);
+
+        assert_eq!(
+            runtime.run("write".to_string(), b"ab".to_vec()).unwrap(),
+            "ok"
+        );
+        assert_eq!(
+            read(&mut runtime, &storage),
+            ("ab--".to_string(), 2),
+            "{}",
+            compiler
+        );
+    }
+}
+
+#[test]
+fn storage_read_into_leaves_a_short_buffer_untouched() {
+    for compiler in available_compilers() {
+        let storage = Arc::new(MemoryStorage::default());
+        let mut runtime = runtime(compiler, storage.clone(), None);
+
+        assert_eq!(
+            runtime
+                .run("write".to_string(), b"abcdefghij".to_vec())
+                .unwrap(),
+            "ok"
+        );
+        // The guest learns the length it needs without anything being written.
+        assert_eq!(
+            read(&mut runtime, &storage),
+            ("----".to_string(), 10),
+            "{}",
+            compiler
+        );
+    }
+}
+
+#[test]
+fn storage_read_into_returns_minus_one_for_a_missing_key() {
+    for compiler in available_compilers() {
+        let storage = Arc::new(MemoryStorage::default());
+        let mut runtime = runtime(compiler, storage.clone(), None);
+
+        assert_eq!(
+            read(&mut runtime, &storage),
+            ("----".to_string(), -1),
+            "{}",
+            compiler
+        );
+    }
+}
@@ -1,4 +1,171 @@
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use ramd_cache::DEFAULT_READ_CACHE_SIZE;
+use ramd_processor::ProcessorConfig;
+use ramd_vm::{
+    CompilerBackend, FloatPolicy, MemoryLimits, RuntimePoolConfig,
+    DEFAULT_RUNTIME_POOL_IDLE_TIMEOUT_MS, DEFAULT_RUNTIME_POOL_SIZE, DEFAULT_WASM_MEMORY_SIZE,
+    MAX_WASM_MEMORY_SIZE,
+};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq, Serialize)]
-pub struct NodeConfig {}
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(default)]
+pub struct NodeConfig {
+    /// The number of worker threads used to execute messages of a batch in parallel.
+    pub processor_workers: usize,
+    /// The number of dedicated threads that take requests off the async runtime and process them.
+    pub executor_workers: usize,
+    /// The maximum number of requests waiting for an executor worker. Requests beyond it are rejected.
+    pub executor_queue_capacity: usize,
+    /// The wall-clock time a live object execution may take before it is interrupted. Zero disables the limit.
+    pub execution_timeout_ms: u64,
+    /// The memory limit in bytes of live objects that don't set their own when they are created.
+    pub default_memory_limit: usize,
+    /// The largest memory limit in bytes that a live object may set when it is created.
+    pub max_memory_limit: usize,
+    /// The number of bytes, counting both keys and values, that each live object may store. Zero disables the quota.
+    pub storage_quota: u64,
+    /// The compiler of live objects, either `cranelift` or `singlepass`. It must be enabled as a cargo feature.
+    pub compiler: CompilerBackend,
+    /// How floating-point operations of live objects are dealt with, either `canonicalize` to replace
+    /// the NaNs they produce with the canonical NaN, or `reject` to refuse live objects using them.
+    pub float_policy: FloatPolicy,
+    /// The number of instantiated runtimes kept per live object for later messages. Zero disables reuse.
+    pub runtime_pool_size: usize,
+    /// How long an unused runtime is kept before it is dropped.
+    pub runtime_pool_idle_timeout_ms: u64,
+    /// The number of bytes, counting both keys and values, of recently read storage kept in memory. Zero disables the cache.
+    pub read_cache_size: usize,
+    /// The directory of the artifacts written by `ramd precompile`, which are loaded instead of compiling
+    /// live objects. Live objects without a compatible artifact there are compiled as usual.
+    pub precompiled_dir: Option<PathBuf>,
+}
+
+impl NodeConfig {
+    pub fn execution_timeout(&self) -> Option<Duration> {
+        (self.execution_timeout_ms > 0).then(|| Duration::from_millis(self.execution_timeout_ms))
+    }
+
+    /// Check that the values make sense together, e.g. that the default memory limit is within the maximum.
+    pub fn validate(&self) -> eyre::Result<()> {
+        self.memory_limits()
+            .resolve(None)
+            .map_err(|err| eyre::eyre!("Invalid `default_memory_limit`: {}", err))?;
+
+        Ok(())
+    }
+
+    pub fn memory_limits(&self) -> MemoryLimits {
+        MemoryLimits {
+            default_size: self.default_memory_limit,
+            max_size: self.max_memory_limit,
+        }
+    }
+
+    pub fn storage_quota(&self) -> Option<u64> {
+        (self.storage_quota > 0).then_some(self.storage_quota)
+    }
+
+    pub fn runtime_pool_config(&self) -> RuntimePoolConfig {
+        RuntimePoolConfig {
+            size: self.runtime_pool_size,
+            idle_timeout: Duration::from_millis(self.runtime_pool_idle_timeout_ms),
+        }
+    }
+
+    pub fn processor_config(&self) -> ProcessorConfig {
+        ProcessorConfig {
+            workers: self.processor_workers,
+            timeout: self.execution_timeout(),
+            memory_limits: self.memory_limits(),
+            storage_quota: self.storage_quota(),
+            compiler: self.compiler,
+            float_policy: self.float_policy,
+            runtime_pool: self.runtime_pool_config(),
+            read_cache_size: self.read_cache_size,
+            precompiled_dir: self.precompiled_dir.clone(),
+        }
+    }
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        let available_parallelism = thread::available_parallelism()
+            .map(|workers| workers.get())
+            .unwrap_or(1);
+        let (executor_workers, processor_workers) = split_workers(available_parallelism);
+
+        Self {
+            processor_workers,
+            executor_workers,
+            executor_queue_capacity: 1024,
+            execution_timeout_ms: 5000,
+            default_memory_limit: DEFAULT_WASM_MEMORY_SIZE,
+            max_memory_limit: MAX_WASM_MEMORY_SIZE,
+            storage_quota: 0,
+            compiler: CompilerBackend::default(),
+            float_policy: FloatPolicy::default(),
+            runtime_pool_size: DEFAULT_RUNTIME_POOL_SIZE,
+            runtime_pool_idle_timeout_ms: DEFAULT_RUNTIME_POOL_IDLE_TIMEOUT_MS,
+            read_cache_size: DEFAULT_READ_CACHE_SIZE,
+            precompiled_dir: None,
+        }
+    }
+}
+
+/// Split the cores between the executor workers and the processor workers that each of them runs a batch on,
+/// so that both kinds of workers busy at once don't outnumber the cores. Half of the cores go to the executor
+/// workers, as requests mostly come one message at a time.
+fn split_workers(available_parallelism: usize) -> (usize, usize) {
+    let executor_workers = (available_parallelism / 2).max(1);
+    let processor_workers = (available_parallelism / executor_workers).max(1);
+
+    (executor_workers, processor_workers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_default_config_is_valid() {
+        NodeConfig::default().validate().unwrap();
+    }
+
+    #[test]
+    fn a_default_memory_limit_over_the_maximum_is_invalid() {
+        let config = NodeConfig {
+            default_memory_limit: 2 * MAX_WASM_MEMORY_SIZE,
+            ..Default::default()
+        };
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn the_default_workers_do_not_outnumber_the_cores() {
+        assert_eq!(split_workers(1), (1, 1));
+        assert_eq!(split_workers(2), (1, 2));
+        assert_eq!(split_workers(8), (4, 2));
+
+        for cores in 1..=64 {
+            let (executor_workers, processor_workers) = split_workers(cores);
+            assert!(executor_workers >= 1 && processor_workers >= 1);
+            // Every core is used, but for the last of an odd number.
+            let busy = executor_workers * processor_workers;
+            assert!(busy <= cores && busy + 1 >= cores, "{}", cores);
+        }
+    }
+
+    #[test]
+    fn the_default_workers_add_up_to_the_available_cores() {
+        let cores = thread::available_parallelism().unwrap().get();
+        let config = NodeConfig::default();
+
+        let busy = config.executor_workers * config.processor_workers;
+        assert!(busy <= cores && busy + 1 >= cores, "{}", cores);
+    }
+}
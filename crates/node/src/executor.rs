@@ -0,0 +1,150 @@
+use std::thread;
+
+use async_channel::{Receiver, Sender, TrySendError};
+use tokio::sync::oneshot;
+use tracing::{error, info};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+#[derive(Debug)]
+pub enum ExecutorError {
+    /// The queue is at capacity, so the job was rejected. The caller may retry later.
+    QueueFull,
+    /// The executor has stopped before the job could finish.
+    Stopped,
+}
+
+impl std::fmt::Display for ExecutorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for ExecutorError {}
+
+/// Runs blocking jobs, such as live object execution, on dedicated worker threads
+/// so that they never block the async runtime.
+pub struct Executor {
+    sender: Sender<Job>,
+}
+
+impl Executor {
+    /// Spawn `workers` threads that take jobs from a queue holding at most `queue_capacity` jobs.
+    pub fn new(workers: usize, queue_capacity: usize) -> eyre::Result<Self> {
+        let (sender, receiver) = async_channel::bounded::<Job>(queue_capacity.max(1));
+
+        for index in 0..workers.max(1) {
+            let receiver: Receiver<Job> = receiver.clone();
+            thread::Builder::new()
+                .name(format!("ramd-executor-{}", index))
+                .spawn(move || {
+                    // The loop ends once the executor is dropped and the queue is drained.
+                    while let Ok(job) = receiver.recv_blocking() {
+                        job();
+                    }
+                })?;
+        }
+
+        info!(target: "ramd::node", "Executor is started with {} workers", workers.max(1));
+
+        Ok(Self { sender })
+    }
+
+    /// Queue the job and return a receiver that resolves to its output.
+    /// Fails immediately with `ExecutorError::QueueFull` if the queue is at capacity.
+    pub fn submit<F, T>(&self, job: F) -> Result<oneshot::Receiver<T>, ExecutorError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (result_sender, result_receiver) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            // The caller may have given up waiting, in which case the output is dropped.
+            let _ = result_sender.send(job());
+        });
+
+        match self.sender.try_send(job) {
+            Ok(()) => Ok(result_receiver),
+            Err(TrySendError::Full(_)) => {
+                error!(target: "ramd::node", "Executor queue is full");
+                Err(ExecutorError::QueueFull)
+            }
+            Err(TrySendError::Closed(_)) => Err(ExecutorError::Stopped),
+        }
+    }
+
+    /// Queue the job and wait for its output.
+    pub async fn execute<F, T>(&self, job: F) -> Result<T, ExecutorError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.submit(job)?.await.map_err(|_| ExecutorError::Stopped)
+    }
+}
+
+impl Drop for Executor {
+    fn drop(&mut self) {
+        self.sender.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{channel, Sender};
+
+    /// Occupy the only worker of the executor until the returned sender is dropped.
+    fn occupy(executor: &Executor) -> Sender<()> {
+        let (started, has_started) = channel();
+        let (release, released) = channel::<()>();
+        executor
+            .submit(move || {
+                started.send(()).unwrap();
+                let _ = released.recv();
+            })
+            .unwrap();
+        has_started.recv().unwrap();
+
+        release
+    }
+
+    #[test]
+    fn jobs_beyond_the_queue_capacity_are_rejected() {
+        let executor = Executor::new(1, 2).unwrap();
+        let release = occupy(&executor);
+
+        let queued = (0..2)
+            .map(|index| executor.submit(move || index).unwrap())
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            executor.submit(|| 2),
+            Err(ExecutorError::QueueFull)
+        ));
+
+        drop(release);
+        let outputs = queued
+            .into_iter()
+            .map(|output| output.blocking_recv().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(outputs, vec![0, 1]);
+    }
+
+    #[test]
+    fn a_rejected_job_runs_once_a_slot_frees() {
+        let executor = Executor::new(1, 1).unwrap();
+        let release = occupy(&executor);
+
+        let queued = executor.submit(|| "queued").unwrap();
+        assert!(matches!(
+            executor.submit(|| "retried"),
+            Err(ExecutorError::QueueFull)
+        ));
+
+        // The worker takes the queued job once it's released, which frees the slot for the retry.
+        drop(release);
+        assert_eq!(queued.blocking_recv().unwrap(), "queued");
+        let retried = executor.submit(|| "retried").unwrap();
+        assert_eq!(retried.blocking_recv().unwrap(), "retried");
+    }
+}
@@ -1,5 +1,64 @@
+use async_trait::async_trait;
+use ramd_cache::ReadCacheStats;
+use ramd_db::state_tree::{Hash, StateProof};
+
+use crate::{ExecutorError, MaintenanceReport, MessageStatus};
+
+#[async_trait]
 pub trait LiveObjectHandler: Send + Sync {
-    fn create_live_object(&self, wasm_bytes: Vec<u8>) -> String;
+    async fn create_live_object(
+        &self,
+        wasm_bytes: Vec<u8>,
+        memory_limit: Option<usize>,
+    ) -> Result<String, ExecutorError>;
+
+    async fn execute_live_object(
+        &self,
+        live_object_id: String,
+        method: String,
+        args: Vec<u8>,
+    ) -> Result<String, ExecutorError>;
+
+    /// Queue the execution and return the ID of its message right away, so that it can be cancelled while
+    /// running. The result is fetched with `message_status`.
+    async fn submit_live_object_execution(
+        &self,
+        live_object_id: String,
+        method: String,
+        args: Vec<u8>,
+    ) -> Result<String, ExecutorError>;
+
+    /// Get the status of a submitted message, or `None` if it is unknown or its result was dropped to make room.
+    fn message_status(&self, message_id: String) -> Option<MessageStatus>;
+
+    /// Get the ABI of the live object as JSON, or `None` if its WASM module doesn't describe one.
+    fn live_object_abi(&self, live_object_id: String) -> eyre::Result<Option<String>>;
+
+    /// Get the number of bytes, counting both keys and values, that the live object stores.
+    fn storage_usage(&self, live_object_id: String) -> eyre::Result<u64>;
+
+    /// Get the root of the Merkle tree over the state of the live object.
+    fn state_root(&self, live_object_id: String) -> eyre::Result<Hash>;
+
+    /// Get the value of a key of the live object, if any, and a proof of it against the current state root.
+    fn state_proof(
+        &self,
+        live_object_id: String,
+        key: Vec<u8>,
+    ) -> eyre::Result<(Option<Vec<u8>>, StateProof)>;
+}
+
+pub trait AdminHandler: Send + Sync {
+    /// Cancel the message being processed. Returns `false` if no such message is being processed.
+    fn cancel_execution(&self, message_id: String) -> bool;
+
+    /// Write a consistent copy of the database to the directory `name` inside the backup directory, which must not
+    /// exist yet, while the node keeps running.
+    fn backup(&self, name: String) -> eyre::Result<()>;
+
+    /// Get the hit and miss statistics of the cache of storage reads shared by all messages.
+    fn read_cache_stats(&self) -> eyre::Result<ReadCacheStats>;
 
-    fn execute_live_object(&self, live_object_id: String, method: String, args: Vec<u8>) -> String;
+    /// Compact the ranges of deleted state and prune the message log now, without waiting for the schedule.
+    fn run_maintenance(&self) -> eyre::Result<MaintenanceReport>;
 }
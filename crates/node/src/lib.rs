@@ -1,7 +1,13 @@
 mod config;
+mod executor;
 mod handlers;
+mod maintenance;
 mod node;
+mod results;
 
 pub use config::*;
+pub use executor::*;
 pub use handlers::*;
+pub use maintenance::*;
 pub use node::*;
+pub use results::{MessageStatus, MESSAGE_RESULTS_CAPACITY};
@@ -0,0 +1,76 @@
+use ramd_db::compaction::{pending_compaction_key, pending_compactions};
+use ramd_db::state_tree::STATE_TREE_KEY_PREFIX;
+use ramd_db::storage::CompactableStorage;
+use ramd_processor::MessageLog;
+
+/// What a maintenance run did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MaintenanceReport {
+    /// The number of live objects whose state and state tree were compacted, as they had keys deleted.
+    pub compacted_live_objects: u64,
+    pub pruned_log_entries: u64,
+    /// How much the estimated size of the data on disk shrank, in bytes.
+    pub reclaimed_bytes: u64,
+}
+
+/// Compact the ranges of the live objects that had keys deleted, and prune the message log down to
+/// the most recent `message_log_retention` entries if set. Live objects are never removed, so the keys deleted
+/// through `storage_delete` are what leaves tombstones. Writes go through `storage`, so pass the storage
+/// that caches reads rather than the one behind it.
+pub fn run_maintenance<S>(
+    storage: &S,
+    message_log_retention: Option<u64>,
+) -> eyre::Result<MaintenanceReport>
+where
+    S: CompactableStorage<Vec<u8>, Vec<u8>>,
+{
+    let size_before = storage.live_data_size()?;
+
+    // Clear the markers before compacting, so that keys deleted meanwhile mark their live object again.
+    let deleted_live_objects = pending_compactions(storage)?;
+    storage.write_batch(
+        deleted_live_objects
+            .iter()
+            .map(|live_object_id| (pending_compaction_key(live_object_id.as_bytes()), None))
+            .collect(),
+    )?;
+    for live_object_id in deleted_live_objects.iter() {
+        let (from, to) = prefix_range(live_object_id.as_bytes().to_vec());
+        storage.compact_range(&from, &to)?;
+
+        // Deleting keys collapses the state tree, so its range has tombstones as well.
+        let mut state_tree_prefix = STATE_TREE_KEY_PREFIX.to_vec();
+        state_tree_prefix.extend_from_slice(live_object_id.as_bytes());
+        let (from, to) = prefix_range(state_tree_prefix);
+        storage.compact_range(&from, &to)?;
+    }
+
+    let pruned = match message_log_retention {
+        Some(retention) => MessageLog::prune(storage, retention)?,
+        None => 0..0,
+    };
+    if !pruned.is_empty() {
+        storage.compact_range(
+            &MessageLog::entry_key(pruned.start),
+            &MessageLog::entry_key(pruned.end),
+        )?;
+    }
+
+    let size_after = storage.live_data_size()?;
+
+    Ok(MaintenanceReport {
+        compacted_live_objects: deleted_live_objects.len() as u64,
+        pruned_log_entries: pruned.end - pruned.start,
+        reclaimed_bytes: size_before.saturating_sub(size_after),
+    })
+}
+
+/// The range of the keys starting with `prefix`, whose last byte is never `0xff` here.
+fn prefix_range(prefix: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
+    let mut end = prefix.clone();
+    if let Some(last) = end.last_mut() {
+        *last += 1;
+    }
+
+    (prefix, end)
+}
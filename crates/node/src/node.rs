@@ -1,56 +1,349 @@
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::config::NodeConfig;
-use crate::handlers::LiveObjectHandler;
-use ramd_db::storage::Storage;
+use crate::executor::{Executor, ExecutorError};
+use crate::handlers::{AdminHandler, LiveObjectHandler};
+use crate::maintenance::{run_maintenance, MaintenanceReport};
+use crate::results::{MessageResults, MessageStatus};
+use async_channel::Sender;
+use async_trait::async_trait;
+use ramd_cache::ReadCacheStats;
+use ramd_db::state_tree::{state_root, Hash, StateProof, StateTree};
+use ramd_db::storage::{CompactableStorage, SnapshotStorage, Storage};
+use ramd_db::storage_usage::read_storage_usage;
+use ramd_p2p_types::message::P2pMessage;
 use ramd_processor::{Action, CreateLiveObjectAction, ExecuteLiveObjectAction, Message, Processor};
-use tracing::info;
+use ramd_vm::LiveObjectMetadata;
+use tracing::{error, info};
 
 pub struct Node<S>
 where
     S: Storage<Vec<u8>, Vec<u8>> + 'static,
 {
-    processor: Processor<S>,
+    storage: Arc<S>,
+    processor: Arc<Processor<S>>,
+    executor: Executor,
+    /// Broadcasts the state roots of the live objects touched by processed messages.
+    p2p_sender: Option<Sender<P2pMessage>>,
+    /// The number of most recent message log entries kept by maintenance, or `None` to keep the whole log.
+    message_log_retention: Option<u64>,
+    /// The directory that backups requested through the admin API are written to, or `None` to refuse them.
+    backup_dir: Option<PathBuf>,
+    /// The statuses of the most recently submitted messages.
+    results: Arc<MessageResults>,
 }
 
 impl<S> Node<S>
 where
     S: Storage<Vec<u8>, Vec<u8>>,
 {
-    pub fn new(_config: &NodeConfig, storage: Arc<S>) -> eyre::Result<Self> {
+    pub fn new(config: &NodeConfig, storage: Arc<S>) -> eyre::Result<Self> {
+        if !config.compiler.is_available() {
+            return Err(eyre::eyre!(
+                "ramd is built without the `{}` compiler",
+                config.compiler
+            ));
+        }
+
         Ok(Node {
-            processor: Processor::new(storage.clone()),
+            storage: storage.clone(),
+            processor: Arc::new(Processor::new(storage.clone(), config.processor_config())),
+            executor: Executor::new(config.executor_workers, config.executor_queue_capacity)?,
+            p2p_sender: None,
+            message_log_retention: None,
+            backup_dir: None,
+            results: Arc::new(MessageResults::default()),
         })
     }
+
+    /// Gossip the state roots of live objects through the p2p server once messages to them are processed.
+    pub fn with_p2p_sender(mut self, p2p_sender: Sender<P2pMessage>) -> Self {
+        self.p2p_sender = Some(p2p_sender);
+        self
+    }
+
+    /// Prune the message log down to the most recent `message_log_retention` entries during maintenance.
+    pub fn with_message_log_retention(mut self, message_log_retention: Option<u64>) -> Self {
+        self.message_log_retention = message_log_retention;
+        self
+    }
+
+    /// Write backups requested through the admin API to directories inside `backup_dir`.
+    pub fn with_backup_dir(mut self, backup_dir: PathBuf) -> Self {
+        self.backup_dir = Some(backup_dir);
+        self
+    }
+
+    /// Process the messages on an executor worker and wait for the results.
+    async fn process_messages(&self, messages: Vec<Message>) -> Result<String, ExecutorError> {
+        self.executor.execute(self.processing_job(messages)).await
+    }
+
+    /// The job that processes the messages and broadcasts the state roots of the live objects they touched.
+    fn processing_job(&self, messages: Vec<Message>) -> impl FnOnce() -> String + Send + 'static {
+        let storage = self.storage.clone();
+        let processor = self.processor.clone();
+        let p2p_sender = self.p2p_sender.clone();
+
+        move || {
+            let results = processor.process_messages(&messages);
+
+            let live_object_ids = messages
+                .iter()
+                .map(|message| message.action.live_object_id())
+                .collect::<BTreeSet<_>>();
+            broadcast_state_roots(storage.as_ref(), p2p_sender.as_ref(), live_object_ids);
+
+            results
+        }
+    }
 }
 
+fn broadcast_state_roots<S>(
+    storage: &S,
+    p2p_sender: Option<&Sender<P2pMessage>>,
+    live_object_ids: BTreeSet<String>,
+) where
+    S: Storage<Vec<u8>, Vec<u8>>,
+{
+    let Some(p2p_sender) = p2p_sender else {
+        return;
+    };
+
+    for live_object_id in live_object_ids {
+        let state_root = match state_root(storage, live_object_id.as_bytes()) {
+            Ok(state_root) => hex::encode(state_root),
+            Err(e) => {
+                error!(target: "ramd::node", "Failed to get the state root of live object `{}` with error `{}`", live_object_id, e.to_string());
+                continue;
+            }
+        };
+
+        if let Err(e) = p2p_sender.try_send(P2pMessage::StateRoot {
+            live_object_id,
+            state_root,
+        }) {
+            error!(target: "ramd::node", "Failed to broadcast a state root with error `{}`", e.to_string());
+        }
+    }
+}
+
+#[async_trait]
 impl<S> LiveObjectHandler for Node<S>
 where
-    S: Storage<Vec<u8>, Vec<u8>>,
+    S: SnapshotStorage<Vec<u8>, Vec<u8>>,
+{
+    async fn create_live_object(
+        &self,
+        wasm_bytes: Vec<u8>,
+        memory_limit: Option<usize>,
+    ) -> Result<String, ExecutorError> {
+        let message = Message::new(Action::CreateLiveObject(CreateLiveObjectAction {
+            wasm_bytes,
+            memory_limit,
+        }));
+        info!(target: "ramd::node", "New message `{}` with create action", message.id);
+
+        self.process_messages(vec![message]).await
+    }
+
+    async fn execute_live_object(
+        &self,
+        live_object_id: String,
+        method: String,
+        args: Vec<u8>,
+    ) -> Result<String, ExecutorError> {
+        let message = Message::new(Action::ExecuteLiveObject(ExecuteLiveObjectAction {
+            live_object_id,
+            method,
+            args,
+        }));
+        info!(target: "ramd::node", "New message `{}` with execute action", message.id);
+
+        self.process_messages(vec![message]).await
+    }
+
+    async fn submit_live_object_execution(
+        &self,
+        live_object_id: String,
+        method: String,
+        args: Vec<u8>,
+    ) -> Result<String, ExecutorError> {
+        let message = Message::new(Action::ExecuteLiveObject(ExecuteLiveObjectAction {
+            live_object_id,
+            method,
+            args,
+        }));
+        let message_id = message.id.clone();
+        info!(target: "ramd::node", "New submitted message `{}` with execute action", message_id);
+
+        // Known as pending before a worker can pick it up, so that it's never reported as unknown.
+        self.results
+            .insert(message_id.clone(), MessageStatus::Pending);
+        let receiver = match self.executor.submit(self.processing_job(vec![message])) {
+            Ok(receiver) => receiver,
+            Err(e) => {
+                self.results.remove(&message_id);
+                return Err(e);
+            }
+        };
+
+        let results = self.results.clone();
+        let id = message_id.clone();
+        tokio::spawn(async move {
+            let status = match receiver.await {
+                Ok(result) => MessageStatus::Processed(result),
+                Err(_) => MessageStatus::Failed,
+            };
+            results.insert(id, status);
+        });
+
+        Ok(message_id)
+    }
+
+    fn message_status(&self, message_id: String) -> Option<MessageStatus> {
+        self.results.get(&message_id)
+    }
+
+    fn live_object_abi(&self, live_object_id: String) -> eyre::Result<Option<String>> {
+        let metadata = LiveObjectMetadata::load(&self.storage.snapshot(), &live_object_id)?.ok_or(
+            eyre::eyre!("Live object `{}` doesn't exist", live_object_id),
+        )?;
+
+        let abi = metadata
+            .abi
+            .map(|abi| serde_json::to_string(&abi))
+            .transpose()?;
+
+        Ok(abi)
+    }
+
+    fn storage_usage(&self, live_object_id: String) -> eyre::Result<u64> {
+        let snapshot = self.storage.snapshot();
+        ensure_live_object_exists(&snapshot, &live_object_id)?;
+
+        read_storage_usage(&snapshot, live_object_id.as_bytes())
+    }
+
+    fn state_root(&self, live_object_id: String) -> eyre::Result<Hash> {
+        let snapshot = self.storage.snapshot();
+        ensure_live_object_exists(&snapshot, &live_object_id)?;
+
+        state_root(&snapshot, live_object_id.as_bytes())
+    }
+
+    fn state_proof(
+        &self,
+        live_object_id: String,
+        key: Vec<u8>,
+    ) -> eyre::Result<(Option<Vec<u8>>, StateProof)> {
+        // Read the value and the proof from the same snapshot, so that the proof is of the returned value.
+        let snapshot = self.storage.snapshot();
+        ensure_live_object_exists(&snapshot, &live_object_id)?;
+
+        let mut storage_key = live_object_id.as_bytes().to_vec();
+        storage_key.extend_from_slice(&key);
+        let value = snapshot.get_opt(storage_key)?;
+        let proof = StateTree::new(&snapshot, live_object_id.as_bytes()).prove(&key)?;
+
+        Ok((value, proof))
+    }
+}
+
+impl<S> Node<S>
+where
+    S: CompactableStorage<Vec<u8>, Vec<u8>>,
 {
-    fn create_live_object(&self, wasm_bytes: Vec<u8>) -> String {
-        let messages = vec![Message {
-            action: Action::CreateLiveObject(CreateLiveObjectAction { wasm_bytes }),
-        }];
+    /// Run maintenance in the background every `interval`, starting one `interval` from now.
+    pub fn launch_maintenance(self: &Arc<Self>, interval: Duration) {
+        let node = self.clone();
 
-        // TODO: log message ID.
-        info!(target: "ramd::node", "New message with create action");
+        tokio::spawn(async move {
+            let mut ticks =
+                tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            loop {
+                ticks.tick().await;
 
-        self.processor.process_messages(&messages)
+                let node = node.clone();
+                match tokio::task::spawn_blocking(move || node.maintain()).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => {
+                        error!(target: "ramd::node", "Failed to run maintenance with error `{}`", e.to_string())
+                    }
+                    Err(e) => {
+                        error!(target: "ramd::node", "Maintenance panicked with error `{}`", e.to_string())
+                    }
+                }
+            }
+        });
     }
 
-    fn execute_live_object(&self, live_object_id: String, method: String, args: Vec<u8>) -> String {
-        let messages = vec![Message {
-            action: Action::ExecuteLiveObject(ExecuteLiveObjectAction {
-                live_object_id,
-                method,
-                args,
-            }),
-        }];
+    fn maintain(&self) -> eyre::Result<MaintenanceReport> {
+        let report = run_maintenance(
+            self.processor.storage().as_ref(),
+            self.message_log_retention,
+        )?;
+        info!(target: "ramd::node", "Maintenance compacted {} live objects, pruned {} message log entries and reclaimed {} bytes", report.compacted_live_objects, report.pruned_log_entries, report.reclaimed_bytes);
 
-        // TODO: log message ID.
-        info!(target: "ramd::node", "New message with execute action");
+        Ok(report)
+    }
+}
 
-        self.processor.process_messages(&messages)
+impl<S> AdminHandler for Node<S>
+where
+    S: SnapshotStorage<Vec<u8>, Vec<u8>> + CompactableStorage<Vec<u8>, Vec<u8>>,
+{
+    fn cancel_execution(&self, message_id: String) -> bool {
+        info!(target: "ramd::node", "Cancelling message `{}`", message_id);
+
+        self.processor.cancel(&message_id)
+    }
+
+    fn backup(&self, name: String) -> eyre::Result<()> {
+        let backup_dir = self
+            .backup_dir
+            .as_ref()
+            .ok_or(eyre::eyre!("Backups through the admin API are disabled"))?;
+
+        // Only a plain directory name, so that callers can't write anywhere else.
+        let mut components = Path::new(&name).components();
+        if !matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        ) {
+            return Err(eyre::eyre!("Invalid backup name `{}`", name));
+        }
+
+        let path = backup_dir.join(name);
+        info!(target: "ramd::node", "Backing up the database to `{}`", path.display());
+
+        std::fs::create_dir_all(backup_dir)?;
+        self.storage.checkpoint(&path)
+    }
+
+    fn read_cache_stats(&self) -> eyre::Result<ReadCacheStats> {
+        self.processor.read_cache_stats()
+    }
+
+    fn run_maintenance(&self) -> eyre::Result<MaintenanceReport> {
+        info!(target: "ramd::node", "Running maintenance");
+
+        self.maintain()
+    }
+}
+
+fn ensure_live_object_exists<S>(storage: &S, live_object_id: &str) -> eyre::Result<()>
+where
+    S: Storage<Vec<u8>, Vec<u8>>,
+{
+    match storage.has(live_object_id.to_owned().into_bytes())? {
+        true => Ok(()),
+        false => Err(eyre::eyre!(
+            "Live object `{}` doesn't exist",
+            live_object_id
+        )),
     }
 }
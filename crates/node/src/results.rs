@@ -0,0 +1,57 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// The number of results of submitted messages kept for clients to fetch. The oldest are dropped first.
+pub const MESSAGE_RESULTS_CAPACITY: usize = 4096;
+
+/// Where a submitted message is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageStatus {
+    /// Queued or being processed.
+    Pending,
+    /// Processed, with the JSON object mapping the message ID to either its result or its error.
+    Processed(String),
+    /// The worker processing the message stopped before it finished.
+    Failed,
+}
+
+/// The statuses of the messages submitted to the node, bounded by `MESSAGE_RESULTS_CAPACITY`.
+#[derive(Debug, Default)]
+pub(crate) struct MessageResults {
+    inner: Mutex<MessageResultsInner>,
+}
+
+#[derive(Debug, Default)]
+struct MessageResultsInner {
+    statuses: HashMap<String, MessageStatus>,
+    /// Message IDs in the order they were submitted.
+    order: VecDeque<String>,
+}
+
+impl MessageResults {
+    pub(crate) fn insert(&self, message_id: String, status: MessageStatus) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+
+        if inner.statuses.insert(message_id.clone(), status).is_none() {
+            inner.order.push_back(message_id);
+        }
+        while inner.order.len() > MESSAGE_RESULTS_CAPACITY {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.statuses.remove(&oldest);
+            }
+        }
+    }
+
+    pub(crate) fn remove(&self, message_id: &str) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.statuses.remove(message_id);
+            inner.order.retain(|id| id != message_id);
+        }
+    }
+
+    pub(crate) fn get(&self, message_id: &str) -> Option<MessageStatus> {
+        self.inner.lock().ok()?.statuses.get(message_id).cloned()
+    }
+}
@@ -0,0 +1,67 @@
+//! Maintenance compacts the live objects marked by deletions and prunes the message log through the read cache.
+
+use std::sync::Arc;
+
+use ramd_cache::{ReadCache, DEFAULT_READ_CACHE_SIZE};
+use ramd_db::compaction::{pending_compaction_writes, pending_compactions};
+use ramd_db::config::RocksConfig;
+use ramd_db::rocks::RocksStorage;
+use ramd_db::storage::Storage;
+use ramd_node::run_maintenance;
+use ramd_processor::{Action, ExecuteLiveObjectAction, Message, MessageLog};
+
+fn live_object_id() -> String {
+    "a".repeat(64)
+}
+
+fn increment() -> Message {
+    Message::new(Action::ExecuteLiveObject(ExecuteLiveObjectAction {
+        live_object_id: live_object_id(),
+        method: "increment".to_owned(),
+        args: vec![],
+    }))
+}
+
+#[test]
+fn maintenance_compacts_marked_live_objects_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Arc::new(RocksStorage::new(&RocksConfig::new(dir.path().to_path_buf())).unwrap());
+    let cache = ReadCache::new(storage.clone(), DEFAULT_READ_CACHE_SIZE);
+
+    let mut key = live_object_id().into_bytes();
+    key.extend_from_slice(b"k");
+    cache
+        .write_batch(pending_compaction_writes([(&key, &None)]))
+        .unwrap();
+
+    // The marker is in the database, so it outlives the process.
+    assert_eq!(
+        pending_compactions(storage.as_ref()).unwrap(),
+        vec![live_object_id()]
+    );
+
+    let report = run_maintenance(&cache, None).unwrap();
+    assert_eq!(report.compacted_live_objects, 1);
+    assert!(pending_compactions(storage.as_ref()).unwrap().is_empty());
+
+    let report = run_maintenance(&cache, None).unwrap();
+    assert_eq!(report.compacted_live_objects, 0);
+}
+
+#[test]
+fn pruned_log_entries_are_not_served_from_the_read_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Arc::new(RocksStorage::new(&RocksConfig::new(dir.path().to_path_buf())).unwrap());
+    let cache = ReadCache::new(storage, DEFAULT_READ_CACHE_SIZE);
+
+    for _ in 0..3 {
+        MessageLog::append(&cache, &increment(), "result").unwrap();
+    }
+    assert!(MessageLog::entry(&cache, 0).unwrap().is_some());
+
+    let report = run_maintenance(&cache, Some(1)).unwrap();
+    assert_eq!(report.pruned_log_entries, 2);
+    assert!(MessageLog::entry(&cache, 0).unwrap().is_none());
+    assert!(MessageLog::entry(&cache, 1).unwrap().is_none());
+    assert!(MessageLog::entry(&cache, 2).unwrap().is_some());
+}
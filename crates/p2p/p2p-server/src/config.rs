@@ -1,6 +1,6 @@
 use libp2p::{multiaddr::Protocol, Multiaddr};
 use serde::{Deserialize, Serialize};
-use std::{str::FromStr, time::Duration};
+use std::{path::PathBuf, str::FromStr, time::Duration};
 use tracing::error;
 
 pub const RAM_PROTOCOL_VERSION: &str = "ram/0.1.0";
@@ -21,6 +21,8 @@ pub struct P2pConfig {
     pub peers: Option<Vec<String>>,
     pub topic: String,
     pub max_peers_limit: usize,
+    /// Path of the encrypted keystore holding the node's identity key
+    pub keystore_path: Option<PathBuf>,
 }
 
 impl P2pConfig {
@@ -98,6 +100,7 @@ impl Default for P2pConfig {
             peers: None,
             topic: "ramd-topic".to_owned(),
             max_peers_limit: 1,
+            keystore_path: None,
         }
     }
 }
@@ -0,0 +1,222 @@
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use libp2p::{identity, PeerId};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use zeroize::Zeroizing;
+
+/// Version of the keystore file format
+pub const KEYSTORE_VERSION: u32 = 1;
+
+/// Environment variable holding the passphrase that unlocks the keystore
+pub const KEYSTORE_PASSPHRASE_ENV: &str = "RAMD_KEYSTORE_PASSPHRASE";
+
+const KDF_NAME: &str = "scrypt";
+const CIPHER_NAME: &str = "chacha20poly1305";
+
+/// scrypt cost parameters for newly written keystores: 2^15 rounds with r = 8 take 32 MiB of memory
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+const SALT_LEN: usize = 32;
+
+/// The node's p2p identity keypair, encrypted with a key derived from a passphrase.
+///
+/// The keypair's protobuf encoding is sealed with ChaCha20-Poly1305 under a key derived by scrypt.
+/// The peer id is kept in plain text so that the identity can be told apart without unlocking it,
+/// and is authenticated as associated data.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct KeystoreFile {
+    version: u32,
+    peer_id: String,
+    kdf: KdfParams,
+    cipher: CipherParams,
+    ciphertext: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct KdfParams {
+    name: String,
+    log_n: u8,
+    r: u32,
+    p: u32,
+    salt: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct CipherParams {
+    name: String,
+    nonce: String,
+}
+
+/// Returns the peer id of the identity stored at `path` without unlocking it
+pub fn peer_id(path: &Path) -> eyre::Result<PeerId> {
+    let file = read(path)?;
+    Ok(PeerId::from_str(&file.peer_id)?)
+}
+
+/// Decrypts the identity keypair stored at `path`
+pub fn load(path: &Path, passphrase: &str) -> eyre::Result<identity::Keypair> {
+    let file = read(path)?;
+
+    if file.kdf.name != KDF_NAME {
+        return Err(eyre::eyre!("Unsupported keystore kdf: {}", file.kdf.name));
+    }
+    if file.cipher.name != CIPHER_NAME {
+        return Err(eyre::eyre!(
+            "Unsupported keystore cipher: {}",
+            file.cipher.name
+        ));
+    }
+
+    let salt = hex::decode(&file.kdf.salt)?;
+    let nonce = hex::decode(&file.cipher.nonce)?;
+    if nonce.len() != 12 {
+        return Err(eyre::eyre!("Keystore nonce has invalid length"));
+    }
+    let ciphertext = hex::decode(&file.ciphertext)?;
+
+    let key = derive_key(passphrase, &salt, file.kdf.log_n, file.kdf.r, file.kdf.p)?;
+    let encoded = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: file.peer_id.as_bytes(),
+            },
+        )
+        .map(Zeroizing::new)
+        .map_err(|_| {
+            eyre::eyre!(
+                "Failed to unlock keystore {}: wrong passphrase or corrupted file",
+                path.display()
+            )
+        })?;
+
+    let keypair = identity::Keypair::from_protobuf_encoding(&encoded)?;
+    if keypair.public().to_peer_id().to_string() != file.peer_id {
+        return Err(eyre::eyre!(
+            "Keystore {} holds a key that doesn't match its peer id",
+            path.display()
+        ));
+    }
+
+    Ok(keypair)
+}
+
+/// Encrypts `keypair` with `passphrase` and writes it to `path`, replacing any existing keystore.
+///
+/// The file is written next to its destination and renamed over it, so a crash never leaves a
+/// partially written identity behind.
+pub fn store(path: &Path, keypair: &identity::Keypair, passphrase: &str) -> eyre::Result<()> {
+    let peer_id = keypair.public().to_peer_id().to_string();
+
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let key = derive_key(passphrase, &salt, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)?;
+    let encoded = Zeroizing::new(keypair.to_protobuf_encoding()?);
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: &encoded,
+                aad: peer_id.as_bytes(),
+            },
+        )
+        .map_err(|_| eyre::eyre!("Failed to encrypt node key"))?;
+
+    let file = KeystoreFile {
+        version: KEYSTORE_VERSION,
+        peer_id,
+        kdf: KdfParams {
+            name: KDF_NAME.to_owned(),
+            log_n: SCRYPT_LOG_N,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+            salt: hex::encode(salt),
+        },
+        cipher: CipherParams {
+            name: CIPHER_NAME.to_owned(),
+            nonce: hex::encode(nonce),
+        },
+        ciphertext: hex::encode(ciphertext),
+    };
+
+    write_private(path, serde_json::to_string_pretty(&file)?.as_bytes())
+}
+
+/// Writes `contents` to `path` through a temporary file readable by the owner only.
+///
+/// A temporary file left behind by an earlier write is removed first, as opening it again would keep whatever
+/// permissions it has. The new one is created exclusively, so it always gets the owner-only mode.
+pub fn write_private(path: &Path, contents: &[u8]) -> eyre::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut tmp_path = PathBuf::from(path);
+    tmp_path.as_mut_os_string().push(".tmp");
+    match fs::remove_file(&tmp_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut tmp = options.open(&tmp_path)?;
+    tmp.write_all(contents)?;
+    tmp.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+
+    Ok(())
+}
+
+fn read(path: &Path) -> eyre::Result<KeystoreFile> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| eyre::eyre!("Failed to read keystore {}: {}", path.display(), e))?;
+    let file: KeystoreFile = serde_json::from_str(&contents)?;
+
+    if file.version != KEYSTORE_VERSION {
+        return Err(eyre::eyre!(
+            "Keystore {} has version {}, but this binary only supports version {}",
+            path.display(),
+            file.version,
+            KEYSTORE_VERSION
+        ));
+    }
+
+    Ok(file)
+}
+
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    log_n: u8,
+    r: u32,
+    p: u32,
+) -> eyre::Result<Zeroizing<[u8; 32]>> {
+    let params = scrypt::Params::new(log_n, r, p, 32)
+        .map_err(|e| eyre::eyre!("Invalid keystore kdf parameters: {}", e))?;
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, key.as_mut())
+        .map_err(|e| eyre::eyre!("Failed to derive keystore key: {}", e))?;
+
+    Ok(key)
+}
@@ -1,4 +1,5 @@
 pub mod config;
+pub mod keystore;
 mod server;
 
 pub use server::*;
@@ -4,7 +4,7 @@ use libp2p::{
     gossipsub, gossipsub::IdentTopic, identify, identity, kad, kad::Mode, noise,
     swarm::NetworkBehaviour, swarm::SwarmEvent, tcp, yamux, PeerId,
 };
-use ramd_db::{keys::RAMD_P2P_KEYPAIR_KEY, storage::Storage};
+use ramd_db::{state_tree::state_root, storage::Storage};
 use ramd_p2p_types::message::P2pMessage;
 use std::{
     collections::hash_map::DefaultHasher,
@@ -27,7 +27,7 @@ pub struct Server<S>
 where
     S: Storage<Vec<u8>, Vec<u8>>,
 {
-    _storage: Arc<S>,
+    storage: Arc<S>,
     swarm: libp2p::Swarm<RamdBehavior>,
     boot_nodes: Vec<PeerId>,
     topic: IdentTopic,
@@ -38,11 +38,14 @@ where
 
 impl<S> Server<S>
 where
-    S: Storage<Vec<u8>, Vec<u8>>,
+    S: Storage<Vec<u8>, Vec<u8>> + 'static,
 {
-    pub fn new(p2p_cfg: &P2pConfig, storage: Arc<S>) -> eyre::Result<(Self, Sender<P2pMessage>)> {
-        let node_key = Self::get_node_key(storage.clone())?;
-
+    /// Constructs a server that identifies itself with `node_key`, unlocked from the node's keystore
+    pub fn new(
+        p2p_cfg: &P2pConfig,
+        node_key: identity::Keypair,
+        storage: Arc<S>,
+    ) -> eyre::Result<(Self, Sender<P2pMessage>)> {
         let mut swarm = libp2p::SwarmBuilder::with_existing_identity(node_key)
             .with_tokio()
             .with_tcp(
@@ -140,7 +143,7 @@ where
 
         Ok((
             Self {
-                _storage: storage,
+                storage,
                 swarm,
                 boot_nodes,
                 topic,
@@ -178,7 +181,13 @@ where
                         self.topic.clone(),
                         msg.as_bytes(), // TODO: for now just forward the message
                     ) {
-                        error!("Failed to broadcast due to: {}", e.to_string());
+                        match e {
+                            // a node without peers has no one to broadcast to
+                            gossipsub::PublishError::InsufficientPeers => {
+                                debug!(target: "p2p", "No peers to broadcast the message to");
+                            }
+                            e => error!("Failed to broadcast due to: {}", e.to_string()),
+                        }
                     }
                 }
                 // libp2p related events
@@ -261,6 +270,11 @@ where
                         // first validate that received message is received from the right topic
                         if message.topic != self.topic.hash() {
                             self.disconnect_peer(&peer_id);
+                            continue;
+                        }
+
+                        if let Ok(P2pMessage::StateRoot { live_object_id, state_root }) = serde_json::from_slice(&message.data) {
+                            self.compare_state_root(peer_id, live_object_id, state_root);
                         }
                     }
                     SwarmEvent::Behaviour(RamdBehaviorEvent::Gossipsub(gossipsub::Event::Subscribed {
@@ -296,6 +310,32 @@ where
         }
     }
 
+    /// Compare the state root of a live object gossiped by a peer with the local one.
+    /// Reading the tree hits the storage, so it runs on the blocking pool rather than the swarm loop.
+    fn compare_state_root(&self, peer_id: PeerId, live_object_id: String, peer_state_root: String) {
+        let storage = self.storage.clone();
+        tokio::task::spawn_blocking(move || {
+            match state_root(storage.as_ref(), live_object_id.as_bytes()) {
+                Ok(local_state_root) if hex::encode(local_state_root) == peer_state_root => {
+                    debug!(target: "p2p", "State of live object {} agrees with peer {}", live_object_id, peer_id);
+                }
+                Ok(local_state_root) => {
+                    info!(
+                        target: "p2p",
+                        "State of live object {} differs from peer {}. Local root {}, peer root {}",
+                        live_object_id,
+                        peer_id,
+                        hex::encode(local_state_root),
+                        peer_state_root
+                    );
+                }
+                Err(e) => {
+                    error!(target: "p2p", "Failed to get the state root of live object {}. Reason: {}", live_object_id, e.to_string());
+                }
+            }
+        });
+    }
+
     /// Checks does peer id is one of the boot nodes from the config
     fn is_boot_node(&self, peer_id: &PeerId) -> bool {
         self.boot_nodes.iter().any(|peer| peer == peer_id)
@@ -317,19 +357,4 @@ where
         // remove it from kademlia table
         let _ = self.swarm.behaviour_mut().kademlia.remove_peer(peer_id);
     }
-
-    /// If private key was already created then recover it from the storage,
-    /// otherwise create a new pair and store it
-    fn get_node_key(storage: Arc<S>) -> eyre::Result<identity::Keypair> {
-        if let Some(pk) = storage.get_opt(RAMD_P2P_KEYPAIR_KEY.into())? {
-            // pk is already stored, recover it
-            Ok(identity::Keypair::from_protobuf_encoding(&pk)?)
-        } else {
-            // pk doesn't exists yet, create a new one and store it
-            let pk = identity::Keypair::generate_ed25519();
-            storage.set(RAMD_P2P_KEYPAIR_KEY.into(), pk.to_protobuf_encoding()?)?;
-
-            Ok(pk)
-        }
-    }
 }
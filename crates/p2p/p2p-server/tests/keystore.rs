@@ -0,0 +1,123 @@
+//! Sealing the node identity in a keystore and unlocking it again.
+
+use std::path::PathBuf;
+
+use libp2p::identity::Keypair;
+use ramd_p2p_server::keystore::{load, peer_id, store, write_private};
+use tempfile::TempDir;
+
+fn keystore(dir: &TempDir) -> PathBuf {
+    dir.path().join("node_key.json")
+}
+
+/// Rewrite a field of the keystore file.
+fn edit_keystore(path: &PathBuf, field: &str, edit: impl FnOnce(&str) -> String) {
+    let mut file: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let value = edit(file[field].as_str().unwrap());
+    file[field] = serde_json::Value::String(value);
+    std::fs::write(path, serde_json::to_string(&file).unwrap()).unwrap();
+}
+
+#[test]
+fn a_stored_identity_unlocks_with_its_passphrase() {
+    let dir = tempfile::tempdir().unwrap();
+    let keypair = Keypair::generate_ed25519();
+
+    store(&keystore(&dir), &keypair, "passphrase").unwrap();
+
+    let loaded = load(&keystore(&dir), "passphrase").unwrap();
+    assert_eq!(loaded.public(), keypair.public());
+    assert_eq!(
+        loaded.to_protobuf_encoding().unwrap(),
+        keypair.to_protobuf_encoding().unwrap()
+    );
+    assert_eq!(
+        peer_id(&keystore(&dir)).unwrap(),
+        keypair.public().to_peer_id()
+    );
+
+    // The key isn't stored in plain text.
+    let contents = std::fs::read_to_string(keystore(&dir)).unwrap();
+    assert!(!contents.contains(&hex::encode(keypair.to_protobuf_encoding().unwrap())));
+}
+
+#[test]
+fn a_wrong_passphrase_is_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    store(&keystore(&dir), &Keypair::generate_ed25519(), "passphrase").unwrap();
+
+    assert!(load(&keystore(&dir), "other").is_err());
+    assert!(load(&keystore(&dir), "").is_err());
+}
+
+#[test]
+fn a_tampered_ciphertext_is_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    store(&keystore(&dir), &Keypair::generate_ed25519(), "passphrase").unwrap();
+
+    edit_keystore(&keystore(&dir), "ciphertext", |ciphertext| {
+        let flipped = if ciphertext.starts_with('0') {
+            "1"
+        } else {
+            "0"
+        };
+        format!("{}{}", flipped, &ciphertext[1..])
+    });
+
+    assert!(load(&keystore(&dir), "passphrase").is_err());
+}
+
+#[test]
+fn a_keystore_claiming_another_peer_id_is_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    store(&keystore(&dir), &Keypair::generate_ed25519(), "passphrase").unwrap();
+
+    // The peer id is authenticated, so it can't be swapped for another without the passphrase.
+    let other_peer_id = Keypair::generate_ed25519()
+        .public()
+        .to_peer_id()
+        .to_string();
+    edit_keystore(&keystore(&dir), "peer_id", |_| other_peer_id.clone());
+
+    assert_eq!(peer_id(&keystore(&dir)).unwrap().to_string(), other_peer_id);
+    assert!(load(&keystore(&dir), "passphrase").is_err());
+}
+
+#[test]
+fn storing_replaces_the_identity() {
+    let dir = tempfile::tempdir().unwrap();
+    store(&keystore(&dir), &Keypair::generate_ed25519(), "passphrase").unwrap();
+
+    let keypair = Keypair::generate_ed25519();
+    store(&keystore(&dir), &keypair, "new passphrase").unwrap();
+
+    assert!(load(&keystore(&dir), "passphrase").is_err());
+    assert_eq!(
+        load(&keystore(&dir), "new passphrase").unwrap().public(),
+        keypair.public()
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn private_files_are_readable_by_the_owner_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("private");
+    let tmp_path = dir.path().join("private.tmp");
+
+    // A temporary file left behind by an earlier write, readable by everyone.
+    std::fs::write(&tmp_path, b"stale").unwrap();
+    std::fs::set_permissions(&tmp_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+    write_private(&path, b"secret").unwrap();
+
+    assert_eq!(std::fs::read(&path).unwrap(), b"secret");
+    assert_eq!(
+        std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+        0o600
+    );
+    assert!(!tmp_path.exists());
+}
@@ -2,5 +2,12 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub enum P2pMessage {
-    Noop { data: String },
+    Noop {
+        data: String,
+    },
+    /// The hex-encoded state root of a live object after the sender processed messages to it.
+    StateRoot {
+        live_object_id: String,
+        state_root: String,
+    },
 }
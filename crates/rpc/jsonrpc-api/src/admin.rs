@@ -0,0 +1,19 @@
+use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use ramd_jsonrpc_types::admin::{
+    Backup, CancelExecution, MaintenanceReportResponse, ReadCacheStatsResponse,
+};
+
+#[rpc(server, client, namespace = "admin")]
+pub trait AdminApi {
+    #[method(name = "cancel")]
+    async fn cancel_execution(&self, request: CancelExecution) -> RpcResult<bool>;
+
+    #[method(name = "backup")]
+    async fn backup(&self, request: Backup) -> RpcResult<()>;
+
+    #[method(name = "read_cache_stats")]
+    async fn read_cache_stats(&self) -> RpcResult<ReadCacheStatsResponse>;
+
+    #[method(name = "run_maintenance")]
+    async fn run_maintenance(&self) -> RpcResult<MaintenanceReportResponse>;
+}
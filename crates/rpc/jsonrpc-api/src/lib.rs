@@ -1,9 +1,12 @@
+mod admin;
 mod live_object;
 
 pub mod server {
+    pub use crate::admin::AdminApiServer;
     pub use crate::live_object::LiveObjectApiServer;
 }
 
 pub mod client {
+    pub use crate::admin::AdminApiClient;
     pub use crate::live_object::LiveObjectApiClient;
 }
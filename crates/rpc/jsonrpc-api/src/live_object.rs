@@ -1,5 +1,8 @@
 use jsonrpsee::{core::RpcResult, proc_macros::rpc};
-use ramd_jsonrpc_types::live_object::{CreateLiveObject, ExecuteLiveObject};
+use ramd_jsonrpc_types::live_object::{
+    CreateLiveObject, ExecuteLiveObject, GetLiveObjectAbi, GetMessageStatus, GetStateProof,
+    GetStateRoot, GetStorageUsage, MessageStatusResponse, StateProofResponse,
+};
 
 #[rpc(server, client, namespace = "live_object")]
 pub trait LiveObjectApi {
@@ -8,4 +11,25 @@ pub trait LiveObjectApi {
 
     #[method(name = "execute")]
     async fn execute_live_object(&self, request: ExecuteLiveObject) -> RpcResult<String>;
+
+    #[method(name = "submit")]
+    async fn submit_live_object_execution(&self, request: ExecuteLiveObject) -> RpcResult<String>;
+
+    #[method(name = "message_status")]
+    async fn message_status(
+        &self,
+        request: GetMessageStatus,
+    ) -> RpcResult<Option<MessageStatusResponse>>;
+
+    #[method(name = "abi")]
+    async fn live_object_abi(&self, request: GetLiveObjectAbi) -> RpcResult<Option<String>>;
+
+    #[method(name = "storage_usage")]
+    async fn storage_usage(&self, request: GetStorageUsage) -> RpcResult<u64>;
+
+    #[method(name = "state_root")]
+    async fn state_root(&self, request: GetStateRoot) -> RpcResult<String>;
+
+    #[method(name = "state_proof")]
+    async fn state_proof(&self, request: GetStateProof) -> RpcResult<StateProofResponse>;
 }
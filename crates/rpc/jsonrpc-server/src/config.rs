@@ -3,10 +3,20 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq, Serialize)]
 pub struct JsonRpcServerConfig {
     pub port: u16,
+    /// Port of the admin API, which only listens on localhost.
+    #[serde(default = "default_admin_port")]
+    pub admin_port: u16,
 }
 
 impl Default for JsonRpcServerConfig {
     fn default() -> Self {
-        Self { port: 1319 }
+        Self {
+            port: 1319,
+            admin_port: default_admin_port(),
+        }
     }
 }
+
+fn default_admin_port() -> u16 {
+    1320
+}
@@ -1,24 +1,25 @@
 pub mod config;
 
-use std::net::SocketAddr;
+use std::net::{Ipv4Addr, SocketAddr};
 use std::sync::Arc;
 
 use config::JsonRpcServerConfig;
 pub use jsonrpsee::server::ServerBuilder;
 use jsonrpsee::{server::ServerHandle, RpcModule};
-use ramd_db::storage::Storage;
+use ramd_db::storage::{CompactableStorage, SnapshotStorage};
+use ramd_jsonrpc::admin::AdminApi;
 use ramd_jsonrpc::live_object::LiveObjectApi;
-use ramd_jsonrpc_api::server::LiveObjectApiServer;
+use ramd_jsonrpc_api::server::{AdminApiServer, LiveObjectApiServer};
 use ramd_node::Node;
 use tracing::info;
 
-/// Launch configured jsonrpc server
+/// Launch configured jsonrpc server, which serves the live object API on all interfaces
 pub async fn launch<S>(
     config: &JsonRpcServerConfig,
     node: Arc<Node<S>>,
 ) -> eyre::Result<ServerHandle>
 where
-    S: Storage<Vec<u8>, Vec<u8>> + 'static,
+    S: SnapshotStorage<Vec<u8>, Vec<u8>> + 'static,
 {
     let mut module = RpcModule::new(());
 
@@ -28,6 +29,29 @@ where
         .map_err(|_| eyre::eyre!("Live object API has conflicting methods"))?;
 
     let socket_addr = format!("0.0.0.0:{}", config.port).parse::<SocketAddr>()?;
+    start(socket_addr, module).await
+}
+
+/// Launch the admin API, which only listens on localhost, as it can cancel messages and write backups
+pub async fn launch_admin<S>(
+    config: &JsonRpcServerConfig,
+    node: Arc<Node<S>>,
+) -> eyre::Result<ServerHandle>
+where
+    S: SnapshotStorage<Vec<u8>, Vec<u8>> + CompactableStorage<Vec<u8>, Vec<u8>> + 'static,
+{
+    let mut module = RpcModule::new(());
+
+    let admin_api = AdminApi::new(node.clone());
+    module
+        .merge(admin_api.into_rpc())
+        .map_err(|_| eyre::eyre!("Admin API has conflicting methods"))?;
+
+    let socket_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, config.admin_port));
+    start(socket_addr, module).await
+}
+
+async fn start(socket_addr: SocketAddr, module: RpcModule<()>) -> eyre::Result<ServerHandle> {
     let server = ServerBuilder::new()
         .build(socket_addr)
         .await
@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CancelExecution {
+    pub message_id: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Backup {
+    pub name: String, // The directory inside the backup directory to write the backup to. It must not exist yet.
+}
+
+/// Hit and miss statistics of the cache of storage reads.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadCacheStatsResponse {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: u64,
+    pub size: u64, // The number of bytes the cached keys and values take.
+}
+
+/// What a maintenance run did.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceReportResponse {
+    pub compacted_live_objects: u64,
+    pub pruned_log_entries: u64,
+    pub reclaimed_bytes: u64,
+}
@@ -7,6 +7,8 @@ use tracing::error;
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CreateLiveObject {
     pub wasm_bytes: String, // Base64 encoded wasm bytes.
+    #[serde(default)]
+    pub memory_limit: Option<usize>, // In bytes. The node's default applies if unset.
 }
 
 impl CreateLiveObject {
@@ -22,9 +24,76 @@ impl CreateLiveObject {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetLiveObjectAbi {
+    pub live_object_id: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetStorageUsage {
+    pub live_object_id: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetStateRoot {
+    pub live_object_id: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetStateProof {
+    pub live_object_id: String,
+    pub key: String, // Hex encoded key, without the live object ID.
+}
+
+impl GetStateProof {
+    pub fn decode_key(&self) -> RpcResult<Vec<u8>> {
+        match hex::decode(&self.key) {
+            Ok(key) => Ok(key),
+            Err(e) => {
+                error!(target: "ramd::jsonrpc-types", "Failed to decode key with error `{}`", e.to_string());
+
+                Err(ErrorObject::from(ErrorCode::InvalidParams))
+            }
+        }
+    }
+}
+
+/// A proof of the value of a key against the state root of a live object. All bytes are hex encoded.
+/// The value is `None` and the proof shows that the key doesn't exist if the live object doesn't have the key.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateProofResponse {
+    pub state_root: String,
+    pub value: Option<String>,
+    /// Hashes of the siblings along the path of the key, from the root down.
+    pub siblings: Vec<String>,
+    /// The leaf at the end of the path, or `None` if the path ends at an empty subtree.
+    pub leaf: Option<StateProofLeaf>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateProofLeaf {
+    pub key_hash: String,
+    pub value_hash: String,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ExecuteLiveObject {
     pub live_object_id: String,
     pub method: String,
     pub args: String,
 }
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetMessageStatus {
+    pub message_id: String,
+}
+
+/// The status of a submitted message. `result` is the JSON object mapping the message ID to either its result or
+/// its error.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum MessageStatusResponse {
+    Pending,
+    Processed { result: String },
+    Failed,
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use jsonrpsee::core::RpcResult;
+use jsonrpsee::types::{error::ErrorObject, ErrorCode};
+use ramd_jsonrpc_api::server::AdminApiServer;
+use ramd_jsonrpc_types::admin::{
+    Backup, CancelExecution, MaintenanceReportResponse, ReadCacheStatsResponse,
+};
+use ramd_node::AdminHandler;
+use tracing::{error, info};
+
+pub struct AdminApi<H>
+where
+    H: AdminHandler,
+{
+    node: Arc<H>,
+}
+
+impl<H> AdminApi<H>
+where
+    H: AdminHandler,
+{
+    pub fn new(node: Arc<H>) -> Self {
+        Self { node: node.clone() }
+    }
+}
+
+#[async_trait]
+impl<H> AdminApiServer for AdminApi<H>
+where
+    H: AdminHandler + 'static,
+{
+    async fn cancel_execution(&self, request: CancelExecution) -> RpcResult<bool> {
+        info!(target: "ramd::jsonrpc", "Request to cancel message `{}`", request.message_id);
+
+        Ok(self.node.cancel_execution(request.message_id))
+    }
+
+    async fn backup(&self, request: Backup) -> RpcResult<()> {
+        info!(target: "ramd::jsonrpc", "Request to back up the database as `{}`", request.name);
+
+        // Writing a checkpoint blocks on disk, so keep it off the async runtime.
+        let node = self.node.clone();
+        tokio::task::spawn_blocking(move || node.backup(request.name))
+            .await
+            .map_err(|e| {
+                error!(target: "ramd::jsonrpc", "Backup panicked with error `{}`", e.to_string());
+                ErrorObject::from(ErrorCode::InternalError)
+            })?
+            .map_err(|e| {
+                error!(target: "ramd::jsonrpc", "Failed to back up the database with error `{}`", e.to_string());
+                ErrorObject::from(ErrorCode::InvalidParams)
+            })
+    }
+
+    async fn read_cache_stats(&self) -> RpcResult<ReadCacheStatsResponse> {
+        info!(target: "ramd::jsonrpc", "Request to get the read cache statistics");
+
+        let stats = self.node.read_cache_stats().map_err(|e| {
+            error!(target: "ramd::jsonrpc", "Failed to get the read cache statistics with error `{}`", e.to_string());
+            ErrorObject::from(ErrorCode::InternalError)
+        })?;
+
+        Ok(ReadCacheStatsResponse {
+            hits: stats.hits,
+            misses: stats.misses,
+            evictions: stats.evictions,
+            entries: stats.entries,
+            size: stats.size,
+        })
+    }
+
+    async fn run_maintenance(&self) -> RpcResult<MaintenanceReportResponse> {
+        info!(target: "ramd::jsonrpc", "Request to run maintenance");
+
+        // Compaction blocks on disk, so keep it off the async runtime.
+        let node = self.node.clone();
+        let report = tokio::task::spawn_blocking(move || node.run_maintenance())
+            .await
+            .map_err(|e| {
+                error!(target: "ramd::jsonrpc", "Maintenance panicked with error `{}`", e.to_string());
+                ErrorObject::from(ErrorCode::InternalError)
+            })?
+            .map_err(|e| {
+                error!(target: "ramd::jsonrpc", "Failed to run maintenance with error `{}`", e.to_string());
+                ErrorObject::from(ErrorCode::InternalError)
+            })?;
+
+        Ok(MaintenanceReportResponse {
+            compacted_live_objects: report.compacted_live_objects,
+            pruned_log_entries: report.pruned_log_entries,
+            reclaimed_bytes: report.reclaimed_bytes,
+        })
+    }
+}
@@ -2,10 +2,15 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use jsonrpsee::core::RpcResult;
+use jsonrpsee::types::{error::ErrorObject, ErrorCode};
+use ramd_db::state_tree::StateProof;
 use ramd_jsonrpc_api::server::LiveObjectApiServer;
-use ramd_jsonrpc_types::live_object::{CreateLiveObject, ExecuteLiveObject};
-use ramd_node::LiveObjectHandler;
-use tracing::info;
+use ramd_jsonrpc_types::live_object::{
+    CreateLiveObject, ExecuteLiveObject, GetLiveObjectAbi, GetMessageStatus, GetStateProof,
+    GetStateRoot, GetStorageUsage, MessageStatusResponse, StateProofLeaf, StateProofResponse,
+};
+use ramd_node::{ExecutorError, LiveObjectHandler, MessageStatus};
+use tracing::{error, info};
 
 pub struct LiveObjectApi<H>
 where
@@ -31,16 +36,115 @@ where
     async fn create_live_object(&self, request: CreateLiveObject) -> RpcResult<String> {
         info!(target: "ramd::jsonrpc", "Request to create a live object");
 
-        Ok(self.node.create_live_object(request.decode_wasm_bytes()?))
+        self.node
+            .create_live_object(request.decode_wasm_bytes()?, request.memory_limit)
+            .await
+            .map_err(executor_error)
     }
 
     async fn execute_live_object(&self, request: ExecuteLiveObject) -> RpcResult<String> {
         info!(target: "ramd::jsonrpc", "Request to execute a live object");
 
-        Ok(self.node.execute_live_object(
-            request.live_object_id,
-            request.method,
-            request.args.as_bytes().to_vec(),
-        ))
+        self.node
+            .execute_live_object(
+                request.live_object_id,
+                request.method,
+                request.args.as_bytes().to_vec(),
+            )
+            .await
+            .map_err(executor_error)
+    }
+
+    async fn submit_live_object_execution(&self, request: ExecuteLiveObject) -> RpcResult<String> {
+        info!(target: "ramd::jsonrpc", "Request to submit a live object execution");
+
+        self.node
+            .submit_live_object_execution(
+                request.live_object_id,
+                request.method,
+                request.args.as_bytes().to_vec(),
+            )
+            .await
+            .map_err(executor_error)
+    }
+
+    async fn message_status(
+        &self,
+        request: GetMessageStatus,
+    ) -> RpcResult<Option<MessageStatusResponse>> {
+        info!(target: "ramd::jsonrpc", "Request to get the status of message `{}`", request.message_id);
+
+        Ok(self
+            .node
+            .message_status(request.message_id)
+            .map(|status| match status {
+                MessageStatus::Pending => MessageStatusResponse::Pending,
+                MessageStatus::Processed(result) => MessageStatusResponse::Processed { result },
+                MessageStatus::Failed => MessageStatusResponse::Failed,
+            }))
+    }
+
+    async fn live_object_abi(&self, request: GetLiveObjectAbi) -> RpcResult<Option<String>> {
+        info!(target: "ramd::jsonrpc", "Request to get the ABI of a live object");
+        self.node
+            .live_object_abi(request.live_object_id)
+            .map_err(|e| {
+                error!(target: "ramd::jsonrpc", "Failed to get the ABI with error `{}`", e.to_string());
+                ErrorObject::from(ErrorCode::InvalidParams)
+            })
+    }
+
+    async fn storage_usage(&self, request: GetStorageUsage) -> RpcResult<u64> {
+        info!(target: "ramd::jsonrpc", "Request to get the storage usage of a live object");
+        self.node
+            .storage_usage(request.live_object_id)
+            .map_err(|e| {
+                error!(target: "ramd::jsonrpc", "Failed to get the storage usage with error `{}`", e.to_string());
+                ErrorObject::from(ErrorCode::InvalidParams)
+            })
+    }
+
+    async fn state_root(&self, request: GetStateRoot) -> RpcResult<String> {
+        info!(target: "ramd::jsonrpc", "Request to get the state root of a live object");
+        self.node
+            .state_root(request.live_object_id)
+            .map(hex::encode)
+            .map_err(|e| {
+                error!(target: "ramd::jsonrpc", "Failed to get the state root with error `{}`", e.to_string());
+                ErrorObject::from(ErrorCode::InvalidParams)
+            })
+    }
+
+    async fn state_proof(&self, request: GetStateProof) -> RpcResult<StateProofResponse> {
+        info!(target: "ramd::jsonrpc", "Request to get a state proof of a live object");
+        self.node
+            .state_proof(request.live_object_id.clone(), request.decode_key()?)
+            .map(|(value, proof)| state_proof_response(value, proof))
+            .map_err(|e| {
+                error!(target: "ramd::jsonrpc", "Failed to get the state proof with error `{}`", e.to_string());
+                ErrorObject::from(ErrorCode::InvalidParams)
+            })
+    }
+}
+
+fn state_proof_response(value: Option<Vec<u8>>, proof: StateProof) -> StateProofResponse {
+    StateProofResponse {
+        state_root: hex::encode(proof.root),
+        value: value.map(hex::encode),
+        siblings: proof.siblings.iter().map(hex::encode).collect(),
+        leaf: proof.leaf.map(|leaf| StateProofLeaf {
+            key_hash: hex::encode(leaf.key_hash),
+            value_hash: hex::encode(leaf.value_hash),
+        }),
+    }
+}
+
+/// Convert an executor error into a JSON-RPC error. A full queue is reported as a busy server.
+fn executor_error(err: ExecutorError) -> ErrorObject<'static> {
+    error!(target: "ramd::jsonrpc", "Failed to execute request with error `{}`", err.to_string());
+
+    match err {
+        ExecutorError::QueueFull => ErrorObject::from(ErrorCode::ServerIsBusy),
+        ExecutorError::Stopped => ErrorObject::from(ErrorCode::InternalError),
     }
 }
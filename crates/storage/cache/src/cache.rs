@@ -1,3 +1,30 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use ramd_db::storage::{Storage, WriteBatch};
+
+/// Extra writes to make alongside the changes of a commit, such as the state tree nodes derived from them.
+/// It gets the storage as of before the commit and the changes, with `None` marking a deletion, and its writes
+/// are applied in the same batch as the changes.
+pub type CommitHook = Arc<
+    dyn Fn(
+            &dyn Storage<Vec<u8>, Vec<u8>>,
+            &BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+        ) -> eyre::Result<WriteBatch>
+        + Send
+        + Sync,
+>;
+
 pub trait Cache: Send + Sync {
+    /// Write all uncommitted changes, including those in open savepoints, to the underlying storage at once.
     fn commit(&self) -> eyre::Result<()>;
+
+    /// Open a new savepoint. Changes made afterwards can be rolled back without affecting earlier ones.
+    fn savepoint(&self) -> eyre::Result<()>;
+
+    /// Discard all changes made since the most recent savepoint and close it.
+    fn rollback_to_savepoint(&self) -> eyre::Result<()>;
+
+    /// Close the most recent savepoint, keeping its changes in the enclosing one.
+    fn release_savepoint(&self) -> eyre::Result<()>;
 }
@@ -1,16 +1,61 @@
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
-use crate::Cache;
+use crate::{Cache, CommitHook};
 use ramd_db::storage::Storage;
 
+/// Values read from the storage, with `None` marking a key that doesn't exist there.
+type StorageReads = HashMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// A single layer of uncommitted changes. The bottom layer is always present and
+/// every savepoint pushes a new layer on top of it.
+#[derive(Default)]
+struct CacheLayer {
+    /// Values written through this layer.
+    cache: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Keys deleted through this layer, which shadow the layers below and the storage.
+    tombstone: HashSet<Vec<u8>>,
+    /// Keys read through this layer, whether or not they were found.
+    read_set: HashSet<Vec<u8>>,
+    /// Keys explicitly written through this layer, with `None` marking a deletion.
+    write_set: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl CacheLayer {
+    fn set(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.tombstone.remove(&key);
+        self.cache.insert(key, value);
+    }
+
+    fn delete(&mut self, key: Vec<u8>) {
+        self.cache.remove(&key);
+        self.tombstone.insert(key);
+    }
+
+    /// Apply the changes of the given layer on top of this layer.
+    fn merge(&mut self, layer: CacheLayer) {
+        for (key, value) in layer.cache {
+            self.set(key, value);
+        }
+
+        for key in layer.tombstone {
+            self.delete(key);
+        }
+
+        self.read_set.extend(layer.read_set);
+        self.write_set.extend(layer.write_set);
+    }
+}
+
 pub struct InMemoryCache<S>
 where
     S: Storage<Vec<u8>, Vec<u8>>,
 {
-    cache: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
-    tombstone: RwLock<HashSet<Vec<u8>>>,
+    layers: RwLock<Vec<CacheLayer>>,
+    /// Kept apart from the layers, so that reads are never written back on commit.
+    storage_reads: RwLock<StorageReads>,
     storage: Arc<S>,
+    commit_hook: Option<CommitHook>,
 }
 
 impl<S> InMemoryCache<S>
@@ -19,46 +64,116 @@ where
 {
     pub fn new(storage: Arc<S>) -> Self {
         Self {
-            cache: RwLock::new(BTreeMap::new()),
-            tombstone: RwLock::new(HashSet::new()),
+            layers: RwLock::new(vec![CacheLayer::default()]),
+            storage_reads: RwLock::new(HashMap::new()),
             storage,
+            commit_hook: None,
+        }
+    }
+
+    /// Make the writes of the hook in the same batch as the changes of every commit.
+    pub fn with_commit_hook(mut self, commit_hook: CommitHook) -> Self {
+        self.commit_hook = Some(commit_hook);
+        self
+    }
+
+    /// Keys read since the most recent savepoint was opened.
+    pub fn read_set(&self) -> eyre::Result<HashSet<Vec<u8>>> {
+        let read_set = self
+            .read_layers()?
+            .last()
+            .map(|layer| layer.read_set.clone())
+            .unwrap_or_default();
+
+        Ok(read_set)
+    }
+
+    /// Keys written since the most recent savepoint was opened, with `None` marking a deletion.
+    pub fn write_set(&self) -> eyre::Result<BTreeMap<Vec<u8>, Option<Vec<u8>>>> {
+        let write_set = self
+            .read_layers()?
+            .last()
+            .map(|layer| layer.write_set.clone())
+            .unwrap_or_default();
+
+        Ok(write_set)
+    }
+
+    /// The value of the key as of when the most recent savepoint was opened, ignoring the changes made since.
+    /// The key is recorded in the read set of the top layer.
+    pub fn get_opt_before_savepoint(&self, key: Vec<u8>) -> eyre::Result<Option<Vec<u8>>> {
+        self.read_below(key, 1)
+    }
+
+    /// Read the key as seen through all layers, falling back to the storage if no layer has changed it.
+    /// The key is recorded in the read set of the top layer.
+    fn read(&self, key: Vec<u8>) -> eyre::Result<Option<Vec<u8>>> {
+        self.read_below(key, 0)
+    }
+
+    /// Read the key as seen through all but the top `skip` layers.
+    fn read_below(&self, key: Vec<u8>, skip: usize) -> eyre::Result<Option<Vec<u8>>> {
+        {
+            let mut layers = self.write_layers()?;
+            if let Some(layer) = layers.last_mut() {
+                layer.read_set.insert(key.clone());
+            }
+
+            // The most recent layer that wrote or deleted the key decides its value.
+            for layer in layers.iter().rev().skip(skip) {
+                if let Some(value) = layer.cache.get(&key) {
+                    return Ok(Some(value.clone()));
+                }
+                if layer.tombstone.contains(&key) {
+                    return Ok(None);
+                }
+            }
+        }
+
+        if let Some(value) = self.storage_reads()?.get(&key) {
+            return Ok(value.clone());
         }
+
+        // Keep the value read from the storage so that later reads don't hit the storage again.
+        let value = self.storage.get_opt(key.clone())?;
+        self.write_storage_reads()?.insert(key, value.clone());
+        Ok(value)
     }
 
-    fn read_cache(&self) -> eyre::Result<RwLockReadGuard<BTreeMap<Vec<u8>, Vec<u8>>>> {
-        let cache = self
-            .cache
+    fn storage_reads(&self) -> eyre::Result<RwLockReadGuard<StorageReads>> {
+        let storage_reads = self
+            .storage_reads
             .read()
             .map_err(|err| eyre::eyre!(err.to_string()))?;
 
-        Ok(cache)
+        Ok(storage_reads)
     }
 
-    fn write_cache(&self) -> eyre::Result<RwLockWriteGuard<BTreeMap<Vec<u8>, Vec<u8>>>> {
-        let cache = self
-            .cache
+    fn write_storage_reads(&self) -> eyre::Result<RwLockWriteGuard<StorageReads>> {
+        let storage_reads = self
+            .storage_reads
             .write()
             .map_err(|err| eyre::eyre!(err.to_string()))?;
 
-        Ok(cache)
+        Ok(storage_reads)
     }
 
-    fn read_tombstone(&self) -> eyre::Result<RwLockReadGuard<HashSet<Vec<u8>>>> {
-        let tombstone = self
-            .tombstone
+    fn read_layers(&self) -> eyre::Result<RwLockReadGuard<Vec<CacheLayer>>> {
+        let layers = self
+            .layers
             .read()
             .map_err(|err| eyre::eyre!(err.to_string()))?;
 
-        Ok(tombstone)
+        Ok(layers)
     }
 
-    fn write_tombstone(&self) -> eyre::Result<RwLockWriteGuard<HashSet<Vec<u8>>>> {
-        let tombstone = self
-            .tombstone
+    fn write_layers(&self) -> eyre::Result<RwLockWriteGuard<Vec<CacheLayer>>> {
+        let layers = self
+            .layers
             .write()
             .map_err(|err| eyre::eyre!(err.to_string()))?;
 
-        Ok(tombstone)
+        Ok(layers)
     }
 }
 
@@ -67,52 +182,46 @@ where
     S: Storage<Vec<u8>, Vec<u8>>,
 {
     fn has(&self, key: Vec<u8>) -> eyre::Result<bool> {
-        if self.read_cache()?.contains_key(&key) {
-            return Ok(true);
-        }
-
-        match self.storage.get_opt(key.clone())? {
-            Some(value) => {
-                self.set(key, value.clone())?;
-                Ok(true)
-            }
-            None => Ok(false),
-        }
+        Ok(self.read(key)?.is_some())
     }
 
     fn get(&self, key: Vec<u8>) -> eyre::Result<Vec<u8>> {
-        if let Some(value) = self.read_cache()?.get(&key) {
-            return Ok(value.clone());
-        }
-
-        let value = self.storage.get(key.clone())?;
-        self.set(key, value.clone())?;
-        Ok(value)
+        self.read(key)?.ok_or(eyre::eyre!("Key not found"))
     }
 
     fn get_opt(&self, key: Vec<u8>) -> eyre::Result<Option<Vec<u8>>> {
-        if let Some(value) = self.read_cache()?.get(&key) {
-            return Ok(Some(value.clone()));
-        }
-
-        match self.storage.get_opt(key.clone())? {
-            Some(value) => {
-                self.set(key, value.clone())?;
-                Ok(Some(value))
-            }
-            None => Ok(None),
-        }
+        self.read(key)
     }
 
     fn set(&self, key: Vec<u8>, value: Vec<u8>) -> eyre::Result<()> {
-        self.write_tombstone()?.remove(&key);
-        self.write_cache()?.insert(key, value);
+        if let Some(layer) = self.write_layers()?.last_mut() {
+            layer.write_set.insert(key.clone(), Some(value.clone()));
+            layer.set(key, value);
+        }
+
         Ok(())
     }
 
     fn delete(&self, key: Vec<u8>) -> eyre::Result<()> {
-        self.write_cache()?.remove(&key);
-        self.write_tombstone()?.insert(key);
+        if let Some(layer) = self.write_layers()?.last_mut() {
+            layer.write_set.insert(key.clone(), None);
+            layer.delete(key);
+        }
+
+        Ok(())
+    }
+
+    fn write_batch(&self, writes: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> eyre::Result<()> {
+        if let Some(layer) = self.write_layers()?.last_mut() {
+            for (key, value) in writes {
+                layer.write_set.insert(key.clone(), value.clone());
+                match value {
+                    Some(value) => layer.set(key, value),
+                    None => layer.delete(key),
+                }
+            }
+        }
+
         Ok(())
     }
 }
@@ -122,12 +231,61 @@ where
     S: Storage<Vec<u8>, Vec<u8>>,
 {
     fn commit(&self) -> eyre::Result<()> {
-        for (key, value) in self.read_cache()?.iter() {
-            self.storage.set(key.clone(), value.clone())?;
+        let mut layers = self.write_layers()?;
+
+        // The changes of all layers in one batch, with later layers overriding earlier ones.
+        let mut changes = BTreeMap::new();
+        for layer in layers.iter() {
+            for (key, value) in layer.cache.iter() {
+                changes.insert(key.clone(), Some(value.clone()));
+            }
+            for key in layer.tombstone.iter() {
+                changes.insert(key.clone(), None);
+            }
         }
 
-        for key in self.read_tombstone()?.iter() {
-            self.storage.delete(key.clone())?;
+        let hook_writes = match &self.commit_hook {
+            Some(commit_hook) => commit_hook(self.storage.as_ref(), &changes)?,
+            None => Vec::new(),
+        };
+
+        // The layers are kept until the batch is written, so a failed commit can be retried.
+        let mut writes: Vec<_> = changes.into_iter().collect();
+        writes.extend(hook_writes);
+        self.storage.write_batch(writes)?;
+
+        // Committed changes now live in the storage, so start over with an empty layer and forget earlier reads.
+        *layers = vec![CacheLayer::default()];
+        self.write_storage_reads()?.clear();
+
+        Ok(())
+    }
+
+    fn savepoint(&self) -> eyre::Result<()> {
+        self.write_layers()?.push(CacheLayer::default());
+        Ok(())
+    }
+
+    fn rollback_to_savepoint(&self) -> eyre::Result<()> {
+        let mut layers = self.write_layers()?;
+        if layers.len() <= 1 {
+            return Err(eyre::eyre!("No savepoint to roll back to"));
+        }
+
+        layers.pop();
+        Ok(())
+    }
+
+    fn release_savepoint(&self) -> eyre::Result<()> {
+        let mut layers = self.write_layers()?;
+        if layers.len() <= 1 {
+            return Err(eyre::eyre!("No savepoint to release"));
+        }
+
+        if let Some(layer) = layers.pop() {
+            if let Some(parent) = layers.last_mut() {
+                parent.merge(layer);
+            }
         }
 
         Ok(())
@@ -1,5 +1,7 @@
 pub mod cache;
 pub mod in_memory;
+pub mod read_cache;
 
 pub use cache::*;
 pub use in_memory::*;
+pub use read_cache::*;
@@ -0,0 +1,195 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use lru::LruCache;
+use ramd_db::storage::{CompactableStorage, Storage};
+
+/// The default number of bytes a `ReadCache` may hold.
+pub const DEFAULT_READ_CACHE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Hit and miss counters of a `ReadCache`, and how much it holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReadCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// The number of entries dropped to stay within the size of the cache.
+    pub evictions: u64,
+    pub entries: u64,
+    /// The number of bytes, counting both keys and values, the cached entries take.
+    pub size: u64,
+}
+
+struct ReadCacheState {
+    /// The values of recently read keys, with `None` marking a key that doesn't exist.
+    entries: LruCache<Vec<u8>, Option<Vec<u8>>>,
+    /// Bumped by every write, so that a value read from the storage before a write isn't cached after it.
+    generation: u64,
+    stats: ReadCacheStats,
+}
+
+/// A size-bounded read-through cache of the most recently used keys of the storage, shared across messages.
+/// Writes go straight to the storage and replace the cached value, so all writes to the storage must go
+/// through the cache for it to stay coherent. Safe to share between concurrent processors.
+pub struct ReadCache<S>
+where
+    S: Storage<Vec<u8>, Vec<u8>>,
+{
+    storage: Arc<S>,
+    /// The number of bytes, counting both keys and values, the cache may hold. Zero disables caching.
+    capacity: usize,
+    state: Mutex<ReadCacheState>,
+}
+
+impl<S> ReadCache<S>
+where
+    S: Storage<Vec<u8>, Vec<u8>>,
+{
+    pub fn new(storage: Arc<S>, capacity: usize) -> Self {
+        Self {
+            storage,
+            capacity,
+            state: Mutex::new(ReadCacheState {
+                entries: LruCache::unbounded(),
+                generation: 0,
+                stats: ReadCacheStats::default(),
+            }),
+        }
+    }
+
+    pub fn stats(&self) -> eyre::Result<ReadCacheStats> {
+        let state = self.state()?;
+
+        Ok(ReadCacheStats {
+            entries: state.entries.len() as u64,
+            ..state.stats
+        })
+    }
+
+    fn read(&self, key: Vec<u8>) -> eyre::Result<Option<Vec<u8>>> {
+        let generation = {
+            let mut state = self.state()?;
+            if let Some(value) = state.entries.get(&key).cloned() {
+                state.stats.hits += 1;
+                return Ok(value);
+            }
+
+            state.stats.misses += 1;
+            state.generation
+        };
+
+        // Don't hold the lock while reading from the storage, so that other readers aren't blocked on it.
+        let value = self.storage.get_opt(key.clone())?;
+
+        let mut state = self.state()?;
+        if state.generation == generation {
+            self.insert(&mut state, key, value.clone());
+        }
+
+        Ok(value)
+    }
+
+    fn write(&self, key: Vec<u8>, value: Option<Vec<u8>>) -> eyre::Result<()> {
+        // Hold the lock across the write, so that concurrent writes to the key are cached in storage order.
+        let mut state = self.state()?;
+        match &value {
+            Some(value) => self.storage.set(key.clone(), value.clone())?,
+            None => self.storage.delete(key.clone())?,
+        }
+
+        state.generation += 1;
+        self.insert(&mut state, key, value);
+
+        Ok(())
+    }
+
+    fn write_all(&self, writes: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> eyre::Result<()> {
+        let mut state = self.state()?;
+        self.storage.write_batch(writes.clone())?;
+
+        state.generation += 1;
+        for (key, value) in writes {
+            self.insert(&mut state, key, value);
+        }
+
+        Ok(())
+    }
+
+    /// Cache the value of the key, evicting the least recently used entries to make room for it.
+    fn insert(&self, state: &mut ReadCacheState, key: Vec<u8>, value: Option<Vec<u8>>) {
+        if let Some(old_value) = state.entries.pop(&key) {
+            state.stats.size -= entry_size(&key, &old_value);
+        }
+
+        let size = entry_size(&key, &value);
+        if size > self.capacity as u64 {
+            return;
+        }
+
+        while state.stats.size + size > self.capacity as u64 {
+            let Some((evicted_key, evicted_value)) = state.entries.pop_lru() else {
+                break;
+            };
+            state.stats.size -= entry_size(&evicted_key, &evicted_value);
+            state.stats.evictions += 1;
+        }
+
+        state.stats.size += size;
+        state.entries.put(key, value);
+    }
+
+    fn state(&self) -> eyre::Result<MutexGuard<ReadCacheState>> {
+        self.state
+            .lock()
+            .map_err(|err| eyre::eyre!(err.to_string()))
+    }
+}
+
+impl<S> Storage<Vec<u8>, Vec<u8>> for ReadCache<S>
+where
+    S: Storage<Vec<u8>, Vec<u8>>,
+{
+    fn has(&self, key: Vec<u8>) -> eyre::Result<bool> {
+        Ok(self.read(key)?.is_some())
+    }
+
+    fn get(&self, key: Vec<u8>) -> eyre::Result<Vec<u8>> {
+        self.read(key)?.ok_or(eyre::eyre!("Key not found"))
+    }
+
+    fn get_opt(&self, key: Vec<u8>) -> eyre::Result<Option<Vec<u8>>> {
+        self.read(key)
+    }
+
+    fn set(&self, key: Vec<u8>, value: Vec<u8>) -> eyre::Result<()> {
+        self.write(key, Some(value))
+    }
+
+    fn delete(&self, key: Vec<u8>) -> eyre::Result<()> {
+        self.write(key, None)
+    }
+
+    fn write_batch(&self, writes: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> eyre::Result<()> {
+        self.write_all(writes)
+    }
+}
+
+/// Compaction doesn't change what the storage holds, so it goes straight to the storage.
+impl<S> CompactableStorage<Vec<u8>, Vec<u8>> for ReadCache<S>
+where
+    S: CompactableStorage<Vec<u8>, Vec<u8>>,
+{
+    fn keys_with_prefix(&self, prefix: &[u8]) -> eyre::Result<Vec<Vec<u8>>> {
+        self.storage.keys_with_prefix(prefix)
+    }
+
+    fn compact_range(&self, from: &[u8], to: &[u8]) -> eyre::Result<()> {
+        self.storage.compact_range(from, to)
+    }
+
+    fn live_data_size(&self) -> eyre::Result<u64> {
+        self.storage.live_data_size()
+    }
+}
+
+fn entry_size(key: &[u8], value: &Option<Vec<u8>>) -> u64 {
+    (key.len() + value.as_ref().map_or(0, Vec::len)) as u64
+}
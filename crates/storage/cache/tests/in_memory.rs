@@ -0,0 +1,408 @@
+//! Random sequences of operations on an `InMemoryCache`, checked against a reference model of layered changes,
+//! and how savepoints and commits behave.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use proptest::prelude::*;
+use ramd_cache::{Cache, CommitHook, InMemoryCache};
+use ramd_db::memory::MemoryStorage;
+use ramd_db::state_tree::{state_root, state_tree_writes, EMPTY_HASH};
+use ramd_db::storage::Storage;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Has(Vec<u8>),
+    Get(Vec<u8>),
+    GetOpt(Vec<u8>),
+    Set(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    Savepoint,
+    Rollback,
+    Release,
+    Commit,
+}
+
+/// The expected behaviour: the storage, and a stack of layers mapping keys to their new value,
+/// with `None` marking a deletion. Each layer also keeps the keys read and written through it.
+struct Model {
+    storage: BTreeMap<Vec<u8>, Vec<u8>>,
+    layers: Vec<ModelLayer>,
+}
+
+#[derive(Default)]
+struct ModelLayer {
+    changes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    read_set: BTreeSet<Vec<u8>>,
+}
+
+impl Model {
+    fn new(storage: BTreeMap<Vec<u8>, Vec<u8>>) -> Self {
+        Self {
+            storage,
+            layers: vec![ModelLayer::default()],
+        }
+    }
+
+    fn read(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        self.layers
+            .last_mut()
+            .unwrap()
+            .read_set
+            .insert(key.to_vec());
+
+        self.layers
+            .iter()
+            .rev()
+            .find_map(|layer| layer.changes.get(key))
+            .cloned()
+            .unwrap_or_else(|| self.storage.get(key).cloned())
+    }
+
+    fn write(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) {
+        self.layers.last_mut().unwrap().changes.insert(key, value);
+    }
+
+    fn savepoint(&mut self) {
+        self.layers.push(ModelLayer::default());
+    }
+
+    fn rollback(&mut self) -> bool {
+        if self.layers.len() <= 1 {
+            return false;
+        }
+
+        self.layers.pop();
+        true
+    }
+
+    fn release(&mut self) -> bool {
+        if self.layers.len() <= 1 {
+            return false;
+        }
+
+        let layer = self.layers.pop().unwrap();
+        let parent = self.layers.last_mut().unwrap();
+        parent.changes.extend(layer.changes);
+        parent.read_set.extend(layer.read_set);
+        true
+    }
+
+    fn commit(&mut self) {
+        for layer in self.layers.drain(..) {
+            for (key, value) in layer.changes {
+                match value {
+                    Some(value) => self.storage.insert(key, value),
+                    None => self.storage.remove(&key),
+                };
+            }
+        }
+
+        self.layers.push(ModelLayer::default());
+    }
+}
+
+fn key() -> impl Strategy<Value = Vec<u8>> {
+    // Few distinct keys, so that operations often hit the same key.
+    (0u8..6).prop_map(|key| vec![key])
+}
+
+fn value() -> impl Strategy<Value = Vec<u8>> {
+    prop::collection::vec(any::<u8>(), 0..4)
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        3 => key().prop_map(Op::Has),
+        3 => key().prop_map(Op::Get),
+        3 => key().prop_map(Op::GetOpt),
+        4 => (key(), value()).prop_map(|(key, value)| Op::Set(key, value)),
+        3 => key().prop_map(Op::Delete),
+        2 => Just(Op::Savepoint),
+        1 => Just(Op::Rollback),
+        1 => Just(Op::Release),
+        1 => Just(Op::Commit),
+    ]
+}
+
+fn initial_storage() -> impl Strategy<Value = BTreeMap<Vec<u8>, Vec<u8>>> {
+    prop::collection::btree_map(key(), value(), 0..6)
+}
+
+proptest! {
+    #[test]
+    fn matches_reference_model(
+        initial in initial_storage(),
+        ops in prop::collection::vec(op(), 0..64),
+    ) {
+        let storage = Arc::new(MemoryStorage::new(initial.clone()));
+        let cache = InMemoryCache::new(storage.clone());
+        let mut model = Model::new(initial);
+
+        for op in ops {
+            match op {
+                Op::Has(key) => {
+                    prop_assert_eq!(cache.has(key.clone()).unwrap(), model.read(&key).is_some());
+                }
+                Op::Get(key) => {
+                    prop_assert_eq!(cache.get(key.clone()).ok(), model.read(&key));
+                }
+                Op::GetOpt(key) => {
+                    prop_assert_eq!(cache.get_opt(key.clone()).unwrap(), model.read(&key));
+                }
+                Op::Set(key, value) => {
+                    cache.set(key.clone(), value.clone()).unwrap();
+                    model.write(key, Some(value));
+                }
+                Op::Delete(key) => {
+                    cache.delete(key.clone()).unwrap();
+                    model.write(key, None);
+                }
+                Op::Savepoint => {
+                    cache.savepoint().unwrap();
+                    model.savepoint();
+                }
+                Op::Rollback => {
+                    prop_assert_eq!(cache.rollback_to_savepoint().is_ok(), model.rollback());
+                }
+                Op::Release => {
+                    prop_assert_eq!(cache.release_savepoint().is_ok(), model.release());
+                }
+                Op::Commit => {
+                    cache.commit().unwrap();
+                    model.commit();
+                }
+            }
+
+            // Nothing reaches the storage before a commit, and reads are never written back.
+            prop_assert_eq!(&storage.data(), &model.storage);
+
+            let top = model.layers.last().unwrap();
+            prop_assert_eq!(cache.write_set().unwrap(), top.changes.clone());
+            prop_assert_eq!(
+                cache.read_set().unwrap().into_iter().collect::<BTreeSet<_>>(),
+                top.read_set.clone()
+            );
+        }
+    }
+
+    #[test]
+    fn reads_alone_commit_nothing(
+        initial in initial_storage(),
+        keys in prop::collection::vec(key(), 0..16),
+    ) {
+        let storage = Arc::new(CountingStorage {
+            inner: MemoryStorage::new(initial.clone()),
+            writes: RwLock::new(0),
+        });
+        let cache = InMemoryCache::new(storage.clone());
+
+        for key in keys {
+            prop_assert_eq!(cache.get_opt(key.clone()).unwrap(), initial.get(&key).cloned());
+        }
+        cache.commit().unwrap();
+
+        prop_assert_eq!(*storage.writes.read().unwrap(), 0);
+    }
+}
+
+/// Counts the writes that reach the storage.
+struct CountingStorage {
+    inner: MemoryStorage,
+    writes: RwLock<usize>,
+}
+
+impl Storage<Vec<u8>, Vec<u8>> for CountingStorage {
+    fn has(&self, key: Vec<u8>) -> eyre::Result<bool> {
+        self.inner.has(key)
+    }
+
+    fn get(&self, key: Vec<u8>) -> eyre::Result<Vec<u8>> {
+        self.inner.get(key)
+    }
+
+    fn get_opt(&self, key: Vec<u8>) -> eyre::Result<Option<Vec<u8>>> {
+        self.inner.get_opt(key)
+    }
+
+    fn set(&self, key: Vec<u8>, value: Vec<u8>) -> eyre::Result<()> {
+        *self.writes.write().unwrap() += 1;
+        self.inner.set(key, value)
+    }
+
+    fn delete(&self, key: Vec<u8>) -> eyre::Result<()> {
+        *self.writes.write().unwrap() += 1;
+        self.inner.delete(key)
+    }
+
+    fn write_batch(&self, writes: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> eyre::Result<()> {
+        *self.writes.write().unwrap() += writes.len();
+        self.inner.write_batch(writes)
+    }
+}
+
+/// Fails every write while `failing` is set.
+#[derive(Default)]
+struct FailingStorage {
+    inner: MemoryStorage,
+    failing: AtomicBool,
+}
+
+impl FailingStorage {
+    fn check(&self) -> eyre::Result<()> {
+        match self.failing.load(Ordering::SeqCst) {
+            true => Err(eyre::eyre!("Write failed")),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Storage<Vec<u8>, Vec<u8>> for FailingStorage {
+    fn has(&self, key: Vec<u8>) -> eyre::Result<bool> {
+        self.inner.has(key)
+    }
+
+    fn get(&self, key: Vec<u8>) -> eyre::Result<Vec<u8>> {
+        self.inner.get(key)
+    }
+
+    fn get_opt(&self, key: Vec<u8>) -> eyre::Result<Option<Vec<u8>>> {
+        self.inner.get_opt(key)
+    }
+
+    fn set(&self, key: Vec<u8>, value: Vec<u8>) -> eyre::Result<()> {
+        self.check()?;
+        self.inner.set(key, value)
+    }
+
+    fn delete(&self, key: Vec<u8>) -> eyre::Result<()> {
+        self.check()?;
+        self.inner.delete(key)
+    }
+
+    fn write_batch(&self, writes: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> eyre::Result<()> {
+        self.check()?;
+        self.inner.write_batch(writes)
+    }
+}
+
+fn bytes(value: &str) -> Vec<u8> {
+    value.as_bytes().to_vec()
+}
+
+#[test]
+fn rollback_discards_changes_since_the_savepoint() {
+    let storage = Arc::new(MemoryStorage::new(BTreeMap::from([(
+        bytes("a"),
+        bytes("0"),
+    )])));
+    let cache = InMemoryCache::new(storage.clone());
+
+    cache.set(bytes("a"), bytes("1")).unwrap();
+    cache.savepoint().unwrap();
+    cache.set(bytes("a"), bytes("2")).unwrap();
+    cache.set(bytes("b"), bytes("2")).unwrap();
+    cache.rollback_to_savepoint().unwrap();
+
+    assert_eq!(cache.get_opt(bytes("a")).unwrap(), Some(bytes("1")));
+    assert_eq!(cache.get_opt(bytes("b")).unwrap(), None);
+    assert!(cache.rollback_to_savepoint().is_err());
+
+    cache.commit().unwrap();
+    assert_eq!(storage.data(), BTreeMap::from([(bytes("a"), bytes("1"))]));
+}
+
+#[test]
+fn release_keeps_changes_in_the_enclosing_savepoint() {
+    let storage = Arc::new(MemoryStorage::new(BTreeMap::from([(
+        bytes("a"),
+        bytes("0"),
+    )])));
+    let cache = InMemoryCache::new(storage.clone());
+
+    cache.savepoint().unwrap();
+    cache.savepoint().unwrap();
+    cache.delete(bytes("a")).unwrap();
+    cache.set(bytes("b"), bytes("1")).unwrap();
+    cache.release_savepoint().unwrap();
+    assert_eq!(cache.get_opt(bytes("a")).unwrap(), None);
+
+    // Rolling back the outer savepoint discards what the released one kept.
+    cache.rollback_to_savepoint().unwrap();
+    assert_eq!(cache.get_opt(bytes("a")).unwrap(), Some(bytes("0")));
+    assert_eq!(cache.get_opt(bytes("b")).unwrap(), None);
+    assert!(cache.release_savepoint().is_err());
+}
+
+#[test]
+fn commit_includes_open_savepoints() {
+    let storage = Arc::new(MemoryStorage::default());
+    let cache = InMemoryCache::new(storage.clone());
+
+    cache.set(bytes("a"), bytes("1")).unwrap();
+    cache.savepoint().unwrap();
+    cache.set(bytes("b"), bytes("1")).unwrap();
+    cache.commit().unwrap();
+
+    assert_eq!(
+        storage.data(),
+        BTreeMap::from([(bytes("a"), bytes("1")), (bytes("b"), bytes("1"))])
+    );
+    assert!(cache.rollback_to_savepoint().is_err());
+}
+
+#[test]
+fn failed_commit_keeps_the_changes() {
+    let storage = Arc::new(FailingStorage {
+        inner: MemoryStorage::new(BTreeMap::from([(bytes("a"), bytes("0"))])),
+        ..FailingStorage::default()
+    });
+    let cache = InMemoryCache::new(storage.clone());
+
+    cache.set(bytes("a"), bytes("1")).unwrap();
+    cache.savepoint().unwrap();
+    cache.delete(bytes("a")).unwrap();
+    cache.set(bytes("b"), bytes("1")).unwrap();
+
+    storage.failing.store(true, Ordering::SeqCst);
+    assert!(cache.commit().is_err());
+    assert_eq!(
+        storage.inner.data(),
+        BTreeMap::from([(bytes("a"), bytes("0"))])
+    );
+
+    // The cache still holds the changes and takes new ones, and commits all of them once the storage recovers.
+    cache.set(bytes("c"), bytes("1")).unwrap();
+    assert_eq!(cache.get_opt(bytes("b")).unwrap(), Some(bytes("1")));
+
+    storage.failing.store(false, Ordering::SeqCst);
+    cache.commit().unwrap();
+    assert_eq!(
+        storage.inner.data(),
+        BTreeMap::from([(bytes("b"), bytes("1")), (bytes("c"), bytes("1"))])
+    );
+}
+
+#[test]
+fn commit_hook_writes_land_in_the_same_batch() {
+    let storage = Arc::new(FailingStorage::default());
+    let hook: CommitHook = Arc::new(|storage, changes| state_tree_writes(storage, changes));
+    let cache = InMemoryCache::new(storage.clone()).with_commit_hook(hook);
+
+    let live_object_id = "a".repeat(64);
+    let key = format!("{}counter", live_object_id).into_bytes();
+    cache.set(key.clone(), bytes("1")).unwrap();
+
+    // Neither the state nor its tree reach the storage if the batch fails.
+    storage.failing.store(true, Ordering::SeqCst);
+    assert!(cache.commit().is_err());
+    assert!(storage.inner.data().is_empty());
+
+    storage.failing.store(false, Ordering::SeqCst);
+    cache.commit().unwrap();
+    assert_eq!(storage.inner.get_opt(key).unwrap(), Some(bytes("1")));
+
+    let root = state_root(&storage.inner, live_object_id.as_bytes()).unwrap();
+    assert_ne!(root, EMPTY_HASH);
+}
@@ -0,0 +1,213 @@
+//! How a `ReadCache` evicts, stays coherent with writes, and counts hits and misses.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use ramd_cache::{ReadCache, ReadCacheStats};
+use ramd_db::memory::MemoryStorage;
+use ramd_db::storage::Storage;
+
+/// A storage that counts its reads, and can hold up the next read after it has read the value.
+#[derive(Default)]
+struct TestStorage {
+    inner: MemoryStorage,
+    reads: AtomicU64,
+    /// Signalled once the held-up read has its value, then waited on before that value is returned.
+    gate: Mutex<Option<(Sender<()>, Receiver<()>)>>,
+}
+
+impl TestStorage {
+    fn reads(&self) -> u64 {
+        self.reads.load(Ordering::SeqCst)
+    }
+}
+
+impl Storage<Vec<u8>, Vec<u8>> for TestStorage {
+    fn has(&self, key: Vec<u8>) -> eyre::Result<bool> {
+        Ok(self.get_opt(key)?.is_some())
+    }
+
+    fn get(&self, key: Vec<u8>) -> eyre::Result<Vec<u8>> {
+        self.get_opt(key)?
+            .ok_or_else(|| eyre::eyre!("Key not found"))
+    }
+
+    fn get_opt(&self, key: Vec<u8>) -> eyre::Result<Option<Vec<u8>>> {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        let value = self.inner.get_opt(key)?;
+
+        let gate = self.gate.lock().unwrap().take();
+        if let Some((read, resume)) = gate {
+            read.send(()).unwrap();
+            resume.recv().unwrap();
+        }
+
+        Ok(value)
+    }
+
+    fn set(&self, key: Vec<u8>, value: Vec<u8>) -> eyre::Result<()> {
+        self.inner.set(key, value)
+    }
+
+    fn delete(&self, key: Vec<u8>) -> eyre::Result<()> {
+        self.inner.delete(key)
+    }
+
+    fn write_batch(&self, writes: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> eyre::Result<()> {
+        self.inner.write_batch(writes)
+    }
+}
+
+/// A storage holding each key with a value of `value_len` bytes.
+fn storage_with(keys: &[&str], value_len: usize) -> Arc<TestStorage> {
+    let storage = Arc::new(TestStorage::default());
+    for key in keys {
+        storage
+            .set(Vec::from(key.as_bytes()), vec![0; value_len])
+            .unwrap();
+    }
+    storage
+}
+
+fn get(cache: &ReadCache<TestStorage>, key: &str) -> Option<Vec<u8>> {
+    cache.get_opt(Vec::from(key.as_bytes())).unwrap()
+}
+
+#[test]
+fn hits_and_misses_are_counted() {
+    let storage = storage_with(&["a"], 9);
+    let cache = ReadCache::new(storage.clone(), 1024);
+
+    assert_eq!(get(&cache, "a"), Some(vec![0; 9]));
+    assert_eq!(get(&cache, "a"), Some(vec![0; 9]));
+    // Keys that don't exist are cached too.
+    assert_eq!(get(&cache, "x"), None);
+    assert_eq!(get(&cache, "x"), None);
+    assert!(!cache.has(Vec::from(*b"x")).unwrap());
+
+    assert_eq!(storage.reads(), 2);
+    assert_eq!(
+        cache.stats().unwrap(),
+        ReadCacheStats {
+            hits: 3,
+            misses: 2,
+            evictions: 0,
+            entries: 2,
+            size: 11,
+        }
+    );
+}
+
+#[test]
+fn least_recently_used_entries_are_evicted_by_size() {
+    // Each entry takes 10 bytes, so three fit.
+    let storage = storage_with(&["a", "b", "c", "d"], 9);
+    let cache = ReadCache::new(storage.clone(), 30);
+
+    get(&cache, "a");
+    get(&cache, "b");
+    get(&cache, "c");
+    // Reading `a` again makes `b` the least recently used.
+    get(&cache, "a");
+    get(&cache, "d");
+
+    let stats = cache.stats().unwrap();
+    assert_eq!((stats.evictions, stats.entries, stats.size), (1, 3, 30));
+
+    let reads = storage.reads();
+    get(&cache, "a");
+    get(&cache, "c");
+    get(&cache, "d");
+    assert_eq!(storage.reads(), reads);
+    get(&cache, "b");
+    assert_eq!(storage.reads(), reads + 1);
+}
+
+#[test]
+fn entries_larger_than_the_cache_are_not_cached() {
+    let storage = storage_with(&["a", "big"], 9);
+    storage.set(Vec::from(*b"big"), vec![0; 40]).unwrap();
+    let cache = ReadCache::new(storage.clone(), 30);
+
+    get(&cache, "a");
+    get(&cache, "big");
+    get(&cache, "big");
+
+    assert_eq!(storage.reads(), 3);
+    let stats = cache.stats().unwrap();
+    assert_eq!((stats.evictions, stats.entries, stats.size), (0, 1, 10));
+}
+
+#[test]
+fn a_zero_size_disables_caching() {
+    let storage = storage_with(&["a"], 9);
+    let cache = ReadCache::new(storage.clone(), 0);
+
+    get(&cache, "a");
+    get(&cache, "a");
+
+    assert_eq!(storage.reads(), 2);
+    assert_eq!(cache.stats().unwrap().entries, 0);
+}
+
+#[test]
+fn writes_replace_the_cached_values() {
+    let storage = storage_with(&["a", "b"], 9);
+    let cache = ReadCache::new(storage.clone(), 1024);
+    get(&cache, "a");
+    get(&cache, "b");
+    let reads = storage.reads();
+
+    cache.set(Vec::from(*b"a"), Vec::from(*b"new")).unwrap();
+    assert_eq!(get(&cache, "a"), Some(Vec::from(*b"new")));
+
+    cache.delete(Vec::from(*b"a")).unwrap();
+    assert_eq!(get(&cache, "a"), None);
+
+    cache
+        .write_batch(vec![
+            (Vec::from(*b"a"), Some(Vec::from(*b"again"))),
+            (Vec::from(*b"b"), None),
+        ])
+        .unwrap();
+    assert_eq!(get(&cache, "a"), Some(Vec::from(*b"again")));
+    assert_eq!(get(&cache, "b"), None);
+
+    // The writes reached the storage, and the reads after them were served from the cache.
+    assert_eq!(storage.reads(), reads);
+    assert_eq!(
+        storage.inner.get_opt(Vec::from(*b"a")).unwrap(),
+        Some(Vec::from(*b"again"))
+    );
+    assert_eq!(storage.inner.get_opt(Vec::from(*b"b")).unwrap(), None);
+
+    let stats = cache.stats().unwrap();
+    assert_eq!((stats.entries, stats.size), (2, 7));
+}
+
+#[test]
+fn a_read_racing_a_write_does_not_cache_the_older_value() {
+    let storage = storage_with(&["a"], 9);
+    let cache = Arc::new(ReadCache::new(storage.clone(), 1024));
+
+    let (read, has_read) = channel();
+    let (resume, resumed) = channel();
+    *storage.gate.lock().unwrap() = Some((read, resumed));
+
+    // The reader misses, reads the old value from the storage, and is held up before it caches it.
+    let reader = {
+        let cache = cache.clone();
+        thread::spawn(move || get(&cache, "a"))
+    };
+    has_read.recv().unwrap();
+
+    cache.set(Vec::from(*b"a"), Vec::from(*b"new")).unwrap();
+    resume.send(()).unwrap();
+
+    // The read took place before the write, so it may return the old value, but mustn't cache it.
+    assert_eq!(reader.join().unwrap(), Some(vec![0; 9]));
+    assert_eq!(get(&cache, "a"), Some(Vec::from(*b"new")));
+    assert_eq!(storage.reads(), 1);
+}
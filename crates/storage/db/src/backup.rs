@@ -0,0 +1,45 @@
+use std::path::Path;
+
+use crate::config::RocksConfig;
+use crate::migrations::{check_schema_version, MIGRATIONS};
+use crate::rocks::RocksStorage;
+
+/// Write a consistent copy of the database to `backup_path`. RocksDB locks the database of a running node,
+/// so use the `admin_backup` RPC to back up a running node instead. Fails if the database was written by a
+/// newer binary.
+pub fn backup(config: &RocksConfig, backup_path: &Path) -> eyre::Result<()> {
+    if !config.path.exists() {
+        return Err(eyre::eyre!(
+            "There is no database at `{}` to back up",
+            config.path.display()
+        ));
+    }
+
+    let storage = RocksStorage::new(config)?;
+    check_schema_version(&storage, MIGRATIONS)?;
+    storage.checkpoint(backup_path)
+}
+
+/// Restore the database from a backup made by `backup` or the `admin_backup` RPC. The node must be stopped,
+/// and the current database moved away first, so that a restore never overwrites data. The backup is opened
+/// read-only, so restoring leaves it as it was. Fails if the backup was made by a newer binary, while an older
+/// backup is migrated when the node next starts.
+pub fn restore(config: &RocksConfig, backup_path: &Path) -> eyre::Result<()> {
+    if !backup_path.exists() {
+        return Err(eyre::eyre!(
+            "There is no backup at `{}`",
+            backup_path.display()
+        ));
+    }
+    if config.path.exists() {
+        return Err(eyre::eyre!(
+            "`{}` already exists, move it away before restoring",
+            config.path.display()
+        ));
+    }
+
+    // A checkpoint of the backup is a copy of it that shares its immutable files where possible.
+    let backup = RocksStorage::open_read_only(&config.with_path(backup_path.to_path_buf()))?;
+    check_schema_version(&backup, MIGRATIONS)?;
+    backup.checkpoint(&config.path)
+}
@@ -0,0 +1,107 @@
+//! The binary encoding of records kept in the storage: fixed-size integers are little-endian,
+//! and variable-length fields are prefixed with their length as a `u64`.
+
+/// Append a length-prefixed field.
+pub fn encode_field(bytes: &mut Vec<u8>, field: &[u8]) {
+    bytes.extend_from_slice(&(field.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(field);
+}
+
+/// Reads the fields written by `encode_field` back, failing on truncated or trailing bytes.
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn byte(&mut self) -> eyre::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u64(&mut self) -> eyre::Result<u64> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into()?))
+    }
+
+    pub fn field(&mut self) -> eyre::Result<&'a [u8]> {
+        let len = usize::try_from(self.u64()?)?;
+        self.take(len)
+    }
+
+    pub fn u64_field(&mut self) -> eyre::Result<u64> {
+        let field = self.field()?;
+        Ok(u64::from_le_bytes(field.try_into()?))
+    }
+
+    pub fn string_field(&mut self) -> eyre::Result<String> {
+        Ok(String::from_utf8(self.field()?.to_vec())?)
+    }
+
+    /// Fail if any bytes are left.
+    pub fn finish(self) -> eyre::Result<()> {
+        match self.bytes.is_empty() {
+            true => Ok(()),
+            false => Err(eyre::eyre!("{} trailing bytes", self.bytes.len())),
+        }
+    }
+
+    fn take(&mut self, len: usize) -> eyre::Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(eyre::eyre!("Unexpected end of encoded bytes"));
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_round_trip() {
+        let mut bytes = vec![7];
+        encode_field(&mut bytes, b"id");
+        encode_field(&mut bytes, &[]);
+        encode_field(&mut bytes, &42u64.to_le_bytes());
+
+        let mut decoder = Decoder::new(&bytes);
+        assert_eq!(decoder.byte().unwrap(), 7);
+        assert_eq!(decoder.string_field().unwrap(), "id");
+        assert_eq!(decoder.field().unwrap(), b"");
+        assert_eq!(decoder.u64_field().unwrap(), 42);
+        decoder.finish().unwrap();
+    }
+
+    #[test]
+    fn truncated_fields_are_rejected() {
+        let mut bytes = Vec::new();
+        encode_field(&mut bytes, b"field");
+
+        for len in 0..bytes.len() {
+            assert!(Decoder::new(&bytes[..len]).field().is_err(), "{}", len);
+        }
+    }
+
+    #[test]
+    fn oversized_lengths_and_trailing_bytes_are_rejected() {
+        let bytes = u64::MAX.to_le_bytes();
+        assert!(Decoder::new(&bytes).field().is_err());
+
+        let mut bytes = Vec::new();
+        encode_field(&mut bytes, b"field");
+        bytes.push(0);
+        let mut decoder = Decoder::new(&bytes);
+        decoder.field().unwrap();
+        assert!(decoder.finish().is_err());
+    }
+}
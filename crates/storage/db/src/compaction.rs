@@ -0,0 +1,49 @@
+use std::collections::BTreeSet;
+
+use crate::state_tree::split_live_object_key;
+use crate::storage::{CompactableStorage, WriteBatch};
+
+/// Prefix of the keys that mark a live object as having had keys deleted since maintenance last compacted it,
+/// which are followed by the live object ID. The markers are written along with the deletions, so they survive
+/// restarts.
+pub const PENDING_COMPACTION_KEY_PREFIX: &[u8] = b"ramd::pending_compaction::";
+
+/// The key that marks the live object as needing compaction.
+pub fn pending_compaction_key(live_object_id: &[u8]) -> Vec<u8> {
+    let mut key = PENDING_COMPACTION_KEY_PREFIX.to_vec();
+    key.extend_from_slice(live_object_id);
+    key
+}
+
+/// The markers of the live objects that the writes delete keys of, to be written in the same batch.
+/// Live objects themselves are never removed, so their deleted keys are the only tombstones they leave:
+/// a live object is marked as soon as it deletes any key of its state, rather than once it's gone.
+pub fn pending_compaction_writes<'k>(
+    writes: impl IntoIterator<Item = (&'k Vec<u8>, &'k Option<Vec<u8>>)>,
+) -> WriteBatch {
+    writes
+        .into_iter()
+        .filter(|(_, value)| value.is_none())
+        .filter_map(|(key, _)| split_live_object_key(key))
+        .map(|(live_object_id, _)| live_object_id)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|live_object_id| (pending_compaction_key(live_object_id), Some(Vec::new())))
+        .collect()
+}
+
+/// The IDs of the live objects marked as needing compaction.
+pub fn pending_compactions<S>(storage: &S) -> eyre::Result<Vec<String>>
+where
+    S: CompactableStorage<Vec<u8>, Vec<u8>> + ?Sized,
+{
+    storage
+        .keys_with_prefix(PENDING_COMPACTION_KEY_PREFIX)?
+        .into_iter()
+        .map(|key| {
+            Ok(String::from_utf8(
+                key[PENDING_COMPACTION_KEY_PREFIX.len()..].to_vec(),
+            )?)
+        })
+        .collect()
+}
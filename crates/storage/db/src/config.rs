@@ -1,19 +1,72 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq, Serialize)]
 pub struct RocksConfig {
     pub path: PathBuf,
+    /// How often the node compacts the ranges of deleted state and prunes the message log. Zero disables maintenance.
+    #[serde(default = "default_maintenance_interval_secs")]
+    pub maintenance_interval_secs: u64,
+    /// The number of most recent message log entries kept by maintenance. Zero keeps the whole log,
+    /// which `ramd replay` needs.
+    #[serde(default)]
+    pub message_log_retention: u64,
+    /// The directory that backups requested through the admin API are written to. Defaults to `backups` next to
+    /// the database.
+    #[serde(default)]
+    pub backup_dir: Option<PathBuf>,
 }
 
 impl RocksConfig {
     pub fn new(root_path: PathBuf) -> Self {
         let db_path = root_path.join(Self::db_name());
-        Self { path: db_path }
+        Self {
+            path: db_path,
+            maintenance_interval_secs: default_maintenance_interval_secs(),
+            message_log_retention: 0,
+            backup_dir: Some(root_path.join(Self::backup_dir_name())),
+        }
+    }
+
+    /// The configuration of another database at `path`, with the same maintenance schedule.
+    pub fn with_path(&self, path: PathBuf) -> Self {
+        Self {
+            path,
+            ..self.clone()
+        }
+    }
+
+    pub fn maintenance_interval(&self) -> Option<Duration> {
+        (self.maintenance_interval_secs > 0)
+            .then(|| Duration::from_secs(self.maintenance_interval_secs))
+    }
+
+    pub fn message_log_retention(&self) -> Option<u64> {
+        (self.message_log_retention > 0).then_some(self.message_log_retention)
+    }
+
+    pub fn backup_dir(&self) -> PathBuf {
+        match &self.backup_dir {
+            Some(backup_dir) => backup_dir.clone(),
+            None => self
+                .path
+                .parent()
+                .unwrap_or(&self.path)
+                .join(Self::backup_dir_name()),
+        }
+    }
+
+    fn backup_dir_name() -> PathBuf {
+        "backups".into()
     }
 
     fn db_name() -> PathBuf {
         "ramd_db".into()
     }
 }
+
+fn default_maintenance_interval_secs() -> u64 {
+    60 * 60
+}
@@ -1,2 +1,11 @@
-/// Storage key used for storing p2p private key
+/// Storage key older versions kept the p2p private key under in plain text, moved to the keystore on start-up
 pub const RAMD_P2P_KEYPAIR_KEY: &[u8] = "ramd_p2p_pk".as_bytes();
+
+/// Storage key used for storing the schema version of the database
+pub const RAMD_SCHEMA_VERSION_KEY: &[u8] = "ramd::schema_version".as_bytes();
+
+/// Prefix of the keys the WASM code of live objects is stored at, which are followed by the live object ID.
+/// The code is kept apart from the metadata, which is stored at the live object ID itself, so that reading
+/// the metadata doesn't load the whole module. The metadata includes the hash of the code, so the code is
+/// still covered by the state root of the live object.
+pub const LIVE_OBJECT_CODE_KEY_PREFIX: &[u8] = b"ramd::live_object_code::";
@@ -1,4 +1,12 @@
+pub mod backup;
+pub mod codec;
+pub mod compaction;
 pub mod config;
 pub mod keys;
+#[cfg(feature = "test-utils")]
+pub mod memory;
+pub mod migrations;
 pub mod rocks;
+pub mod state_tree;
 pub mod storage;
+pub mod storage_usage;
@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use crate::storage::Storage;
+
+/// A storage that keeps everything in memory, for tests and benchmarks.
+#[derive(Default)]
+pub struct MemoryStorage {
+    data: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new(data: BTreeMap<Vec<u8>, Vec<u8>>) -> Self {
+        Self {
+            data: RwLock::new(data),
+        }
+    }
+
+    /// A copy of all key-value pairs in key order.
+    pub fn data(&self) -> BTreeMap<Vec<u8>, Vec<u8>> {
+        self.data
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+}
+
+impl Storage<Vec<u8>, Vec<u8>> for MemoryStorage {
+    fn has(&self, key: Vec<u8>) -> eyre::Result<bool> {
+        Ok(self.get_opt(key)?.is_some())
+    }
+
+    fn get(&self, key: Vec<u8>) -> eyre::Result<Vec<u8>> {
+        self.get_opt(key)?
+            .ok_or_else(|| eyre::eyre!("Key not found"))
+    }
+
+    fn get_opt(&self, key: Vec<u8>) -> eyre::Result<Option<Vec<u8>>> {
+        let data = self
+            .data
+            .read()
+            .map_err(|err| eyre::eyre!(err.to_string()))?;
+
+        Ok(data.get(&key).cloned())
+    }
+
+    fn set(&self, key: Vec<u8>, value: Vec<u8>) -> eyre::Result<()> {
+        self.data
+            .write()
+            .map_err(|err| eyre::eyre!(err.to_string()))?
+            .insert(key, value);
+
+        Ok(())
+    }
+
+    fn delete(&self, key: Vec<u8>) -> eyre::Result<()> {
+        self.data
+            .write()
+            .map_err(|err| eyre::eyre!(err.to_string()))?
+            .remove(&key);
+
+        Ok(())
+    }
+
+    fn write_batch(&self, writes: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> eyre::Result<()> {
+        let mut data = self
+            .data
+            .write()
+            .map_err(|err| eyre::eyre!(err.to_string()))?;
+
+        for (key, value) in writes {
+            match value {
+                Some(value) => data.insert(key, value),
+                None => data.remove(&key),
+            };
+        }
+
+        Ok(())
+    }
+}
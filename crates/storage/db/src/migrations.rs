@@ -0,0 +1,205 @@
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use tracing::info;
+
+use crate::codec::encode_field;
+use crate::config::RocksConfig;
+use crate::keys::{LIVE_OBJECT_CODE_KEY_PREFIX, RAMD_SCHEMA_VERSION_KEY};
+use crate::rocks::RocksStorage;
+use crate::state_tree::{split_live_object_key, state_tree_writes};
+use crate::storage::{Storage, WriteBatch};
+use crate::storage_usage::{count_storage_usage, storage_usage_write, STORAGE_USAGE_KEY_PREFIX};
+
+/// A change to the layout of the data in the database, which brings it from `version - 1` to `version`.
+/// `run` only reads the storage and returns the writes, which reach the storage in one batch together with
+/// the new schema version, so a migration is either applied completely or not at all.
+pub struct Migration<S>
+where
+    S: Storage<Vec<u8>, Vec<u8>>,
+{
+    pub version: u64,
+    pub description: &'static str,
+    pub run: fn(&S) -> eyre::Result<WriteBatch>,
+}
+
+/// Migrations of the on-disk data, in order. The version of the last one is the schema version this binary
+/// writes, so append a migration whenever the layout of stored data changes. Migrations 2 and 3 write the state
+/// trees and the storage usage through the current code rather than a copy frozen at their version, so a change
+/// to either layout needs a migration that rebuilds it from the state instead of converting the previous layout.
+pub const MIGRATIONS: &[Migration<RocksStorage>] = &[
+    Migration {
+        version: 1,
+        description: "Version databases created before the schema was versioned",
+        run: |_| Ok(Vec::new()),
+    },
+    Migration {
+        version: 2,
+        description: "Encode live objects in binary and store their code apart from their metadata",
+        run: encode_live_objects_in_binary,
+    },
+    Migration {
+        version: 3,
+        description: "Count the bytes each live object stores",
+        run: backfill_storage_usage,
+    },
+];
+
+/// Open the database and bring it to the schema version of this binary.
+pub fn open_storage(config: &RocksConfig) -> eyre::Result<RocksStorage> {
+    let storage = RocksStorage::new(config)?;
+    migrate(&storage, MIGRATIONS)?;
+
+    Ok(storage)
+}
+
+/// Get the schema version of the database. Databases written before the schema was versioned are at version 0.
+pub fn schema_version<S>(storage: &S) -> eyre::Result<u64>
+where
+    S: Storage<Vec<u8>, Vec<u8>>,
+{
+    match storage.get_opt(RAMD_SCHEMA_VERSION_KEY.into())? {
+        Some(version) => {
+            let version = <[u8; 8]>::try_from(version.as_slice())
+                .map_err(|_| eyre::eyre!("Schema version of the database is malformed"))?;
+            Ok(u64::from_be_bytes(version))
+        }
+        None => Ok(0),
+    }
+}
+
+/// Get the schema version of the database, failing if it is newer than the last migration, as the database
+/// was then written by a newer binary.
+pub fn check_schema_version<S>(storage: &S, migrations: &[Migration<S>]) -> eyre::Result<u64>
+where
+    S: Storage<Vec<u8>, Vec<u8>>,
+{
+    let latest_version = migrations.last().map_or(0, |migration| migration.version);
+    let version = schema_version(storage)?;
+    if version > latest_version {
+        return Err(eyre::eyre!(
+            "Database has schema version {}, but this binary only supports up to {}",
+            version,
+            latest_version
+        ));
+    }
+
+    Ok(version)
+}
+
+/// Run the migrations that the database hasn't gone through yet, in order. The migrations must have the
+/// versions 1, 2, 3 and so on, without gaps. Each migration is written in one batch with its version, so an
+/// interrupted run picks up at the migration it stopped in.
+pub fn migrate<S>(storage: &S, migrations: &[Migration<S>]) -> eyre::Result<()>
+where
+    S: Storage<Vec<u8>, Vec<u8>>,
+{
+    for (expected_version, migration) in (1..).zip(migrations) {
+        if migration.version != expected_version {
+            return Err(eyre::eyre!(
+                "Migration to schema version {} is out of order, expected version {}",
+                migration.version,
+                expected_version
+            ));
+        }
+    }
+
+    let version = check_schema_version(storage, migrations)?;
+    for migration in migrations
+        .iter()
+        .filter(|migration| migration.version > version)
+    {
+        info!(target: "ramd::db", "Migrating database to schema version {}: {}", migration.version, migration.description);
+
+        let mut writes = (migration.run)(storage)?;
+        writes.push((
+            RAMD_SCHEMA_VERSION_KEY.into(),
+            Some(migration.version.to_be_bytes().into()),
+        ));
+        storage.write_batch(writes)?;
+    }
+
+    Ok(())
+}
+
+/// A live object as databases before schema version 2 stored it, in JSON at its bare ID.
+#[derive(Deserialize)]
+struct JsonLiveObject {
+    id: String,
+    hash: Vec<u8>,
+    wasm_bytes: Vec<u8>,
+    #[serde(default)]
+    memory_limit: Option<usize>,
+    #[serde(default)]
+    abi: Option<Box<RawValue>>,
+}
+
+/// Rewrite the live objects stored as JSON, which sit at their bare ID, in version 1 of the binary encoding of
+/// their metadata, and store their code apart. The ABI is carried over as the JSON it was stored as. The
+/// metadata is part of the state tree of the live object, so the trees of all live objects are brought up to
+/// date with it.
+fn encode_live_objects_in_binary(storage: &RocksStorage) -> eyre::Result<WriteBatch> {
+    let mut writes = Vec::new();
+    for entry in storage.iter() {
+        let (key, value) = entry?;
+        if !matches!(split_live_object_key(&key), Some((_, suffix)) if suffix.is_empty()) {
+            continue;
+        }
+        if value.first() != Some(&b'{') {
+            writes.push((key, Some(value)));
+            continue;
+        }
+
+        let live_object = serde_json::from_slice::<JsonLiveObject>(&value)?;
+        info!(target: "ramd::db", "Encoding live object `{}` in binary", live_object.id);
+
+        let mut metadata = vec![1];
+        encode_field(&mut metadata, live_object.id.as_bytes());
+        encode_field(&mut metadata, &live_object.hash);
+        match live_object.memory_limit {
+            Some(memory_limit) => {
+                metadata.push(1);
+                metadata.extend_from_slice(&(memory_limit as u64).to_le_bytes());
+            }
+            None => metadata.push(0),
+        }
+        match &live_object.abi {
+            Some(abi) => {
+                metadata.push(1);
+                encode_field(&mut metadata, abi.get().as_bytes());
+            }
+            None => metadata.push(0),
+        }
+
+        let mut code_key = LIVE_OBJECT_CODE_KEY_PREFIX.to_vec();
+        code_key.extend_from_slice(live_object.id.as_bytes());
+        writes.push((code_key, Some(live_object.wasm_bytes)));
+        writes.push((key, Some(metadata)));
+    }
+
+    let tree_writes = state_tree_writes(storage, writes.iter().map(|(key, value)| (key, value)))?;
+    writes.extend(tree_writes);
+
+    Ok(writes)
+}
+
+/// Count the state of every live object, which was stored before its usage was tracked. The counts replace
+/// whatever usage is stored.
+fn backfill_storage_usage(storage: &RocksStorage) -> eyre::Result<WriteBatch> {
+    let usage = count_storage_usage(storage.iter())?;
+    info!(target: "ramd::db", "Counted the storage usage of {} live objects", usage.len());
+
+    let mut writes = Vec::new();
+    for entry in storage.iter() {
+        let (key, _) = entry?;
+        if key.starts_with(STORAGE_USAGE_KEY_PREFIX) {
+            writes.push((key, None));
+        }
+    }
+    writes.extend(
+        usage
+            .iter()
+            .map(|(live_object_id, usage)| storage_usage_write(live_object_id, *usage)),
+    );
+
+    Ok(writes)
+}
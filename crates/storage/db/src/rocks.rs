@@ -1,5 +1,7 @@
+use std::path::Path;
+
 use crate::config::RocksConfig;
-use crate::storage::Storage;
+use crate::storage::{CompactableStorage, SnapshotStorage, Storage};
 
 pub struct RocksStorage {
     db: rocksdb::DB,
@@ -15,6 +17,31 @@ impl RocksStorage {
 
         Ok(Self { db })
     }
+
+    /// Open the database at the path of `config` without the ability to write to it. Fails if there is no database.
+    pub fn open_read_only(config: &RocksConfig) -> eyre::Result<Self> {
+        let db =
+            rocksdb::DB::open_for_read_only(&rocksdb::Options::default(), &config.path, false)?;
+
+        Ok(Self { db })
+    }
+
+    /// Write a consistent copy of the database to `path`, which must not exist yet.
+    /// Files that never change are hard-linked when `path` is on the same filesystem.
+    pub fn checkpoint(&self, path: &Path) -> eyre::Result<()> {
+        let checkpoint = rocksdb::checkpoint::Checkpoint::new(&self.db)?;
+        checkpoint.create_checkpoint(path)?;
+        Ok(())
+    }
+
+    /// Iterate over all key-value pairs in key order.
+    pub fn iter(&self) -> impl Iterator<Item = eyre::Result<(Vec<u8>, Vec<u8>)>> + '_ {
+        self.db.iterator(rocksdb::IteratorMode::Start).map(|entry| {
+            entry
+                .map(|(key, value)| (key.into_vec(), value.into_vec()))
+                .map_err(eyre::Report::from)
+        })
+    }
 }
 
 impl<K: AsRef<[u8]>, V: AsRef<[u8]>> Storage<K, V> for RocksStorage {
@@ -46,4 +73,101 @@ impl<K: AsRef<[u8]>, V: AsRef<[u8]>> Storage<K, V> for RocksStorage {
         self.db.delete(key)?;
         Ok(())
     }
+
+    fn write_batch(&self, writes: Vec<(K, Option<V>)>) -> eyre::Result<()> {
+        let mut batch = rocksdb::WriteBatch::default();
+        for (key, value) in writes {
+            match value {
+                Some(value) => batch.put(key, value),
+                None => batch.delete(key),
+            }
+        }
+
+        self.db.write(batch)?;
+        Ok(())
+    }
+}
+
+impl<K: AsRef<[u8]>, V: AsRef<[u8]>> SnapshotStorage<K, V> for RocksStorage {
+    type Snapshot<'a> = RocksSnapshot<'a>;
+
+    fn snapshot(&self) -> RocksSnapshot<'_> {
+        RocksSnapshot {
+            snapshot: self.db.snapshot(),
+        }
+    }
+
+    fn checkpoint(&self, path: &Path) -> eyre::Result<()> {
+        RocksStorage::checkpoint(self, path)
+    }
+}
+
+impl<K: AsRef<[u8]>, V: AsRef<[u8]>> CompactableStorage<K, V> for RocksStorage {
+    fn keys_with_prefix(&self, prefix: &[u8]) -> eyre::Result<Vec<Vec<u8>>> {
+        let mut keys = Vec::new();
+        for entry in self.db.iterator(rocksdb::IteratorMode::From(
+            prefix,
+            rocksdb::Direction::Forward,
+        )) {
+            let (key, _) = entry?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            keys.push(key.into_vec());
+        }
+
+        Ok(keys)
+    }
+
+    fn compact_range(&self, from: &[u8], to: &[u8]) -> eyre::Result<()> {
+        self.db.compact_range(Some(from), Some(to));
+        Ok(())
+    }
+
+    fn live_data_size(&self) -> eyre::Result<u64> {
+        let size = self
+            .db
+            .property_int_value("rocksdb.estimate-live-data-size")?
+            .unwrap_or_default();
+
+        Ok(size)
+    }
+}
+
+/// A point-in-time view of a `RocksStorage`.
+pub struct RocksSnapshot<'a> {
+    snapshot: rocksdb::Snapshot<'a>,
+}
+
+impl<K: AsRef<[u8]>, V: AsRef<[u8]>> Storage<K, V> for RocksSnapshot<'_> {
+    fn has(&self, key: K) -> eyre::Result<bool> {
+        let v = self.snapshot.get(key)?;
+        Ok(v.is_some())
+    }
+
+    fn get(&self, key: K) -> eyre::Result<Vec<u8>> {
+        let v = self.snapshot.get(key)?;
+        if let Some(v) = v {
+            Ok(v)
+        } else {
+            Err(eyre::eyre!("Key not found"))
+        }
+    }
+
+    fn get_opt(&self, key: K) -> eyre::Result<Option<Vec<u8>>> {
+        let v = self.snapshot.get(key)?;
+        Ok(v)
+    }
+
+    fn set(&self, _key: K, _value: V) -> eyre::Result<()> {
+        Err(eyre::eyre!("Snapshots are read-only"))
+    }
+
+    fn delete(&self, _key: K) -> eyre::Result<()> {
+        Err(eyre::eyre!("Snapshots are read-only"))
+    }
+
+    fn write_batch(&self, _writes: Vec<(K, Option<V>)>) -> eyre::Result<()> {
+        Err(eyre::eyre!("Snapshots are read-only"))
+    }
 }
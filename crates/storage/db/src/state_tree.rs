@@ -0,0 +1,444 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::storage::{Storage, WriteBatch};
+use sha3::{Digest, Keccak256};
+
+pub type Hash = [u8; 32];
+
+/// The hash of an empty (sub)tree.
+pub const EMPTY_HASH: Hash = [0; 32];
+/// Live object IDs are hex-encoded Keccak-256 hashes, and prefix the keys of their state.
+pub const LIVE_OBJECT_ID_LEN: usize = 64;
+/// Prefix of the keys of tree nodes, which are followed by the live object ID, the depth and the path of the node.
+pub const STATE_TREE_KEY_PREFIX: &[u8] = b"ramd::state_tree::";
+/// The number of bits of a path, which is the Keccak-256 hash of a key.
+const TREE_DEPTH: usize = 256;
+
+const LEAF_DOMAIN: u8 = 0;
+const INTERNAL_DOMAIN: u8 = 1;
+
+/// A node of the sparse Merkle tree of a live object. A subtree holding a single key is stored as a leaf
+/// at the root of the subtree, so only the nodes above diverging paths are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Node {
+    Leaf { key_hash: Hash, value_hash: Hash },
+    Internal { left: Hash, right: Hash },
+}
+
+impl Node {
+    fn hash(&self) -> Hash {
+        match self {
+            Node::Leaf {
+                key_hash,
+                value_hash,
+            } => hash_node(LEAF_DOMAIN, key_hash, value_hash),
+            Node::Internal { left, right } => hash_node(INTERNAL_DOMAIN, left, right),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let (domain, first, second) = match self {
+            Node::Leaf {
+                key_hash,
+                value_hash,
+            } => (LEAF_DOMAIN, key_hash, value_hash),
+            Node::Internal { left, right } => (INTERNAL_DOMAIN, left, right),
+        };
+
+        let mut bytes = Vec::with_capacity(65);
+        bytes.push(domain);
+        bytes.extend_from_slice(first);
+        bytes.extend_from_slice(second);
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> eyre::Result<Self> {
+        if bytes.len() != 65 {
+            return Err(eyre::eyre!("State tree node is malformed"));
+        }
+
+        let first = Hash::try_from(&bytes[1..33])?;
+        let second = Hash::try_from(&bytes[33..])?;
+
+        match bytes[0] {
+            LEAF_DOMAIN => Ok(Node::Leaf {
+                key_hash: first,
+                value_hash: second,
+            }),
+            INTERNAL_DOMAIN => Ok(Node::Internal {
+                left: first,
+                right: second,
+            }),
+            domain => Err(eyre::eyre!("Unknown state tree node type `{}`", domain)),
+        }
+    }
+}
+
+/// The leaf that a proof ends at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProofLeaf {
+    pub key_hash: Hash,
+    pub value_hash: Hash,
+}
+
+/// A proof that a key has a value, or doesn't exist, under a state root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateProof {
+    /// The state root that the proof was made against.
+    pub root: Hash,
+    /// Hashes of the siblings along the path of the key, from the root down.
+    pub siblings: Vec<Hash>,
+    /// The leaf at the end of the path, or `None` if the path ends at an empty subtree.
+    /// A leaf of another key proves that the key doesn't exist.
+    pub leaf: Option<ProofLeaf>,
+}
+
+/// The sparse Merkle tree over the keys of a live object. Changes are kept in memory until `commit`.
+pub struct StateTree<'a, S>
+where
+    S: Storage<Vec<u8>, Vec<u8>> + ?Sized,
+{
+    storage: &'a S,
+    live_object_id: &'a [u8],
+    /// Nodes changed since the tree was opened, keyed by their storage key. `None` marks a removed node.
+    changes: HashMap<Vec<u8>, Option<Node>>,
+}
+
+impl<'a, S> StateTree<'a, S>
+where
+    S: Storage<Vec<u8>, Vec<u8>> + ?Sized,
+{
+    pub fn new(storage: &'a S, live_object_id: &'a [u8]) -> Self {
+        Self {
+            storage,
+            live_object_id,
+            changes: HashMap::new(),
+        }
+    }
+
+    /// The root hash of the tree, which is `EMPTY_HASH` if the live object has no keys.
+    pub fn root(&self) -> eyre::Result<Hash> {
+        self.hash_at(0, &EMPTY_HASH)
+    }
+
+    /// Set the value of a key, where the key doesn't include the live object ID.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> eyre::Result<()> {
+        let key_hash = keccak256(key);
+        let leaf = Node::Leaf {
+            key_hash,
+            value_hash: keccak256(value),
+        };
+
+        let mut depth = 0;
+        loop {
+            match self.node(depth, &key_hash)? {
+                Some(Node::Internal { .. }) => depth += 1,
+                Some(
+                    other_leaf @ Node::Leaf {
+                        key_hash: other_key_hash,
+                        ..
+                    },
+                ) if other_key_hash != key_hash => {
+                    // Push the other leaf down to where the paths of both keys diverge.
+                    let diverging_depth = (depth..TREE_DEPTH)
+                        .find(|&bit| path_bit(&key_hash, bit) != path_bit(&other_key_hash, bit))
+                        .unwrap_or(TREE_DEPTH - 1);
+
+                    self.set_node(diverging_depth + 1, &other_key_hash, Some(other_leaf));
+                    self.set_node(diverging_depth + 1, &key_hash, Some(leaf));
+                    depth = diverging_depth + 1;
+                    break;
+                }
+                _ => {
+                    self.set_node(depth, &key_hash, Some(leaf));
+                    break;
+                }
+            }
+        }
+
+        self.update_path(&key_hash, depth)
+    }
+
+    /// Remove a key, where the key doesn't include the live object ID.
+    pub fn remove(&mut self, key: &[u8]) -> eyre::Result<()> {
+        let key_hash = keccak256(key);
+
+        let mut depth = 0;
+        loop {
+            match self.node(depth, &key_hash)? {
+                Some(Node::Internal { .. }) => depth += 1,
+                Some(Node::Leaf {
+                    key_hash: other_key_hash,
+                    ..
+                }) if other_key_hash == key_hash => break,
+                _ => return Ok(()),
+            }
+        }
+        self.set_node(depth, &key_hash, None);
+
+        // A leaf left without a sibling moves up to keep the tree compressed.
+        while depth > 0 {
+            let sibling_path = flip_bit(&key_hash, depth - 1);
+            let node = self.node(depth, &key_hash)?;
+            let sibling = self.node(depth, &sibling_path)?;
+
+            let parent = match (node, sibling) {
+                (None, None) => None,
+                (Some(leaf @ Node::Leaf { .. }), None) => {
+                    self.set_node(depth, &key_hash, None);
+                    Some(leaf)
+                }
+                (None, Some(leaf @ Node::Leaf { .. })) => {
+                    self.set_node(depth, &sibling_path, None);
+                    Some(leaf)
+                }
+                _ => break,
+            };
+
+            depth -= 1;
+            self.set_node(depth, &key_hash, parent);
+        }
+
+        self.update_path(&key_hash, depth)
+    }
+
+    /// Prove the value of a key, or that it doesn't exist. The key doesn't include the live object ID.
+    pub fn prove(&self, key: &[u8]) -> eyre::Result<StateProof> {
+        let key_hash = keccak256(key);
+        let mut siblings = Vec::new();
+
+        let mut depth = 0;
+        let leaf = loop {
+            match self.node(depth, &key_hash)? {
+                Some(Node::Internal { left, right }) => {
+                    siblings.push(if path_bit(&key_hash, depth) {
+                        left
+                    } else {
+                        right
+                    });
+                    depth += 1;
+                }
+                Some(Node::Leaf {
+                    key_hash,
+                    value_hash,
+                }) => {
+                    break Some(ProofLeaf {
+                        key_hash,
+                        value_hash,
+                    })
+                }
+                None => break None,
+            }
+        };
+
+        Ok(StateProof {
+            root: self.root()?,
+            siblings,
+            leaf,
+        })
+    }
+
+    /// Write the changed nodes to the storage in one batch.
+    pub fn commit(self) -> eyre::Result<()> {
+        let storage = self.storage;
+        storage.write_batch(self.into_writes())
+    }
+
+    /// The storage writes of the changed nodes, with `None` marking a removed node, for the caller to apply
+    /// together with the writes the tree was updated for.
+    pub fn into_writes(self) -> WriteBatch {
+        self.changes
+            .into_iter()
+            .map(|(key, node)| (key, node.map(|node| node.encode())))
+            .collect()
+    }
+
+    /// Recompute the internal nodes above `depth` on the path.
+    fn update_path(&mut self, path: &Hash, depth: usize) -> eyre::Result<()> {
+        for depth in (0..depth).rev() {
+            let left = self.hash_at(depth + 1, &set_bit(path, depth, false))?;
+            let right = self.hash_at(depth + 1, &set_bit(path, depth, true))?;
+
+            self.set_node(depth, path, Some(Node::Internal { left, right }));
+        }
+
+        Ok(())
+    }
+
+    fn hash_at(&self, depth: usize, path: &Hash) -> eyre::Result<Hash> {
+        Ok(self
+            .node(depth, path)?
+            .map(|node| node.hash())
+            .unwrap_or(EMPTY_HASH))
+    }
+
+    fn node(&self, depth: usize, path: &Hash) -> eyre::Result<Option<Node>> {
+        let key = self.node_key(depth, path);
+        if let Some(node) = self.changes.get(&key) {
+            return Ok(*node);
+        }
+
+        self.storage
+            .get_opt(key)?
+            .map(|bytes| Node::decode(&bytes))
+            .transpose()
+    }
+
+    fn set_node(&mut self, depth: usize, path: &Hash, node: Option<Node>) {
+        self.changes.insert(self.node_key(depth, path), node);
+    }
+
+    /// The storage key of the node at `depth` on the path, which only depends on the first `depth` bits of the path.
+    fn node_key(&self, depth: usize, path: &Hash) -> Vec<u8> {
+        let mut key = STATE_TREE_KEY_PREFIX.to_vec();
+        key.extend_from_slice(self.live_object_id);
+        key.extend_from_slice(&(depth as u16).to_be_bytes());
+
+        let mut prefix = path[..depth.div_ceil(8)].to_vec();
+        if let Some(last) = prefix.last_mut() {
+            if depth % 8 != 0 {
+                *last &= 0xff << (8 - depth % 8);
+            }
+        }
+        key.extend_from_slice(&prefix);
+
+        key
+    }
+}
+
+/// Get the state root of a live object.
+pub fn state_root<S>(storage: &S, live_object_id: &[u8]) -> eyre::Result<Hash>
+where
+    S: Storage<Vec<u8>, Vec<u8>> + ?Sized,
+{
+    StateTree::new(storage, live_object_id).root()
+}
+
+/// Update the state trees of the live objects whose keys are written, with `None` marking a deletion.
+/// Keys that don't belong to a live object are skipped.
+pub fn update_state_trees<'k, S>(
+    storage: &S,
+    writes: impl IntoIterator<Item = (&'k Vec<u8>, &'k Option<Vec<u8>>)>,
+) -> eyre::Result<()>
+where
+    S: Storage<Vec<u8>, Vec<u8>> + ?Sized,
+{
+    storage.write_batch(state_tree_writes(storage, writes)?)
+}
+
+/// The node writes that bring the state trees of the live objects whose keys are written up to date, without
+/// applying them. The trees are read as of before the writes, so both must reach the storage in the same batch.
+pub fn state_tree_writes<'k, S>(
+    storage: &S,
+    writes: impl IntoIterator<Item = (&'k Vec<u8>, &'k Option<Vec<u8>>)>,
+) -> eyre::Result<WriteBatch>
+where
+    S: Storage<Vec<u8>, Vec<u8>> + ?Sized,
+{
+    let mut trees = BTreeMap::new();
+    for (key, value) in writes {
+        let Some((live_object_id, key)) = split_live_object_key(key) else {
+            continue;
+        };
+
+        let tree = trees
+            .entry(live_object_id)
+            .or_insert_with(|| StateTree::new(storage, live_object_id));
+        match value {
+            Some(value) => tree.insert(key, value)?,
+            None => tree.remove(key)?,
+        }
+    }
+
+    Ok(trees
+        .into_values()
+        .flat_map(StateTree::into_writes)
+        .collect())
+}
+
+/// Split a storage key into the ID of the live object it belongs to and the key within the live object.
+pub fn split_live_object_key(key: &[u8]) -> Option<(&[u8], &[u8])> {
+    if key.len() < LIVE_OBJECT_ID_LEN {
+        return None;
+    }
+
+    let (live_object_id, key) = key.split_at(LIVE_OBJECT_ID_LEN);
+    live_object_id
+        .iter()
+        .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+        .then_some((live_object_id, key))
+}
+
+/// Verify that `key` has `value` under `root`, or doesn't exist if `value` is `None`.
+/// The key doesn't include the live object ID. Only needs the root, so light clients can check proofs
+/// against a root they trust without the state.
+pub fn verify_state_proof(
+    root: &Hash,
+    key: &[u8],
+    value: Option<&[u8]>,
+    proof: &StateProof,
+) -> bool {
+    let key_hash = keccak256(key);
+    let depth = proof.siblings.len();
+    if depth > TREE_DEPTH {
+        return false;
+    }
+
+    let mut hash = match (&proof.leaf, value) {
+        (Some(leaf), Some(value)) => {
+            if leaf.key_hash != key_hash || leaf.value_hash != keccak256(value) {
+                return false;
+            }
+            hash_node(LEAF_DOMAIN, &leaf.key_hash, &leaf.value_hash)
+        }
+        (Some(leaf), None) => {
+            // Another key's leaf can only be on the path if both keys share the path so far.
+            let on_path =
+                (0..depth).all(|bit| path_bit(&leaf.key_hash, bit) == path_bit(&key_hash, bit));
+            if leaf.key_hash == key_hash || !on_path {
+                return false;
+            }
+            hash_node(LEAF_DOMAIN, &leaf.key_hash, &leaf.value_hash)
+        }
+        (None, Some(_)) => return false,
+        (None, None) => EMPTY_HASH,
+    };
+
+    for (depth, sibling) in proof.siblings.iter().enumerate().rev() {
+        hash = match path_bit(&key_hash, depth) {
+            false => hash_node(INTERNAL_DOMAIN, &hash, sibling),
+            true => hash_node(INTERNAL_DOMAIN, sibling, &hash),
+        };
+    }
+
+    hash == *root
+}
+
+fn hash_node(domain: u8, first: &Hash, second: &Hash) -> Hash {
+    let mut hasher = Keccak256::new();
+    hasher.update([domain]);
+    hasher.update(first);
+    hasher.update(second);
+    hasher.finalize().into()
+}
+
+fn keccak256(data: &[u8]) -> Hash {
+    Keccak256::digest(data).into()
+}
+
+fn path_bit(path: &Hash, bit: usize) -> bool {
+    path[bit / 8] >> (7 - bit % 8) & 1 == 1
+}
+
+fn set_bit(path: &Hash, bit: usize, value: bool) -> Hash {
+    let mut path = *path;
+    match value {
+        true => path[bit / 8] |= 1 << (7 - bit % 8),
+        false => path[bit / 8] &= !(1 << (7 - bit % 8)),
+    }
+    path
+}
+
+fn flip_bit(path: &Hash, bit: usize) -> Hash {
+    set_bit(path, bit, !path_bit(path, bit))
+}
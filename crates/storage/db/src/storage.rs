@@ -1,3 +1,8 @@
+use std::path::Path;
+
+/// Writes to apply together, with `None` marking a deletion.
+pub type WriteBatch = Vec<(Vec<u8>, Option<Vec<u8>>)>;
+
 pub trait Storage<K, V>: Send + Sync
 where
     K: AsRef<[u8]>,
@@ -8,4 +13,40 @@ where
     fn get_opt(&self, key: K) -> eyre::Result<Option<Vec<u8>>>;
     fn set(&self, key: K, value: V) -> eyre::Result<()>;
     fn delete(&self, key: K) -> eyre::Result<()>;
+    /// Apply the writes together, with `None` marking a deletion. Either all of them reach the storage or none do.
+    fn write_batch(&self, writes: Vec<(K, Option<V>)>) -> eyre::Result<()>;
+}
+
+/// A storage that can take consistent point-in-time views of itself.
+pub trait SnapshotStorage<K, V>: Storage<K, V>
+where
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+{
+    /// A read-only view of the storage as of when it was taken. Writes through it fail.
+    type Snapshot<'a>: Storage<K, V>
+    where
+        Self: 'a;
+
+    /// Take a snapshot, so that a series of reads sees the same state while the storage keeps changing.
+    fn snapshot(&self) -> Self::Snapshot<'_>;
+
+    /// Write a consistent copy of the storage to `path`, which must not exist yet.
+    fn checkpoint(&self, path: &Path) -> eyre::Result<()>;
+}
+
+/// A storage whose deleted data keeps taking space until it is compacted away.
+pub trait CompactableStorage<K, V>: Storage<K, V>
+where
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+{
+    /// The keys starting with `prefix`, in key order.
+    fn keys_with_prefix(&self, prefix: &[u8]) -> eyre::Result<Vec<Vec<u8>>>;
+
+    /// Compact the keys from `from` up to but excluding `to`, dropping deleted and overwritten values.
+    fn compact_range(&self, from: &[u8], to: &[u8]) -> eyre::Result<()>;
+
+    /// The estimated number of bytes the data that hasn't been deleted takes on disk.
+    fn live_data_size(&self) -> eyre::Result<u64>;
 }
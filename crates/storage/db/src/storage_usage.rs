@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+
+use crate::state_tree::split_live_object_key;
+use crate::storage::Storage;
+
+/// Prefix of the keys of the storage usage of live objects, which are followed by the live object ID.
+/// Starts with a character that live object IDs, being hex, never start with.
+pub const STORAGE_USAGE_KEY_PREFIX: &[u8] = b"ramd::storage_usage::";
+
+/// The key under which the storage usage of a live object is kept.
+pub fn storage_usage_key(live_object_id: &[u8]) -> Vec<u8> {
+    let mut key = STORAGE_USAGE_KEY_PREFIX.to_vec();
+    key.extend_from_slice(live_object_id);
+    key
+}
+
+/// Read the number of bytes stored by a live object, counting both keys and values.
+pub fn read_storage_usage<S>(storage: &S, live_object_id: &[u8]) -> eyre::Result<u64>
+where
+    S: Storage<Vec<u8>, Vec<u8>> + ?Sized,
+{
+    match storage.get_opt(storage_usage_key(live_object_id))? {
+        Some(bytes) => {
+            let bytes = <[u8; 8]>::try_from(bytes.as_slice())
+                .map_err(|_| eyre::eyre!("Storage usage of {} bytes is malformed", bytes.len()))?;
+            Ok(u64::from_be_bytes(bytes))
+        }
+        None => Ok(0),
+    }
+}
+
+/// The write that sets the storage usage of a live object. The key is deleted once nothing is stored.
+pub fn storage_usage_write(live_object_id: &[u8], usage: u64) -> (Vec<u8>, Option<Vec<u8>>) {
+    let value = (usage > 0).then(|| Vec::from(usage.to_be_bytes()));
+    (storage_usage_key(live_object_id), value)
+}
+
+/// The number of bytes each live object stores, counting the keys and values of its state.
+/// The metadata of a live object, stored at its bare ID, isn't counted.
+pub fn count_storage_usage(
+    entries: impl IntoIterator<Item = eyre::Result<(Vec<u8>, Vec<u8>)>>,
+) -> eyre::Result<BTreeMap<Vec<u8>, u64>> {
+    let mut usage = BTreeMap::new();
+    for entry in entries {
+        let (key, value) = entry?;
+        if let Some(live_object_id) = state_key_owner(&key) {
+            *usage.entry(live_object_id.to_vec()).or_default() += entry_len(&key, Some(&value));
+        }
+    }
+
+    Ok(usage)
+}
+
+/// The ID of the live object whose state the key belongs to, if any.
+pub fn state_key_owner(key: &[u8]) -> Option<&[u8]> {
+    split_live_object_key(key)
+        .filter(|(_, key)| !key.is_empty())
+        .map(|(live_object_id, _)| live_object_id)
+}
+
+/// The bytes an entry takes, which is nothing if the value is `None`.
+pub fn entry_len(key: &[u8], value: Option<&[u8]>) -> u64 {
+    value.map_or(0, |value| (key.len() + value.len()) as u64)
+}
@@ -0,0 +1,134 @@
+//! Backing up the database and restoring it from the backup.
+
+use ramd_db::backup::{backup, restore};
+use ramd_db::config::RocksConfig;
+use ramd_db::keys::RAMD_SCHEMA_VERSION_KEY;
+use ramd_db::migrations::MIGRATIONS;
+use ramd_db::rocks::RocksStorage;
+use ramd_db::storage::Storage;
+
+fn contents(storage: &RocksStorage) -> Vec<(Vec<u8>, Vec<u8>)> {
+    storage.iter().collect::<eyre::Result<_>>().unwrap()
+}
+
+#[test]
+fn restore_gives_back_the_backed_up_data() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = RocksConfig::new(dir.path().to_path_buf());
+    let backup_path = dir.path().join("backup");
+
+    let storage = RocksStorage::new(&config).unwrap();
+    for i in 0..100u32 {
+        storage
+            .set(
+                Vec::from(i.to_be_bytes()),
+                format!("value {}", i).into_bytes(),
+            )
+            .unwrap();
+    }
+    Storage::<Vec<u8>, Vec<u8>>::delete(&storage, Vec::from(7u32.to_be_bytes())).unwrap();
+    let expected = contents(&storage);
+
+    backup(&config, &backup_path).unwrap();
+
+    // Changes after the backup aren't part of it
+    storage
+        .set(Vec::from(*b"later"), Vec::from(*b"change"))
+        .unwrap();
+    drop(storage);
+
+    let restored_config = config.with_path(dir.path().join("restored"));
+    restore(&restored_config, &backup_path).unwrap();
+
+    let restored = RocksStorage::new(&restored_config).unwrap();
+    assert_eq!(contents(&restored), expected);
+    assert_eq!(
+        contents(&RocksStorage::open_read_only(&config.with_path(backup_path)).unwrap()),
+        expected
+    );
+}
+
+#[test]
+fn restore_refuses_to_overwrite_a_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = RocksConfig::new(dir.path().to_path_buf());
+    let backup_path = dir.path().join("backup");
+
+    RocksStorage::new(&config)
+        .unwrap()
+        .set(Vec::from(*b"key"), Vec::from(*b"value"))
+        .unwrap();
+    backup(&config, &backup_path).unwrap();
+    std::fs::create_dir_all(&config.path).unwrap();
+
+    assert!(restore(&config, &backup_path).is_err());
+    assert!(restore(
+        &config.with_path(dir.path().join("restored")),
+        &dir.path().join("missing")
+    )
+    .is_err());
+}
+
+#[test]
+fn backups_are_opened_read_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = RocksConfig::new(dir.path().to_path_buf());
+    let backup_path = dir.path().join("backup");
+
+    RocksStorage::new(&config)
+        .unwrap()
+        .set(Vec::from(*b"key"), Vec::from(*b"value"))
+        .unwrap();
+    backup(&config, &backup_path).unwrap();
+
+    let backup = RocksStorage::open_read_only(&config.with_path(backup_path)).unwrap();
+    assert!(backup
+        .set(Vec::from(*b"key"), Vec::from(*b"other"))
+        .is_err());
+    assert_eq!(
+        Storage::<Vec<u8>, Vec<u8>>::get(&backup, Vec::from(*b"key")).unwrap(),
+        Vec::from(*b"value")
+    );
+}
+
+#[test]
+fn databases_of_a_newer_binary_are_neither_backed_up_nor_restored() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = RocksConfig::new(dir.path().to_path_buf());
+    let backup_path = dir.path().join("backup");
+
+    let storage = RocksStorage::new(&config).unwrap();
+    storage
+        .set(
+            Vec::from(RAMD_SCHEMA_VERSION_KEY),
+            Vec::from((MIGRATIONS.len() as u64).to_be_bytes()),
+        )
+        .unwrap();
+    drop(storage);
+    backup(&config, &backup_path).unwrap();
+
+    RocksStorage::new(&config)
+        .unwrap()
+        .set(
+            Vec::from(RAMD_SCHEMA_VERSION_KEY),
+            Vec::from((MIGRATIONS.len() as u64 + 1).to_be_bytes()),
+        )
+        .unwrap();
+    assert!(backup(&config, &dir.path().join("newer-backup")).is_err());
+    assert!(!dir.path().join("newer-backup").exists());
+
+    // A backup of the newer database, made by the newer binary.
+    RocksStorage::new(&config)
+        .unwrap()
+        .checkpoint(&dir.path().join("newer-backup"))
+        .unwrap();
+    let restored_path = dir.path().join("restored");
+    assert!(restore(
+        &config.with_path(restored_path.clone()),
+        &dir.path().join("newer-backup")
+    )
+    .is_err());
+    assert!(!restored_path.exists());
+
+    restore(&config.with_path(restored_path), &backup_path).unwrap();
+}
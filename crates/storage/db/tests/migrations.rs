@@ -0,0 +1,188 @@
+//! Bringing the database to the schema version of the binary.
+
+use ramd_db::codec::Decoder;
+use ramd_db::config::RocksConfig;
+use ramd_db::keys::{LIVE_OBJECT_CODE_KEY_PREFIX, RAMD_SCHEMA_VERSION_KEY};
+use ramd_db::memory::MemoryStorage;
+use ramd_db::migrations::{migrate, open_storage, schema_version, Migration, MIGRATIONS};
+use ramd_db::rocks::RocksStorage;
+use ramd_db::state_tree::{state_root, update_state_trees};
+use ramd_db::storage::{Storage, WriteBatch};
+use ramd_db::storage_usage::read_storage_usage;
+
+/// Key the test migrations append their version to, so the order they ran in can be checked.
+const LOG_KEY: &[u8] = b"log";
+
+fn log_version(storage: &MemoryStorage, version: u8) -> eyre::Result<WriteBatch> {
+    let mut log = storage.get_opt(LOG_KEY.into())?.unwrap_or_default();
+    log.push(version);
+    Ok(vec![(LOG_KEY.into(), Some(log))])
+}
+
+fn migration(
+    version: u64,
+    run: fn(&MemoryStorage) -> eyre::Result<WriteBatch>,
+) -> Migration<MemoryStorage> {
+    Migration {
+        version,
+        description: "test",
+        run,
+    }
+}
+
+fn log(storage: &MemoryStorage) -> Vec<u8> {
+    storage.get_opt(LOG_KEY.into()).unwrap().unwrap_or_default()
+}
+
+fn set_version(storage: &MemoryStorage, version: u64) {
+    storage
+        .set(
+            RAMD_SCHEMA_VERSION_KEY.into(),
+            Vec::from(version.to_be_bytes()),
+        )
+        .unwrap();
+}
+
+#[test]
+fn migrations_apply_in_order() {
+    let migrations = [
+        migration(1, |storage| log_version(storage, 1)),
+        migration(2, |storage| log_version(storage, 2)),
+        migration(3, |storage| log_version(storage, 3)),
+    ];
+
+    let storage = MemoryStorage::default();
+    assert_eq!(schema_version(&storage).unwrap(), 0);
+    migrate(&storage, &migrations).unwrap();
+    assert_eq!(log(&storage), vec![1, 2, 3]);
+    assert_eq!(schema_version(&storage).unwrap(), 3);
+
+    // Migrating again changes nothing.
+    migrate(&storage, &migrations).unwrap();
+    assert_eq!(log(&storage), vec![1, 2, 3]);
+
+    // Only the migrations a database hasn't gone through are run.
+    let storage = MemoryStorage::default();
+    set_version(&storage, 2);
+    migrate(&storage, &migrations).unwrap();
+    assert_eq!(log(&storage), vec![3]);
+}
+
+#[test]
+fn a_failed_migration_writes_nothing() {
+    let migrations = [
+        migration(1, |storage| log_version(storage, 1)),
+        migration(2, |_| Err(eyre::eyre!("failed"))),
+    ];
+
+    let storage = MemoryStorage::default();
+    assert!(migrate(&storage, &migrations).is_err());
+    assert_eq!(log(&storage), vec![1]);
+    assert_eq!(schema_version(&storage).unwrap(), 1);
+}
+
+#[test]
+fn a_newer_database_is_refused() {
+    let migrations = [
+        migration(1, |storage| log_version(storage, 1)),
+        migration(2, |storage| log_version(storage, 2)),
+    ];
+
+    let storage = MemoryStorage::default();
+    set_version(&storage, 3);
+    assert!(migrate(&storage, &migrations).is_err());
+    assert_eq!(log(&storage), Vec::<u8>::new());
+    assert_eq!(schema_version(&storage).unwrap(), 3);
+}
+
+#[test]
+fn a_version_gap_is_rejected() {
+    let gap = [
+        migration(1, |storage| log_version(storage, 1)),
+        migration(3, |storage| log_version(storage, 3)),
+    ];
+    let unordered = [
+        migration(2, |storage| log_version(storage, 2)),
+        migration(1, |storage| log_version(storage, 1)),
+    ];
+
+    for migrations in [&gap, &unordered] {
+        let storage = MemoryStorage::default();
+        assert!(migrate(&storage, migrations).is_err());
+        assert_eq!(log(&storage), Vec::<u8>::new());
+        assert_eq!(schema_version(&storage).unwrap(), 0);
+    }
+}
+
+#[test]
+fn a_database_written_before_versioning_is_brought_up_to_date() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = RocksConfig::new(dir.path().to_path_buf());
+
+    let live_object_id = "a".repeat(64);
+    let mut state_key = live_object_id.clone().into_bytes();
+    state_key.extend_from_slice(b"k");
+    let legacy = serde_json::json!({
+        "id": live_object_id,
+        "hash": [1, 2, 3],
+        "wasm_bytes": [0, 97, 115, 109],
+        "memory_limit": 65536,
+        "abi": {"methods": []},
+    });
+
+    let storage = RocksStorage::new(&config).unwrap();
+    storage
+        .set(
+            live_object_id.clone().into_bytes(),
+            serde_json::to_vec(&legacy).unwrap(),
+        )
+        .unwrap();
+    let state = vec![(state_key.clone(), Some(vec![0; 10]))];
+    storage.write_batch(state.clone()).unwrap();
+    update_state_trees(&storage, state.iter().map(|(key, value)| (key, value))).unwrap();
+    drop(storage);
+
+    let storage = open_storage(&config).unwrap();
+    assert_eq!(
+        schema_version(&storage).unwrap(),
+        MIGRATIONS.last().unwrap().version
+    );
+
+    let mut code_key = LIVE_OBJECT_CODE_KEY_PREFIX.to_vec();
+    code_key.extend_from_slice(live_object_id.as_bytes());
+    assert_eq!(
+        Storage::<Vec<u8>, Vec<u8>>::get_opt(&storage, code_key).unwrap(),
+        Some(vec![0, 97, 115, 109])
+    );
+
+    let metadata =
+        Storage::<Vec<u8>, Vec<u8>>::get_opt(&storage, live_object_id.clone().into_bytes())
+            .unwrap()
+            .unwrap();
+    let mut decoder = Decoder::new(&metadata);
+    assert_eq!(decoder.byte().unwrap(), 1);
+    assert_eq!(decoder.string_field().unwrap(), live_object_id);
+    assert_eq!(decoder.field().unwrap(), [1, 2, 3]);
+    assert_eq!(decoder.byte().unwrap(), 1);
+    assert_eq!(decoder.u64().unwrap(), 65536);
+    assert_eq!(decoder.byte().unwrap(), 1);
+    assert_eq!(decoder.field().unwrap(), br#"{"methods":[]}"#);
+    decoder.finish().unwrap();
+
+    // The state tree covers the new metadata along with the state.
+    let expected = MemoryStorage::default();
+    let writes = vec![
+        (live_object_id.clone().into_bytes(), Some(metadata)),
+        (state_key.clone(), Some(vec![0; 10])),
+    ];
+    expected.write_batch(writes.clone()).unwrap();
+    update_state_trees(&expected, writes.iter().map(|(key, value)| (key, value))).unwrap();
+    assert_eq!(
+        state_root(&storage, live_object_id.as_bytes()).unwrap(),
+        state_root(&expected, live_object_id.as_bytes()).unwrap()
+    );
+    assert_eq!(
+        read_storage_usage(&storage, live_object_id.as_bytes()).unwrap(),
+        state_key.len() as u64 + 10
+    );
+}
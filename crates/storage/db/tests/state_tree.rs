@@ -0,0 +1,214 @@
+//! The state root of a live object and proofs of its keys against the root.
+
+use ramd_db::memory::MemoryStorage;
+use ramd_db::state_tree::{
+    state_root, update_state_trees, verify_state_proof, Hash, StateTree, EMPTY_HASH,
+};
+
+const LIVE_OBJECT_ID: &[u8] = b"67700b725575434de878141282f35a6d154688b608491b2a2539783ceef20996";
+
+fn entries() -> Vec<(Vec<u8>, Vec<u8>)> {
+    (0..32u32)
+        .map(|i| (format!("key {}", i).into_bytes(), i.to_le_bytes().to_vec()))
+        .collect()
+}
+
+fn storage_with(entries: &[(Vec<u8>, Vec<u8>)]) -> MemoryStorage {
+    let storage = MemoryStorage::default();
+    let mut tree = StateTree::new(&storage, LIVE_OBJECT_ID);
+    for (key, value) in entries {
+        tree.insert(key, value).unwrap();
+    }
+    tree.commit().unwrap();
+
+    storage
+}
+
+fn root_after_inserting(entries: &[(Vec<u8>, Vec<u8>)]) -> Hash {
+    state_root(&storage_with(entries), LIVE_OBJECT_ID).unwrap()
+}
+
+#[test]
+fn root_doesnt_depend_on_the_insert_order() {
+    let mut entries = entries();
+    let root = root_after_inserting(&entries);
+    assert_ne!(root, EMPTY_HASH);
+
+    entries.reverse();
+    assert_eq!(root_after_inserting(&entries), root);
+
+    // An interleaving that is neither sorted nor reversed
+    let (even, odd): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .enumerate()
+        .partition(|(index, _)| index % 2 == 0);
+    let shuffled = odd
+        .into_iter()
+        .chain(even)
+        .map(|(_, entry)| entry)
+        .collect::<Vec<_>>();
+    assert_eq!(root_after_inserting(&shuffled), root);
+}
+
+#[test]
+fn overwriting_a_value_changes_the_root() {
+    let entries = entries();
+    let storage = storage_with(&entries);
+    let root = state_root(&storage, LIVE_OBJECT_ID).unwrap();
+
+    let mut tree = StateTree::new(&storage, LIVE_OBJECT_ID);
+    tree.insert(&entries[0].0, b"other").unwrap();
+    assert_ne!(tree.root().unwrap(), root);
+
+    tree.insert(&entries[0].0, &entries[0].1).unwrap();
+    assert_eq!(tree.root().unwrap(), root);
+}
+
+#[test]
+fn remove_restores_the_previous_root() {
+    let entries = entries();
+    let storage = storage_with(&entries);
+    let root = state_root(&storage, LIVE_OBJECT_ID).unwrap();
+
+    let mut tree = StateTree::new(&storage, LIVE_OBJECT_ID);
+    tree.insert(b"extra", b"value").unwrap();
+    tree.commit().unwrap();
+    assert_ne!(state_root(&storage, LIVE_OBJECT_ID).unwrap(), root);
+
+    let mut tree = StateTree::new(&storage, LIVE_OBJECT_ID);
+    tree.remove(b"extra").unwrap();
+    tree.commit().unwrap();
+    assert_eq!(state_root(&storage, LIVE_OBJECT_ID).unwrap(), root);
+
+    // Removing every key leaves the empty tree, without nodes behind
+    let mut tree = StateTree::new(&storage, LIVE_OBJECT_ID);
+    for (key, _) in entries.iter() {
+        tree.remove(key).unwrap();
+    }
+    tree.commit().unwrap();
+    assert_eq!(state_root(&storage, LIVE_OBJECT_ID).unwrap(), EMPTY_HASH);
+    assert!(storage.data().is_empty());
+}
+
+#[test]
+fn update_state_trees_matches_the_tree_of_the_same_keys() {
+    let entries = entries();
+    let storage = MemoryStorage::default();
+    let writes = entries
+        .iter()
+        .map(|(key, value)| {
+            let mut storage_key = LIVE_OBJECT_ID.to_vec();
+            storage_key.extend_from_slice(key);
+            (storage_key, Some(value.clone()))
+        })
+        .chain([(b"not a live object key".to_vec(), Some(b"value".to_vec()))])
+        .collect::<Vec<_>>();
+
+    update_state_trees(&storage, writes.iter().map(|(key, value)| (key, value))).unwrap();
+
+    assert_eq!(
+        state_root(&storage, LIVE_OBJECT_ID).unwrap(),
+        root_after_inserting(&entries)
+    );
+}
+
+#[test]
+fn membership_proofs_verify() {
+    let entries = entries();
+    let storage = storage_with(&entries);
+    let root = state_root(&storage, LIVE_OBJECT_ID).unwrap();
+    let tree = StateTree::new(&storage, LIVE_OBJECT_ID);
+
+    for (key, value) in entries.iter() {
+        let proof = tree.prove(key).unwrap();
+        assert_eq!(proof.root, root);
+        assert!(verify_state_proof(&root, key, Some(value), &proof));
+    }
+}
+
+#[test]
+fn non_membership_proofs_verify() {
+    let storage = storage_with(&entries());
+    let root = state_root(&storage, LIVE_OBJECT_ID).unwrap();
+    let tree = StateTree::new(&storage, LIVE_OBJECT_ID);
+
+    for i in 0..32u32 {
+        let key = format!("missing {}", i).into_bytes();
+        let proof = tree.prove(&key).unwrap();
+        assert!(verify_state_proof(&root, &key, None, &proof));
+        assert!(!verify_state_proof(&root, &key, Some(b"value"), &proof));
+    }
+
+    // Everything is missing from the empty tree
+    let empty = MemoryStorage::default();
+    let proof = StateTree::new(&empty, LIVE_OBJECT_ID)
+        .prove(b"key")
+        .unwrap();
+    assert!(verify_state_proof(&EMPTY_HASH, b"key", None, &proof));
+}
+
+#[test]
+fn tampered_proofs_are_rejected() {
+    let entries = entries();
+    let storage = storage_with(&entries);
+    let root = state_root(&storage, LIVE_OBJECT_ID).unwrap();
+    let (key, value) = &entries[3];
+    let proof = StateTree::new(&storage, LIVE_OBJECT_ID).prove(key).unwrap();
+
+    // A wrong value
+    assert!(!verify_state_proof(&root, key, Some(b"other"), &proof));
+
+    // A wrong root
+    let mut other_root = root;
+    other_root[0] ^= 1;
+    assert!(!verify_state_proof(&other_root, key, Some(value), &proof));
+
+    // A flipped bit in any sibling
+    for index in 0..proof.siblings.len() {
+        let mut tampered = proof.clone();
+        tampered.siblings[index][31] ^= 1;
+        assert!(!verify_state_proof(&root, key, Some(value), &tampered));
+    }
+
+    // A missing or extra sibling
+    let mut tampered = proof.clone();
+    tampered.siblings.pop();
+    assert!(!verify_state_proof(&root, key, Some(value), &tampered));
+    let mut tampered = proof.clone();
+    tampered.siblings.push(EMPTY_HASH);
+    assert!(!verify_state_proof(&root, key, Some(value), &tampered));
+
+    // A tampered leaf
+    let mut tampered = proof.clone();
+    if let Some(leaf) = tampered.leaf.as_mut() {
+        leaf.value_hash[0] ^= 1;
+    }
+    assert!(!verify_state_proof(&root, key, Some(value), &tampered));
+
+    // Claiming that the key is missing with its own leaf, or with no leaf
+    assert!(!verify_state_proof(&root, key, None, &proof));
+    let mut tampered = proof;
+    tampered.leaf = None;
+    assert!(!verify_state_proof(&root, key, None, &tampered));
+}
+
+#[test]
+fn proofs_of_other_keys_are_rejected() {
+    let entries = entries();
+    let storage = storage_with(&entries);
+    let root = state_root(&storage, LIVE_OBJECT_ID).unwrap();
+    let tree = StateTree::new(&storage, LIVE_OBJECT_ID);
+
+    let (key, value) = &entries[0];
+    let (other_key, other_value) = &entries[1];
+    let proof = tree.prove(key).unwrap();
+
+    assert!(!verify_state_proof(&root, other_key, Some(value), &proof));
+    assert!(!verify_state_proof(
+        &root,
+        other_key,
+        Some(other_value),
+        &proof
+    ));
+    assert!(!verify_state_proof(&root, other_key, None, &proof));
+}
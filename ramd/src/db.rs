@@ -0,0 +1,27 @@
+use std::path::Path;
+
+use ramd_db::config::RocksConfig;
+
+/// Write a consistent copy of the stopped node's database to `backup_path`.
+pub(crate) fn backup(config: &RocksConfig, backup_path: &Path) -> eyre::Result<()> {
+    ramd_db::backup::backup(config, backup_path)?;
+    println!(
+        "Backed up `{}` to `{}`",
+        config.path.display(),
+        backup_path.display()
+    );
+
+    Ok(())
+}
+
+/// Restore the database of the stopped node from the backup at `backup_path`.
+pub(crate) fn restore(config: &RocksConfig, backup_path: &Path) -> eyre::Result<()> {
+    ramd_db::backup::restore(config, backup_path)?;
+    println!(
+        "Restored `{}` from `{}`",
+        config.path.display(),
+        backup_path.display()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,248 @@
+use std::path::{Path, PathBuf};
+
+use libp2p::identity::Keypair;
+use ramd_config::RamdConfig;
+use ramd_db::keys::RAMD_P2P_KEYPAIR_KEY;
+use ramd_db::storage::CompactableStorage;
+use ramd_p2p_server::keystore::{self, KEYSTORE_PASSPHRASE_ENV};
+use tracing::info;
+use zeroize::Zeroizing;
+
+/// Unlock the node identity for start-up with the passphrase taken from the environment, or one prompted for.
+/// A node without a keystore moves the key older versions kept in plain text in the database into a new keystore,
+/// or generates a new identity if it has none.
+pub(crate) fn unlock<S>(
+    config: &RamdConfig,
+    storage: &S,
+    passphrase: Option<Zeroizing<String>>,
+) -> eyre::Result<Keypair>
+where
+    S: CompactableStorage<Vec<u8>, Vec<u8>>,
+{
+    let path = config.keystore_path()?;
+    if path.exists() {
+        let passphrase = read_passphrase(passphrase, false)?;
+        let keypair = keystore::load(&path, &passphrase)?;
+        info!(target: "ramd::key", "Unlocked node identity {}", keypair.public().to_peer_id());
+
+        return Ok(keypair);
+    }
+
+    let legacy_key = storage.get_opt(Vec::from(RAMD_P2P_KEYPAIR_KEY))?;
+    let keypair = match &legacy_key {
+        Some(encoded) => Keypair::from_protobuf_encoding(encoded)?,
+        None => Keypair::generate_ed25519(),
+    };
+
+    let passphrase = read_passphrase(passphrase, true)?;
+    keystore::store(&path, &keypair, &passphrase)?;
+
+    if legacy_key.is_some() {
+        // The keystore is in place, so the plain text copy can go
+        storage.delete(Vec::from(RAMD_P2P_KEYPAIR_KEY))?;
+        let mut end = RAMD_P2P_KEYPAIR_KEY.to_vec();
+        end.push(0);
+        storage.compact_range(RAMD_P2P_KEYPAIR_KEY, &end)?;
+
+        info!(
+            target: "ramd::key",
+            "Moved node identity {} from the database to `{}`",
+            keypair.public().to_peer_id(),
+            path.display()
+        );
+    } else {
+        info!(
+            target: "ramd::key",
+            "Generated node identity {} in `{}`",
+            keypair.public().to_peer_id(),
+            path.display()
+        );
+    }
+
+    Ok(keypair)
+}
+
+/// Generate a new node identity. Refuses to replace an existing one, use `rotate` for that.
+pub(crate) fn generate(path: &Path, passphrase: Option<Zeroizing<String>>) -> eyre::Result<()> {
+    ensure_no_keystore(path)?;
+
+    let keypair = Keypair::generate_ed25519();
+    let passphrase = read_passphrase(passphrase, true)?;
+    keystore::store(path, &keypair, &passphrase)?;
+    println!(
+        "Generated node identity {} in `{}`",
+        keypair.public().to_peer_id(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+/// Import a node identity from the protobuf encoding of its keypair, as written by `export`.
+pub(crate) fn import(
+    path: &Path,
+    key_path: &Path,
+    passphrase: Option<Zeroizing<String>>,
+) -> eyre::Result<()> {
+    ensure_no_keystore(path)?;
+
+    let encoded = Zeroizing::new(std::fs::read(key_path)?);
+    let keypair = Keypair::from_protobuf_encoding(&encoded)?;
+    let passphrase = read_passphrase(passphrase, true)?;
+    keystore::store(path, &keypair, &passphrase)?;
+    println!(
+        "Imported node identity {} into `{}`",
+        keypair.public().to_peer_id(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+/// Write the protobuf encoding of the node's keypair to `key_path` in plain text, readable by the owner only.
+pub(crate) fn export(
+    path: &Path,
+    key_path: &Path,
+    passphrase: Option<Zeroizing<String>>,
+) -> eyre::Result<()> {
+    if key_path.exists() {
+        return Err(eyre::eyre!("`{}` already exists", key_path.display()));
+    }
+
+    let passphrase = read_passphrase(passphrase, false)?;
+    let keypair = keystore::load(path, &passphrase)?;
+    let encoded = Zeroizing::new(keypair.to_protobuf_encoding()?);
+    keystore::write_private(key_path, &encoded)?;
+    println!(
+        "Exported node identity {} to `{}`, it is not encrypted",
+        keypair.public().to_peer_id(),
+        key_path.display()
+    );
+
+    Ok(())
+}
+
+/// Replace the node identity with a new one under the same passphrase. The old keystore is kept next to the new
+/// one, suffixed with its peer id.
+pub(crate) fn rotate(path: &Path, passphrase: Option<Zeroizing<String>>) -> eyre::Result<()> {
+    let passphrase = read_passphrase(passphrase, false)?;
+    let old_keypair = keystore::load(path, &passphrase)?;
+    let old_peer_id = old_keypair.public().to_peer_id();
+
+    let mut backup_path = PathBuf::from(path);
+    backup_path
+        .as_mut_os_string()
+        .push(format!(".{}", old_peer_id));
+    if backup_path.exists() {
+        return Err(eyre::eyre!("`{}` already exists", backup_path.display()));
+    }
+    std::fs::copy(path, &backup_path)?;
+
+    let keypair = Keypair::generate_ed25519();
+    keystore::store(path, &keypair, &passphrase)?;
+    println!(
+        "Rotated node identity from {} to {}, the old keystore is kept in `{}`",
+        old_peer_id,
+        keypair.public().to_peer_id(),
+        backup_path.display()
+    );
+
+    Ok(())
+}
+
+fn ensure_no_keystore(path: &Path) -> eyre::Result<()> {
+    if path.exists() {
+        let peer_id = keystore::peer_id(path)?;
+        return Err(eyre::eyre!(
+            "`{}` already holds node identity {}, use `ramd key rotate` to replace it",
+            path.display(),
+            peer_id
+        ));
+    }
+
+    Ok(())
+}
+
+/// Take the keystore passphrase out of the environment, so that it isn't passed on to child processes or left
+/// readable for the lifetime of the node. Must be called before any other thread is started, as changing
+/// the environment while other threads may read it is unsound.
+pub(crate) fn take_env_passphrase() -> Option<Zeroizing<String>> {
+    let passphrase = std::env::var(KEYSTORE_PASSPHRASE_ENV)
+        .ok()
+        .map(Zeroizing::new);
+    std::env::remove_var(KEYSTORE_PASSPHRASE_ENV);
+    passphrase
+}
+
+/// Use the passphrase taken from the environment, or prompt for it. A passphrase that encrypts a new keystore
+/// must not be empty, and is asked for twice when prompted.
+fn read_passphrase(
+    passphrase: Option<Zeroizing<String>>,
+    new: bool,
+) -> eyre::Result<Zeroizing<String>> {
+    let passphrase = match passphrase {
+        Some(passphrase) => passphrase,
+        None => {
+            let passphrase = Zeroizing::new(rpassword::prompt_password("Keystore passphrase: ")?);
+            if new {
+                let confirmation =
+                    Zeroizing::new(rpassword::prompt_password("Repeat keystore passphrase: ")?);
+                if passphrase != confirmation {
+                    return Err(eyre::eyre!("Passphrases don't match"));
+                }
+            }
+            passphrase
+        }
+    };
+
+    if new && passphrase.is_empty() {
+        return Err(eyre::eyre!(
+            "The keystore passphrase must not be empty, set {} or enter one when prompted",
+            KEYSTORE_PASSPHRASE_ENV
+        ));
+    }
+
+    Ok(passphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ramd_db::config::RocksConfig;
+    use ramd_db::rocks::RocksStorage;
+    use ramd_db::storage::Storage;
+
+    fn passphrase() -> Option<Zeroizing<String>> {
+        Some(Zeroizing::new("passphrase".to_owned()))
+    }
+
+    #[test]
+    fn the_legacy_key_moves_into_the_keystore() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut config = RamdConfig::default();
+        config.p2p.keystore_path = Some(dir.path().join("node_key.json"));
+        let storage = RocksStorage::new(&RocksConfig::new(dir.path().join("db"))).unwrap();
+
+        let legacy_keypair = Keypair::generate_ed25519();
+        storage
+            .set(
+                Vec::from(RAMD_P2P_KEYPAIR_KEY),
+                legacy_keypair.to_protobuf_encoding().unwrap(),
+            )
+            .unwrap();
+
+        let keypair = unlock(&config, &storage, passphrase()).unwrap();
+        assert_eq!(keypair.public(), legacy_keypair.public());
+
+        // The plain text copy is gone, and the next start unlocks the same identity from the keystore.
+        assert_eq!(
+            Storage::<Vec<u8>, Vec<u8>>::get_opt(&storage, Vec::from(RAMD_P2P_KEYPAIR_KEY))
+                .unwrap(),
+            None
+        );
+        let keypair = unlock(&config, &storage, passphrase()).unwrap();
+        assert_eq!(keypair.public(), legacy_keypair.public());
+        assert!(unlock(&config, &storage, Some(Zeroizing::new("other".to_owned()))).is_err());
+    }
+}
@@ -1,11 +1,17 @@
+mod db;
+mod key;
+mod precompile;
+mod replay;
+
 use dotenv::dotenv;
 use ramd_config::RamdConfig;
-use ramd_db::rocks::RocksStorage;
-use ramd_jsonrpc_server::launch;
+use ramd_db::migrations::open_storage;
+use ramd_jsonrpc_server::{launch, launch_admin};
 use ramd_node::Node;
 use ramd_p2p_server::Server as P2pServer;
 use ramd_tracing::init as init_tracing;
-use std::{sync::Arc, thread::park};
+use std::{path::PathBuf, sync::Arc, thread::park};
+use zeroize::Zeroizing;
 
 /// Note: I think ideally inside of a main function we should create a ramd instance, with builder pattern to configure everything needed and then call some
 /// sort of a blocking run function, so that all the modules we have like p2p, jsonrpc etc. are configured outside of the main function.
@@ -16,12 +22,31 @@ use std::{sync::Arc, thread::park};
 ///     let ramd = RamdBuilder::new().with_a().with_b().build().await;
 ///     ramd.run().await;
 /// }
-#[tokio::main(flavor = "multi_thread")]
-async fn main() -> eyre::Result<()> {
+fn main() -> eyre::Result<()> {
     // parse .env faile
     dotenv().ok();
 
-    if let Err(e) = start().await {
+    // Take the passphrase out of the environment while this is the only thread
+    let passphrase = key::take_env_passphrase();
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().map(String::as_str) == Some("precompile") {
+        return run_precompile(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("replay") {
+        return run_replay(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("db") {
+        return run_db(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("key") {
+        return run_key(&args[1..], passphrase);
+    }
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    if let Err(e) = runtime.block_on(start(passphrase)) {
         return Err(eyre::eyre!("Failed to start ramd node. Reason: {}", e));
     }
 
@@ -32,7 +57,7 @@ async fn main() -> eyre::Result<()> {
 }
 
 /// This is a temp solution to properly log received error during start-up process
-async fn start() -> eyre::Result<()> {
+async fn start(passphrase: Option<Zeroizing<String>>) -> eyre::Result<()> {
     // Init or read ramd config
     let ramd_config = RamdConfig::init_or_read()?;
 
@@ -41,21 +66,101 @@ async fn start() -> eyre::Result<()> {
 
     tracing::info!("Topology is a community-driven technology that brings random access memory to the world computer to power lock-free asynchronous decentralized applications.");
 
-    // Construct RocksDB
-    let rocks = Arc::new(RocksStorage::new(&ramd_config.rocks)?);
+    // Construct RocksDB and migrate it to the current schema version
+    let rocks = Arc::new(open_storage(&ramd_config.rocks)?);
 
-    // Construct a RAM node
-    let node = Arc::new(Node::new(&ramd_config.node, rocks.clone())?);
+    // Unlock the node identity from its keystore
+    let node_key = key::unlock(&ramd_config, rocks.as_ref(), passphrase)?;
 
     // Launch p2p server
-    let (mut p2p, _p2p_msg_sender) = P2pServer::new(&ramd_config.p2p, rocks.clone())?;
+    let (mut p2p, p2p_msg_sender) = P2pServer::new(&ramd_config.p2p, node_key, rocks.clone())?;
     tokio::spawn(async move { p2p.launch().await });
 
+    // Construct a RAM node that gossips state roots through the p2p server
+    let node = Arc::new(
+        Node::new(&ramd_config.node, rocks.clone())?
+            .with_p2p_sender(p2p_msg_sender)
+            .with_message_log_retention(ramd_config.rocks.message_log_retention())
+            .with_backup_dir(ramd_config.rocks.backup_dir()),
+    );
+
+    // Compact deleted state and prune the message log in the background
+    if let Some(interval) = ramd_config.rocks.maintenance_interval() {
+        node.launch_maintenance(interval);
+    }
+
     // Launch jsonrpc server
     // TODO: for now we don't care about server, simply start it and forget
     // Revisit once proper server handle handling will be required
     let handle = launch(&ramd_config.json_rpc, node.clone()).await?;
     tokio::spawn(handle.stopped());
 
+    // Launch the admin API on localhost only
+    let admin_handle = launch_admin(&ramd_config.json_rpc, node.clone()).await?;
+    tokio::spawn(admin_handle.stopped());
+
     Ok(())
 }
+
+/// Usage: `ramd precompile <output-dir> [<live-object-id>...]`
+fn run_precompile(args: &[String]) -> eyre::Result<()> {
+    if args.is_empty() {
+        return Err(eyre::eyre!(
+            "Usage: ramd precompile <output-dir> [<live-object-id>...]"
+        ));
+    }
+
+    let ramd_config = RamdConfig::init_or_read()?;
+    let output_dir = PathBuf::from(&args[0]);
+
+    precompile::precompile(&ramd_config, &output_dir, &args[1..])
+}
+
+/// Usage: `ramd replay <db-path>`
+fn run_replay(args: &[String]) -> eyre::Result<()> {
+    if args.len() != 1 {
+        return Err(eyre::eyre!("Usage: ramd replay <db-path>"));
+    }
+
+    let ramd_config = RamdConfig::init_or_read()?;
+
+    replay::replay(&ramd_config, &PathBuf::from(&args[0]))
+}
+
+/// Usage: `ramd db <backup|restore> <backup-path>`
+fn run_db(args: &[String]) -> eyre::Result<()> {
+    let usage = || eyre::eyre!("Usage: ramd db <backup|restore> <backup-path>");
+    if args.len() != 2 {
+        return Err(usage());
+    }
+
+    let ramd_config = RamdConfig::init_or_read()?;
+    let backup_path = PathBuf::from(&args[1]);
+
+    match args[0].as_str() {
+        "backup" => db::backup(&ramd_config.rocks, &backup_path),
+        "restore" => db::restore(&ramd_config.rocks, &backup_path),
+        _ => Err(usage()),
+    }
+}
+
+/// Usage: `ramd key <generate|import <key-file>|export <key-file>|rotate>`
+fn run_key(args: &[String], passphrase: Option<Zeroizing<String>>) -> eyre::Result<()> {
+    let usage =
+        || eyre::eyre!("Usage: ramd key <generate|import <key-file>|export <key-file>|rotate>");
+
+    let ramd_config = RamdConfig::init_or_read()?;
+    let keystore_path = ramd_config.keystore_path()?;
+
+    match args {
+        [command] if command == "generate" => key::generate(&keystore_path, passphrase),
+        [command] if command == "rotate" => key::rotate(&keystore_path, passphrase),
+        [command, key_path] if command == "import" => {
+            key::import(&keystore_path, &PathBuf::from(key_path), passphrase)
+        }
+        [command, key_path] if command == "export" => {
+            key::export(&keystore_path, &PathBuf::from(key_path), passphrase)
+        }
+        _ => Err(usage()),
+    }
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::Path;
+
+use ramd_config::RamdConfig;
+use ramd_db::keys::LIVE_OBJECT_CODE_KEY_PREFIX;
+use ramd_db::migrations::{schema_version, MIGRATIONS};
+use ramd_db::rocks::RocksStorage;
+use ramd_db::storage::CompactableStorage;
+use ramd_vm::{artifact_file_name, precompile as precompile_module, LiveObjectInfo};
+
+/// Compile the live objects of the node's database ahead of time with the compiler, float policy and memory
+/// limits of the node config, and write the artifacts to the output directory. Each live object is compiled for
+/// the memory limit it was created with, so the node can load its artifact. Compiles every live object if no
+/// IDs are given. The database is opened read-only, so this can run next to the node.
+pub(crate) fn precompile(
+    config: &RamdConfig,
+    output_dir: &Path,
+    live_object_ids: &[String],
+) -> eyre::Result<()> {
+    let node_config = &config.node;
+    if !node_config.compiler.is_available() {
+        return Err(eyre::eyre!(
+            "ramd is built without the `{}` compiler",
+            node_config.compiler
+        ));
+    }
+
+    let storage = RocksStorage::open_read_only(&config.rocks)?;
+    let latest_version = MIGRATIONS.last().map_or(0, |migration| migration.version);
+    if schema_version(&storage)? != latest_version {
+        return Err(eyre::eyre!(
+            "The database isn't at schema version {}, start the node once to migrate it",
+            latest_version
+        ));
+    }
+
+    let live_object_ids = match live_object_ids {
+        [] => CompactableStorage::<Vec<u8>, Vec<u8>>::keys_with_prefix(
+            &storage,
+            LIVE_OBJECT_CODE_KEY_PREFIX,
+        )?
+        .into_iter()
+        .map(|key| String::from_utf8(key[LIVE_OBJECT_CODE_KEY_PREFIX.len()..].to_vec()))
+        .collect::<Result<Vec<_>, _>>()?,
+        live_object_ids => live_object_ids.to_vec(),
+    };
+
+    fs::create_dir_all(output_dir)?;
+
+    for live_object_id in live_object_ids {
+        let live_object_info = LiveObjectInfo::load(&storage, &live_object_id)?
+            .ok_or_else(|| eyre::eyre!("Live object `{}` doesn't exist", live_object_id))?;
+        let memory_limit = node_config
+            .memory_limits()
+            .resolve(live_object_info.memory_limit)?;
+
+        let artifact = precompile_module(
+            &live_object_info,
+            node_config.compiler,
+            node_config.float_policy,
+            memory_limit,
+        )?;
+        let artifact_path =
+            output_dir.join(artifact_file_name(&live_object_id, node_config.compiler));
+        fs::write(&artifact_path, artifact)?;
+
+        println!("{} -> {}", live_object_id, artifact_path.display());
+    }
+
+    Ok(())
+}
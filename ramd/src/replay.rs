@@ -0,0 +1,339 @@
+use std::path::Path;
+use std::slice;
+use std::sync::Arc;
+
+use ramd_config::RamdConfig;
+use ramd_db::compaction::PENDING_COMPACTION_KEY_PREFIX;
+use ramd_db::keys::RAMD_P2P_KEYPAIR_KEY;
+use ramd_db::migrations::open_storage;
+use ramd_db::rocks::RocksStorage;
+use ramd_processor::{MessageLog, Processor};
+
+/// Rebuild the state from the message log of the node's database into a fresh database at `db_path`,
+/// checking that every entry follows the one before it and the result of every message, and verify that
+/// both databases end up with the same content.
+pub(crate) fn replay(config: &RamdConfig, db_path: &Path) -> eyre::Result<()> {
+    if !config.node.compiler.is_available() {
+        return Err(eyre::eyre!(
+            "ramd is built without the `{}` compiler",
+            config.node.compiler
+        ));
+    }
+    if db_path.exists() {
+        return Err(eyre::eyre!(
+            "`{}` already exists, replay needs a fresh database",
+            db_path.display()
+        ));
+    }
+
+    let source = Arc::new(open_storage(&config.rocks)?);
+    let target = Arc::new(open_storage(
+        &config.rocks.with_path(db_path.to_path_buf()),
+    )?);
+    let processor = Processor::new(target.clone(), config.node.processor_config());
+
+    let length = MessageLog::len(source.as_ref())?;
+    let first = MessageLog::first(source.as_ref())?;
+    if first > 0 {
+        return Err(eyre::eyre!(
+            "The message log was pruned up to entry {}, so the state can't be rebuilt from it",
+            first
+        ));
+    }
+
+    let mut previous_message_id = None;
+    for sequence in 0..length {
+        let entry = MessageLog::entry(source.as_ref(), sequence)?
+            .ok_or_else(|| eyre::eyre!("Log entry {} is missing", sequence))?;
+        let message_id = entry.message.id.clone();
+
+        // Each entry names the message logged before it, so entries can't be reordered or dropped unnoticed.
+        let expected_predecessors = previous_message_id.iter().cloned().collect::<Vec<_>>();
+        if entry.predecessors != expected_predecessors {
+            return Err(eyre::eyre!(
+                "Log entry {} follows {:?}, but the entry before it is {:?}",
+                sequence,
+                entry.predecessors,
+                expected_predecessors
+            ));
+        }
+        previous_message_id = Some(message_id.clone());
+
+        let output = processor.process_messages(slice::from_ref(&entry.message));
+        let result = serde_json::from_str::<serde_json::Value>(&output)
+            .ok()
+            .and_then(|results| results[&message_id]["result"].as_str().map(str::to_owned))
+            .ok_or_else(|| eyre::eyre!("Message `{}` failed on replay: {}", message_id, output))?;
+
+        if MessageLog::result_hash(&result) != entry.result_hash {
+            return Err(eyre::eyre!(
+                "Message `{}` has result hash {} on replay, but {} in the log",
+                message_id,
+                hex::encode(MessageLog::result_hash(&result)),
+                hex::encode(&entry.result_hash)
+            ));
+        }
+    }
+
+    verify_state(&source, &target)?;
+    println!("Replayed {} messages into `{}`", length, db_path.display());
+
+    Ok(())
+}
+
+/// Compare the content of both databases, leaving out the keys of the node rather than of the state.
+fn verify_state(source: &RocksStorage, target: &RocksStorage) -> eyre::Result<()> {
+    let is_state = |entry: &eyre::Result<(Vec<u8>, Vec<u8>)>| !matches!(entry, Ok((key, _)) if is_node_local(key));
+    let mut source_entries = source.iter().filter(is_state);
+    let mut target_entries = target.iter().filter(is_state);
+
+    loop {
+        let source_entry = source_entries.next().transpose()?;
+        let target_entry = target_entries.next().transpose()?;
+        if source_entry == target_entry && source_entry.is_some() {
+            continue;
+        }
+
+        let differing_key = match (source_entry, target_entry) {
+            (None, None) => return Ok(()),
+            (Some((source_key, _)), Some((target_key, _))) => source_key.min(target_key),
+            (Some((key, _)), None) | (None, Some((key, _))) => key,
+        };
+
+        return Err(eyre::eyre!(
+            "Replayed state differs at key `{}`",
+            hex::encode(differing_key)
+        ));
+    }
+}
+
+/// Whether the key is housekeeping of the node, which differs between databases holding the same state:
+/// the p2p keypair of older versions, and the compaction markers that maintenance clears but a replay writes.
+fn is_node_local(key: &[u8]) -> bool {
+    key == RAMD_P2P_KEYPAIR_KEY || key.starts_with(PENDING_COMPACTION_KEY_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ramd_db::compaction::pending_compactions;
+    use ramd_db::config::RocksConfig;
+    use ramd_db::storage::Storage;
+    use ramd_node::run_maintenance;
+    use ramd_processor::{Action, CreateLiveObjectAction, ExecuteLiveObjectAction, Message};
+    use ramd_vm::MessageContext;
+
+    /// A live object whose `set` method writes key `k`, whose `seed` method writes the random seed of the
+    /// message at `k`, and whose `remove` method deletes it.
+    const KEY_MODULE: &str = r#"
+    (module
+      (import "env" "storage_write" (func $storage_write (param i32 i32)))
+      (import "env" "storage_delete" (func $storage_delete (param i32)))
+      (import "env" "random_seed" (func $random_seed (result i32)))
+      (memory (export "memory") 1)
+      (global $next (mut i32) (i32.const 1024))
+      ;; Slices of the key at 64, of the value at 72, and of the result at 80.
+      (data (i32.const 16) "\40\00\00\00\01\00\00\00\48\00\00\00\01\00\00\00\50\00\00\00\02\00\00\00")
+      (data (i32.const 64) "k")
+      (data (i32.const 72) "v")
+      (data (i32.const 80) "ok")
+      (func $allocate (export "allocate") (param $len i32) (result i32)
+        (local $slice i32)
+        (local.set $slice (global.get $next))
+        (i32.store (local.get $slice) (i32.add (local.get $slice) (i32.const 8)))
+        (i32.store offset=4 (local.get $slice) (local.get $len))
+        (global.set $next (i32.add (global.get $next) (i32.add (local.get $len) (i32.const 8))))
+        (local.get $slice))
+      (func $deallocate (export "deallocate") (param i32))
+      (func (export "set") (param $args i32) (result i32)
+        (call $deallocate (local.get $args))
+        (call $storage_write (i32.const 16) (i32.const 24))
+        (i32.const 32))
+      (func (export "seed") (param $args i32) (result i32)
+        (call $deallocate (local.get $args))
+        (call $storage_write (i32.const 16) (call $random_seed))
+        (i32.const 32))
+      (func (export "remove") (param $args i32) (result i32)
+        (call $deallocate (local.get $args))
+        (call $storage_delete (i32.const 16))
+        (i32.const 32)))
+    "#;
+
+    fn create() -> Message {
+        Message::new(Action::CreateLiveObject(CreateLiveObjectAction {
+            wasm_bytes: wat::parse_str(KEY_MODULE).unwrap(),
+            memory_limit: None,
+        }))
+    }
+
+    fn execute(live_object_id: &str, method: &str) -> Message {
+        Message::new(Action::ExecuteLiveObject(ExecuteLiveObjectAction {
+            live_object_id: live_object_id.to_owned(),
+            method: method.to_owned(),
+            args: Vec::new(),
+        }))
+    }
+
+    /// Process the messages one by one into the node's database, and return their results.
+    fn process(config: &RamdConfig, messages: Vec<Message>) -> Vec<String> {
+        let storage = Arc::new(open_storage(&config.rocks).unwrap());
+        let processor = Processor::new(storage, config.node.processor_config());
+
+        messages
+            .into_iter()
+            .map(|message| {
+                let results: serde_json::Value =
+                    serde_json::from_str(&processor.process_messages(slice::from_ref(&message)))
+                        .unwrap();
+                results[&message.id]["result"].as_str().unwrap().to_owned()
+            })
+            .collect()
+    }
+
+    fn config(dir: &Path) -> RamdConfig {
+        RamdConfig {
+            rocks: RocksConfig::new(dir.join("db")),
+            ..RamdConfig::default()
+        }
+    }
+
+    fn contents(storage: &RocksStorage) -> Vec<(Vec<u8>, Vec<u8>)> {
+        storage.iter().collect::<eyre::Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn replay_rebuilds_the_state_and_the_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+
+        let create = create();
+        let live_object_id = create.action.live_object_id();
+        let results = process(
+            &config,
+            vec![
+                create,
+                execute(&live_object_id, "set"),
+                execute(&live_object_id, "remove"),
+                execute(&live_object_id, "set"),
+            ],
+        );
+
+        let db_path = dir.path().join("replayed");
+        replay(&config, &db_path).unwrap();
+
+        let source = open_storage(&config.rocks).unwrap();
+        let target = open_storage(&config.rocks.with_path(db_path)).unwrap();
+        for (sequence, result) in results.iter().enumerate() {
+            let source_entry = MessageLog::entry(&source, sequence as u64)
+                .unwrap()
+                .unwrap();
+            let target_entry = MessageLog::entry(&target, sequence as u64)
+                .unwrap()
+                .unwrap();
+            assert_eq!(target_entry.message.id, source_entry.message.id);
+            assert_eq!(target_entry.predecessors, source_entry.predecessors);
+            assert_eq!(target_entry.result_hash, MessageLog::result_hash(result));
+        }
+
+        let mut state_key = live_object_id.into_bytes();
+        state_key.push(b'k');
+        assert!(contents(&target).contains(&(state_key, Vec::from(*b"v"))));
+        assert_eq!(contents(&target), contents(&source));
+    }
+
+    #[test]
+    fn replay_reproduces_the_random_seed() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+
+        let create = create();
+        let live_object_id = create.action.live_object_id();
+        let seed = execute(&live_object_id, "seed");
+        let expected_seed = MessageContext {
+            id: seed.id.clone(),
+            timestamp: seed.timestamp,
+        }
+        .random_seed();
+        process(&config, vec![create, seed]);
+
+        // Replaying fails unless the replayed state matches, so the seed must be the same.
+        let db_path = dir.path().join("replayed");
+        replay(&config, &db_path).unwrap();
+
+        let target = open_storage(&config.rocks.with_path(db_path)).unwrap();
+        let mut state_key = live_object_id.into_bytes();
+        state_key.push(b'k');
+        assert_eq!(
+            Storage::<Vec<u8>, Vec<u8>>::get(&target, state_key).unwrap(),
+            expected_seed
+        );
+    }
+
+    #[test]
+    fn replay_fails_on_reordered_log_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+
+        let create = create();
+        let live_object_id = create.action.live_object_id();
+        process(
+            &config,
+            vec![
+                create,
+                execute(&live_object_id, "set"),
+                execute(&live_object_id, "remove"),
+            ],
+        );
+
+        let storage = open_storage(&config.rocks).unwrap();
+        let entry = |sequence| {
+            Storage::<Vec<u8>, Vec<u8>>::get(&storage, MessageLog::entry_key(sequence)).unwrap()
+        };
+        let (first, second) = (entry(1), entry(2));
+        storage
+            .write_batch(vec![
+                (MessageLog::entry_key(1), Some(second)),
+                (MessageLog::entry_key(2), Some(first)),
+            ])
+            .unwrap();
+        drop(storage);
+
+        let err = replay(&config, &dir.path().join("replayed")).unwrap_err();
+        assert!(
+            err.to_string().starts_with("Log entry 1 follows"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn replay_after_maintenance_matches_the_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+
+        let create = create();
+        let live_object_id = create.action.live_object_id();
+        process(
+            &config,
+            vec![
+                create,
+                execute(&live_object_id, "set"),
+                execute(&live_object_id, "remove"),
+            ],
+        );
+
+        // Maintenance clears the marker the deletion left in the node's database, while replaying the
+        // deletion writes it again.
+        let storage = open_storage(&config.rocks).unwrap();
+        assert_eq!(pending_compactions(&storage).unwrap(), vec![live_object_id]);
+        assert_eq!(
+            run_maintenance(&storage, None)
+                .unwrap()
+                .compacted_live_objects,
+            1
+        );
+        drop(storage);
+
+        replay(&config, &dir.path().join("replayed")).unwrap();
+    }
+}
@@ -0,0 +1,10 @@
+#!/bin/bash
+
+curl --location '127.0.0.1:1320' \
+--header 'Content-Type: application/json' \
+--data '{
+  "jsonrpc": "2.0",
+  "method": "admin_read_cache_stats",
+  "params": {},
+  "id": 1
+}'
@@ -0,0 +1,10 @@
+#!/bin/bash
+
+curl --location '127.0.0.1:1320' \
+--header 'Content-Type: application/json' \
+--data '{
+  "jsonrpc": "2.0",
+  "method": "admin_run_maintenance",
+  "params": {},
+  "id": 1
+}'
@@ -0,0 +1,25 @@
+#!/bin/bash
+
+if [ "$1" == "sum" ]; then
+    live_object_id="67700b725575434de878141282f35a6d154688b608491b2a2539783ceef20996"
+elif [ "$1" == "gcounter" ]; then
+    live_object_id="eabe86b1378265b7dc3416274f565a98ecd88147a291c6d5fcaaf344e85d9bc5"
+elif [ "$1" == "read_only" ]; then
+    live_object_id="5ad7ecfe23d31ba7eff234a233e013be50adc8d9a0300cf91a93aba3f489e362"
+else
+    echo "Invalid argument. Please use 'sum', 'gcounter' or 'read_only'."
+    exit 1
+fi
+
+curl --location '0.0.0.0:1319' \
+--header 'Content-Type: application/json' \
+--data '{
+  "jsonrpc": "2.0",
+  "method": "live_object_abi",
+  "params": {
+      "request": {
+          "live_object_id": "'"$live_object_id"'"
+      }
+  },
+  "id": 1
+}'
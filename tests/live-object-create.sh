@@ -4,8 +4,10 @@ if [ "$1" == "sum" ]; then
     wasm_bytes=$(cat tests/wasms/live_object_sum_base64)
 elif [ "$1" == "gcounter" ]; then
     wasm_bytes=$(cat tests/wasms/live_object_gcounter_base64)
+elif [ "$1" == "read_only" ]; then
+    wasm_bytes=$(cat tests/wasms/live_object_read_only_base64)
 else
-    echo "Invalid argument. Please use 'sum' or 'gcounter'."
+    echo "Invalid argument. Please use 'sum', 'gcounter' or 'read_only'."
     exit 1
 fi
 
@@ -21,8 +21,17 @@ elif [ "$1" == "gcounter" ]; then
     args="{\\\"delta\\\": $delta}"
     live_object_id="eabe86b1378265b7dc3416274f565a98ecd88147a291c6d5fcaaf344e85d9bc5"
     method="increment"
+elif [ "$1" == "read_only" ]; then
+    if [ -z "$2" ] || [ -z "$3" ]; then
+        echo "Missing arguments for read_only. Usage: $0 read_only method key"
+        exit 1
+    fi
+
+    method=$2
+    args="\\\"$3\\\""
+    live_object_id="5ad7ecfe23d31ba7eff234a233e013be50adc8d9a0300cf91a93aba3f489e362"
 else
-    echo "Invalid operation. Use 'sum' or 'gcounter'."
+    echo "Invalid operation. Use 'sum', 'gcounter' or 'read_only'."
     exit 1
 fi
 
@@ -0,0 +1,19 @@
+#!/bin/bash
+
+if [ -z "$1" ]; then
+    echo "Missing message ID. Usage: $0 <message_id>"
+    exit 1
+fi
+
+curl --location '0.0.0.0:1319' \
+--header 'Content-Type: application/json' \
+--data '{
+  "jsonrpc": "2.0",
+  "method": "live_object_message_status",
+  "params": {
+      "request": {
+          "message_id": "'"$1"'"
+      }
+  },
+  "id": 1
+}'
@@ -0,0 +1,23 @@
+#!/bin/bash
+
+if [ "$1" == "sum" ]; then
+    live_object_id="67700b725575434de878141282f35a6d154688b608491b2a2539783ceef20996"
+elif [ "$1" == "gcounter" ]; then
+    live_object_id="eabe86b1378265b7dc3416274f565a98ecd88147a291c6d5fcaaf344e85d9bc5"
+else
+    echo "Invalid argument. Please use 'sum' or 'gcounter'."
+    exit 1
+fi
+
+curl --location '0.0.0.0:1319' \
+--header 'Content-Type: application/json' \
+--data '{
+  "jsonrpc": "2.0",
+  "method": "live_object_storage_usage",
+  "params": {
+      "request": {
+          "live_object_id": "'"$live_object_id"'"
+      }
+  },
+  "id": 1
+}'
@@ -0,0 +1,27 @@
+;; A live object whose ABI declares `get` and `sneaky_set` read-only, though `sneaky_set` writes the storage.
+;; Each method takes a JSON string as the key: `get` returns its value, `set` and `sneaky_set` store the key
+;; as its own value, and `count` returns the key, which its ABI declares an integer.
+(module
+  (import "env" "storage_read" (func $storage_read (param i32) (result i32)))
+  (import "env" "storage_write" (func $storage_write (param i32 i32)))
+  (@custom "ramd_abi" "{\"methods\": [{\"name\": \"get\", \"args\": {\"type\": \"string\"}, \"returns\": {\"type\": \"string\"}, \"read_only\": true}, {\"name\": \"set\", \"args\": {\"type\": \"string\"}, \"returns\": {\"type\": \"string\"}}, {\"name\": \"sneaky_set\", \"args\": {\"type\": \"string\"}, \"returns\": {\"type\": \"string\"}, \"read_only\": true}, {\"name\": \"count\", \"args\": {\"type\": \"string\"}, \"returns\": {\"type\": \"integer\"}, \"read_only\": true}]}")
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  ;; A slice is a header of the data pointer and the length, followed by the data. Nothing is ever freed.
+  (func (export "allocate") (param $len i32) (result i32)
+    (local $slice i32)
+    (local.set $slice (global.get $next))
+    (i32.store (local.get $slice) (i32.add (local.get $slice) (i32.const 8)))
+    (i32.store offset=4 (local.get $slice) (local.get $len))
+    (global.set $next (i32.add (global.get $next) (i32.add (local.get $len) (i32.const 8))))
+    (local.get $slice))
+  (func (export "deallocate") (param $slice i32))
+  (func (export "get") (param $args i32) (result i32)
+    (call $storage_read (local.get $args)))
+  (func $set (export "set") (param $args i32) (result i32)
+    (call $storage_write (local.get $args) (local.get $args))
+    (local.get $args))
+  (func (export "sneaky_set") (param $args i32) (result i32)
+    (call $set (local.get $args)))
+  (func (export "count") (param $args i32) (result i32)
+    (local.get $args)))